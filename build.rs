@@ -4,8 +4,10 @@ use std::io::Read;
 use std::process::Command;
 
 fn main() {
-    // static link the musl target
-    if env::var("TARGET").unwrap() == "x86_64-unknown-linux-musl" {
+    // static link the musl target, unless the pure Rust seccomp backend is used
+    if env::var("TARGET").unwrap() == "x86_64-unknown-linux-musl"
+        && env::var_os("CARGO_FEATURE_LIBSECCOMP").is_some()
+    {
         let mut cmd = Command::new("./build_seccomp.sh");
         let output = cmd.output().expect("cmd failed to start");
         if !output.status.success() {
@@ -0,0 +1,49 @@
+/*
+ * Fire 容器运行时的 C 接口
+ *
+ * 所有请求和响应都是 UTF-8 编码的 JSON 字符串。函数成功返回 0，失败返回 -1，
+ * 失败时 response 为 {"error": "...", "code": n}，code 与 fire 命令行的退出码
+ * 相同。response 不为 NULL 时总会被写入，需要用 fire_string_free 释放。
+ *
+ * 容器状态保存在 $HOME/.fire 下，与 fire 命令行共享。
+ *
+ * 容器进程通过重新执行 /proc/self/exe，即宿主程序本身启动，宿主程序必须在 main
+ * 开头、创建线程之前调用 fire_init，否则容器无法启动。
+ */
+
+#ifndef FIRE_H
+#define FIRE_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* 当前进程是 fire 启动的容器进程时执行容器命令，不再返回，否则直接返回 */
+void fire_init(void);
+
+/* {"id", "bundle", "dns"?, "dnsSearch"?, "dnsOptions"?, "rootfsLayers"?, "cgroupParent"?}
+ * 成功时 response 为容器的 OCI 状态 */
+int fire_create(const char *request, char **response);
+
+/* {"id"} */
+int fire_start(const char *request, char **response);
+
+/* {"id", "signal"?}，signal 默认为 SIGTERM */
+int fire_kill(const char *request, char **response);
+
+/* {"id", "force"?} */
+int fire_delete(const char *request, char **response);
+
+/* {"id"}，成功时 response 为容器的 OCI 状态 */
+int fire_state(const char *request, char **response);
+
+void fire_string_free(char *s);
+
+/* 静态字符串，不需要释放 */
+const char *fire_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FIRE_H */
@@ -0,0 +1,171 @@
+//! Fire 容器运行时的 C 接口
+//!
+//! 所有函数的参数都是 UTF-8 编码的 JSON 请求，成功返回 0，失败返回 -1。
+//! 结果通过 response 以 JSON 返回，失败时为 `{"error": "...", "code": n}`，由调用方
+//! 使用 fire_string_free 释放。code 与命令行的退出码相同。函数声明见 include/fire.h。
+//!
+//! 容器进程通过重新执行 `/proc/self/exe`，即链接 libfire 的宿主程序启动，宿主程序
+//! 需要在 main 开头调用 [`fire_init`]。
+
+use fire::api::{Builder, ContainerHandle};
+use fire::errors::{FireError, Result};
+use fire::network::etcfiles::DnsConfig;
+use libc::{c_char, c_int};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::ffi::{CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateRequest {
+    id: String,
+    bundle: PathBuf,
+    #[serde(default)]
+    dns: Vec<String>,
+    #[serde(default)]
+    dns_search: Vec<String>,
+    #[serde(default)]
+    dns_options: Vec<String>,
+    #[serde(default)]
+    rootfs_layers: Vec<PathBuf>,
+    #[serde(default)]
+    cgroup_parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdRequest {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct KillRequest {
+    id: String,
+    #[serde(default = "default_signal")]
+    signal: i32,
+}
+
+#[derive(Deserialize)]
+struct DeleteRequest {
+    id: String,
+    #[serde(default)]
+    force: bool,
+}
+
+fn default_signal() -> i32 {
+    libc::SIGTERM
+}
+
+/// 解析请求、执行操作并写回响应
+///
+/// panic 不能跨越 C ABI 传播，这里统一转换为错误返回。
+fn call<R, F>(request: *const c_char, response: *mut *mut c_char, f: F) -> c_int
+where
+    R: for<'de> Deserialize<'de>,
+    F: FnOnce(R) -> Result<Value>,
+{
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        if request.is_null() {
+            return Err(FireError::Generic("请求为空".to_string()));
+        }
+        let request = unsafe { CStr::from_ptr(request) }
+            .to_str()
+            .map_err(|e| FireError::Generic(format!("请求不是合法的 UTF-8: {}", e)))?;
+        f(serde_json::from_str(request)?)
+    }))
+    .unwrap_or_else(|_| Err(FireError::Generic("fire 内部错误".to_string())));
+
+    let (code, body) = match result {
+        Ok(value) => (0, value),
+        Err(e) => (-1, json!({ "error": e.to_string(), "code": e.exit_code() })),
+    };
+    if !response.is_null() {
+        // JSON 序列化会转义 NUL，这里不会失败
+        let body = CString::new(body.to_string()).unwrap_or_default();
+        unsafe { *response = body.into_raw() };
+    }
+    code
+}
+
+/// 当前进程是 fire 启动的容器进程时进入 init 阶段执行容器命令，不再返回，否则直接返回
+///
+/// 需要在宿主程序 main 开头、创建线程之前调用，否则通过本接口创建的容器无法启动。
+#[no_mangle]
+pub extern "C" fn fire_init() {
+    // init 阶段 panic 时不能回到宿主程序的 main 继续执行
+    if catch_unwind(fire::api::init).is_err() {
+        std::process::exit(1);
+    }
+}
+
+/// 创建容器
+///
+/// 请求: `{"id", "bundle", "dns"?, "dnsSearch"?, "dnsOptions"?, "rootfsLayers"?, "cgroupParent"?}`
+/// 响应: 容器的 OCI 状态
+#[no_mangle]
+pub extern "C" fn fire_create(request: *const c_char, response: *mut *mut c_char) -> c_int {
+    call(request, response, |req: CreateRequest| {
+        let mut builder = Builder::new(req.id)
+            .bundle(req.bundle)
+            .dns(DnsConfig::new(req.dns, req.dns_search, req.dns_options))
+            .rootfs_layers(req.rootfs_layers);
+        if let Some(parent) = req.cgroup_parent {
+            builder = builder.cgroup_parent(parent);
+        }
+        Ok(serde_json::to_value(builder.spawn()?.state()?)?)
+    })
+}
+
+/// 启动容器，请求: `{"id"}`，响应: `{}`
+#[no_mangle]
+pub extern "C" fn fire_start(request: *const c_char, response: *mut *mut c_char) -> c_int {
+    call(request, response, |req: IdRequest| {
+        ContainerHandle::open(req.id)?.start()?;
+        Ok(json!({}))
+    })
+}
+
+/// 向容器发送信号，请求: `{"id", "signal"?}`，signal 默认为 SIGTERM，响应: `{}`
+#[no_mangle]
+pub extern "C" fn fire_kill(request: *const c_char, response: *mut *mut c_char) -> c_int {
+    call(request, response, |req: KillRequest| {
+        ContainerHandle::open(req.id)?.kill(req.signal)?;
+        Ok(json!({}))
+    })
+}
+
+/// 删除容器，请求: `{"id", "force"?}`，响应: `{}`
+#[no_mangle]
+pub extern "C" fn fire_delete(request: *const c_char, response: *mut *mut c_char) -> c_int {
+    call(request, response, |req: DeleteRequest| {
+        ContainerHandle::open(req.id)?.delete(req.force)?;
+        Ok(json!({}))
+    })
+}
+
+/// 查询容器状态，请求: `{"id"}`，响应: 容器的 OCI 状态
+#[no_mangle]
+pub extern "C" fn fire_state(request: *const c_char, response: *mut *mut c_char) -> c_int {
+    call(request, response, |req: IdRequest| {
+        Ok(serde_json::to_value(ContainerHandle::open(req.id)?.state()?)?)
+    })
+}
+
+/// 释放 fire_* 函数返回的字符串
+///
+/// # Safety
+///
+/// s 必须是 fire_* 函数写入 response 的指针，且只能释放一次。
+#[no_mangle]
+pub unsafe extern "C" fn fire_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// 接口版本，返回的字符串为静态分配，不需要释放
+#[no_mangle]
+pub extern "C" fn fire_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
@@ -0,0 +1,193 @@
+use super::*;
+
+/// Fluent builder for `Spec`.
+///
+/// Fields that are not set keep their `Default` values, so the result
+/// serializes to the same JSON as a hand-written config that omits them.
+///
+/// ```
+/// use oci::{LinuxNamespaceType, Spec};
+///
+/// let spec = Spec::builder()
+///     .root("rootfs", true)
+///     .args(["/bin/sh", "-c", "echo hello"])
+///     .env(["PATH=/usr/bin:/bin"])
+///     .namespaces([LinuxNamespaceType::pid, LinuxNamespaceType::mount])
+///     .build();
+/// assert_eq!(spec.process.args[0], "/bin/sh");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpecBuilder {
+    spec: Spec,
+}
+
+impl Default for SpecBuilder {
+    fn default() -> SpecBuilder {
+        SpecBuilder::new()
+    }
+}
+
+impl SpecBuilder {
+    pub fn new() -> SpecBuilder {
+        SpecBuilder {
+            spec: Spec {
+                version: VERSION.to_string(),
+                process: Process {
+                    cwd: "/".to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn version<S: Into<String>>(mut self, version: S) -> Self {
+        self.spec.version = version.into();
+        self
+    }
+
+    pub fn root<S: Into<String>>(mut self, path: S, readonly: bool) -> Self {
+        self.spec.root = Root {
+            path: path.into(),
+            readonly,
+        };
+        self
+    }
+
+    pub fn hostname<S: Into<String>>(mut self, hostname: S) -> Self {
+        self.spec.hostname = hostname.into();
+        self
+    }
+
+    /// Replaces the process arguments.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.spec.process.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Appends `KEY=VALUE` entries to the process environment.
+    pub fn env<I, S>(mut self, env: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.spec.process.env.extend(env.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn cwd<S: Into<String>>(mut self, cwd: S) -> Self {
+        self.spec.process.cwd = cwd.into();
+        self
+    }
+
+    pub fn user(mut self, uid: u32, gid: u32) -> Self {
+        self.spec.process.user.uid = uid;
+        self.spec.process.user.gid = gid;
+        self
+    }
+
+    pub fn terminal(mut self, terminal: bool) -> Self {
+        self.spec.process.terminal = terminal;
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: LinuxCapabilities) -> Self {
+        self.spec.process.capabilities = Some(capabilities);
+        self
+    }
+
+    pub fn no_new_privileges(mut self, no_new_privileges: bool) -> Self {
+        self.spec.process.no_new_privileges = no_new_privileges;
+        self
+    }
+
+    /// Appends a mount.
+    pub fn mount(mut self, mount: Mount) -> Self {
+        self.spec.mounts.push(mount);
+        self
+    }
+
+    /// Appends mounts.
+    pub fn mounts<I: IntoIterator<Item = Mount>>(mut self, mounts: I) -> Self {
+        self.spec.mounts.extend(mounts);
+        self
+    }
+
+    pub fn annotation<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.spec.annotations.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.spec.hooks = Some(hooks);
+        self
+    }
+
+    /// Adds new namespaces of the given types.
+    pub fn namespaces<I: IntoIterator<Item = LinuxNamespaceType>>(mut self, types: I) -> Self {
+        self.linux().namespaces.extend(types.into_iter().map(|typ| LinuxNamespace {
+            typ,
+            path: String::new(),
+        }));
+        self
+    }
+
+    /// Joins an existing namespace at `path`.
+    pub fn namespace_path<S: Into<String>>(mut self, typ: LinuxNamespaceType, path: S) -> Self {
+        self.linux().namespaces.push(LinuxNamespace {
+            typ,
+            path: path.into(),
+        });
+        self
+    }
+
+    pub fn resources(mut self, resources: LinuxResources) -> Self {
+        self.linux().resources = Some(resources);
+        self
+    }
+
+    pub fn device(mut self, device: LinuxDevice) -> Self {
+        self.linux().devices.push(device);
+        self
+    }
+
+    pub fn uid_mapping(mut self, container_id: u32, host_id: u32, size: u32) -> Self {
+        self.linux().uid_mappings.push(LinuxIDMapping {
+            host_id,
+            container_id,
+            size,
+        });
+        self
+    }
+
+    pub fn gid_mapping(mut self, container_id: u32, host_id: u32, size: u32) -> Self {
+        self.linux().gid_mappings.push(LinuxIDMapping {
+            host_id,
+            container_id,
+            size,
+        });
+        self
+    }
+
+    pub fn cgroups_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.linux().cgroups_path = path.into();
+        self
+    }
+
+    pub fn seccomp(mut self, seccomp: LinuxSeccomp) -> Self {
+        self.linux().seccomp = Some(seccomp);
+        self
+    }
+
+    pub fn build(self) -> Spec {
+        self.spec
+    }
+
+    fn linux(&mut self) -> &mut Linux {
+        self.spec.linux.get_or_insert_with(Linux::default)
+    }
+}
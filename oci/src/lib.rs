@@ -1,13 +1,20 @@
 #![allow(non_camel_case_types)]
 extern crate serde;
 extern crate serde_json;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
 //extern crate nix;
 
+mod builder;
 pub mod serialize;
+mod validate;
+mod version;
 
-use std::collections::HashMap;
+pub use builder::SpecBuilder;
+pub use validate::ValidationError;
+pub use version::{Version, VERSION};
+
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 
 //use nix::unistd::{Gid,Pid,Uid};
@@ -16,7 +23,16 @@ fn is_false(b: &bool) -> bool {
     !b
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// serialize maps with sorted keys so that saved configs are stable
+fn sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Platform {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub os: String,
@@ -37,7 +53,7 @@ fn is_default<T: Default + PartialEq>(b: &T) -> bool {
 }
 
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     #[serde(default)]
     pub uid: u32,
@@ -51,7 +67,7 @@ pub struct User {
 }
 
 // this converts directly to the correct int
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum LinuxRlimitType {
     RLIMIT_CPU, // CPU time in sec
     RLIMIT_FSIZE, // Maximum filesize
@@ -60,6 +76,7 @@ pub enum LinuxRlimitType {
     RLIMIT_CORE, // max core file size
     RLIMIT_RSS, // max resident set size
     RLIMIT_NPROC, // max number of processes
+    #[default]
     RLIMIT_NOFILE, // max number of open files
     RLIMIT_MEMLOCK, // max locked-in-memory address space
     RLIMIT_AS, // address space limit
@@ -71,7 +88,7 @@ pub enum LinuxRlimitType {
     RLIMIT_RTTIME, // timeout for RT tasks in us
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxRlimit {
     #[serde(rename = "type")]
     pub typ: LinuxRlimitType,
@@ -81,9 +98,38 @@ pub struct LinuxRlimit {
     pub soft: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-#[repr(u8)]
-pub enum LinuxCapabilityType {
+macro_rules! capability_types {
+    ($($name:ident),* $(,)?) => {
+        /// A capability name. Names unknown to this crate are kept as
+        /// `Unknown` so that newer specs still parse.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+        #[serde(from = "String", into = "String")]
+        pub enum LinuxCapabilityType {
+            $($name,)*
+            Unknown(String),
+        }
+
+        impl LinuxCapabilityType {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(LinuxCapabilityType::$name => stringify!($name),)*
+                    LinuxCapabilityType::Unknown(name) => name,
+                }
+            }
+        }
+
+        impl From<String> for LinuxCapabilityType {
+            fn from(name: String) -> Self {
+                match name.as_str() {
+                    $(stringify!($name) => LinuxCapabilityType::$name,)*
+                    _ => LinuxCapabilityType::Unknown(name),
+                }
+            }
+        }
+    };
+}
+
+capability_types! {
     CAP_CHOWN,
     CAP_DAC_OVERRIDE,
     CAP_DAC_READ_SEARCH,
@@ -122,9 +168,18 @@ pub enum LinuxCapabilityType {
     CAP_WAKE_ALARM,
     CAP_BLOCK_SUSPEND,
     CAP_AUDIT_READ,
+    CAP_PERFMON,
+    CAP_BPF,
+    CAP_CHECKPOINT_RESTORE,
+}
+
+impl From<LinuxCapabilityType> for String {
+    fn from(cap: LinuxCapabilityType) -> Self {
+        cap.as_str().to_string()
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxCapabilities {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub bounding: Vec<LinuxCapabilityType>,
@@ -138,14 +193,69 @@ pub struct LinuxCapabilities {
     pub ambient: Vec<LinuxCapabilityType>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxSchedulerPolicy {
+    #[default]
+    SCHED_OTHER,
+    SCHED_FIFO,
+    SCHED_RR,
+    SCHED_BATCH,
+    SCHED_ISO,
+    SCHED_IDLE,
+    SCHED_DEADLINE,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct Scheduler {
+    pub policy: LinuxSchedulerPolicy,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub nice: i32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub runtime: u64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub deadline: u64,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub period: u64,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxIOPriorityClass {
+    IOPRIO_CLASS_RT,
+    #[default]
+    IOPRIO_CLASS_BE,
+    IOPRIO_CLASS_IDLE,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct LinuxIOPriority {
+    pub class: LinuxIOPriorityClass,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+// cpu lists in cpuset format (e.g. "0-3,7") applied around exec
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct CPUAffinity {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub initial: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub r#final: String,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Process {
     #[serde(default, skip_serializing_if = "is_false")]
     pub terminal: bool,
     #[serde(default, skip_serializing_if = "is_default",
             rename = "consoleSize")]
     pub console_size: Box,
+    #[serde(default)]
     pub user: User,
+    #[serde(default)]
     pub args: Vec<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub env: Vec<String>,
@@ -165,6 +275,15 @@ pub struct Process {
     #[serde(default, skip_serializing_if = "String::is_empty",
             rename = "selinuxLabel")]
     pub selinux_label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduler: Option<Scheduler>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "ioPriority")]
+    pub io_priority: Option<LinuxIOPriority>,
+    #[serde(skip_serializing_if = "Option::is_none",
+            rename = "execCPUAffinity")]
+    pub exec_cpu_affinity: Option<CPUAffinity>,
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
 }
 
 fn cap_from_array<'de, D>(
@@ -243,7 +362,7 @@ where
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Root {
     #[serde(default)]
     pub path: String,
@@ -251,7 +370,7 @@ pub struct Root {
     pub readonly: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Mount {
     #[serde(default)]
     pub destination: String,
@@ -261,9 +380,15 @@ pub struct Mount {
     pub source: String,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub options: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty",
+            rename = "uidMappings")]
+    pub uid_mappings: Vec<LinuxIDMapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty",
+            rename = "gidMappings")]
+    pub gid_mappings: Vec<LinuxIDMapping>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Hook {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub path: String,
@@ -275,7 +400,7 @@ pub struct Hook {
     pub timeout: Option<i64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Hooks {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub prestart: Vec<Hook>,
@@ -285,7 +410,7 @@ pub struct Hooks {
     pub poststop: Vec<Hook>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxIDMapping {
     #[serde(default, rename = "hostID")]
     pub host_id: u32,
@@ -311,7 +436,7 @@ impl Default for LinuxDeviceType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxDeviceCgroup {
     #[serde(default, skip_serializing_if = "is_false")]
     pub allow: bool,
@@ -325,7 +450,7 @@ pub struct LinuxDeviceCgroup {
     pub access: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxMemory {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<i64>,
@@ -341,7 +466,7 @@ pub struct LinuxMemory {
     pub swappiness: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxCPU {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub shares: Option<u64>,
@@ -349,6 +474,10 @@ pub struct LinuxCPU {
     pub quota: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub period: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none",
             rename = "realtimeRuntime")]
     pub realtime_runtime: Option<i64>,
@@ -360,13 +489,13 @@ pub struct LinuxCPU {
     pub mems: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxPids {
     #[serde(default)]
     pub limit: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxWeightDevice {
     #[serde(default)]
     pub major: i64,
@@ -378,7 +507,7 @@ pub struct LinuxWeightDevice {
     pub leaf_weight: Option<u16>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxThrottleDevice {
     #[serde(default)]
     pub major: i64,
@@ -388,7 +517,7 @@ pub struct LinuxThrottleDevice {
     pub rate: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxBlockIO {
     #[serde(skip_serializing_if = "Option::is_none", rename = "blkioWeight")]
     pub weight: Option<u16>,
@@ -412,7 +541,7 @@ pub struct LinuxBlockIO {
     pub throttle_write_iops_device: Vec<LinuxThrottleDevice>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxHugepageLimit {
     #[serde(default, skip_serializing_if = "String::is_empty",
             rename = "pageSize")]
@@ -422,7 +551,7 @@ pub struct LinuxHugepageLimit {
 }
 
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxInterfacePriority {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub name: String,
@@ -430,7 +559,7 @@ pub struct LinuxInterfacePriority {
     pub priority: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxNetwork {
     #[serde(skip_serializing_if = "Option::is_none", rename = "classID")]
     pub class_id: Option<u32>,
@@ -464,10 +593,21 @@ pub struct LinuxResources {
     pub hugepage_limits: Vec<LinuxHugepageLimit>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub network: Option<LinuxNetwork>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty",
+            serialize_with = "sorted")]
+    pub unified: HashMap<String, String>,
+    // NOTE: not part of the OCI spec; limits for the cgroup v2 misc
+    //       controller keyed by resource name (e.g. sgx_epc), -1 == max
+    #[serde(default, skip_serializing_if = "HashMap::is_empty",
+            serialize_with = "sorted")]
+    pub misc: HashMap<String, i64>,
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LinuxNamespaceType {
+    #[default]
     mount = 0x00020000, /* New mount namespace group */
     cgroup = 0x02000000, /* New cgroup namespace */
     uts = 0x04000000, /* New utsname namespace */
@@ -475,9 +615,10 @@ pub enum LinuxNamespaceType {
     user = 0x10000000, /* New user namespace */
     pid = 0x20000000, /* New pid namespace */
     network = 0x40000000, /* New network namespace */
+    time = 0x00000080, /* New time namespace */
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxNamespace {
     #[serde(rename = "type")]
     pub typ: LinuxNamespaceType,
@@ -485,7 +626,7 @@ pub struct LinuxNamespace {
     pub path: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxDevice {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub path: String,
@@ -503,14 +644,20 @@ pub struct LinuxDevice {
     pub gid: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-#[repr(u32)]
+// KILL and KILL_THREAD share a value, so the runtime maps these to
+// libseccomp actions instead of relying on discriminants
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LinuxSeccompAction {
-    SCMP_ACT_KILL = 0x00000000,
-    SCMP_ACT_TRAP = 0x00030000,
-    SCMP_ACT_ERRNO = 0x00050001, /* ERRNO + EPERM */
-    SCMP_ACT_TRACE = 0x7ff00001, /* TRACE + EPERM */
-    SCMP_ACT_ALLOW = 0x7fff0000,
+    SCMP_ACT_KILL,
+    SCMP_ACT_KILL_PROCESS,
+    SCMP_ACT_KILL_THREAD,
+    SCMP_ACT_TRAP,
+    SCMP_ACT_ERRNO,
+    SCMP_ACT_TRACE,
+    #[default]
+    SCMP_ACT_ALLOW,
+    SCMP_ACT_LOG,
+    SCMP_ACT_NOTIFY,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -534,19 +681,20 @@ pub enum Arch {
     SCMP_ARCH_S390X = 0x80000016,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy)]
 #[repr(u32)]
 pub enum LinuxSeccompOperator {
     SCMP_CMP_NE = 1, /* not equal */
     SCMP_CMP_LT = 2, /* less than */
     SCMP_CMP_LE = 3, /* less than or equal */
+    #[default]
     SCMP_CMP_EQ = 4, /* equal */
     SCMP_CMP_GE = 5, /* greater than or equal */
     SCMP_CMP_GT = 6, /* greater than */
     SCMP_CMP_MASKED_EQ = 7, /* masked equality */
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxSeccompArg {
     #[serde(default)]
     pub index: usize,
@@ -557,7 +705,7 @@ pub struct LinuxSeccompArg {
     pub op: LinuxSeccompOperator,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxSyscall {
     // old version used name
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -565,21 +713,75 @@ pub struct LinuxSyscall {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub names: Vec<String>,
     pub action: LinuxSeccompAction,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "errnoRet")]
+    pub errno_ret: Option<u32>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub args: Vec<LinuxSeccompArg>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct LinuxSeccomp {
     #[serde(rename = "defaultAction")]
     pub default_action: LinuxSeccompAction,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "defaultErrnoRet")]
+    pub default_errno_ret: Option<u32>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub architectures: Vec<Arch>,
+    #[serde(default, skip_serializing_if = "String::is_empty",
+            rename = "listenerPath")]
+    pub listener_path: String,
+    #[serde(default, skip_serializing_if = "String::is_empty",
+            rename = "listenerMetadata")]
+    pub listener_metadata: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub syscalls: Vec<LinuxSyscall>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// keyed by clock name, "boottime" or "monotonic"
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct LinuxTimeOffset {
+    #[serde(default)]
+    pub secs: i64,
+    #[serde(default)]
+    pub nanosecs: u32,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxPersonalityDomain {
+    #[default]
+    LINUX,
+    LINUX32,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct LinuxPersonality {
+    pub domain: LinuxPersonalityDomain,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct LinuxIntelRdt {
+    #[serde(default, skip_serializing_if = "String::is_empty",
+            rename = "closID")]
+    pub clos_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty",
+            rename = "l3CacheSchema")]
+    pub l3_cache_schema: String,
+    #[serde(default, skip_serializing_if = "String::is_empty",
+            rename = "memBwSchema")]
+    pub mem_bw_schema: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schemata: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false", rename = "enableCMT")]
+    pub enable_cmt: bool,
+    #[serde(default, skip_serializing_if = "is_false", rename = "enableMBM")]
+    pub enable_mbm: bool,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Linux {
     #[serde(default, skip_serializing_if = "Vec::is_empty",
             rename = "uidMappings")]
@@ -587,7 +789,8 @@ pub struct Linux {
     #[serde(default, skip_serializing_if = "Vec::is_empty",
             rename = "gidMappings")]
     pub gid_mappings: Vec<LinuxIDMapping>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty",
+            serialize_with = "sorted")]
     pub sysctl: HashMap<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub resources: Option<LinuxResources>,
@@ -612,6 +815,15 @@ pub struct Linux {
     #[serde(default, skip_serializing_if = "String::is_empty",
             rename = "mountLabel")]
     pub mount_label: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty",
+            serialize_with = "sorted", rename = "timeOffsets")]
+    pub time_offsets: HashMap<String, LinuxTimeOffset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub personality: Option<LinuxPersonality>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "intelRdt")]
+    pub intel_rdt: Option<LinuxIntelRdt>,
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
 }
 
 // NOTE: Solaris and Windows are ignored for the moment
@@ -619,7 +831,7 @@ pub type Solaris = Value;
 pub type Windows = Value;
 
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Spec {
     #[serde(default, skip_serializing_if = "String::is_empty",
             rename = "ociVersion")]
@@ -628,7 +840,11 @@ pub struct Spec {
     //       to support older docker versions
     #[serde(skip_serializing_if = "Option::is_none")]
     pub platform: Option<Platform>,
+    // NOTE: both are optional in the spec; missing values are reported by
+    //       validate() instead of failing to parse
+    #[serde(default)]
     pub process: Process,
+    #[serde(default)]
     pub root: Root,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub hostname: String,
@@ -636,7 +852,8 @@ pub struct Spec {
     pub mounts: Vec<Mount>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hooks: Option<Hooks>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty",
+            serialize_with = "sorted")]
     pub annotations: HashMap<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub linux: Option<Linux>,
@@ -644,19 +861,65 @@ pub struct Spec {
     pub solaris: Option<Solaris>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub windows: Option<Windows>,
+    // NOTE: not part of the OCI spec; fields unknown to this crate are kept
+    //       here so that rewriting a config does not drop them
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
 }
 
+// platform sections other than linux; extensions not listed here are
+// kept without comment
+const OTHER_PLATFORMS: [&str; 2] = ["vm", "zos"];
+
 impl Spec {
+    /// Returns a builder for constructing a spec programmatically.
+    pub fn builder() -> SpecBuilder {
+        SpecBuilder::new()
+    }
+
+    /// Names of the non-linux platform sections present in the spec.
+    pub fn other_platforms(&self) -> Vec<&str> {
+        let mut platforms = Vec::new();
+        if self.solaris.is_some() {
+            platforms.push("solaris");
+        }
+        if self.windows.is_some() {
+            platforms.push("windows");
+        }
+        platforms.extend(
+            OTHER_PLATFORMS
+                .iter()
+                .filter(|p| self.extensions.contains_key(**p)),
+        );
+        platforms
+    }
+
     pub fn load(path: &str) -> Result<Spec, serialize::SerializeError> {
         serialize::deserialize(path)
     }
 
+    /// Writes the spec as pretty-printed JSON, replacing `path` atomically.
+    ///
+    /// Fields keep their declaration order, map keys are sorted and unset
+    /// fields are omitted, so a load/save round trip is stable and keeps
+    /// fields this crate does not know about.
+    ///
+    /// ```
+    /// let json = r#"{"ociVersion": "1.0.2", "root": {"path": "rootfs"},
+    ///     "process": {"user": {"uid": 0, "gid": 0}, "args": ["sh"]},
+    ///     "vm": {"hypervisor": {"path": "/usr/bin/qemu"}}}"#;
+    /// let spec: oci::Spec = serde_json::from_str(json).unwrap();
+    /// let path = std::env::temp_dir().join("oci-save-example.json");
+    /// spec.save(path.to_str().unwrap()).unwrap();
+    /// let saved = oci::Spec::load(path.to_str().unwrap()).unwrap();
+    /// assert_eq!(saved.extensions["vm"], spec.extensions["vm"]);
+    /// ```
     pub fn save(&self, path: &str) -> Result<(), serialize::SerializeError> {
         serialize::serialize(self, path)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct State {
     #[serde(default, skip_serializing_if = "String::is_empty",
             rename = "ociVersion")]
@@ -669,7 +932,8 @@ pub struct State {
     pub pid: i32,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub bundle: String,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty",
+            serialize_with = "sorted")]
     pub annotations: HashMap<String, String>,
 }
 
@@ -5,7 +5,8 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::io;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::Write;
 
 #[derive(Debug)]
 pub enum SerializeError {
@@ -55,12 +56,18 @@ pub fn to_writer<W: io::Write, T: Serialize>(
 //     Ok(serde_json::from_reader(reader)?)
 // }
 
+// writes to a temporary file first so a failed write never leaves a
+// truncated config behind
 pub fn serialize<T: Serialize>(
     obj: &T,
     path: &str,
 ) -> Result<(), SerializeError> {
-    let mut file = File::create(path)?;
-    Ok(serde_json::to_writer(&mut file, &obj)?)
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    serde_json::to_writer_pretty(&mut file, &obj)?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    Ok(fs::rename(&tmp, path)?)
 }
 
 pub fn deserialize<T: for<'de> Deserialize<'de>>(
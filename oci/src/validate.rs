@@ -0,0 +1,300 @@
+use super::*;
+use std::fmt;
+
+/// A single spec violation, located by its JSON path in config.json.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Errors(Vec<ValidationError>);
+
+impl Errors {
+    fn push<P: Into<String>, M: Into<String>>(&mut self, path: P, message: M) {
+        self.0.push(ValidationError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+}
+
+// mount options that only make sense one way
+const CONFLICTING_OPTIONS: [(&str, &str); 5] = [
+    ("ro", "rw"),
+    ("suid", "nosuid"),
+    ("dev", "nodev"),
+    ("exec", "noexec"),
+    ("atime", "noatime"),
+];
+
+impl Spec {
+    /// Checks the spec for violations that would otherwise only surface
+    /// as failed syscalls while the container is being set up.
+    ///
+    /// All violations are returned, not just the first one.
+    ///
+    /// ```
+    /// let spec = oci::Spec::builder()
+    ///     .root("rootfs", false)
+    ///     .uid_mapping(0, 1000, 0)
+    ///     .build();
+    /// let errors = spec.validate().unwrap_err();
+    /// let paths: Vec<_> = errors.iter().map(|e| e.path.as_str()).collect();
+    /// assert!(paths.contains(&"process.args"));
+    /// assert!(paths.contains(&"linux.uidMappings[0].size"));
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Errors::default();
+        if let Some(declared) = validate_version(&self.version, &mut errors) {
+            validate_features(self, declared, &mut errors);
+        }
+        validate_process(&self.process, &mut errors);
+        if self.root.path.is_empty() {
+            errors.push("root.path", "must not be empty");
+        }
+        for (i, mount) in self.mounts.iter().enumerate() {
+            validate_mount(mount, &format!("mounts[{}]", i), &mut errors);
+        }
+        if let Some(ref linux) = self.linux {
+            validate_linux(linux, &self.hostname, &mut errors);
+        }
+
+        if errors.0.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.0)
+        }
+    }
+}
+
+fn validate_version(version: &str, errors: &mut Errors) -> Option<Version> {
+    if version.is_empty() {
+        return None;
+    }
+    match Version::parse(version) {
+        Some(v) if v.major == Version::supported().major => Some(v),
+        Some(v) => {
+            errors.push("ociVersion", format!("unsupported major version {}", v.major));
+            None
+        }
+        None => {
+            errors.push("ociVersion", format!("invalid version {:?}", version));
+            None
+        }
+    }
+}
+
+// fields introduced after 1.0 are rejected when the spec declares an
+// older version, since the config author may not expect them to apply
+fn validate_features(spec: &Spec, declared: Version, errors: &mut Errors) {
+    let mut require = |used: bool, path: String, since: Version| {
+        if used && declared < since {
+            errors.push(path, format!("requires ociVersion {} or later, spec declares {}", since, declared));
+        }
+    };
+    let v1_1 = Version::new(1, 1, 0);
+    let v1_2 = Version::new(1, 2, 0);
+
+    let process = &spec.process;
+    require(process.scheduler.is_some(), "process.scheduler".to_string(), v1_2);
+    require(process.io_priority.is_some(), "process.ioPriority".to_string(), v1_2);
+    require(process.exec_cpu_affinity.is_some(), "process.execCPUAffinity".to_string(), v1_2);
+
+    for (i, mount) in spec.mounts.iter().enumerate() {
+        let idmapped = !mount.uid_mappings.is_empty() || !mount.gid_mappings.is_empty();
+        require(idmapped, format!("mounts[{}].uidMappings", i), v1_1);
+    }
+
+    if let Some(ref linux) = spec.linux {
+        require(!linux.time_offsets.is_empty(), "linux.timeOffsets".to_string(), v1_1);
+        require(linux.personality.is_some(), "linux.personality".to_string(), v1_1);
+        for (i, ns) in linux.namespaces.iter().enumerate() {
+            require(ns.typ == LinuxNamespaceType::time, format!("linux.namespaces[{}]", i), v1_1);
+        }
+    }
+}
+
+fn validate_process(process: &Process, errors: &mut Errors) {
+    if process.args.is_empty() {
+        errors.push("process.args", "must not be empty");
+    }
+    if !process.cwd.is_empty() && !process.cwd.starts_with('/') {
+        errors.push("process.cwd", format!("{:?} is not an absolute path", process.cwd));
+    }
+    for (i, env) in process.env.iter().enumerate() {
+        if !env.contains('=') || env.starts_with('=') {
+            errors.push(format!("process.env[{}]", i), format!("{:?} is not KEY=VALUE", env));
+        }
+    }
+    for (i, rlimit) in process.rlimits.iter().enumerate() {
+        if rlimit.soft > rlimit.hard {
+            errors.push(format!("process.rlimits[{}]", i), "soft limit exceeds hard limit");
+        }
+    }
+    if let Some(ref scheduler) = process.scheduler {
+        if !(-20..=19).contains(&scheduler.nice) {
+            errors.push("process.scheduler.nice", "must be between -20 and 19");
+        }
+        if scheduler.policy == LinuxSchedulerPolicy::SCHED_DEADLINE
+            && !(scheduler.runtime <= scheduler.deadline && scheduler.deadline <= scheduler.period)
+        {
+            errors.push("process.scheduler", "SCHED_DEADLINE requires runtime <= deadline <= period");
+        }
+    }
+    if let Some(ref io_priority) = process.io_priority {
+        if !(0..=7).contains(&io_priority.priority) {
+            errors.push("process.ioPriority.priority", "must be between 0 and 7");
+        }
+    }
+}
+
+fn validate_mount(mount: &Mount, path: &str, errors: &mut Errors) {
+    if !mount.destination.starts_with('/') {
+        errors.push(
+            format!("{}.destination", path),
+            format!("{:?} is not an absolute path", mount.destination),
+        );
+    }
+
+    let has = |option: &str| mount.options.iter().any(|o| o == option);
+    let bind = mount.typ == "bind" || has("bind") || has("rbind");
+    if bind && mount.source.is_empty() {
+        errors.push(format!("{}.source", path), "bind mounts require a source");
+    }
+    for (a, b) in CONFLICTING_OPTIONS {
+        if has(a) && has(b) {
+            errors.push(
+                format!("{}.options", path),
+                format!("{:?} and {:?} are mutually exclusive", a, b),
+            );
+        }
+    }
+    validate_mappings(&mount.uid_mappings, &format!("{}.uidMappings", path), errors);
+    validate_mappings(&mount.gid_mappings, &format!("{}.gidMappings", path), errors);
+}
+
+fn validate_linux(linux: &Linux, hostname: &str, errors: &mut Errors) {
+    let mut seen: Vec<LinuxNamespaceType> = Vec::new();
+    for (i, ns) in linux.namespaces.iter().enumerate() {
+        if seen.contains(&ns.typ) {
+            errors.push(format!("linux.namespaces[{}]", i), format!("duplicate {:?} namespace", ns.typ));
+        }
+        seen.push(ns.typ);
+        if !ns.path.is_empty() && !ns.path.starts_with('/') {
+            errors.push(format!("linux.namespaces[{}].path", i), format!("{:?} is not an absolute path", ns.path));
+        }
+    }
+    let has_namespace = |typ| linux.namespaces.iter().any(|ns| ns.typ == typ);
+
+    if !hostname.is_empty() && !has_namespace(LinuxNamespaceType::uts) {
+        errors.push("hostname", "requires a uts namespace");
+    }
+
+    let mut keys: Vec<&String> = linux.sysctl.keys().collect();
+    keys.sort();
+    for key in keys {
+        let path = format!("linux.sysctl.{}", key);
+        match sysctl_namespace(key) {
+            Some(typ) if !has_namespace(typ) => errors.push(path, format!("requires a {:?} namespace", typ)),
+            Some(_) => {}
+            None => errors.push(path, "is not namespaced, setting it would change the host"),
+        }
+    }
+
+    validate_mappings(&linux.uid_mappings, "linux.uidMappings", errors);
+    validate_mappings(&linux.gid_mappings, "linux.gidMappings", errors);
+    let has_mappings = !linux.uid_mappings.is_empty() || !linux.gid_mappings.is_empty();
+    if linux.uid_mappings.is_empty() != linux.gid_mappings.is_empty() {
+        errors.push("linux.uidMappings", "uidMappings and gidMappings must be set together");
+    }
+    if has_mappings && !has_namespace(LinuxNamespaceType::user) {
+        errors.push("linux.uidMappings", "id mappings require a user namespace");
+    }
+
+    for clock in linux.time_offsets.keys() {
+        if clock != "boottime" && clock != "monotonic" {
+            errors.push(format!("linux.timeOffsets.{}", clock), "only boottime and monotonic can be offset");
+        }
+    }
+    if !linux.time_offsets.is_empty() && !linux.namespaces.iter().any(|ns| ns.typ == LinuxNamespaceType::time) {
+        errors.push("linux.timeOffsets", "requires a time namespace");
+    }
+
+    for (i, device) in linux.devices.iter().enumerate() {
+        let path = format!("linux.devices[{}]", i);
+        if !device.path.starts_with('/') {
+            errors.push(format!("{}.path", path), format!("{:?} is not an absolute path", device.path));
+        }
+        if let LinuxDeviceType::a = device.typ {
+            errors.push(format!("{}.type", path), "must be one of b, c, u or p");
+        }
+    }
+
+    if let Some(ref resources) = linux.resources {
+        for (i, device) in resources.devices.iter().enumerate() {
+            if device.access.chars().any(|c| !matches!(c, 'r' | 'w' | 'm')) {
+                errors.push(
+                    format!("linux.resources.devices[{}].access", i),
+                    format!("{:?} may only contain r, w and m", device.access),
+                );
+            }
+        }
+    }
+}
+
+/// The namespace that isolates a sysctl, or None when it is global to the host.
+fn sysctl_namespace(key: &str) -> Option<LinuxNamespaceType> {
+    const IPC: [&str; 8] = [
+        "kernel.msgmax",
+        "kernel.msgmnb",
+        "kernel.msgmni",
+        "kernel.sem",
+        "kernel.shmall",
+        "kernel.shmmax",
+        "kernel.shmmni",
+        "kernel.shm_rmid_forced",
+    ];
+    if key.starts_with("net.") {
+        Some(LinuxNamespaceType::network)
+    } else if IPC.contains(&key) || key.starts_with("fs.mqueue.") {
+        Some(LinuxNamespaceType::ipc)
+    } else if key == "kernel.hostname" || key == "kernel.domainname" {
+        Some(LinuxNamespaceType::uts)
+    } else {
+        None
+    }
+}
+
+fn validate_mappings(mappings: &[LinuxIDMapping], path: &str, errors: &mut Errors) {
+    for (i, mapping) in mappings.iter().enumerate() {
+        if mapping.size == 0 {
+            errors.push(format!("{}[{}].size", path, i), "must be greater than zero");
+            continue;
+        }
+        for (field, start) in [("containerID", mapping.container_id), ("hostID", mapping.host_id)] {
+            if start.checked_add(mapping.size - 1).is_none() {
+                errors.push(format!("{}[{}].{}", path, i, field), "range overflows 32-bit ids");
+            }
+        }
+
+        let overlaps = |a: u32, b: u32, size: u32| {
+            (a as u64) < b as u64 + size as u64 && (b as u64) < a as u64 + mapping.size as u64
+        };
+        for (j, other) in mappings.iter().enumerate().take(i) {
+            if overlaps(mapping.container_id, other.container_id, other.size) {
+                errors.push(format!("{}[{}]", path, i), format!("container ids overlap with entry {}", j));
+            }
+            if overlaps(mapping.host_id, other.host_id, other.size) {
+                errors.push(format!("{}[{}]", path, i), format!("host ids overlap with entry {}", j));
+            }
+        }
+    }
+}
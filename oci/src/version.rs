@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// The newest runtime-spec version this crate models.
+pub const VERSION: &str = "1.2.0";
+
+/// A runtime-spec version as declared in `ociVersion`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses `MAJOR.MINOR[.PATCH]`, ignoring pre-release and build
+    /// suffixes such as `-rc.1` or `+dev`.
+    pub fn parse(version: &str) -> Option<Version> {
+        let core = version.split(['-', '+']).next().unwrap_or_default();
+        let parts: Vec<u32> = core
+            .split('.')
+            .map(|p| p.parse().ok())
+            .collect::<Option<_>>()?;
+        match parts.as_slice() {
+            [major, minor] => Some(Version::new(*major, *minor, 0)),
+            [major, minor, patch] => Some(Version::new(*major, *minor, *patch)),
+            _ => None,
+        }
+    }
+
+    /// The newest version this crate models.
+    pub fn supported() -> Version {
+        Version::parse(VERSION).unwrap()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
@@ -0,0 +1,269 @@
+//! Prometheus 指标
+//!
+//! fire 本身没有常驻进程，嵌入 fire 的 daemon 或 shim 调用 serve 后，`GET /metrics`
+//! 返回状态目录下各状态的容器数、运行中容器的 cpu、内存和进程数，以及当前进程通过
+//! ContainerHandle 启动容器的次数、失败次数和耗时分布。
+//!
+//! ```no_run
+//! let addr = fire::api::metrics::serve("127.0.0.1:9100")?;
+//! println!("metrics: http://{}/metrics", addr);
+//! # Ok::<(), fire::errors::FireError>(())
+//! ```
+
+use super::ContainerHandle;
+use crate::cgroups::stats::CgroupStats;
+use crate::errors::Result;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 启动耗时直方图的桶上界，单位为秒
+const START_DURATION_BUCKETS: [f64; 9] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+#[derive(Default)]
+struct StartCounters {
+    total: u64,
+    failures: u64,
+    /// 每个桶的计数，不累加
+    buckets: [u64; START_DURATION_BUCKETS.len()],
+    sum: f64,
+}
+
+lazy_static! {
+    static ref STARTS: Mutex<StartCounters> = Mutex::new(StartCounters::default());
+}
+
+/// 记录一次 ContainerHandle::start 的结果和耗时
+pub(super) fn record_start(elapsed: Duration, ok: bool) {
+    let secs = elapsed.as_secs_f64();
+    let mut starts = STARTS.lock().unwrap();
+    starts.total += 1;
+    if !ok {
+        starts.failures += 1;
+    }
+    if let Some(i) = START_DURATION_BUCKETS.iter().position(|&le| secs <= le) {
+        starts.buckets[i] += 1;
+    }
+    starts.sum += secs;
+}
+
+/// 在后台线程中监听 addr 并提供 /metrics，返回实际监听的地址
+pub fn serve<A: ToSocketAddrs>(addr: A) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    std::thread::Builder::new()
+        .name("fire-metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("接受 metrics 连接失败: {}", e);
+                        continue;
+                    }
+                };
+                // 每个连接单独处理，不发送请求的连接不会阻塞其他抓取
+                let spawned = std::thread::Builder::new()
+                    .name("fire-metrics-conn".to_string())
+                    .spawn(move || {
+                        if let Err(e) = handle(stream) {
+                            debug!("处理 metrics 请求失败: {}", e);
+                        }
+                    });
+                if let Err(e) = spawned {
+                    warn!("创建 metrics 连接处理线程失败: {}", e);
+                }
+            }
+        })?;
+    info!("metrics 监听于 {}", local_addr);
+    Ok(local_addr)
+}
+
+/// 处理一个请求，响应后关闭连接
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // 读完请求头，请求体忽略
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// 生成 Prometheus 文本格式的指标
+pub fn render() -> String {
+    let mut out = String::new();
+    render_containers(&mut out, &containers());
+    render_starts(&mut out, &STARTS.lock().unwrap());
+    out
+}
+
+/// 状态目录下所有容器的状态，以及运行中容器的资源统计
+fn containers() -> Vec<(String, String, Option<CgroupStats>)> {
+    let Ok(entries) = fs::read_dir(crate::container::state::root_dir()) else {
+        return Vec::new();
+    };
+
+    let mut containers = Vec::new();
+    for entry in entries.flatten() {
+        let id = entry.file_name().to_string_lossy().to_string();
+        let Ok(handle) = ContainerHandle::open(id.as_str()) else {
+            continue;
+        };
+        let Ok(state) = handle.state() else {
+            continue;
+        };
+        let stats = if state.status == "running" || state.status == "paused" {
+            handle
+                .stats()
+                .map_err(|e| debug!("读取容器 {} 的资源统计失败: {}", id, e))
+                .ok()
+        } else {
+            None
+        };
+        containers.push((id, state.status, stats));
+    }
+    containers.sort_by(|a, b| a.0.cmp(&b.0));
+    containers
+}
+
+fn render_containers(out: &mut String, containers: &[(String, String, Option<CgroupStats>)]) {
+    let mut by_status = BTreeMap::new();
+    for (_, status, _) in containers {
+        *by_status.entry(status.as_str()).or_insert(0u64) += 1;
+    }
+    header(out, "fire_containers", "gauge", "Number of containers by status.");
+    for (status, count) in by_status {
+        let _ = writeln!(out, "fire_containers{{status=\"{}\"}} {}", escape(status), count);
+    }
+
+    let stats: Vec<(&str, &CgroupStats)> = containers
+        .iter()
+        .filter_map(|(id, _, stats)| stats.as_ref().map(|s| (id.as_str(), s)))
+        .collect();
+
+    header(
+        out,
+        "fire_container_cpu_usage_seconds_total",
+        "counter",
+        "Cumulative CPU time consumed by the container.",
+    );
+    for (id, s) in &stats {
+        let _ = writeln!(
+            out,
+            "fire_container_cpu_usage_seconds_total{{id=\"{}\"}} {}",
+            escape(id),
+            s.cpu.usage_usec as f64 / 1e6
+        );
+    }
+
+    header(out, "fire_container_memory_usage_bytes", "gauge", "Current memory usage of the container.");
+    for (id, s) in &stats {
+        let _ = writeln!(out, "fire_container_memory_usage_bytes{{id=\"{}\"}} {}", escape(id), s.memory.current);
+    }
+
+    header(out, "fire_container_memory_limit_bytes", "gauge", "Memory limit of the container.");
+    for (id, s) in &stats {
+        if let Some(limit) = s.memory.limit {
+            let _ = writeln!(out, "fire_container_memory_limit_bytes{{id=\"{}\"}} {}", escape(id), limit);
+        }
+    }
+
+    header(out, "fire_container_pids", "gauge", "Number of processes in the container.");
+    for (id, s) in &stats {
+        let _ = writeln!(out, "fire_container_pids{{id=\"{}\"}} {}", escape(id), s.pids.current);
+    }
+}
+
+fn render_starts(out: &mut String, starts: &StartCounters) {
+    header(out, "fire_container_starts_total", "counter", "Container starts attempted by this process.");
+    let _ = writeln!(out, "fire_container_starts_total {}", starts.total);
+
+    header(out, "fire_container_start_failures_total", "counter", "Container starts that failed.");
+    let _ = writeln!(out, "fire_container_start_failures_total {}", starts.failures);
+
+    header(
+        out,
+        "fire_container_start_duration_seconds",
+        "histogram",
+        "Time taken to start a container.",
+    );
+    let mut cumulative = 0;
+    for (le, count) in START_DURATION_BUCKETS.iter().zip(starts.buckets) {
+        cumulative += count;
+        let _ = writeln!(out, "fire_container_start_duration_seconds_bucket{{le=\"{}\"}} {}", le, cumulative);
+    }
+    let _ = writeln!(out, "fire_container_start_duration_seconds_bucket{{le=\"+Inf\"}} {}", starts.total);
+    let _ = writeln!(out, "fire_container_start_duration_seconds_sum {}", starts.sum);
+    let _ = writeln!(out, "fire_container_start_duration_seconds_count {}", starts.total);
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// 转义标签值中的反斜杠、双引号和换行
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_render_start_histogram() {
+        let starts = StartCounters {
+            total: 3,
+            failures: 1,
+            buckets: [1, 0, 0, 0, 1, 0, 0, 0, 0],
+            sum: 0.3,
+        };
+
+        let mut out = String::new();
+        render_starts(&mut out, &starts);
+        assert!(out.contains("fire_container_start_failures_total 1\n"));
+        assert!(out.contains("fire_container_start_duration_seconds_bucket{le=\"0.01\"} 1\n"));
+        assert!(out.contains("fire_container_start_duration_seconds_bucket{le=\"0.25\"} 2\n"));
+        assert!(out.contains("fire_container_start_duration_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(out.contains("fire_container_start_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        let addr = serve("127.0.0.1:0").unwrap();
+        // 不发送请求的连接不影响其他连接
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE fire_container_starts_total counter\n"));
+    }
+}
@@ -0,0 +1,240 @@
+//! 供其他程序嵌入 fire 的高层接口
+//!
+//! 容器进程由 `/proc/self/exe init` 启动，嵌入 fire 的程序需要在 main 开头调用 [`init`]。
+//!
+//! ```no_run
+//! fire::api::init();
+//!
+//! let container = fire::api::Builder::new("web").bundle("/srv/bundles/web").spawn()?;
+//! container.start()?;
+//! let exit_code = container.wait()?;
+//! container.delete(false)?;
+//! # Ok::<(), fire::errors::FireError>(())
+//! ```
+
+use crate::cgroups::{self, stats::CgroupStats};
+use crate::commands::create::CreateCommand;
+use crate::commands::delete::DeleteCommand;
+use crate::commands::kill::KillCommand;
+use crate::commands::start::StartCommand;
+use crate::commands::{self, Command};
+use crate::container::{self, process};
+use crate::errors::{FireError, Result};
+use crate::logger;
+use crate::network::etcfiles::DnsConfig;
+use crate::runtime::manager::RUNTIME_MANAGER;
+use log::warn;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+pub mod metrics;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod supervisor;
+
+pub use supervisor::{on_exited, on_oom, on_started, on_state_change, on_watch_error};
+
+/// 当前进程是 fire 启动的容器进程时进入 init 阶段执行容器命令，不再返回，否则直接返回
+///
+/// 需要在 main 开头、创建线程之前调用。
+pub fn init() {
+    if !process::is_init() {
+        return;
+    }
+    let _ = logger::init();
+    if let Err(e) = process::init() {
+        log::error!("容器进程初始化失败: {}", e);
+    }
+    std::process::exit(1);
+}
+
+/// 容器的创建参数，对应 `fire create` 的命令行选项
+pub struct Builder {
+    id: String,
+    bundle: PathBuf,
+    dns: DnsConfig,
+    rootfs_layers: Vec<PathBuf>,
+    cgroup_parent: Option<String>,
+}
+
+impl Builder {
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        Self {
+            id: id.into(),
+            bundle: PathBuf::from("."),
+            dns: DnsConfig::default(),
+            rootfs_layers: Vec::new(),
+            cgroup_parent: None,
+        }
+    }
+
+    pub fn bundle<P: Into<PathBuf>>(mut self, bundle: P) -> Self {
+        self.bundle = bundle.into();
+        self
+    }
+
+    pub fn dns(mut self, dns: DnsConfig) -> Self {
+        self.dns = dns;
+        self
+    }
+
+    pub fn rootfs_layers(mut self, layers: Vec<PathBuf>) -> Self {
+        self.rootfs_layers = layers;
+        self
+    }
+
+    pub fn cgroup_parent<S: Into<String>>(mut self, parent: S) -> Self {
+        self.cgroup_parent = Some(parent.into());
+        self
+    }
+
+    /// 创建容器，返回处于 created 状态的句柄
+    pub fn spawn(self) -> Result<ContainerHandle> {
+        let bundle = self.bundle.to_string_lossy().to_string();
+        let mut cmd = CreateCommand::new(self.id.clone(), Some(bundle));
+        cmd.set_dns(self.dns);
+        cmd.set_rootfs_layers(self.rootfs_layers);
+        cmd.set_cgroup_parent(self.cgroup_parent);
+        cmd.execute()?;
+        ContainerHandle::open(self.id)
+    }
+}
+
+/// 已创建的容器
+///
+/// 在当前进程中启动的容器，主进程是当前进程的子进程，可以通过 wait 获取退出码。
+#[derive(Debug, Clone)]
+pub struct ContainerHandle {
+    id: String,
+    state_file: PathBuf,
+}
+
+impl ContainerHandle {
+    /// 打开已存在的容器
+    pub fn open<S: Into<String>>(id: S) -> Result<Self> {
+        let id = id.into();
+        let state_file = container::state::root_dir().join(&id).join("state.json");
+        if !state_file.exists() {
+            return Err(FireError::NotFound(format!("容器 {} 不存在", id)));
+        }
+        Ok(Self { id, state_file })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// 读取容器的 OCI 状态
+    pub fn state(&self) -> Result<oci::State> {
+        Ok(serde_json::from_str(&fs::read_to_string(&self.state_file)?)?)
+    }
+
+    pub fn start(&self) -> Result<()> {
+        let begin = Instant::now();
+        let result = StartCommand::new(self.id.clone()).execute();
+        metrics::record_start(begin.elapsed(), result.is_ok());
+        result?;
+
+        let pid = self.state()?.pid;
+        supervisor::state_changed(&self.id, "created", "running");
+        supervisor::started(&self.id, pid);
+        if let Err(e) = supervisor::watch(self, pid) {
+            warn!("监听容器 {} 的事件失败: {}", self.id, e);
+        }
+        Ok(())
+    }
+
+    pub fn kill(&self, signal: i32) -> Result<()> {
+        KillCommand::new(self.id.clone(), signal).execute()
+    }
+
+    /// 冻结容器 cgroup 中的所有进程
+    pub fn pause(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
+        self.expect_status("running")?;
+        cgroups::freeze(&self.cgroup_path()?)?;
+        self.set_status("paused")
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
+        self.expect_status("paused")?;
+        cgroups::thaw(&self.cgroup_path()?)?;
+        self.set_status("running")
+    }
+
+    /// 等待容器主进程退出并返回退出码，被信号终止时为 128 + 信号值
+    pub fn wait(&self) -> Result<i32> {
+        let pid = self.state()?.pid;
+        if pid <= 0 {
+            return Err(FireError::InvalidState(format!("容器 {} 没有运行中的进程", self.id)));
+        }
+
+        let exit_code = match wait_pid(pid) {
+            Ok(code) => code,
+            Err(nix::errno::Errno::ECHILD) => {
+                return Err(FireError::Generic(format!(
+                    "容器 {} 的主进程不是当前进程的子进程，无法获取退出码",
+                    self.id
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.set_status("stopped")?;
+        Ok(exit_code)
+    }
+
+    /// 容器 cgroup 的资源使用统计
+    pub fn stats(&self) -> Result<CgroupStats> {
+        cgroups::stats::collect(&self.cgroup_path()?)
+    }
+
+    /// 删除容器，force 为 true 时先终止仍在运行的容器
+    pub fn delete(self, force: bool) -> Result<()> {
+        DeleteCommand::new(self.id.clone(), force).execute()?;
+        RUNTIME_MANAGER.lock().unwrap().remove_container(&self.id);
+        Ok(())
+    }
+
+    fn cgroup_path(&self) -> Result<String> {
+        let spec = commands::load_spec(&self.state()?)?;
+        cgroups::cgroup_path_for_spec(&self.id, &spec)
+    }
+
+    fn expect_status(&self, status: &str) -> Result<()> {
+        let state = self.state()?;
+        if state.status != status {
+            return Err(FireError::InvalidState(format!(
+                "容器 {} 的状态为 {}，需要为 {}",
+                self.id, state.status, status
+            )));
+        }
+        Ok(())
+    }
+
+    fn set_status(&self, status: &str) -> Result<()> {
+        let state = self.state()?;
+        if state.status == status {
+            return Ok(());
+        }
+        container::state::persist(self.state_file.parent().unwrap(), status, state.pid)?;
+        supervisor::state_changed(&self.id, &state.status, status);
+        Ok(())
+    }
+}
+
+/// 等待子进程退出并返回退出码，被信号终止时为 128 + 信号值
+fn wait_pid(pid: i32) -> nix::Result<i32> {
+    loop {
+        match nix::sys::wait::waitpid(nix::unistd::Pid::from_raw(pid), None) {
+            Ok(status) => {
+                if let Some(code) = process::exit_code(status) {
+                    return Ok(code);
+                }
+            }
+            Err(nix::errno::Errno::EINTR) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
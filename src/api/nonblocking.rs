@@ -0,0 +1,362 @@
+//! 基于 tokio 的非阻塞接口，需要启用 async 特性
+//!
+//! 主进程退出通过 pidfd、状态变化和 OOM 通过 inotify 交给 tokio 的 reactor
+//! 监听，同时管理大量容器时不需要为每个容器占用一个线程。
+//!
+//! ```no_run
+//! # async fn example() -> fire::errors::Result<()> {
+//! use fire::api::{nonblocking::Event, Builder};
+//!
+//! let container = Builder::new("web").bundle("/srv/bundles/web").spawn_async().await?;
+//! let mut events = container.events()?;
+//! container.start().await?;
+//! while let Some(event) = events.next().await {
+//!     if let Event::Exited { code } = event {
+//!         println!("容器退出: {:?}", code);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{supervisor, Builder};
+use crate::cgroups::{self, events::oom_kill_count_v2, stats::CgroupStats};
+use crate::errors::{FireError, Result};
+use futures_core::Stream;
+use log::{info, warn};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc;
+
+/// 容器生命周期事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Started { pid: i32 },
+    Paused,
+    Resumed,
+    /// oom_kill 为 cgroup 内累计被 OOM kill 的进程数
+    Oom { oom_kill: u64 },
+    /// 主进程退出，不是当前进程的子进程时无法获得退出码
+    Exited { code: Option<i32> },
+}
+
+/// 在阻塞线程池中执行文件和 cgroup 操作
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| FireError::Generic(format!("后台任务失败: {}", e)))?
+}
+
+impl Builder {
+    /// spawn 的非阻塞版本
+    pub async fn spawn_async(self) -> Result<ContainerHandle> {
+        let inner = blocking(move || self.spawn()).await?;
+        Ok(ContainerHandle { inner })
+    }
+}
+
+/// 已创建的容器，操作均不会阻塞 tokio 的工作线程
+#[derive(Debug, Clone)]
+pub struct ContainerHandle {
+    inner: super::ContainerHandle,
+}
+
+impl From<super::ContainerHandle> for ContainerHandle {
+    fn from(inner: super::ContainerHandle) -> Self {
+        Self { inner }
+    }
+}
+
+impl ContainerHandle {
+    /// 打开已存在的容器
+    pub fn open<S: Into<String>>(id: S) -> Result<Self> {
+        super::ContainerHandle::open(id).map(Self::from)
+    }
+
+    pub fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    pub async fn state(&self) -> Result<oci::State> {
+        self.run(|c| c.state()).await
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        self.run(|c| c.start()).await
+    }
+
+    pub async fn kill(&self, signal: i32) -> Result<()> {
+        self.run(move |c| c.kill(signal)).await
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        self.run(|c| c.pause()).await
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        self.run(|c| c.resume()).await
+    }
+
+    pub async fn stats(&self) -> Result<CgroupStats> {
+        self.run(|c| c.stats()).await
+    }
+
+    pub async fn delete(self, force: bool) -> Result<()> {
+        blocking(move || self.inner.delete(force)).await
+    }
+
+    /// 等待容器主进程退出并返回退出码，被信号终止时为 128 + 信号值
+    pub async fn wait(&self) -> Result<i32> {
+        let pid = self.state().await?.pid;
+        if pid <= 0 {
+            return Err(FireError::InvalidState(format!("容器 {} 没有运行中的进程", self.id())));
+        }
+        PidFd::open(pid)?.exited().await?;
+
+        // 主进程已经退出，同步的 wait 只负责回收并更新状态
+        self.run(|c| c.wait()).await
+    }
+
+    /// 订阅容器之后发生的事件，主进程退出或容器被删除后流结束
+    ///
+    /// 需要在 tokio 运行时中调用。
+    pub fn events(&self) -> Result<EventStream> {
+        let state_watch = Inotify::new()?;
+        state_watch.add_watch(&self.inner.state_file, libc::IN_CLOSE_WRITE | libc::IN_DELETE_SELF)?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = self.inner.clone();
+        tokio::spawn(async move {
+            if let Err(e) = watch_events(&handle, state_watch, &tx).await {
+                warn!("监听容器 {} 的事件失败: {}", handle.id(), e);
+            }
+        });
+        Ok(EventStream { rx })
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&super::ContainerHandle) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+        blocking(move || f(&inner)).await
+    }
+}
+
+/// 容器事件流
+pub struct EventStream {
+    rx: mpsc::UnboundedReceiver<Event>,
+}
+
+impl EventStream {
+    /// 下一个事件，流结束时返回 None
+    pub async fn next(&mut self) -> Option<Event> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+async fn watch_events(
+    handle: &super::ContainerHandle,
+    state_watch: Inotify,
+    tx: &mpsc::UnboundedSender<Event>,
+) -> Result<()> {
+    let mut state = handle.state()?;
+    let mut pidfd = None;
+    let mut oom = None;
+    if state.status == "running" || state.status == "paused" {
+        pidfd = Some(PidFd::open(state.pid)?);
+        oom = OomWatch::open(handle)?;
+    }
+
+    loop {
+        tokio::select! {
+            _ = tx.closed() => return Ok(()),
+            changed = state_watch.changed() => {
+                changed?;
+                // 状态文件被删除说明容器已删除
+                let new_state = match handle.state() {
+                    Ok(s) => s,
+                    Err(_) => return Ok(()),
+                };
+                let event = match (state.status.as_str(), new_state.status.as_str()) {
+                    ("created", "running") => {
+                        pidfd = Some(PidFd::open(new_state.pid)?);
+                        oom = OomWatch::open(handle)?;
+                        Some(Event::Started { pid: new_state.pid })
+                    }
+                    ("running", "paused") => Some(Event::Paused),
+                    ("paused", "running") => Some(Event::Resumed),
+                    _ => None,
+                };
+                state = new_state;
+                if let Some(event) = event {
+                    let _ = tx.send(event);
+                }
+            }
+            exited = wait_exited(pidfd.as_ref()) => {
+                exited?;
+                let code = pidfd.as_ref().and_then(PidFd::exit_code);
+                let _ = tx.send(Event::Exited { code });
+                return Ok(());
+            }
+            oom_kill = wait_oom(oom.as_mut()) => {
+                let _ = tx.send(Event::Oom { oom_kill: oom_kill? });
+            }
+        }
+    }
+}
+
+async fn wait_exited(pidfd: Option<&PidFd>) -> Result<()> {
+    match pidfd {
+        Some(pidfd) => pidfd.exited().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn wait_oom(oom: Option<&mut OomWatch>) -> Result<u64> {
+    match oom {
+        Some(oom) => oom.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 进程的 pidfd，进程退出后变为可读
+struct PidFd(AsyncFd<OwnedFd>);
+
+impl PidFd {
+    fn open(pid: i32) -> Result<Self> {
+        let fd = supervisor::pidfd_open(pid)
+            .map_err(|e| FireError::Generic(format!("打开进程 {} 的 pidfd 失败: {}", pid, e)))?;
+        Ok(Self(AsyncFd::with_interest(fd, Interest::READABLE)?))
+    }
+
+    async fn exited(&self) -> Result<()> {
+        let mut guard = self.0.readable().await?;
+        guard.retain_ready();
+        Ok(())
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        supervisor::pidfd_exit_code(self.0.as_raw_fd())
+    }
+}
+
+struct Inotify(AsyncFd<OwnedFd>);
+
+impl Inotify {
+    fn new() -> Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(Self(AsyncFd::with_interest(fd, Interest::READABLE)?))
+    }
+
+    fn add_watch(&self, path: &Path, mask: u32) -> Result<()> {
+        let path_cstr = CString::new(path.as_os_str().as_bytes())?;
+        let wd = unsafe { libc::inotify_add_watch(self.0.as_raw_fd(), path_cstr.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(FireError::Generic(format!(
+                "监听 {} 失败: {}",
+                path.display(),
+                io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    /// 等待下一批 inotify 事件，只关心有变化，不解析事件内容
+    async fn changed(&self) -> Result<()> {
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut guard = self.0.readable().await?;
+            let result = guard.try_io(|fd| {
+                let n = unsafe {
+                    libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                };
+                if n < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+            match result {
+                Ok(result) => return Ok(result?),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// cgroup v2 memory.events 中 oom_kill 计数的变化
+struct OomWatch {
+    inotify: Inotify,
+    memory_events: String,
+    last: u64,
+}
+
+impl OomWatch {
+    fn open(handle: &super::ContainerHandle) -> Result<Option<Self>> {
+        if cgroups::detect_cgroup_version()? == 1 {
+            info!("cgroup v1 不支持异步监听 OOM 事件，容器 {} 不会产生 OOM 事件", handle.id());
+            return Ok(None);
+        }
+        let memory_events = format!("/sys/fs/cgroup{}/memory.events", handle.cgroup_path()?);
+        let inotify = Inotify::new()?;
+        inotify.add_watch(Path::new(&memory_events), libc::IN_MODIFY)?;
+        let last = oom_kill_count_v2(&memory_events);
+        Ok(Some(Self {
+            inotify,
+            memory_events,
+            last,
+        }))
+    }
+
+    async fn next(&mut self) -> Result<u64> {
+        loop {
+            self.inotify.changed().await?;
+            let count = oom_kill_count_v2(&self.memory_events);
+            if count > self.last {
+                self.last = count;
+                return Ok(count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pidfd_exit_code() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let pidfd = PidFd::open(child.id() as i32).unwrap();
+        pidfd.exited().await.unwrap();
+        assert_eq!(pidfd.exit_code(), Some(3));
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+    }
+}
@@ -0,0 +1,396 @@
+//! 容器事件回调
+//!
+//! 注册回调之后通过 ContainerHandle 启动的容器，由后台的 supervisor 线程用 epoll
+//! 监听主进程的 pidfd 和 cgroup v2 的 memory.events，不再需要轮询状态文件。
+//! supervisor 不回收主进程，退出码仍然可以通过 ContainerHandle::wait 获取。
+//!
+//! 回调在 supervisor 线程或调用 ContainerHandle 的线程中执行，应当尽快返回。
+//!
+//! epoll 出现无法恢复的错误时 supervisor 停止监听，通过 on_watch_error 通知仍在监听的容器，
+//! 之后启动的容器会使用新的 supervisor 线程。
+
+use super::ContainerHandle;
+use crate::cgroups::{self, events::oom_kill_count_v2};
+use crate::errors::Result;
+use crate::logger;
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use nix::errno::Errno;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+type StartedCallback = Arc<dyn Fn(&str, i32) + Send + Sync>;
+type ExitedCallback = Arc<dyn Fn(&str, Option<i32>) + Send + Sync>;
+type OomCallback = Arc<dyn Fn(&str, u64) + Send + Sync>;
+type StateChangeCallback = Arc<dyn Fn(&str, &str, &str) + Send + Sync>;
+type WatchErrorCallback = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// epoll_wait 连续失败的次数上限，超过后视为无法恢复
+const MAX_WAIT_FAILURES: u32 = 10;
+
+#[derive(Default)]
+struct Callbacks {
+    started: Vec<StartedCallback>,
+    exited: Vec<ExitedCallback>,
+    oom: Vec<OomCallback>,
+    state_change: Vec<StateChangeCallback>,
+    watch_error: Vec<WatchErrorCallback>,
+}
+
+impl Callbacks {
+    /// 只注册了 on_watch_error 时没有需要监听的事件
+    fn is_empty(&self) -> bool {
+        self.started.is_empty() && self.exited.is_empty() && self.oom.is_empty() && self.state_change.is_empty()
+    }
+}
+
+/// supervisor 监听的文件描述符
+enum Watched {
+    Exit {
+        container: ContainerHandle,
+        pidfd: OwnedFd,
+    },
+    Oom {
+        id: String,
+        inotify: OwnedFd,
+        memory_events: String,
+        last: u64,
+    },
+}
+
+lazy_static! {
+    static ref CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks::default());
+    static ref WATCHED: Mutex<HashMap<RawFd, Watched>> = Mutex::new(HashMap::new());
+    static ref EPOLL: Mutex<Option<OwnedFd>> = Mutex::new(None);
+}
+
+/// 容器主进程启动后调用，参数为容器 id 和主进程 pid
+pub fn on_started<F>(callback: F)
+where
+    F: Fn(&str, i32) + Send + Sync + 'static,
+{
+    CALLBACKS.lock().unwrap().started.push(Arc::new(callback));
+}
+
+/// 容器主进程退出后调用，主进程不是当前进程的子进程时退出码为 None
+pub fn on_exited<F>(callback: F)
+where
+    F: Fn(&str, Option<i32>) + Send + Sync + 'static,
+{
+    CALLBACKS.lock().unwrap().exited.push(Arc::new(callback));
+}
+
+/// 容器 cgroup 中发生 OOM kill 后调用，参数为累计被 OOM kill 的进程数
+pub fn on_oom<F>(callback: F)
+where
+    F: Fn(&str, u64) + Send + Sync + 'static,
+{
+    CALLBACKS.lock().unwrap().oom.push(Arc::new(callback));
+}
+
+/// 容器状态变化后调用，参数为容器 id、原状态和新状态
+pub fn on_state_change<F>(callback: F)
+where
+    F: Fn(&str, &str, &str) + Send + Sync + 'static,
+{
+    CALLBACKS.lock().unwrap().state_change.push(Arc::new(callback));
+}
+
+/// supervisor 无法继续监听容器时调用，参数为容器 id 和错误信息
+///
+/// 之后该容器不会再触发 on_exited 和 on_oom，需要时改用 ContainerHandle::wait 等待退出。
+pub fn on_watch_error<F>(callback: F)
+where
+    F: Fn(&str, &str) + Send + Sync + 'static,
+{
+    CALLBACKS.lock().unwrap().watch_error.push(Arc::new(callback));
+}
+
+/// 取出回调后释放锁再调用，回调中可以继续注册回调或操作容器
+fn dispatch<T: Clone>(select: impl FnOnce(&Callbacks) -> &Vec<T>, call: impl Fn(&T)) {
+    let callbacks = select(&CALLBACKS.lock().unwrap()).clone();
+    for callback in &callbacks {
+        call(callback);
+    }
+}
+
+pub(super) fn started(id: &str, pid: i32) {
+    dispatch(|c| &c.started, |f| f(id, pid));
+}
+
+pub(super) fn state_changed(id: &str, old: &str, new: &str) {
+    dispatch(|c| &c.state_change, |f| f(id, old, new));
+}
+
+/// 开始监听容器主进程的退出和 OOM，没有注册回调时不做任何事
+pub(super) fn watch(container: &ContainerHandle, pid: i32) -> Result<()> {
+    if CALLBACKS.lock().unwrap().is_empty() {
+        return Ok(());
+    }
+    let epoll = epoll()?;
+    let pidfd = pidfd_open(pid)?;
+
+    if cgroups::detect_cgroup_version()? == 1 {
+        info!("cgroup v1 不支持监听 OOM 事件，容器 {} 不会触发 on_oom", container.id());
+    } else {
+        let memory_events = format!("/sys/fs/cgroup{}/memory.events", container.cgroup_path()?);
+        let inotify = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        let inotify = unsafe { OwnedFd::from_raw_fd(Errno::result(inotify)?) };
+        let path_cstr = CString::new(memory_events.as_str())?;
+        Errno::result(unsafe {
+            libc::inotify_add_watch(inotify.as_raw_fd(), path_cstr.as_ptr(), libc::IN_MODIFY)
+        })?;
+        let last = oom_kill_count_v2(&memory_events);
+        add(epoll, Watched::Oom {
+            id: container.id().to_string(),
+            inotify,
+            memory_events,
+            last,
+        })?;
+    }
+
+    // 主进程退出时会一并移除 OOM 的监听，所以最后加入
+    add(epoll, Watched::Exit {
+        container: container.clone(),
+        pidfd,
+    })
+}
+
+/// 先登记再加入 epoll，supervisor 线程收到事件时一定能找到对应的记录
+///
+/// 描述符关闭时内核会自动将其移出 epoll，移除记录即停止监听。
+fn add(epoll: RawFd, watched: Watched) -> Result<()> {
+    let fd = match watched {
+        Watched::Exit { ref pidfd, .. } => pidfd.as_raw_fd(),
+        Watched::Oom { ref inotify, .. } => inotify.as_raw_fd(),
+    };
+    WATCHED.lock().unwrap().insert(fd, watched);
+
+    let mut event = libc::epoll_event {
+        events: libc::EPOLLIN as u32,
+        u64: fd as u64,
+    };
+    if let Err(e) = Errno::result(unsafe { libc::epoll_ctl(epoll, libc::EPOLL_CTL_ADD, fd, &mut event) }) {
+        WATCHED.lock().unwrap().remove(&fd);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// 第一次监听容器时创建 epoll 并启动 supervisor 线程
+fn epoll() -> Result<RawFd> {
+    let mut epoll = EPOLL.lock().unwrap();
+    if let Some(ref fd) = *epoll {
+        return Ok(fd.as_raw_fd());
+    }
+
+    let fd = unsafe { OwnedFd::from_raw_fd(Errno::result(libc::epoll_create1(libc::EPOLL_CLOEXEC))?) };
+    let raw = fd.as_raw_fd();
+    std::thread::Builder::new()
+        .name("fire-supervisor".to_string())
+        .spawn(move || run(raw))?;
+    info!("supervisor 线程已启动");
+    *epoll = Some(fd);
+    Ok(raw)
+}
+
+fn run(epoll: RawFd) {
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 16];
+    let mut failures = 0;
+    loop {
+        let n = unsafe { libc::epoll_wait(epoll, events.as_mut_ptr(), events.len() as i32, -1) };
+        match Errno::result(n) {
+            Ok(n) => {
+                failures = 0;
+                for event in &events[..n as usize] {
+                    handle(event.u64 as RawFd);
+                }
+            }
+            Err(Errno::EINTR) => continue,
+            // EBADF、EINVAL、EFAULT 说明 epoll 本身不可用，其他错误（如内存不足）稍后重试
+            Err(e)
+                if !matches!(e, Errno::EBADF | Errno::EINVAL | Errno::EFAULT)
+                    && failures < MAX_WAIT_FAILURES =>
+            {
+                failures += 1;
+                warn!("supervisor 等待事件失败，稍后重试: {}", e);
+                std::thread::sleep(std::time::Duration::from_millis(100 * failures as u64));
+            }
+            Err(e) => {
+                error!("supervisor 等待事件失败，停止监听: {}", e);
+                fail_all(epoll, &e.to_string());
+                return;
+            }
+        }
+    }
+}
+
+/// 停止监听所有容器并通知 on_watch_error
+///
+/// 同时丢弃当前的 epoll，之后的 watch 会重新创建 epoll 和 supervisor 线程。
+fn fail_all(epoll: RawFd, reason: &str) {
+    let watched: Vec<Watched> = {
+        let mut current = EPOLL.lock().unwrap();
+        let watched = WATCHED.lock().unwrap().drain().map(|(_, w)| w).collect();
+        if current.as_ref().is_some_and(|fd| fd.as_raw_fd() == epoll) {
+            *current = None;
+        }
+        watched
+    };
+    for w in watched {
+        if let Watched::Exit { container, .. } = w {
+            let _scope = logger::container_scope(container.id());
+            warn!("不再监听容器 {} 的退出和 OOM 事件", container.id());
+            dispatch(|c| &c.watch_error, |f| f(container.id(), reason));
+        }
+    }
+}
+
+fn handle(fd: RawFd) {
+    let mut watched = WATCHED.lock().unwrap();
+    match watched.get(&fd) {
+        Some(Watched::Oom { .. }) => {
+            let oom = check_oom(watched.get_mut(&fd));
+            drop(watched);
+            if let Some((id, count)) = oom {
+                dispatch(|c| &c.oom, |f| f(&id, count));
+            }
+        }
+        Some(Watched::Exit { .. }) => {
+            let Some(Watched::Exit { container, pidfd }) = watched.remove(&fd) else {
+                return;
+            };
+            let _scope = logger::container_scope(container.id());
+
+            // OOM kill 往往就是主进程退出的原因，先补发尚未处理的 OOM 事件
+            let oom_fds: Vec<RawFd> = watched
+                .iter()
+                .filter(|(_, w)| matches!(w, Watched::Oom { id, .. } if id == container.id()))
+                .map(|(fd, _)| *fd)
+                .collect();
+            let mut ooms = Vec::new();
+            for oom_fd in oom_fds {
+                ooms.extend(check_oom(watched.get_mut(&oom_fd)));
+                watched.remove(&oom_fd);
+            }
+            drop(watched);
+
+            for (id, count) in ooms {
+                dispatch(|c| &c.oom, |f| f(&id, count));
+            }
+            let code = pidfd_exit_code(pidfd.as_raw_fd());
+            drop(pidfd);
+            if let Err(e) = container.set_status("stopped") {
+                warn!("更新容器 {} 的状态失败: {}", container.id(), e);
+            }
+            dispatch(|c| &c.exited, |f| f(container.id(), code));
+        }
+        None => {}
+    }
+}
+
+/// 读空 inotify 事件，oom_kill 计数增加时返回容器 id 和新的计数
+fn check_oom(watched: Option<&mut Watched>) -> Option<(String, u64)> {
+    let Some(Watched::Oom {
+        id,
+        inotify,
+        memory_events,
+        last,
+    }) = watched
+    else {
+        return None;
+    };
+    let mut buf = [0u8; 4096];
+    while unsafe { libc::read(inotify.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+
+    let count = oom_kill_count_v2(memory_events);
+    if count > *last {
+        *last = count;
+        Some((id.clone(), count))
+    } else {
+        None
+    }
+}
+
+pub(super) fn pidfd_open(pid: i32) -> Result<OwnedFd> {
+    let fd = Errno::result(unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) })?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// 读取已退出进程的退出码，被信号终止时为 128 + 信号值
+///
+/// 使用 WNOWAIT 保留僵尸进程留给 wait 回收，不是当前进程的子进程时返回 None。
+pub(super) fn pidfd_exit_code(pidfd: RawFd) -> Option<i32> {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::waitid(
+            libc::P_PIDFD,
+            pidfd as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+        )
+    };
+    if ret < 0 {
+        return None;
+    }
+    let status = unsafe { info.si_status() };
+    match info.si_code {
+        libc::CLD_EXITED => Some(status),
+        libc::CLD_KILLED | libc::CLD_DUMPED => Some(128 + status),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pidfd_exit_code() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap();
+        let pidfd = pidfd_open(child.id() as i32).unwrap();
+        let mut pollfd = libc::pollfd {
+            fd: pidfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 5000) }, 1);
+        assert_eq!(pidfd_exit_code(pidfd.as_raw_fd()), Some(3));
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+    }
+
+    #[test]
+    fn test_dispatch_state_change() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        on_state_change(move |id, old, new| {
+            recorder.lock().unwrap().push(format!("{}:{}->{}", id, old, new));
+        });
+        state_changed("c1", "running", "paused");
+        assert!(seen.lock().unwrap().contains(&"c1:running->paused".to_string()));
+    }
+
+    #[test]
+    fn test_fail_all() {
+        let failed = Arc::new(Mutex::new(Vec::new()));
+        let recorder = failed.clone();
+        on_watch_error(move |id, reason| {
+            recorder.lock().unwrap().push(format!("{}:{}", id, reason));
+        });
+        let pidfd = pidfd_open(std::process::id() as i32).unwrap();
+        let container = ContainerHandle {
+            id: "c-watch-error".to_string(),
+            state_file: Default::default(),
+        };
+        WATCHED.lock().unwrap().insert(pidfd.as_raw_fd(), Watched::Exit { container, pidfd });
+
+        fail_all(-1, "EBADF");
+        assert!(failed.lock().unwrap().contains(&"c-watch-error:EBADF".to_string()));
+        assert!(WATCHED.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,23 @@
+use crate::errors::*;
+use std::path::Path;
+
+/// 主机是否启用了 AppArmor
+pub fn enabled() -> bool {
+    std::fs::read_to_string("/sys/module/apparmor/parameters/enabled").is_ok_and(|s| s.starts_with('Y'))
+}
+
+/// 在 exec 时切换到 profile，与 SELinux 的 setexeccon 类似，在执行容器命令之前调用
+pub fn apply_profile(profile: &str) -> Result<()> {
+    if profile.is_empty() {
+        return Ok(());
+    }
+
+    // 5.8 之后的内核为每个 LSM 提供单独的属性目录
+    let path = if Path::new("/proc/self/attr/apparmor/exec").exists() {
+        "/proc/self/attr/apparmor/exec"
+    } else {
+        "/proc/self/attr/exec"
+    };
+    std::fs::write(path, format!("exec {}", profile))?;
+    Ok(())
+}
@@ -1,41 +1,103 @@
-use caps::{all, clear, set, CapSet, Capability};
+use caps::runtime::thread_all_supported;
+use caps::{read, set, CapSet, Capability};
 use log::{debug, warn};
 use oci::{LinuxCapabilities, LinuxCapabilityType};
 use std::collections::HashSet;
 
 use crate::errors::*;
 
-fn to_cap(cap: LinuxCapabilityType) -> Capability {
-    unsafe { ::std::mem::transmute(cap) }
+/// spec 未配置 capabilities 时使用的默认集合，与 Docker 的默认值一致
+const DEFAULT_CAPABILITIES: [LinuxCapabilityType; 14] = [
+    LinuxCapabilityType::CAP_CHOWN,
+    LinuxCapabilityType::CAP_DAC_OVERRIDE,
+    LinuxCapabilityType::CAP_FSETID,
+    LinuxCapabilityType::CAP_FOWNER,
+    LinuxCapabilityType::CAP_MKNOD,
+    LinuxCapabilityType::CAP_NET_RAW,
+    LinuxCapabilityType::CAP_SETGID,
+    LinuxCapabilityType::CAP_SETUID,
+    LinuxCapabilityType::CAP_SETFCAP,
+    LinuxCapabilityType::CAP_SETPCAP,
+    LinuxCapabilityType::CAP_NET_BIND_SERVICE,
+    LinuxCapabilityType::CAP_SYS_CHROOT,
+    LinuxCapabilityType::CAP_KILL,
+    LinuxCapabilityType::CAP_AUDIT_WRITE,
+];
+
+/// 按名称映射为 caps 中的 capability，未知的名称返回 None
+fn to_cap(cap: &LinuxCapabilityType) -> Option<Capability> {
+    cap.as_str().parse().ok()
 }
 
-fn to_set(caps: &[LinuxCapabilityType]) -> HashSet<Capability> {
+/// 转换为当前内核支持的 capability 集合，跳过未知或内核不支持的名称
+fn to_set(caps: &[LinuxCapabilityType], supported: &HashSet<Capability>) -> HashSet<Capability> {
     let mut capabilities = HashSet::new();
     for c in caps {
-        capabilities.insert(to_cap(*c));
+        match to_cap(c) {
+            Some(cap) if supported.contains(&cap) => {
+                capabilities.insert(cap);
+            }
+            Some(_) => warn!("capability {} is not supported by the kernel, skipping", c.as_str()),
+            None => warn!("unknown capability {}, skipping", c.as_str()),
+        }
     }
     capabilities
 }
 
+/// 将有效集恢复为全部允许的 capabilities
 pub fn reset_effective() -> Result<()> {
-    clear(None, CapSet::Effective)?;
-    set(None, CapSet::Effective, &all())?;
+    set(None, CapSet::Effective, &read(None, CapSet::Permitted)?)?;
     Ok(())
 }
 
+/// spec 未配置 capabilities 时的默认值
+///
+/// 用户命名空间中的 root 返回 None，保留命名空间内的全部 capabilities；
+/// 其他情况使用 [`DEFAULT_CAPABILITIES`]，而不是沿用运行时自身的集合。
+pub fn default_capabilities(uid: u32, user_namespace: bool) -> Option<LinuxCapabilities> {
+    if uid == 0 && user_namespace {
+        return None;
+    }
+    let caps = DEFAULT_CAPABILITIES.to_vec();
+    Some(LinuxCapabilities {
+        bounding: caps.clone(),
+        effective: caps.clone(),
+        inheritable: Vec::new(),
+        permitted: caps,
+        ambient: Vec::new(),
+    })
+}
+
 pub fn drop_privileges(cs: &LinuxCapabilities) -> Result<()> {
-    let all_caps = all();
+    // 旧内核不认识较新的 capability（如 CAP_PERFMON），只处理内核支持的部分
+    let supported = thread_all_supported();
+    // 切换到非 root 用户后有效集被清空，删除 bounding 集需要有效集中的 CAP_SETPCAP
+    set(None, CapSet::Effective, &read(None, CapSet::Permitted)?)?;
     debug!("dropping bounding capabilities to {:?}", cs.bounding);
     // drop excluded caps from the bounding set
-    for c in all_caps.difference(&to_set(&cs.bounding)) {
+    for c in supported.difference(&to_set(&cs.bounding, &supported)) {
         caps::drop(None, CapSet::Bounding, *c)?;
     }
     // set other sets for current process
-    set(None, CapSet::Effective, &to_set(&cs.effective))?;
-    set(None, CapSet::Permitted, &to_set(&cs.permitted))?;
-    set(None, CapSet::Inheritable, &to_set(&cs.inheritable))?;
-    if let Err(e) = set(None, CapSet::Ambient, &to_set(&cs.ambient)) {
+    set(None, CapSet::Effective, &to_set(&cs.effective, &supported))?;
+    set(None, CapSet::Permitted, &to_set(&cs.permitted, &supported))?;
+    set(None, CapSet::Inheritable, &to_set(&cs.inheritable, &supported))?;
+    if let Err(e) = set(None, CapSet::Ambient, &to_set(&cs.ambient, &supported)) {
         warn!("failed to set ambient capabilities: {}", e);
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_cap() {
+        let parse = |name: &str| to_cap(&LinuxCapabilityType::from(name.to_string()));
+        assert_eq!(parse("CAP_CHOWN"), Some(Capability::CAP_CHOWN));
+        assert_eq!(parse("CAP_PERFMON"), Some(Capability::CAP_PERFMON));
+        assert_eq!(parse("CAP_CHECKPOINT_RESTORE"), Some(Capability::CAP_CHECKPOINT_RESTORE));
+        assert_eq!(parse("CAP_FUTURE"), None);
+    }
+}
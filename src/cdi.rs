@@ -0,0 +1,331 @@
+use crate::errors::{FireError, Result};
+use log::{info, warn};
+use oci::{Hook, Hooks, Linux, LinuxDevice, LinuxDeviceCgroup, LinuxDeviceType, LinuxResources, Mount, Spec};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+/// 注解中引用 CDI 设备的键前缀，值为逗号分隔的设备全名，如
+/// `cdi.k8s.io/gpu = "nvidia.com/gpu=0,nvidia.com/gpu=1"`
+pub const ANNOTATION_PREFIX: &str = "cdi.k8s.io/";
+
+/// CDI spec 的搜索目录，后面的目录优先级更高
+const SPEC_DIRS: [&str; 2] = ["/etc/cdi", "/var/run/cdi"];
+
+/// CDI spec 文件
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CdiSpec {
+    #[serde(default)]
+    cdi_version: String,
+    kind: String,
+    #[serde(default)]
+    devices: Vec<CdiDevice>,
+    /// 使用该 spec 中任意设备时都会应用的修改
+    #[serde(default)]
+    container_edits: ContainerEdits,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CdiDevice {
+    name: String,
+    #[serde(default)]
+    container_edits: ContainerEdits,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct ContainerEdits {
+    #[serde(default)]
+    env: Vec<String>,
+    #[serde(default)]
+    device_nodes: Vec<DeviceNode>,
+    #[serde(default)]
+    mounts: Vec<CdiMount>,
+    #[serde(default)]
+    hooks: Vec<CdiHook>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DeviceNode {
+    path: String,
+    #[serde(default)]
+    host_path: String,
+    #[serde(default, rename = "type")]
+    typ: String,
+    major: Option<u64>,
+    minor: Option<u64>,
+    file_mode: Option<u32>,
+    #[serde(default)]
+    permissions: String,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CdiMount {
+    host_path: String,
+    container_path: String,
+    #[serde(default)]
+    options: Vec<String>,
+    #[serde(default, rename = "type")]
+    typ: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CdiHook {
+    hook_name: String,
+    path: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: Vec<String>,
+    timeout: Option<i64>,
+}
+
+/// 注解中引用的 CDI 设备全名，按出现顺序去重
+pub fn requested_devices(annotations: &HashMap<String, String>) -> Vec<String> {
+    let mut keys: Vec<&String> = annotations.keys().filter(|k| k.starts_with(ANNOTATION_PREFIX)).collect();
+    keys.sort();
+
+    let mut devices: Vec<String> = Vec::new();
+    for key in keys {
+        for name in annotations[key].split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            if !devices.iter().any(|d| d == name) {
+                devices.push(name.to_string());
+            }
+        }
+    }
+    devices
+}
+
+/// 解析设备全名 `vendor.com/class=name`，返回 (kind, name)
+fn parse_device_name(device: &str) -> Result<(&str, &str)> {
+    let invalid = || FireError::InvalidSpec(format!("无效的 CDI 设备名: {}", device));
+    let (kind, name) = device.split_once('=').ok_or_else(invalid)?;
+    let (vendor, class) = kind.split_once('/').ok_or_else(invalid)?;
+    if vendor.is_empty() || class.is_empty() || name.is_empty() {
+        return Err(invalid());
+    }
+    Ok((kind, name))
+}
+
+/// 从各搜索目录加载 CDI spec，同一 kind 的设备以优先级高的目录为准
+///
+/// 目前只支持 JSON 格式的 spec，YAML 文件会被跳过。
+fn load_specs(dirs: &[&str]) -> Vec<CdiSpec> {
+    let mut specs = Vec::new();
+    for dir in dirs.iter().rev() {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        paths.sort();
+
+        for path in paths {
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => {}
+                Some("yaml") | Some("yml") => {
+                    warn!("暂不支持 YAML 格式的 CDI spec，已跳过: {}", path.display());
+                    continue;
+                }
+                _ => continue,
+            }
+            let spec = fs::read_to_string(&path)
+                .map_err(FireError::from)
+                .and_then(|content| Ok(serde_json::from_str::<CdiSpec>(&content)?));
+            match spec {
+                Ok(spec) => specs.push(spec),
+                Err(e) => warn!("读取 CDI spec {} 失败: {}", path.display(), e),
+            }
+        }
+    }
+    specs
+}
+
+/// 将注解中引用的 CDI 设备注入 spec
+///
+/// 依次应用设备所在 spec 的公共修改和设备自身的修改：追加设备节点及
+/// 对应的 cgroup 设备规则、挂载、环境变量和钩子。
+#[tracing::instrument(name = "cdi_inject", skip_all)]
+pub fn inject(spec: &mut Spec) -> Result<()> {
+    let devices = requested_devices(&spec.annotations);
+    if devices.is_empty() {
+        return Ok(());
+    }
+
+    let specs = load_specs(&SPEC_DIRS);
+    for device in &devices {
+        let (kind, name) = parse_device_name(device)?;
+        let (cdi_spec, cdi_device) = specs
+            .iter()
+            .filter(|s| s.kind == kind)
+            .find_map(|s| s.devices.iter().find(|d| d.name == name).map(|d| (s, d)))
+            .ok_or_else(|| FireError::InvalidSpec(format!("未找到 CDI 设备: {}", device)))?;
+
+        apply_edits(spec, &cdi_spec.container_edits)?;
+        apply_edits(spec, &cdi_device.container_edits)?;
+        info!("注入 CDI 设备 {} (cdiVersion {})", device, cdi_spec.cdi_version);
+    }
+    Ok(())
+}
+
+fn apply_edits(spec: &mut Spec, edits: &ContainerEdits) -> Result<()> {
+    for env in &edits.env {
+        let key = env.split('=').next().unwrap_or_default();
+        spec.process.env.retain(|e| e.split('=').next() != Some(key));
+        spec.process.env.push(env.clone());
+    }
+
+    for node in &edits.device_nodes {
+        add_device(spec, node)?;
+    }
+
+    for m in &edits.mounts {
+        spec.mounts.retain(|existing| existing.destination != m.container_path);
+        spec.mounts.push(Mount {
+            destination: m.container_path.clone(),
+            typ: if m.typ.is_empty() { "bind".to_string() } else { m.typ.clone() },
+            source: m.host_path.clone(),
+            options: m.options.clone(),
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
+        });
+    }
+
+    for hook in &edits.hooks {
+        let hooks = spec.hooks.get_or_insert_with(Hooks::default);
+        let target = match hook.hook_name.as_str() {
+            "prestart" | "createRuntime" => &mut hooks.prestart,
+            "poststart" => &mut hooks.poststart,
+            "poststop" => &mut hooks.poststop,
+            name => {
+                warn!("不支持的 CDI 钩子 {}，已跳过: {}", name, hook.path);
+                continue;
+            }
+        };
+        target.push(Hook {
+            path: hook.path.clone(),
+            args: hook.args.clone(),
+            env: hook.env.clone(),
+            timeout: hook.timeout,
+        });
+    }
+    Ok(())
+}
+
+/// 追加设备节点，未指定类型或设备号时从主机上的设备文件获取
+fn add_device(spec: &mut Spec, node: &DeviceNode) -> Result<()> {
+    let linux = spec.linux.get_or_insert_with(Linux::default);
+
+    let (typ, major, minor) = match (parse_device_type(&node.typ)?, node.major, node.minor) {
+        (Some(typ), Some(major), Some(minor)) => (typ, major, minor),
+        (Some(LinuxDeviceType::p), _, _) => (LinuxDeviceType::p, 0, 0),
+        (typ, major, minor) => {
+            let host_path = if node.host_path.is_empty() { &node.path } else { &node.host_path };
+            let (host_typ, host_major, host_minor) = stat_device(host_path)?;
+            (typ.unwrap_or(host_typ), major.unwrap_or(host_major), minor.unwrap_or(host_minor))
+        }
+    };
+
+    linux.devices.retain(|d| d.path != node.path);
+    linux.devices.push(LinuxDevice {
+        path: node.path.clone(),
+        typ,
+        major,
+        minor,
+        file_mode: node.file_mode,
+        uid: node.uid,
+        gid: node.gid,
+    });
+
+    if let LinuxDeviceType::b | LinuxDeviceType::c = typ {
+        let access = if node.permissions.is_empty() { "rwm" } else { node.permissions.as_str() };
+        linux
+            .resources
+            .get_or_insert_with(LinuxResources::default)
+            .devices
+            .push(LinuxDeviceCgroup {
+                allow: true,
+                typ,
+                major: Some(major as i64),
+                minor: Some(minor as i64),
+                access: access.to_string(),
+            });
+    }
+    Ok(())
+}
+
+fn parse_device_type(typ: &str) -> Result<Option<LinuxDeviceType>> {
+    match typ {
+        "" => Ok(None),
+        "b" => Ok(Some(LinuxDeviceType::b)),
+        "c" | "u" => Ok(Some(LinuxDeviceType::c)),
+        "p" => Ok(Some(LinuxDeviceType::p)),
+        _ => Err(FireError::InvalidSpec(format!("无效的 CDI 设备类型: {}", typ))),
+    }
+}
+
+/// 获取主机设备文件的类型和主次设备号
+fn stat_device(path: &str) -> Result<(LinuxDeviceType, u64, u64)> {
+    let metadata = fs::metadata(Path::new(path))
+        .map_err(|e| FireError::InvalidSpec(format!("无法访问 CDI 设备 {}: {}", path, e)))?;
+    let file_type = metadata.file_type();
+    let typ = if file_type.is_block_device() {
+        LinuxDeviceType::b
+    } else if file_type.is_char_device() {
+        LinuxDeviceType::c
+    } else if file_type.is_fifo() {
+        LinuxDeviceType::p
+    } else {
+        return Err(FireError::InvalidSpec(format!("{} 不是设备文件", path)));
+    };
+    let rdev = metadata.rdev();
+    Ok((typ, libc::major(rdev) as u64, libc::minor(rdev) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_edits() {
+        let annotations: HashMap<String, String> = [
+            ("cdi.k8s.io/gpu".to_string(), "vendor.com/gpu=0, vendor.com/gpu=1".to_string()),
+            ("cdi.k8s.io/dup".to_string(), "vendor.com/gpu=0".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(requested_devices(&annotations), vec!["vendor.com/gpu=0", "vendor.com/gpu=1"]);
+        assert_eq!(parse_device_name("vendor.com/gpu=0").unwrap(), ("vendor.com/gpu", "0"));
+        assert!(parse_device_name("gpu=0").is_err());
+
+        let cdi: CdiSpec = serde_json::from_str(
+            r#"{"cdiVersion":"0.6.0","kind":"vendor.com/gpu","devices":[{"name":"0",
+                "containerEdits":{"deviceNodes":[{"path":"/dev/gpu0","type":"c","major":195,"minor":0}]}}],
+                "containerEdits":{"env":["GPU=1","PATH=/opt/bin"],
+                "mounts":[{"hostPath":"/usr/lib/gpu","containerPath":"/usr/lib/gpu","options":["ro","rbind"]}]}}"#,
+        )
+        .unwrap();
+        let mut spec: Spec = serde_json::from_str(
+            r#"{"process":{"user":{"uid":0,"gid":0},"args":["sh"],"env":["PATH=/bin"]},
+                "root":{"path":"rootfs"},"linux":{}}"#,
+        )
+        .unwrap();
+        apply_edits(&mut spec, &cdi.container_edits).unwrap();
+        apply_edits(&mut spec, &cdi.devices[0].container_edits).unwrap();
+
+        assert_eq!(spec.process.env, vec!["GPU=1", "PATH=/opt/bin"]);
+        assert_eq!(spec.mounts[0].typ, "bind");
+        let linux = spec.linux.unwrap();
+        assert_eq!((linux.devices[0].major, linux.devices[0].minor), (195, 0));
+        let rule = &linux.resources.unwrap().devices[0];
+        assert_eq!((rule.major, rule.access.as_str()), (Some(195), "rwm"));
+    }
+}
@@ -0,0 +1,178 @@
+use super::write_file;
+use crate::errors::{FireError, Result};
+use oci::{LinuxBlockIO, LinuxResources, LinuxThrottleDevice};
+use std::collections::HashMap;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+/// 通过注解以设备路径配置限速，如
+/// `org.fire.blkio.throttle.read_bps = "/dev/sda:1048576,/dev/sdb:2097152"`
+pub const ANNOTATION_THROTTLE_PREFIX: &str = "org.fire.blkio.throttle.";
+
+/// 将注解中的设备路径限速解析为主次设备号，追加到资源配置
+pub fn apply_annotations(
+    resources: &mut Option<LinuxResources>,
+    annotations: &HashMap<String, String>,
+) -> Result<()> {
+    for (key, value) in annotations {
+        let kind = match key.strip_prefix(ANNOTATION_THROTTLE_PREFIX) {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        let mut devices = Vec::new();
+        for entry in value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (path, rate) = entry.rsplit_once(':').ok_or_else(|| {
+                FireError::InvalidSpec(format!("无效的限速配置 {}: {}", key, entry))
+            })?;
+            let rate = rate.parse::<u64>().map_err(|_| {
+                FireError::InvalidSpec(format!("无效的限速值 {}: {}", key, entry))
+            })?;
+            let (major, minor) = resolve_device(path)?;
+            devices.push(LinuxThrottleDevice { major, minor, rate });
+        }
+
+        let block_io = resources
+            .get_or_insert_with(LinuxResources::default)
+            .block_io
+            .get_or_insert_with(LinuxBlockIO::default);
+        let target = match kind {
+            "read_bps" => &mut block_io.throttle_read_bps_device,
+            "write_bps" => &mut block_io.throttle_write_bps_device,
+            "read_iops" => &mut block_io.throttle_read_iops_device,
+            "write_iops" => &mut block_io.throttle_write_iops_device,
+            _ => {
+                return Err(FireError::InvalidSpec(format!("未知的限速类型: {}", key)));
+            }
+        };
+        target.extend(devices);
+    }
+    Ok(())
+}
+
+/// 获取块设备节点的主次设备号
+fn resolve_device(path: &str) -> Result<(i64, i64)> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| FireError::InvalidSpec(format!("无法访问设备 {}: {}", path, e)))?;
+    if !metadata.file_type().is_block_device() {
+        return Err(FireError::InvalidSpec(format!("{} 不是块设备", path)));
+    }
+    let rdev = metadata.rdev();
+    Ok((libc::major(rdev) as i64, libc::minor(rdev) as i64))
+}
+
+/// 校验配置中引用的块设备在主机上存在
+pub fn validate(block_io: &LinuxBlockIO) -> Result<()> {
+    let devices = block_io
+        .weight_device
+        .iter()
+        .map(|d| (d.major, d.minor))
+        .chain(throttle_devices(block_io).map(|(_, d)| (d.major, d.minor)));
+    for (major, minor) in devices {
+        if !Path::new(&format!("/sys/dev/block/{}:{}", major, minor)).exists() {
+            return Err(FireError::InvalidSpec(format!(
+                "块设备 {}:{} 不存在",
+                major, minor
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn throttle_devices(block_io: &LinuxBlockIO) -> impl Iterator<Item = (&str, &LinuxThrottleDevice)> {
+    let tag = |key: &'static str| move |d| (key, d);
+    block_io
+        .throttle_read_bps_device
+        .iter()
+        .map(tag("rbps"))
+        .chain(block_io.throttle_write_bps_device.iter().map(tag("wbps")))
+        .chain(block_io.throttle_read_iops_device.iter().map(tag("riops")))
+        .chain(block_io.throttle_write_iops_device.iter().map(tag("wiops")))
+}
+
+/// v1 的 blkio 权重 [10, 1000] 转换为 v2 的 io.weight [1, 10000]
+fn weight_to_v2(weight: u16) -> Result<u64> {
+    if !(10..=1000).contains(&weight) {
+        return Err(FireError::InvalidSpec(format!(
+            "blkio 权重必须在 10 到 1000 之间: {}",
+            weight
+        )));
+    }
+    Ok(1 + (weight as u64 - 10) * 9999 / 990)
+}
+
+/// 生成 io.max 的每设备配置，同一设备的多种限速合并为一行
+fn io_max_lines(block_io: &LinuxBlockIO) -> Vec<String> {
+    let mut lines: Vec<((i64, i64), String)> = Vec::new();
+    for (key, device) in throttle_devices(block_io) {
+        let entry = format!(" {}={}", key, device.rate);
+        match lines.iter_mut().find(|(d, _)| *d == (device.major, device.minor)) {
+            Some((_, line)) => line.push_str(&entry),
+            None => lines.push((
+                (device.major, device.minor),
+                format!("{}:{}{}", device.major, device.minor, entry),
+            )),
+        }
+    }
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+/// 将 blkio 配置转换为 cgroup v2 的 io 控制器参数
+///
+/// 使用 BFQ 调度器时 io.bfq.weight 与 v1 取值范围一致，直接写入；
+/// v2 没有 leaf_weight，配置时报错。
+pub fn apply_v2(block_io: &LinuxBlockIO, cgroup_dir: &str) -> Result<()> {
+    if block_io.leaf_weight.is_some() || block_io.weight_device.iter().any(|d| d.leaf_weight.is_some()) {
+        return Err(FireError::InvalidSpec(
+            "cgroup v2 不支持 blkioLeafWeight".to_string(),
+        ));
+    }
+
+    let bfq = Path::new(cgroup_dir).join("io.bfq.weight").exists();
+    if let Some(weight) = block_io.weight {
+        if bfq {
+            write_file(cgroup_dir, "io.bfq.weight", &weight.to_string())?;
+        } else {
+            write_file(cgroup_dir, "io.weight", &format!("default {}", weight_to_v2(weight)?))?;
+        }
+    }
+    for device in &block_io.weight_device {
+        let weight = match device.weight {
+            Some(weight) => weight,
+            None => continue,
+        };
+        let data = if bfq {
+            format!("{}:{} {}", device.major, device.minor, weight)
+        } else {
+            format!("{}:{} {}", device.major, device.minor, weight_to_v2(weight)?)
+        };
+        write_file(cgroup_dir, if bfq { "io.bfq.weight" } else { "io.weight" }, &data)?;
+    }
+
+    for line in io_max_lines(block_io) {
+        write_file(cgroup_dir, "io.max", &line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_max_lines() {
+        let device = |major, minor, rate| LinuxThrottleDevice { major, minor, rate };
+        let block_io = LinuxBlockIO {
+            throttle_read_bps_device: vec![device(8, 0, 1024), device(8, 16, 4096)],
+            throttle_write_iops_device: vec![device(8, 0, 100)],
+            ..Default::default()
+        };
+        assert_eq!(
+            io_max_lines(&block_io),
+            vec!["8:0 rbps=1024 wiops=100", "8:16 rbps=4096"]
+        );
+        assert_eq!(weight_to_v2(10).unwrap(), 1);
+        assert_eq!(weight_to_v2(1000).unwrap(), 10000);
+        assert!(weight_to_v2(5).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+/// 创建容器时未能使用的 cgroup 控制器（逗号分隔），记录到状态文件中
+pub const ANNOTATION_SKIPPED_CONTROLLERS: &str = "org.fire.cgroup.skipped-controllers";
+
+/// 运行时会配置的 cgroup v2 控制器
+const V2_CONTROLLERS: [&str; 7] = ["cpu", "cpuset", "memory", "io", "pids", "hugetlb", "misc"];
+
+lazy_static! {
+    static ref MOUNTINFO: String = read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    static ref V1_MOUNTPOINTS: HashMap<String, String> = {
+        let enabled = parse_proc_cgroups(&read_to_string("/proc/cgroups").unwrap_or_default());
+        parse_v1_mountpoints(&MOUNTINFO)
+            .into_iter()
+            .filter(|(name, _)| name == "name=systemd" || enabled.contains(name))
+            .map(|(name, path)| (name.trim_start_matches("name=").to_string(), path))
+            .collect()
+    };
+    static ref UNIFIED_MOUNTPOINT: Option<String> = parse_unified_mountpoint(&MOUNTINFO);
+}
+
+/// 控制器所在的 cgroup 层级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hierarchy {
+    /// 挂载在 v1 层级中，值为挂载点
+    V1(&'static str),
+    /// 由 cgroup2 统一层级管理，值为 cgroup2 的挂载点
+    Unified(&'static str),
+}
+
+/// cgroup v1 控制器的挂载点，控制器未启用或未挂载时返回 None
+///
+/// 共同挂载的控制器（如 cpu,cpuacct）返回同一个挂载点。
+pub fn v1_mountpoint(subsystem: &str) -> Option<&'static str> {
+    V1_MOUNTPOINTS.get(subsystem).map(|s| s.as_str())
+}
+
+/// 已挂载的 cgroup v1 控制器
+pub fn v1_available() -> Vec<String> {
+    let mut available: Vec<String> =
+        V1_MOUNTPOINTS.keys().filter(|name| *name != "systemd").cloned().collect();
+    available.sort();
+    available
+}
+
+/// cgroup2 的挂载点，纯 v2 主机为 /sys/fs/cgroup
+pub fn unified_mountpoint() -> &'static str {
+    UNIFIED_MOUNTPOINT.as_deref().unwrap_or("/sys/fs/cgroup")
+}
+
+/// 混合模式下 cgroup2 的挂载点（通常为 /sys/fs/cgroup/unified），其他情况返回 None
+///
+/// 混合模式的主机在挂载 v1 控制器的同时挂载了 cgroup2，没有绑定到 v1 的控制器
+/// 由 cgroup2 管理。
+pub fn hybrid_mountpoint() -> Option<&'static str> {
+    match crate::sysinfo::get().cgroup.hybrid {
+        true => UNIFIED_MOUNTPOINT.as_deref(),
+        false => None,
+    }
+}
+
+/// mountinfo 中是否有 cgroup2 的挂载
+pub fn unified_mounted() -> bool {
+    UNIFIED_MOUNTPOINT.is_some()
+}
+
+/// cgroup2 根目录中可用的控制器，混合模式下为没有绑定到 v1 的控制器
+pub fn v2_available() -> Vec<String> {
+    read_to_string(format!("{}/cgroup.controllers", unified_mountpoint()))
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// v1 子系统在 cgroup2 中对应的控制器，devices、net_cls 等在 v2 中没有对应的控制器
+fn unified_name(subsystem: &str) -> Option<&str> {
+    match subsystem {
+        "blkio" => Some("io"),
+        "cpu" | "cpuset" | "memory" | "pids" | "hugetlb" | "misc" => Some(subsystem),
+        _ => None,
+    }
+}
+
+/// cgroup v1 主机上子系统所在的层级，按 v1 的名称查找
+///
+/// 挂载在 v1 中的优先；混合模式下没有挂载到 v1 的子系统交给 cgroup2 中的对应控制器，
+/// 两者都没有时返回 None。
+pub fn hierarchy(subsystem: &str) -> Option<Hierarchy> {
+    if let Some(mountpoint) = v1_mountpoint(subsystem) {
+        return Some(Hierarchy::V1(mountpoint));
+    }
+    let mountpoint = hybrid_mountpoint()?;
+    let controller = unified_name(subsystem)?;
+    if v2_available().iter().any(|c| c == controller) {
+        Some(Hierarchy::Unified(mountpoint))
+    } else {
+        None
+    }
+}
+
+/// 混合模式下由 cgroup2 管理的 v2 控制器
+pub fn hybrid_controllers() -> Vec<String> {
+    match hybrid_mountpoint() {
+        Some(_) => v2_available(),
+        None => Vec::new(),
+    }
+}
+
+/// 当前主机上不可用、配置时会被跳过的控制器
+pub fn skipped(version: u8, v1_subsystems: &[&str]) -> Vec<String> {
+    match version {
+        1 => v1_subsystems
+            .iter()
+            .filter(|s| hierarchy(s).is_none())
+            .map(|s| s.to_string())
+            .collect(),
+        _ => {
+            let available = v2_available();
+            V2_CONTROLLERS
+                .iter()
+                .filter(|c| !available.iter().any(|a| a == *c))
+                .map(|c| c.to_string())
+                .collect()
+        }
+    }
+}
+
+/// 解析 /proc/cgroups，返回已启用的控制器
+fn parse_proc_cgroups(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [name, _, _, "1"] => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// 解析 mountinfo 中 cgroup v1 的挂载，返回控制器到挂载点的映射
+fn parse_v1_mountpoints(mountinfo: &str) -> HashMap<String, String> {
+    let mut mountpoints = HashMap::new();
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let sep = match fields.iter().position(|f| *f == "-") {
+            Some(sep) => sep,
+            None => continue,
+        };
+        if fields.get(sep + 1) != Some(&"cgroup") || fields.len() < 5 {
+            continue;
+        }
+        let super_options = fields.get(sep + 3).copied().unwrap_or("");
+        for option in super_options.split(',') {
+            if matches!(option, "rw" | "ro") || (option.contains('=') && !option.starts_with("name=")) {
+                continue;
+            }
+            mountpoints
+                .entry(option.to_string())
+                .or_insert_with(|| fields[4].to_string());
+        }
+    }
+    mountpoints
+}
+
+/// 解析 mountinfo 中 cgroup2 的挂载点
+fn parse_unified_mountpoint(mountinfo: &str) -> Option<String> {
+    mountinfo.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let sep = fields.iter().position(|f| *f == "-")?;
+        match fields.get(sep + 1) {
+            Some(&"cgroup2") => fields.get(4).map(|path| path.to_string()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_cgroups() {
+        let content = "#subsys_name\thierarchy\tnum_cgroups\tenabled\n\
+                       cpuset\t2\t1\t1\nmemory\t0\t1\t0\npids\t3\t5\t1\n";
+        assert_eq!(parse_proc_cgroups(content), vec!["cpuset", "pids"]);
+    }
+
+    #[test]
+    fn test_parse_v1_mountpoints() {
+        let mountinfo = "\
+30 25 0:26 / /sys/fs/cgroup/systemd rw,nosuid - cgroup cgroup rw,xattr,name=systemd
+31 25 0:27 / /sys/fs/cgroup/cpu,cpuacct rw,nosuid - cgroup cgroup rw,cpu,cpuacct
+32 25 0:28 / /sys/fs/cgroup/memory rw,nosuid - cgroup cgroup rw,memory
+33 25 0:29 / /sys/fs/cgroup/unified rw,nosuid - cgroup2 cgroup2 rw
+";
+        let mountpoints = parse_v1_mountpoints(mountinfo);
+        assert_eq!(mountpoints["cpu"], "/sys/fs/cgroup/cpu,cpuacct");
+        assert_eq!(mountpoints["cpuacct"], "/sys/fs/cgroup/cpu,cpuacct");
+        assert_eq!(mountpoints["name=systemd"], "/sys/fs/cgroup/systemd");
+        assert!(!mountpoints.contains_key("pids"));
+        assert_eq!(parse_unified_mountpoint(mountinfo).as_deref(), Some("/sys/fs/cgroup/unified"));
+        assert_eq!(parse_unified_mountpoint(&mountinfo.replace("cgroup2", "tmpfs")), None);
+    }
+}
@@ -0,0 +1,186 @@
+use super::controllers::{self, Hierarchy};
+use super::detect_cgroup_version;
+use crate::errors::{FireError, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::fs::{read_to_string, File};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+/// 容器发生 OOM kill 时记录到状态文件中的注解
+pub const ANNOTATION_OOM_KILLED: &str = "org.fire.oom.killed";
+/// 按 OOM 策略重启容器的次数，记录在重新创建的容器的状态文件中
+pub const ANNOTATION_OOM_RESTARTS: &str = "org.fire.oom.restarts";
+
+/// OOM 事件，oom_kill 为 cgroup 内累计被 OOM kill 的进程数
+#[derive(Debug, Clone, Serialize)]
+pub struct OomEvent {
+    pub oom_kill: u64,
+}
+
+/// 监听容器 cgroup 的 OOM 事件
+///
+/// cgroup v2 通过 inotify 监听 memory.events，cgroup v1 通过 eventfd 注册
+/// memory.oom_control。每次发生 OOM 时调用 callback，callback 返回 false 或
+/// cgroup 中不再有进程时停止监听。
+pub fn watch_oom<F>(cgroups_path: &str, callback: F) -> Result<()>
+where
+    F: FnMut(&OomEvent) -> bool,
+{
+    match memory_hierarchy()? {
+        Hierarchy::V1(mountpoint) => watch_oom_v1(&format!("{}{}", mountpoint, cgroups_path), callback),
+        Hierarchy::Unified(mountpoint) => watch_oom_v2(&format!("{}{}", mountpoint, cgroups_path), callback),
+    }
+}
+
+/// cgroup 内累计被 OOM kill 的进程数，读取失败时为 0
+///
+/// init 退出后 cgroup 删除之前仍可读取，用于判断容器是否因 OOM 退出。
+pub fn oom_kill_count(cgroups_path: &str) -> Result<u64> {
+    Ok(match memory_hierarchy()? {
+        Hierarchy::V1(mountpoint) => {
+            oom_kill_count_v1(&format!("{}{}/memory.oom_control", mountpoint, cgroups_path)).unwrap_or(0)
+        }
+        Hierarchy::Unified(mountpoint) => {
+            oom_kill_count_v2(&format!("{}{}/memory.events", mountpoint, cgroups_path))
+        }
+    })
+}
+
+/// memory 控制器所在的层级
+///
+/// cgroup v1 主机上 memory 可能挂载在非标准位置，混合模式下也可能由 cgroup2 管理。
+fn memory_hierarchy() -> Result<Hierarchy> {
+    match detect_cgroup_version()? {
+        1 => controllers::hierarchy("memory")
+            .ok_or_else(|| FireError::KernelUnsupported("memory 控制器未挂载".to_string())),
+        _ => Ok(Hierarchy::Unified(controllers::unified_mountpoint())),
+    }
+}
+
+fn watch_oom_v2<F>(dir: &str, mut callback: F) -> Result<()>
+where
+    F: FnMut(&OomEvent) -> bool,
+{
+    let memory_events = format!("{}/memory.events", dir);
+    let cgroup_events = format!("{}/cgroup.events", dir);
+
+    let inotify = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if inotify < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let inotify = unsafe { OwnedFd::from_raw_fd(inotify) };
+    for path in [&memory_events, &cgroup_events] {
+        let path_cstr = std::ffi::CString::new(path.as_str())?;
+        let wd = unsafe {
+            libc::inotify_add_watch(inotify.as_raw_fd(), path_cstr.as_ptr(), libc::IN_MODIFY)
+        };
+        if wd < 0 {
+            return Err(FireError::Generic(format!(
+                "监听 {} 失败: {}",
+                path,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    let mut last = oom_kill_count_v2(&memory_events);
+    info!("开始监听 cgroup v2 OOM 事件: {}", dir);
+
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = unsafe {
+            libc::read(inotify.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+        };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err.into());
+        }
+
+        let count = oom_kill_count_v2(&memory_events);
+        if count > last {
+            last = count;
+            if !callback(&OomEvent { oom_kill: count }) {
+                return Ok(());
+            }
+        }
+
+        // cgroup 已删除或不再有进程
+        match read_to_string(&cgroup_events) {
+            Ok(content) if !content.lines().any(|l| l == "populated 0") => {}
+            _ => break,
+        }
+    }
+
+    info!("停止监听 OOM 事件: {}", dir);
+    Ok(())
+}
+
+pub(crate) fn oom_kill_count_v2(memory_events: &str) -> u64 {
+    read_to_string(memory_events)
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// 旧内核的 memory.oom_control 没有 oom_kill 计数，此时返回 None
+fn oom_kill_count_v1(oom_control: &str) -> Option<u64> {
+    read_to_string(oom_control)
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn watch_oom_v1<F>(dir: &str, mut callback: F) -> Result<()>
+where
+    F: FnMut(&OomEvent) -> bool,
+{
+    let oom_control = File::open(format!("{}/memory.oom_control", dir))?;
+
+    let efd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+    if efd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let efd = unsafe { OwnedFd::from_raw_fd(efd) };
+    std::fs::write(
+        format!("{}/cgroup.event_control", dir),
+        format!("{} {}", efd.as_raw_fd(), oom_control.as_raw_fd()),
+    )?;
+    info!("开始监听 cgroup v1 OOM 事件: {}", dir);
+
+    let mut oom_kill = 0;
+    let mut buf = [0u8; 8];
+    loop {
+        let n = unsafe { libc::read(efd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, 8) };
+        if n != 8 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            warn!("读取 OOM eventfd 失败: {}", err);
+            break;
+        }
+
+        // cgroup 删除时 eventfd 同样会被触发
+        if !Path::new(dir).exists() {
+            break;
+        }
+
+        // 旧内核的 memory.oom_control 没有 oom_kill 计数，按通知次数累加
+        let count = oom_kill_count_v1(&format!("{}/memory.oom_control", dir))
+            .unwrap_or(oom_kill + u64::from_ne_bytes(buf));
+        oom_kill = count;
+        if !callback(&OomEvent { oom_kill }) {
+            return Ok(());
+        }
+    }
+
+    info!("停止监听 OOM 事件: {}", dir);
+    Ok(())
+}
@@ -0,0 +1,1227 @@
+pub mod blkio;
+pub mod controllers;
+pub mod events;
+pub mod rootless;
+pub mod stats;
+
+use lazy_static::lazy_static;
+use oci::{LinuxDeviceCgroup, LinuxDeviceType, LinuxResources, Spec};
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_to_string, remove_dir, write};
+use std::path::Path;
+use crate::errors::Result;
+use log::{debug, info, warn};
+
+/// 通过注解指定容器 cgroup 的父路径，create 的 --cgroup-parent 也记录在这里
+pub const ANNOTATION_CGROUP_PARENT: &str = "org.fire.cgroup.parent";
+
+/// 生成容器的 cgroup 路径
+///
+/// 容器ID直接用作 parent 下的单级目录名，不做替换，不同的容器不会共用同一个 cgroup；
+/// 包含 `/` 等字符或与 cgroup 接口文件冲突的ID会被拒绝。
+pub fn generate_cgroup_path(container_id: &str, cgroup_parent: Option<&str>) -> Result<String> {
+    let parent = cgroup_parent.unwrap_or("/fire");
+    if !is_valid_cgroup_name(container_id) || is_reserved_cgroup_name(container_id) {
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "容器ID {} 不能用作 cgroup 名称，只能包含字母、数字和 `-`、`_`、`.`，且不能以 `.` 开头",
+            container_id
+        )));
+    }
+    Ok(format!("{}/{}", parent, container_id))
+}
+
+fn is_valid_cgroup_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// cgroup v2 的接口文件以 `<控制器>.` 为前缀，同名目录无法创建
+fn is_reserved_cgroup_name(name: &str) -> bool {
+    const PREFIXES: [&str; 12] = [
+        "cgroup", "cpu", "cpuset", "memory", "io", "pids", "hugetlb", "rdma", "misc", "freezer",
+        "devices", "blkio",
+    ];
+    name.is_empty()
+        || name.starts_with('.')
+        || PREFIXES
+            .iter()
+            .any(|p| name.strip_prefix(p).is_some_and(|rest| rest.starts_with('.')))
+}
+
+/// 解析 cgroup 父路径
+///
+/// 以 .slice 结尾的名称按 systemd 的规则展开，如 `machine-web.slice`
+/// 对应 `/machine.slice/machine-web.slice`。
+pub fn parse_cgroup_parent(parent: &str) -> Result<String> {
+    let parent = parent.trim_end_matches('/');
+    if let Some(name) = parent.strip_suffix(".slice").filter(|_| !parent.contains('/')) {
+        if name.is_empty() || name.starts_with('-') || name.ends_with('-') || name.contains("--") {
+            return Err(crate::errors::FireError::InvalidSpec(format!(
+                "无效的 systemd slice: {}",
+                parent
+            )));
+        }
+        let mut path = String::new();
+        let mut prefix = String::new();
+        for part in name.split('-') {
+            if !prefix.is_empty() {
+                prefix.push('-');
+            }
+            prefix.push_str(part);
+            path.push_str(&format!("/{}.slice", prefix));
+        }
+        return Ok(path);
+    }
+
+    let path = if parent.is_empty() { "/" } else { parent };
+    validate_cgroup_path(path)?;
+    Ok(path.trim_end_matches('/').to_string())
+}
+
+/// 容器使用的 cgroup 路径，spec 未指定时按容器ID生成
+///
+/// 父路径取自 [`ANNOTATION_CGROUP_PARENT`]，未指定时非特权运行生成在
+/// 委派给当前用户的子树下，否则为 /fire。
+pub fn cgroup_path_for_spec(container_id: &str, spec: &Spec) -> Result<String> {
+    match spec.linux {
+        Some(ref linux) if !linux.cgroups_path.is_empty() => Ok(linux.cgroups_path.clone()),
+        _ if spec.annotations.contains_key(ANNOTATION_CGROUP_PARENT) => {
+            let parent = parse_cgroup_parent(&spec.annotations[ANNOTATION_CGROUP_PARENT])?;
+            generate_cgroup_path(container_id, Some(&parent))
+        }
+        _ if rootless::is_rootless() => {
+            generate_cgroup_path(container_id, rootless::delegated_parent().as_deref())
+        }
+        _ => generate_cgroup_path(container_id, None),
+    }
+}
+
+/// 确认 cgroup 目录未被仍有进程的其他容器使用
+pub fn ensure_cgroup_unused(cgroups_path: &str) -> Result<()> {
+    let pids = get_procs(cgroups_path)?;
+    if !pids.is_empty() {
+        return Err(crate::errors::FireError::Generic(format!(
+            "cgroup {} 已存在且仍有 {} 个进程，拒绝复用",
+            cgroups_path,
+            pids.len()
+        )));
+    }
+    Ok(())
+}
+
+/// 检查 cgroup 是否已挂载
+pub fn check_cgroup_mounted() -> Result<()> {
+    if let Some(reason) = rootless::skip_reason() {
+        warn!("非特权运行，跳过 cgroup 配置: {}", reason);
+        return Ok(());
+    }
+
+    let cgroup_root = "/sys/fs/cgroup";
+    if !std::path::Path::new(cgroup_root).exists() {
+        return Err(crate::errors::FireError::KernelUnsupported(
+            "cgroup 文件系统未挂载到 /sys/fs/cgroup".to_string()
+        ));
+    }
+    
+    // 检查是否为 cgroup v2
+    if std::path::Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        info!("检测到 cgroup v2");
+        return check_cgroup_v2();
+    }
+    
+    // 检查 cgroup v1 控制器
+    info!("检测到 cgroup v1");
+    return check_cgroup_v1();
+}
+
+/// 检查 cgroup v1 控制器
+///
+/// 只要求至少有一个控制器可用，缺少的控制器在配置时跳过。
+fn check_cgroup_v1() -> Result<()> {
+    let subsystems: Vec<&str> = CGROUPS.keys().copied().collect();
+    let skipped = controllers::skipped(1, &subsystems);
+    if skipped.len() == subsystems.len() {
+        return Err(crate::errors::FireError::Generic(
+            "没有可用的 cgroup v1 控制器".to_string()
+        ));
+    }
+    if !skipped.is_empty() {
+        warn!("cgroup v1 控制器不可用，将跳过: {:?}", skipped);
+    }
+    if let Some(mountpoint) = controllers::hybrid_mountpoint() {
+        info!(
+            "cgroup v1 与 v2 混合模式，{} 中的控制器: {:?}",
+            mountpoint,
+            controllers::hybrid_controllers()
+        );
+    }
+    Ok(())
+}
+
+/// cgroup v1 中容器在指定控制器下的目录，控制器不可用时返回 None
+fn v1_path(subsystem: &str, cgroups_path: &str) -> Option<String> {
+    controllers::v1_mountpoint(subsystem).map(|mountpoint| format!("{}{}", mountpoint, cgroups_path))
+}
+
+/// 混合模式下容器在 cgroup2 中的目录，其他情况返回 None
+fn hybrid_path(cgroups_path: &str) -> Option<String> {
+    controllers::hybrid_mountpoint().map(|mountpoint| format!("{}{}", mountpoint, cgroups_path))
+}
+
+/// cgroup2 中容器的目录，纯 v1 主机返回 None
+fn unified_path(cgroups_path: &str) -> Option<String> {
+    match detect_cgroup_version() {
+        Ok(2) => Some(format!("/sys/fs/cgroup{}", cgroups_path)),
+        _ => hybrid_path(cgroups_path),
+    }
+}
+
+/// 当前主机上不可用、配置时会被跳过的控制器
+pub fn skipped_controllers() -> Vec<String> {
+    let subsystems: Vec<&str> = CGROUPS.keys().copied().collect();
+    match detect_cgroup_version() {
+        Ok(version) => controllers::skipped(version, &subsystems),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// 检查 cgroup v2 控制器
+fn check_cgroup_v2() -> Result<()> {
+    let controllers_file = "/sys/fs/cgroup/cgroup.controllers";
+    if !std::path::Path::new(controllers_file).exists() {
+        return Err(crate::errors::FireError::Generic(
+            "cgroup v2 controllers 文件不存在".to_string()
+        ));
+    }
+    
+    let controllers_content = std::fs::read_to_string(controllers_file)
+        .map_err(|e| crate::errors::FireError::Generic(
+            format!("读取 cgroup v2 controllers 失败: {}", e)
+        ))?;
+    
+    let available_controllers: Vec<&str> = controllers_content.trim().split_whitespace().collect();
+    info!("可用的 cgroup v2 控制器: {:?}", available_controllers);
+    
+    let skipped = controllers::skipped(2, &[]);
+    if !skipped.is_empty() {
+        warn!("cgroup v2 控制器不可用，将跳过: {:?}", skipped);
+    }
+    
+    Ok(())
+}
+
+/// 检测 cgroup 版本
+pub fn detect_cgroup_version() -> Result<u8> {
+    crate::sysinfo::get().cgroup.version.ok_or_else(|| {
+        crate::errors::FireError::KernelUnsupported("无法检测 cgroup 版本".to_string())
+    })
+}
+
+/// 验证 cgroup 路径是否有效
+pub fn validate_cgroup_path(cgroups_path: &str) -> Result<()> {
+    if cgroups_path.is_empty() {
+        return Err(crate::errors::FireError::InvalidSpec(
+            "cgroup 路径不能为空".to_string()
+        ));
+    }
+    
+    if !cgroups_path.starts_with('/') {
+        return Err(crate::errors::FireError::InvalidSpec(
+            "cgroup 路径必须以 / 开头".to_string()
+        ));
+    }
+
+    if cgroups_path.split('/').any(|c| c == "..") {
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "cgroup 路径不能包含 ..: {}",
+            cgroups_path
+        )));
+    }
+    
+    Ok(())
+}
+
+lazy_static! {
+    static ref CGROUPS: HashMap<&'static str, Apply> = {
+        let mut result = HashMap::new();
+        result.insert("cpuset", cpuset_apply as Apply);
+        result.insert("cpu", cpu_apply as Apply);
+        result.insert("memory", memory_apply as Apply);
+        result.insert("devices", devices_apply as Apply);
+        result.insert("blkio", blkio_apply as Apply);
+        result.insert("pids", pids_apply as Apply);
+        result.insert("net_cls", net_cls_apply as Apply);
+        result.insert("net_prio", net_prio_apply as Apply);
+        result.insert("hugetlb", hugetlb_apply as Apply);
+        // 没有资源配置，加入后 pause 和 freeze_and_kill 才能冻结容器的进程
+        result.insert("freezer", null_apply as Apply);
+        result.insert("systemd", null_apply as Apply);
+        result
+    };
+}
+
+/// 创建容器的 cgroup 并完成加入进程前的准备 (支持 cgroup v1 和 v2)
+///
+/// v2 在父级启用需要的控制器；v1 的 cpuset 需要先从父级继承
+/// cpuset.cpus 和 cpuset.mems，否则进程无法加入。
+#[tracing::instrument(name = "cgroup_create", skip(resources))]
+pub fn create(resources: &Option<LinuxResources>, cgroups_path: &str) -> Result<()> {
+    match detect_cgroup_version()? {
+        1 => {
+            for subsystem in CGROUPS.keys() {
+                let path = match v1_path(subsystem, cgroups_path) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                create_dir_all(&path)?;
+                if *subsystem == "cpuset" {
+                    copy_parent(&path, "cpuset.cpus")?;
+                    copy_parent(&path, "cpuset.mems")?;
+                }
+            }
+            // 混合模式下进程同时加入 cgroup2，没有绑定到 v1 的控制器在这里配置
+            if let (Some(mountpoint), Some(path)) = (controllers::hybrid_mountpoint(), hybrid_path(cgroups_path)) {
+                create_dir_all(&path)?;
+                if let Some(ref res) = resources {
+                    enable_cgroup_v2_controllers(Path::new(mountpoint), &path, res)?;
+                }
+            }
+        }
+        _ => {
+            let cgroup_dir = format!("/sys/fs/cgroup{}", cgroups_path);
+            create_dir_all(&cgroup_dir).map_err(|e| {
+                let message = format!("创建 cgroup v2 目录失败: {}", e);
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    crate::errors::FireError::PermissionDenied(message)
+                } else {
+                    crate::errors::FireError::Generic(message)
+                }
+            })?;
+            if let Some(ref res) = resources {
+                let parent = Path::new(&cgroup_dir).parent().unwrap_or_else(|| Path::new("/sys/fs/cgroup"));
+                enable_cgroup_v2_controllers(parent, &cgroup_dir, res)?;
+            }
+        }
+    }
+    info!("创建 cgroup: {}", cgroups_path);
+    Ok(())
+}
+
+/// 将进程加入容器的 cgroup
+#[tracing::instrument(name = "cgroup_join")]
+pub fn join(cgroups_path: &str, pid: i32) -> Result<()> {
+    match detect_cgroup_version()? {
+        1 => {
+            for subsystem in CGROUPS.keys() {
+                if let Some(path) = v1_path(subsystem, cgroups_path) {
+                    write_file(&path, "cgroup.procs", &pid.to_string())?;
+                    debug!("进程 {} 已添加到 {} cgroup", pid, subsystem);
+                }
+            }
+            // 之前创建的容器在 cgroup2 中没有目录
+            if let Some(path) = hybrid_path(cgroups_path).filter(|path| Path::new(path).exists()) {
+                write_file(&path, "cgroup.procs", &pid.to_string())?;
+                debug!("进程 {} 已添加到 cgroup2", pid);
+            }
+        }
+        _ => {
+            let cgroup_dir = format!("/sys/fs/cgroup{}", cgroups_path);
+            write_file(&cgroup_dir, "cgroup.procs", &pid.to_string()).map_err(|e| {
+                crate::errors::FireError::Generic(format!("添加进程到 cgroup v2 失败: {}", e))
+            })?;
+        }
+    }
+    info!("进程 {} 已加入 cgroup: {}", pid, cgroups_path);
+    Ok(())
+}
+
+/// 应用资源限制 (支持 cgroup v1 和 v2)
+#[tracing::instrument(name = "cgroup_apply", skip(resources))]
+pub fn apply(resources: &Option<LinuxResources>, cgroups_path: &str) -> Result<()> {
+    let res = match resources {
+        Some(res) => res,
+        None => return Ok(()),
+    };
+
+    match detect_cgroup_version()? {
+        1 => apply_v1(res, cgroups_path),
+        _ => apply_v2(res, cgroups_path),
+    }
+}
+
+/// cgroup v1 应用逻辑
+fn apply_v1(res: &LinuxResources, cgroups_path: &str) -> Result<()> {
+    info!("应用 cgroup v1 资源限制, 路径: {}", cgroups_path);
+
+    // 混合模式下 v2 专有的配置写入 cgroup2，对应的控制器需要由 cgroup2 管理
+    let unified = controllers::hybrid_controllers();
+    let in_unified = |controller: &str| unified.iter().any(|c| c == controller);
+    let unified_keys = res.unified.keys().filter_map(|key| key.split_once('.').map(|(c, _)| c));
+    if let Some(controller) = unified_keys.filter(|c| *c != "cgroup").find(|c| !in_unified(c)) {
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "linux.resources.unified 仅支持 cgroup v2，{} 控制器不在 cgroup2 中",
+            controller
+        )));
+    }
+    if !res.misc.is_empty() && !in_unified("misc") {
+        return Err(crate::errors::FireError::InvalidSpec(
+            "misc 控制器仅支持 cgroup v2".to_string()
+        ));
+    }
+
+    let mut routed = Vec::new();
+    for (subsystem, apply_fn) in CGROUPS.iter() {
+        match controllers::hierarchy(subsystem) {
+            Some(controllers::Hierarchy::V1(mountpoint)) => {
+                apply_fn(res, &format!("{}{}", mountpoint, cgroups_path))?
+            }
+            Some(controllers::Hierarchy::Unified(_)) => routed.push(*subsystem),
+            None => debug!("cgroup v1 控制器 {} 不可用，跳过", subsystem),
+        }
+    }
+
+    let Some(dir) = hybrid_path(cgroups_path) else {
+        return Ok(());
+    };
+    if routed.is_empty() && res.misc.is_empty() && res.unified.is_empty() {
+        return Ok(());
+    }
+    info!("由 cgroup2 管理的控制器 {:?}，路径: {}", routed, dir);
+    let v2_owns = |controller: &str| match controller {
+        "io" => routed.contains(&"blkio"),
+        "misc" => true,
+        c => routed.contains(&c),
+    };
+    apply_cgroup_v2_resources(res, &dir, v2_owns)?;
+    apply_cgroup_v2_unified(&res.unified, &dir)
+}
+
+/// cgroup v2 应用逻辑
+fn apply_v2(res: &LinuxResources, cgroups_path: &str) -> Result<()> {
+    info!("应用 cgroup v2 资源限制, 路径: {}", cgroups_path);
+
+    let cgroup_dir = format!("/sys/fs/cgroup{}", cgroups_path);
+
+    // 应用资源限制
+    apply_cgroup_v2_resources(res, &cgroup_dir, |_| true)?;
+
+    // 未建模的参数直接写入，覆盖结构化配置
+    apply_cgroup_v2_unified(&res.unified, &cgroup_dir)
+}
+
+/// 启用 cgroup v2 控制器
+///
+/// 从 top 开始逐级在 cgroup.subtree_control 中启用，直到容器目录的父目录。
+fn enable_cgroup_v2_controllers(top: &Path, cgroup_dir: &str, resources: &LinuxResources) -> Result<()> {
+    let mut dirs: Vec<&Path> = Path::new(cgroup_dir)
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(top))
+        .collect();
+    dirs.reverse();
+    for dir in dirs {
+        enable_subtree_controllers(dir, resources)?;
+    }
+    Ok(())
+}
+
+/// 在 parent_dir 的 cgroup.subtree_control 中启用资源需要的控制器
+fn enable_subtree_controllers(parent_dir: &Path, resources: &LinuxResources) -> Result<()> {
+    let controllers_file = parent_dir.join("cgroup.controllers");
+    if !controllers_file.exists() {
+        return Ok(()); // 根目录，无需启用
+    }
+    
+    let available_controllers = std::fs::read_to_string(&controllers_file)
+        .map_err(|e| crate::errors::FireError::Generic(
+            format!("读取可用控制器失败: {}", e)
+        ))?;
+    
+    let subtree_control_file = parent_dir.join("cgroup.subtree_control");
+    let mut controllers_to_enable = vec!["cpu", "memory", "pids", "hugetlb"];
+    if !resources.misc.is_empty() {
+        controllers_to_enable.push("misc");
+    }
+    if resources.block_io.is_some() {
+        controllers_to_enable.push("io");
+    }
+    // unified 中的参数以控制器名为前缀，如 io.latency
+    for key in resources.unified.keys() {
+        if let Some((controller, _)) = key.split_once('.') {
+            if controller != "cgroup" && !controllers_to_enable.contains(&controller) {
+                controllers_to_enable.push(controller);
+            }
+        }
+    }
+    
+    for controller in &controllers_to_enable {
+        if available_controllers.contains(controller) {
+            let enable_cmd = format!("+{}", controller);
+            if let Err(e) = std::fs::write(&subtree_control_file, &enable_cmd) {
+                warn!("启用控制器 {} 失败: {}", controller, e);
+            } else {
+                info!("已启用 cgroup v2 控制器: {}", controller);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// cgroup v2 使用 cpu.weight 替代 cpu.shares
+///
+/// 转换公式: weight = 1 + ((shares - 2) * 9999) / 262142，shares 先截断到
+/// [2, 262144]；shares 为 0 表示未设置，返回 None 保留内核默认值。
+fn shares_to_weight(shares: u64) -> Option<u64> {
+    if shares == 0 {
+        return None;
+    }
+    let shares = shares.clamp(2, 262144);
+    Some(1 + ((shares - 2) * 9999) / 262142)
+}
+
+fn validate_cpu_idle(idle: i64) -> Result<i64> {
+    if idle != 0 && idle != 1 {
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "cpu.idle 只能为 0 或 1: {}",
+            idle
+        )));
+    }
+    Ok(idle)
+}
+
+/// 应用 cgroup v2 资源限制，只配置 owns 返回 true 的控制器
+fn apply_cgroup_v2_resources(
+    resources: &LinuxResources,
+    cgroup_dir: &str,
+    owns: impl Fn(&str) -> bool,
+) -> Result<()> {
+    // CPU 限制
+    if let Some(cpu) = resources.cpu.as_ref().filter(|_| owns("cpu")) {
+        if let Some(weight) = cpu.shares.and_then(shares_to_weight) {
+            write_file(cgroup_dir, "cpu.weight", &weight.to_string())?;
+        }
+
+        if let Some(idle) = cpu.idle {
+            write_file(cgroup_dir, "cpu.idle", &validate_cpu_idle(idle)?.to_string())?;
+        }
+        
+        if let Some(quota) = cpu.quota {
+            if let Some(period) = cpu.period {
+                if quota > 0 {
+                    let cpu_max = format!("{} {}", quota, period);
+                    write_file(cgroup_dir, "cpu.max", &cpu_max)?;
+                }
+            }
+        }
+
+        // 需要在 cpu.max 之后写入，burst 不能超过配额
+        if let Some(burst) = cpu.burst {
+            write_file(cgroup_dir, "cpu.max.burst", &burst.to_string())?;
+        }
+    }
+
+    // 块设备 IO 限制
+    if let Some(block_io) = resources.block_io.as_ref().filter(|_| owns("io")) {
+        blkio::validate(block_io)?;
+        blkio::apply_v2(block_io, cgroup_dir)?;
+    }
+    
+    // 内存限制
+    if let Some(memory) = resources.memory.as_ref().filter(|_| owns("memory")) {
+        if let Some(limit) = memory.limit {
+            if limit > 0 {
+                write_file(cgroup_dir, "memory.max", &limit.to_string())?;
+            }
+        }
+        
+        if let Some(reservation) = memory.reservation {
+            if reservation > 0 {
+                write_file(cgroup_dir, "memory.low", &reservation.to_string())?;
+            }
+        }
+
+        if let Some(swap) = memory.swap {
+            if let Some(swap_max) = swap_max_v2(swap, memory.limit)? {
+                write_file(cgroup_dir, "memory.swap.max", &swap_max)?;
+            }
+        }
+    }
+    
+    // 进程数限制
+    if let Some(pids) = resources.pids.as_ref().filter(|_| owns("pids")) {
+        if pids.limit > 0 {
+            write_file(cgroup_dir, "pids.max", &pids.limit.to_string())?;
+        }
+    }
+
+    // misc 控制器（SGX EPC、SEV ASID 等）
+    if !resources.misc.is_empty() && owns("misc") {
+        let capacity =
+            read_to_string(format!("{}/misc.capacity", controllers::unified_mountpoint())).unwrap_or_default();
+        for (name, limit) in &resources.misc {
+            if !capacity.lines().any(|l| l.split_whitespace().next() == Some(name.as_str())) {
+                return Err(crate::errors::FireError::InvalidSpec(format!(
+                    "宿主机不支持 misc 资源: {}",
+                    name
+                )));
+            }
+            let value = if *limit < 0 { "max".to_string() } else { limit.to_string() };
+            write_misc_max(cgroup_dir, &format!("{} {}", name, value))?;
+        }
+    }
+
+    // 大页限制，较新的内核同时提供预留（rsvd）限制
+    for limit in resources.hugepage_limits.iter().filter(|_| owns("hugetlb")) {
+        let page_size = normalize_hugepage_size(&limit.page_size)?;
+        write_file(cgroup_dir, &format!("hugetlb.{}.max", page_size), &limit.limit.to_string())?;
+        let rsvd = format!("hugetlb.{}.rsvd.max", page_size);
+        if std::path::Path::new(cgroup_dir).join(&rsvd).exists() {
+            write_file(cgroup_dir, &rsvd, &limit.limit.to_string())?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// 将 OCI 的 memory.swap 转换为 memory.swap.max
+///
+/// OCI 沿用 v1 的语义，swap 表示内存与交换分区的总和；v2 的 memory.swap.max
+/// 只限制交换分区，因此需要减去内存限制。返回 None 表示无需设置。
+fn swap_max_v2(swap: i64, limit: Option<i64>) -> Result<Option<String>> {
+    match (swap, limit) {
+        (0, _) => Ok(None),
+        (-1, _) => Ok(Some("max".to_string())),
+        (_, Some(-1)) => Err(crate::errors::FireError::InvalidSpec(
+            "内存不受限制时不能限制 swap".to_string()
+        )),
+        (_, None) | (_, Some(0)) => Err(crate::errors::FireError::InvalidSpec(
+            "设置 swap 限制时必须同时设置内存限制".to_string()
+        )),
+        (swap, Some(limit)) if swap < limit => Err(crate::errors::FireError::InvalidSpec(format!(
+            "memory+swap 限制 {} 不能小于内存限制 {}",
+            swap, limit
+        ))),
+        (swap, Some(limit)) => Ok(Some((swap - limit).to_string())),
+    }
+}
+
+/// 将 linux.resources.unified 中的键值写入 cgroup v2 目录
+fn apply_cgroup_v2_unified(unified: &HashMap<String, String>, cgroup_dir: &str) -> Result<()> {
+    for (key, value) in unified {
+        // 键必须是 cgroup 目录下的接口文件名
+        if key.contains('/') || key.starts_with('.') || !key.contains('.') {
+            return Err(crate::errors::FireError::InvalidSpec(format!(
+                "无效的 unified cgroup 参数: {}",
+                key
+            )));
+        }
+        let result = if key == "misc.max" {
+            // misc.max 每次写入只接受一个资源
+            value
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .try_for_each(|l| write_misc_max(cgroup_dir, l.trim()))
+        } else {
+            write_file(cgroup_dir, key, value)
+        };
+        result.map_err(|e| {
+            crate::errors::FireError::Generic(format!("写入 cgroup 参数 {}={} 失败: {}", key, value, e))
+        })?;
+        info!("已设置 cgroup v2 参数 {}={}", key, value);
+    }
+    Ok(())
+}
+
+fn write_misc_max(cgroup_dir: &str, entry: &str) -> Result<()> {
+    write_file(cgroup_dir, "misc.max", entry)?;
+    info!("已设置 misc 限制: {}", entry);
+    Ok(())
+}
+
+/// 将大页大小规范化为 cgroup 文件名使用的格式（如 2MB、1GB、64KB）
+pub fn normalize_hugepage_size(page_size: &str) -> Result<String> {
+    let invalid = || crate::errors::FireError::InvalidSpec(format!("无效的大页大小: {}", page_size));
+
+    let trimmed = page_size.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    let bytes = number.checked_mul(multiplier).ok_or_else(invalid)?;
+
+    match bytes {
+        0 => Err(invalid()),
+        b if b % (1 << 30) == 0 => Ok(format!("{}GB", b >> 30)),
+        b if b % (1 << 20) == 0 => Ok(format!("{}MB", b >> 20)),
+        b if b % (1 << 10) == 0 => Ok(format!("{}KB", b >> 10)),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn init() {
+    lazy_static::initialize(&CGROUPS);
+}
+
+pub fn freeze(cgroups_path: &str) -> Result<()> {
+    let cgroup_version = detect_cgroup_version()?;
+    
+    match cgroup_version {
+        1 => freeze_v1(cgroups_path),
+        2 => freeze_v2(cgroups_path),
+        _ => Err(crate::errors::FireError::KernelUnsupported(
+            format!("不支持的 cgroup 版本: {}", cgroup_version)
+        ))
+    }
+}
+
+fn freeze_v1(cgroups_path: &str) -> Result<()> {
+    write_file(&freezer_path(cgroups_path)?, "freezer.state", "FROZEN")
+}
+
+/// cgroup v1 中容器的 freezer 目录，由 create 创建，不存在时冻结不到容器的进程
+fn freezer_path(cgroups_path: &str) -> Result<String> {
+    let path = v1_path("freezer", cgroups_path).ok_or_else(|| {
+        crate::errors::FireError::KernelUnsupported("cgroup v1 freezer 控制器未挂载".to_string())
+    })?;
+    if !Path::new(&path).exists() {
+        return Err(crate::errors::FireError::NotFound(format!("freezer cgroup 不存在: {}", path)));
+    }
+    Ok(path)
+}
+
+fn freeze_v2(cgroups_path: &str) -> Result<()> {
+    let cgroup_dir = format!("/sys/fs/cgroup{}", cgroups_path);
+    
+    // cgroup v2 使用 cgroup.freeze 文件
+    write_file(&cgroup_dir, "cgroup.freeze", "1")
+}
+
+pub fn thaw(cgroups_path: &str) -> Result<()> {
+    match detect_cgroup_version()? {
+        1 => write_file(&freezer_path(cgroups_path)?, "freezer.state", "THAWED"),
+        _ => write_file(&format!("/sys/fs/cgroup{}", cgroups_path), "cgroup.freeze", "0"),
+    }
+}
+
+/// 向 cgroup 内的所有进程发送信号
+///
+/// cgroup v2 和混合模式上的 SIGKILL 通过 cgroup.kill 由内核一次性完成，不会遗漏新 fork
+/// 的进程；其他情况先冻结 cgroup 再逐个发送信号。
+pub fn kill_all(cgroups_path: &str, signal: i32) -> Result<()> {
+    // 混合模式下进程同时在 cgroup2 中，同样可以使用 cgroup.kill
+    let cgroup_kill = unified_path(cgroups_path).filter(|dir| Path::new(dir).join("cgroup.kill").exists());
+    if let Some(cgroup_dir) = cgroup_kill.filter(|_| signal == libc::SIGKILL) {
+        write_file(&cgroup_dir, "cgroup.kill", "1")?;
+        info!("已通过 cgroup.kill 终止 {} 中的所有进程", cgroups_path);
+        return Ok(());
+    }
+
+    // SIGKILL 需要确认进程全部退出，冻结期间仍可能有进程正在 fork
+    let rounds = if signal == libc::SIGKILL { 10 } else { 1 };
+    for _ in 0..rounds {
+        if get_procs(cgroups_path)?.is_empty() {
+            break;
+        }
+        freeze_and_kill(cgroups_path, signal)?;
+        if signal == libc::SIGKILL {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+    Ok(())
+}
+
+/// 冻结 cgroup，向其中所有进程发送信号后再解冻
+///
+/// 冻结期间进程无法 fork，遍历得到的进程列表是完整的，可以对付 fork 炸弹。
+pub fn freeze_and_kill(cgroups_path: &str, signal: i32) -> Result<()> {
+    let frozen = match freeze(cgroups_path) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("冻结 cgroup {} 失败，直接发送信号: {}", cgroups_path, e);
+            false
+        }
+    };
+
+    let result = get_procs(cgroups_path).and_then(|pids| {
+        crate::signals::kill_all_children(&pids, signal)?;
+        info!("已向 {} 中的 {} 个进程发送信号 {}", cgroups_path, pids.len(), signal);
+        Ok(())
+    });
+
+    if frozen {
+        thaw(cgroups_path)?;
+    }
+    result
+}
+
+pub fn remove(cgroups_path: &str) -> Result<()> {
+    let cgroup_version = detect_cgroup_version()?;
+    
+    match cgroup_version {
+        1 => remove_v1(cgroups_path),
+        2 => remove_v2(cgroups_path),
+        _ => Err(crate::errors::FireError::KernelUnsupported(
+            format!("不支持的 cgroup 版本: {}", cgroup_version)
+        ))
+    }
+}
+
+fn remove_v1(cgroups_path: &str) -> Result<()> {
+    for subsystem in CGROUPS.keys() {
+        let path = match v1_path(subsystem, cgroups_path) {
+            Some(path) => path,
+            None => continue,
+        };
+        if std::path::Path::new(&path).exists() {
+            match remove_dir(&path) {
+                Ok(_) => info!("已删除 {} cgroup: {}", subsystem, path),
+                Err(e) => warn!("删除 {} cgroup 失败: {}", subsystem, e),
+            }
+        }
+    }
+    if let Some(path) = hybrid_path(cgroups_path).filter(|path| Path::new(path).exists()) {
+        match remove_dir(&path) {
+            Ok(_) => info!("已删除 cgroup2 中的目录: {}", path),
+            Err(e) => warn!("删除 cgroup2 中的目录失败: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn remove_v2(cgroups_path: &str) -> Result<()> {
+    let cgroup_dir = format!("/sys/fs/cgroup{}", cgroups_path);
+    
+    if std::path::Path::new(&cgroup_dir).exists() {
+        match remove_dir(&cgroup_dir) {
+            Ok(_) => info!("已删除 cgroup v2: {}", cgroup_dir),
+            Err(e) => warn!("删除 cgroup v2 失败: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// 容器 cgroup 中的进程
+///
+/// 从容器实际加入的层级中读取：cgroup v2 为统一层级，v1 为任意一个已挂载的控制器，混合模式下
+/// 都没有时使用 cgroup2。cgroup 已删除时返回空列表，没有可用的层级时返回错误。
+pub fn get_procs(cgroups_path: &str) -> Result<Vec<i32>> {
+    let cgroup_dir = match detect_cgroup_version()? {
+        1 => {
+            let mut subsystems: Vec<&str> = CGROUPS.keys().copied().collect();
+            subsystems.sort();
+            subsystems
+                .iter()
+                .find_map(|subsystem| v1_path(subsystem, cgroups_path))
+                .or_else(|| hybrid_path(cgroups_path))
+        }
+        _ => unified_path(cgroups_path),
+    }
+    .ok_or_else(|| {
+        crate::errors::FireError::KernelUnsupported(format!(
+            "没有可以读取 {} 进程列表的 cgroup 层级",
+            cgroups_path
+        ))
+    })?;
+
+    match read_to_string(format!("{}/cgroup.procs", cgroup_dir)) {
+        Ok(content) => Ok(content
+            .lines()
+            .filter_map(|line| line.trim().parse::<i32>().ok())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn write_file(dir: &str, file: &str, data: &str) -> Result<()> {
+    let path = format!("{}/{}", dir, file);
+    write(&path, data)?;
+    Ok(())
+}
+
+pub fn read_file(dir: &str, file: &str) -> Result<String> {
+    let path = format!("{}/{}", dir, file);
+    Ok(read_to_string(&path)?)
+}
+
+type Apply = fn(&LinuxResources, &str) -> Result<()>;
+
+fn copy_parent(dir: &str, file: &str) -> Result<()> {
+    let parent = if let Some(o) = dir.rfind('/') {
+        &dir[..o]
+    } else {
+        return Err(crate::errors::FireError::Generic(format!(
+            "failed to find {} in parent cgroups",
+            file
+        )));
+    };
+
+    let parent_data = read_file(parent, file)?;
+    write_file(dir, file, &parent_data)?;
+    Ok(())
+}
+
+fn null_apply(_: &LinuxResources, _: &str) -> Result<()> {
+    Ok(())
+}
+
+fn cpuset_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    copy_parent(dir, "cpuset.cpus")?;
+    copy_parent(dir, "cpuset.mems")?;
+    if let Some(ref cpu) = r.cpu {
+        if !cpu.cpus.is_empty() {
+            write_file(dir, "cpuset.cpus", &cpu.cpus)?;
+        }
+        if !cpu.mems.is_empty() {
+            write_file(dir, "cpuset.mems", &cpu.mems)?;
+        }
+    }
+    Ok(())
+}
+
+fn cpu_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    if let Some(ref cpu) = r.cpu {
+        // v1 没有 cpu.idle，使用最小的 cpu.shares 近似
+        if cpu.idle.map(validate_cpu_idle).transpose()? == Some(1) {
+            if cpu.shares.is_some() {
+                warn!("cpu.idle 已设置，忽略 cpu.shares");
+            }
+            write_file(dir, "cpu.shares", "2")?;
+        } else if let Some(shares) = cpu.shares {
+            write_file(dir, "cpu.shares", &shares.to_string())?;
+        }
+        if let Some(quota) = cpu.quota {
+            write_file(dir, "cpu.cfs_quota_us", &quota.to_string())?;
+        }
+        if let Some(period) = cpu.period {
+            write_file(dir, "cpu.cfs_period_us", &period.to_string())?;
+        }
+        if let Some(burst) = cpu.burst {
+            write_file(dir, "cpu.cfs_burst_us", &burst.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn memory_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    if let Some(ref memory) = r.memory {
+        if let Some(limit) = memory.limit {
+            write_file(dir, "memory.limit_in_bytes", &limit.to_string())?;
+        }
+        if let Some(reservation) = memory.reservation {
+            write_file(dir, "memory.soft_limit_in_bytes", &reservation.to_string())?;
+        }
+        if let Some(swap) = memory.swap {
+            write_file(dir, "memory.memsw.limit_in_bytes", &swap.to_string())?;
+        }
+        if let Some(kernel) = memory.kernel {
+            write_file(dir, "memory.kmem.limit_in_bytes", &kernel.to_string())?;
+        }
+        if let Some(kernel_tcp) = memory.kernel_tcp {
+            write_file(
+                dir,
+                "memory.kmem.tcp.limit_in_bytes",
+                &kernel_tcp.to_string(),
+            )?;
+        }
+        if let Some(swappiness) = memory.swappiness {
+            write_file(dir, "memory.swappiness", &swappiness.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn blkio_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    if let Some(ref blkio) = r.block_io {
+        blkio::validate(blkio)?;
+        if let Some(weight) = blkio.weight {
+            write_file(dir, "blkio.weight", &weight.to_string())?;
+        }
+        if let Some(leaf_weight) = blkio.leaf_weight {
+            write_file(dir, "blkio.leaf_weight", &leaf_weight.to_string())?;
+        }
+        for device in &blkio.weight_device {
+            let data = format!(
+                "{}:{} {}",
+                device.major,
+                device.minor,
+                device.weight.unwrap_or(0)
+            );
+            write_file(dir, "blkio.weight_device", &data)?;
+        }
+        for device in &blkio.throttle_read_bps_device {
+            let data = format!("{}:{} {}", device.major, device.minor, device.rate);
+            write_file(dir, "blkio.throttle.read_bps_device", &data)?;
+        }
+        for device in &blkio.throttle_write_bps_device {
+            let data = format!("{}:{} {}", device.major, device.minor, device.rate);
+            write_file(dir, "blkio.throttle.write_bps_device", &data)?;
+        }
+        for device in &blkio.throttle_read_iops_device {
+            let data = format!("{}:{} {}", device.major, device.minor, device.rate);
+            write_file(dir, "blkio.throttle.read_iops_device", &data)?;
+        }
+        for device in &blkio.throttle_write_iops_device {
+            let data = format!("{}:{} {}", device.major, device.minor, device.rate);
+            write_file(dir, "blkio.throttle.write_iops_device", &data)?;
+        }
+    }
+    Ok(())
+}
+
+fn pids_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    if let Some(ref pids) = r.pids {
+        if pids.limit > 0 {
+            write_file(dir, "pids.max", &pids.limit.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn net_cls_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    if let Some(ref network) = r.network {
+        if let Some(class_id) = network.class_id {
+            write_file(dir, "net_cls.classid", &class_id.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn net_prio_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    if let Some(ref network) = r.network {
+        for priority in &network.priorities {
+            let data = format!("{} {}", priority.name, priority.priority);
+            write_file(dir, "net_prio.ifpriomap", &data)?;
+        }
+    }
+    Ok(())
+}
+
+fn hugetlb_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    for limit in &r.hugepage_limits {
+        let file = format!(
+            "hugetlb.{}.limit_in_bytes",
+            normalize_hugepage_size(&limit.page_size)?
+        );
+        write_file(dir, &file, &limit.limit.to_string())?;
+    }
+    Ok(())
+}
+
+fn write_device(d: &LinuxDeviceCgroup, dir: &str) -> Result<()> {
+    let typ = match d.typ {
+        LinuxDeviceType::b => "b",
+        LinuxDeviceType::c => "c",
+        LinuxDeviceType::a => "a",
+        LinuxDeviceType::u => "c", // 'u' 也是字符设备
+        LinuxDeviceType::p => {
+            let msg = format!("invalid device type: {:?}", d.typ);
+            return Err(crate::errors::FireError::InvalidSpec(msg));
+        }
+    };
+
+    let major = d
+        .major
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| "*".to_string());
+    let minor = d
+        .minor
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| "*".to_string());
+    let access = &d.access;
+
+    let data = format!("{} {}:{} {}", typ, major, minor, access);
+    let file = if d.allow { "devices.allow" } else { "devices.deny" };
+    write_file(dir, file, &data)?;
+    Ok(())
+}
+
+/// 容器始终可以访问的设备，与 OCI 运行时的默认规则一致
+fn default_allowed_devices() -> Vec<LinuxDeviceCgroup> {
+    let rule = |major: i64, minor: Option<i64>| LinuxDeviceCgroup {
+        allow: true,
+        typ: LinuxDeviceType::c,
+        major: Some(major),
+        minor,
+        access: "rwm".to_string(),
+    };
+    vec![
+        rule(1, Some(3)),  // /dev/null
+        rule(1, Some(5)),  // /dev/zero
+        rule(1, Some(7)),  // /dev/full
+        rule(1, Some(8)),  // /dev/random
+        rule(1, Some(9)),  // /dev/urandom
+        rule(5, Some(0)),  // /dev/tty
+        rule(5, Some(1)),  // /dev/console
+        rule(5, Some(2)),  // /dev/ptmx
+        rule(136, None),   // /dev/pts/*
+    ]
+}
+
+fn devices_apply(r: &LinuxResources, dir: &str) -> Result<()> {
+    write_file(dir, "devices.deny", "a")?;
+
+    for device in &device_rules(r) {
+        write_device(device, dir)?;
+    }
+    Ok(())
+}
+
+/// 按写入顺序排列的设备规则
+///
+/// 与 runc 一致，先放行默认设备，再按顺序应用 spec 中的规则，spec 可以拒绝默认设备。
+/// 写入规则前已经拒绝了全部设备，spec 开头拒绝全部设备的规则（常见配置的第一条）
+/// 不再写入，否则会清除刚放行的默认设备。
+fn device_rules(r: &LinuxResources) -> Vec<LinuxDeviceCgroup> {
+    let is_deny_all = |d: &LinuxDeviceCgroup| {
+        !d.allow
+            && matches!(d.typ, LinuxDeviceType::a)
+            && d.major.is_none()
+            && d.minor.is_none()
+            && ['r', 'w', 'm'].iter().all(|c| d.access.contains(*c))
+    };
+    let mut rules = default_allowed_devices();
+    rules.extend(r.devices.iter().skip_while(|d| is_deny_all(d)).cloned());
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_cgroup_path() {
+        assert_eq!(generate_cgroup_path("web-1", None).unwrap(), "/fire/web-1");
+        assert!(generate_cgroup_path("a/../etc", None).is_err());
+        assert!(generate_cgroup_path("web 1", None).is_err());
+        assert!(generate_cgroup_path("../etc", None).is_err());
+        assert!(generate_cgroup_path("..", None).is_err());
+        assert!(generate_cgroup_path("memory.max", None).is_err());
+        assert!(generate_cgroup_path("cgroup.procs", Some("/custom")).is_err());
+        assert!(validate_cgroup_path("/fire/../escape").is_err());
+    }
+
+    #[test]
+    fn test_cgroup_path_distinct() {
+        // 替换字符后会得到相同名称的ID
+        let ids = ["a_b", "a/b", "a b", "a:b", "a_b_", "a/b/", "a\u{e9}b"];
+        let paths: Vec<String> = ids.iter().filter_map(|id| generate_cgroup_path(id, None).ok()).collect();
+        let unique: std::collections::HashSet<&String> = paths.iter().collect();
+        assert_eq!(unique.len(), paths.len());
+        assert_eq!(paths, ["/fire/a_b", "/fire/a_b_"]);
+    }
+
+    #[test]
+    fn test_device_rules() {
+        let resources: LinuxResources = serde_json::from_str(
+            r#"{"devices":[
+                {"allow":false,"access":"rwm"},
+                {"allow":true,"type":"c","major":10,"minor":200,"access":"rwm"},
+                {"allow":false,"type":"c","major":1,"minor":3,"access":"rwm"}
+            ]}"#,
+        )
+        .unwrap();
+        let rules = device_rules(&resources);
+        // 开头的全部拒绝不会清除默认设备
+        assert!(!rules.iter().any(|d| !d.allow && matches!(d.typ, LinuxDeviceType::a)));
+        assert!(rules.iter().any(|d| d.allow && d.major == Some(1) && d.minor == Some(5)));
+        // spec 中对默认设备 /dev/null 的拒绝在放行之后写入
+        let null = rules.iter().rev().find(|d| d.major == Some(1) && d.minor == Some(3)).unwrap();
+        assert!(!null.allow);
+    }
+
+    #[test]
+    fn test_parse_cgroup_parent() {
+        assert_eq!(parse_cgroup_parent("/kubepods/burstable/").unwrap(), "/kubepods/burstable");
+        assert_eq!(parse_cgroup_parent("machine.slice").unwrap(), "/machine.slice");
+        assert_eq!(
+            parse_cgroup_parent("machine-web.slice").unwrap(),
+            "/machine.slice/machine-web.slice"
+        );
+        assert_eq!(parse_cgroup_parent("/").unwrap(), "");
+        assert!(parse_cgroup_parent("machine--web.slice").is_err());
+        assert!(parse_cgroup_parent("relative/path").is_err());
+    }
+
+    #[test]
+    fn test_shares_to_weight() {
+        assert_eq!(shares_to_weight(0), None);
+        assert_eq!(shares_to_weight(1), Some(1));
+        assert_eq!(shares_to_weight(2), Some(1));
+        assert_eq!(shares_to_weight(1024), Some(39));
+        assert_eq!(shares_to_weight(262144), Some(10000));
+        assert_eq!(shares_to_weight(1 << 20), Some(10000));
+        assert!(validate_cpu_idle(2).is_err());
+    }
+
+    #[test]
+    fn test_swap_max_v2() {
+        assert_eq!(swap_max_v2(0, Some(1024)).unwrap(), None);
+        assert_eq!(swap_max_v2(-1, Some(1024)).unwrap(), Some("max".to_string()));
+        assert_eq!(swap_max_v2(3072, Some(1024)).unwrap(), Some("2048".to_string()));
+        assert_eq!(swap_max_v2(1024, Some(1024)).unwrap(), Some("0".to_string()));
+        assert!(swap_max_v2(512, Some(1024)).is_err());
+        assert!(swap_max_v2(1024, None).is_err());
+        assert!(swap_max_v2(1024, Some(-1)).is_err());
+    }
+
+    #[test]
+    fn test_normalize_hugepage_size() {
+        assert_eq!(normalize_hugepage_size("2MB").unwrap(), "2MB");
+        assert_eq!(normalize_hugepage_size("2048kB").unwrap(), "2MB");
+        assert_eq!(normalize_hugepage_size("1GiB").unwrap(), "1GB");
+        assert_eq!(normalize_hugepage_size("64k").unwrap(), "64KB");
+        assert!(normalize_hugepage_size("2XB").is_err());
+        assert!(normalize_hugepage_size("0MB").is_err());
+    }
+
+    #[test]
+    fn test_join_v1_freezer() {
+        // 需要 root 和挂载了 freezer 的 cgroup v1
+        if !nix::unistd::geteuid().is_root() || detect_cgroup_version().ok() != Some(1) {
+            return;
+        }
+        let Some(freezer) = controllers::v1_mountpoint("freezer") else {
+            return;
+        };
+        let path = format!("/fire-test-freezer-{}", std::process::id());
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id() as i32;
+        create(&None, &path).unwrap();
+        let joined = join(&path, pid);
+        let procs = read_to_string(format!("{}{}/cgroup.procs", freezer, path)).unwrap_or_default();
+        let _ = child.kill();
+        let _ = child.wait();
+        remove(&path).unwrap();
+
+        joined.unwrap();
+        assert!(procs.lines().any(|line| line.trim() == pid.to_string()));
+    }
+
+    #[test]
+    fn test_get_procs() {
+        if !nix::unistd::geteuid().is_root() || check_cgroup_mounted().is_err() {
+            return;
+        }
+        let path = format!("/fire-test-procs-{}", std::process::id());
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id() as i32;
+        create(&None, &path).unwrap();
+        let joined = join(&path, pid).and_then(|_| get_procs(&path));
+        let _ = child.kill();
+        let _ = child.wait();
+        remove(&path).unwrap();
+
+        assert_eq!(joined.unwrap(), vec![pid]);
+        // cgroup 删除后没有进程
+        assert!(get_procs(&path).unwrap().is_empty());
+    }
+}
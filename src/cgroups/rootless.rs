@@ -0,0 +1,91 @@
+use super::detect_cgroup_version;
+use lazy_static::lazy_static;
+use log::debug;
+use std::ffi::CString;
+use std::fs::read_to_string;
+
+pub use crate::rootless::is_rootless;
+
+/// 未配置 cgroup 时记录到状态文件中的原因
+pub const ANNOTATION_CGROUP_SKIPPED: &str = "org.fire.cgroup.skipped";
+
+lazy_static! {
+    static ref SKIP_REASON: Option<String> = detect_skip_reason();
+}
+
+/// 非特权用户可用的 cgroup 委派子树
+///
+/// systemd 会把 user@<uid>.service 委派给用户，返回其下的 /fire 作为
+/// 容器 cgroup 的父路径；不是 cgroup v2 或子树不可写时返回 None。
+pub fn delegated_parent() -> Option<String> {
+    if detect_cgroup_version().ok()? != 2 {
+        return None;
+    }
+
+    let own = read_to_string("/proc/self/cgroup").ok()?;
+    let (uid, _) = crate::rootless::ids();
+    let base = user_service_path(&own, uid)?;
+    let dir = format!("/sys/fs/cgroup{}", base);
+    if !writable(&dir) || !writable(&format!("{}/cgroup.subtree_control", dir)) {
+        debug!("cgroup 子树 {} 未委派给当前用户", dir);
+        return None;
+    }
+    Some(format!("{}/fire", base))
+}
+
+/// 从 /proc/self/cgroup 中找到 user@<uid>.service 所在的路径
+fn user_service_path(own: &str, uid: u32) -> Option<String> {
+    let path = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let service = format!("user@{}.service", uid);
+    let mut base = String::new();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        base.push('/');
+        base.push_str(component);
+        if component == service {
+            return Some(base);
+        }
+    }
+    None
+}
+
+fn writable(path: &str) -> bool {
+    match CString::new(path) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+/// 需要跳过 cgroup 配置时返回原因
+///
+/// 仅在非特权运行且没有可用的委派子树时跳过，root 用户始终返回 None。
+pub fn skip_reason() -> Option<&'static str> {
+    SKIP_REASON.as_deref()
+}
+
+fn detect_skip_reason() -> Option<String> {
+    if !is_rootless() {
+        return None;
+    }
+    match detect_cgroup_version() {
+        Ok(2) if delegated_parent().is_some() => None,
+        Ok(2) => Some("没有委派给当前用户的 cgroup v2 子树".to_string()),
+        Ok(_) => Some("cgroup v1 不支持非特权委派".to_string()),
+        Err(e) => Some(format!("cgroup 不可用: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_service_path() {
+        let own = "0::/user.slice/user-1000.slice/user@1000.service/app.slice/term.scope\n";
+        assert_eq!(
+            user_service_path(own, 1000),
+            Some("/user.slice/user-1000.slice/user@1000.service".to_string())
+        );
+        assert_eq!(user_service_path(own, 1001), None);
+        assert_eq!(user_service_path("0::/system.slice/sshd.service\n", 1000), None);
+    }
+}
@@ -0,0 +1,302 @@
+use super::{controllers, detect_cgroup_version};
+use crate::errors::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+/// 容器 cgroup 的资源使用统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CgroupStats {
+    pub cpu: CpuStats,
+    pub memory: MemoryStats,
+    pub pids: PidsStats,
+    pub io: IoStats,
+}
+
+/// CPU 使用与节流统计，时间单位为微秒
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CpuStats {
+    pub usage_usec: u64,
+    pub user_usec: u64,
+    pub system_usec: u64,
+    pub nr_periods: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+}
+
+/// 内存使用统计，单位为字节
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MemoryStats {
+    pub current: u64,
+    pub peak: Option<u64>,
+    pub limit: Option<u64>,
+    pub events: MemoryEvents,
+}
+
+/// memory.events 中的事件计数
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MemoryEvents {
+    pub low: u64,
+    pub high: u64,
+    pub max: u64,
+    pub oom: u64,
+    pub oom_kill: u64,
+}
+
+/// 进程数统计，events.max 为因达到 pids 上限而 fork 失败的次数
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PidsStats {
+    pub current: u64,
+    pub peak: Option<u64>,
+    pub limit: Option<u64>,
+    pub events: PidsEvents,
+}
+
+/// pids.events 中的事件计数
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PidsEvents {
+    pub max: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IoStats {
+    pub devices: Vec<IoDeviceStats>,
+}
+
+/// 单个块设备的 IO 统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IoDeviceStats {
+    pub major: u64,
+    pub minor: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_ios: u64,
+    pub write_ios: u64,
+}
+
+/// 收集容器 cgroup 的资源使用统计 (支持 cgroup v1 和 v2)
+pub fn collect(cgroups_path: &str) -> Result<CgroupStats> {
+    match detect_cgroup_version()? {
+        1 => Ok(collect_v1(cgroups_path)),
+        _ => Ok(collect_v2(cgroups_path)),
+    }
+}
+
+fn collect_v2(cgroups_path: &str) -> CgroupStats {
+    let dir = format!("/sys/fs/cgroup{}", cgroups_path);
+    let read = |file: &str| read_to_string(format!("{}/{}", dir, file)).unwrap_or_default();
+
+    let cpu_stat = parse_keyed(&read("cpu.stat"));
+    let cpu = CpuStats {
+        usage_usec: value(&cpu_stat, "usage_usec"),
+        user_usec: value(&cpu_stat, "user_usec"),
+        system_usec: value(&cpu_stat, "system_usec"),
+        nr_periods: value(&cpu_stat, "nr_periods"),
+        nr_throttled: value(&cpu_stat, "nr_throttled"),
+        throttled_usec: value(&cpu_stat, "throttled_usec"),
+    };
+
+    let events = parse_keyed(&read("memory.events"));
+    let memory = MemoryStats {
+        current: parse_limit(&read("memory.current")).unwrap_or(0),
+        peak: parse_limit(&read("memory.peak")),
+        limit: parse_limit(&read("memory.max")),
+        events: MemoryEvents {
+            low: value(&events, "low"),
+            high: value(&events, "high"),
+            max: value(&events, "max"),
+            oom: value(&events, "oom"),
+            oom_kill: value(&events, "oom_kill"),
+        },
+    };
+
+    let pids = PidsStats {
+        current: parse_limit(&read("pids.current")).unwrap_or(0),
+        peak: parse_limit(&read("pids.peak")),
+        limit: parse_limit(&read("pids.max")),
+        events: PidsEvents {
+            max: value(&parse_keyed(&read("pids.events")), "max"),
+        },
+    };
+
+    CgroupStats {
+        cpu,
+        memory,
+        pids,
+        io: parse_io_stat(&read("io.stat")),
+    }
+}
+
+fn collect_v1(cgroups_path: &str) -> CgroupStats {
+    // 共同挂载的控制器（如 cpu,cpuacct）共用同一个目录
+    let read = |subsystem: &str, file: &str| match controllers::v1_mountpoint(subsystem) {
+        Some(mountpoint) => {
+            read_to_string(format!("{}{}/{}", mountpoint, cgroups_path, file)).unwrap_or_default()
+        }
+        None => String::new(),
+    };
+
+    // cpuacct.stat 以 USER_HZ 为单位
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+    let cpuacct = parse_keyed(&read("cpuacct", "cpuacct.stat"));
+    let cpu_stat = parse_keyed(&read("cpu", "cpu.stat"));
+    let cpu = CpuStats {
+        usage_usec: parse_limit(&read("cpuacct", "cpuacct.usage")).unwrap_or(0) / 1000,
+        user_usec: value(&cpuacct, "user") * 1_000_000 / ticks,
+        system_usec: value(&cpuacct, "system") * 1_000_000 / ticks,
+        nr_periods: value(&cpu_stat, "nr_periods"),
+        nr_throttled: value(&cpu_stat, "nr_throttled"),
+        throttled_usec: value(&cpu_stat, "throttled_time") / 1000,
+    };
+
+    let oom_control = parse_keyed(&read("memory", "memory.oom_control"));
+    let memory = MemoryStats {
+        current: parse_limit(&read("memory", "memory.usage_in_bytes")).unwrap_or(0),
+        peak: parse_limit(&read("memory", "memory.max_usage_in_bytes")),
+        limit: parse_limit(&read("memory", "memory.limit_in_bytes")),
+        events: MemoryEvents {
+            max: parse_limit(&read("memory", "memory.failcnt")).unwrap_or(0),
+            oom_kill: value(&oom_control, "oom_kill"),
+            ..Default::default()
+        },
+    };
+
+    // v1 没有 pids.peak
+    let pids = PidsStats {
+        current: parse_limit(&read("pids", "pids.current")).unwrap_or(0),
+        peak: None,
+        limit: parse_limit(&read("pids", "pids.max")),
+        events: PidsEvents {
+            max: value(&parse_keyed(&read("pids", "pids.events")), "max"),
+        },
+    };
+
+    let io = parse_blkio(
+        &read("blkio", "blkio.throttle.io_service_bytes"),
+        &read("blkio", "blkio.throttle.io_serviced"),
+    );
+
+    CgroupStats { cpu, memory, pids, io }
+}
+
+/// 解析 "key value" 形式的统计文件
+fn parse_keyed(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let key = fields.next()?;
+            let value = fields.next()?.parse().ok()?;
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+fn value(map: &HashMap<String, u64>, key: &str) -> u64 {
+    map.get(key).copied().unwrap_or(0)
+}
+
+/// 解析单值文件，"max" 或无法解析时返回 None
+fn parse_limit(content: &str) -> Option<u64> {
+    content.trim().parse().ok()
+}
+
+/// 解析 v2 的 io.stat，如 "8:0 rbytes=1 wbytes=2 rios=3 wios=4"
+fn parse_io_stat(content: &str) -> IoStats {
+    let devices = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (major, minor) = parse_device(fields.next()?)?;
+            let mut device = IoDeviceStats {
+                major,
+                minor,
+                ..Default::default()
+            };
+            for field in fields {
+                let (key, value) = match field.split_once('=') {
+                    Some((key, value)) => (key, value.parse().unwrap_or(0)),
+                    None => continue,
+                };
+                match key {
+                    "rbytes" => device.read_bytes = value,
+                    "wbytes" => device.write_bytes = value,
+                    "rios" => device.read_ios = value,
+                    "wios" => device.write_ios = value,
+                    _ => {}
+                }
+            }
+            Some(device)
+        })
+        .collect();
+    IoStats { devices }
+}
+
+/// 合并 v1 的 blkio 字节数与操作数统计，如 "8:0 Read 4096"
+fn parse_blkio(service_bytes: &str, serviced: &str) -> IoStats {
+    let mut devices: Vec<IoDeviceStats> = Vec::new();
+    for (content, is_bytes) in [(service_bytes, true), (serviced, false)] {
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (major, minor, op, value) = match fields.as_slice() {
+                [device, op, value] => match (parse_device(device), value.parse::<u64>()) {
+                    (Some((major, minor)), Ok(value)) => (major, minor, *op, value),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+
+            let index = match devices.iter().position(|d| d.major == major && d.minor == minor) {
+                Some(index) => index,
+                None => {
+                    devices.push(IoDeviceStats {
+                        major,
+                        minor,
+                        ..Default::default()
+                    });
+                    devices.len() - 1
+                }
+            };
+            let device = &mut devices[index];
+            match (op, is_bytes) {
+                ("Read", true) => device.read_bytes = value,
+                ("Write", true) => device.write_bytes = value,
+                ("Read", false) => device.read_ios = value,
+                ("Write", false) => device.write_ios = value,
+                _ => {}
+            }
+        }
+    }
+    IoStats { devices }
+}
+
+fn parse_device(device: &str) -> Option<(u64, u64)> {
+    let (major, minor) = device.split_once(':')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_io_stat() {
+        let io = parse_io_stat("8:0 rbytes=4096 wbytes=8192 rios=1 wios=2 dbytes=0 dios=0\n");
+        assert_eq!(io.devices.len(), 1);
+        assert_eq!(io.devices[0].major, 8);
+        assert_eq!(io.devices[0].read_bytes, 4096);
+        assert_eq!(io.devices[0].write_ios, 2);
+    }
+
+    #[test]
+    fn test_parse_blkio() {
+        let io = parse_blkio(
+            "8:0 Read 4096\n8:0 Write 8192\n8:0 Total 12288\nTotal 12288\n",
+            "8:0 Read 1\n8:0 Write 2\n",
+        );
+        assert_eq!(io.devices.len(), 1);
+        assert_eq!(io.devices[0].write_bytes, 8192);
+        assert_eq!(io.devices[0].read_ios, 1);
+        assert_eq!(parse_limit("max\n"), None);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::errors::{FireError, Result};
+use crate::tty::{self, RawMode};
+use log::info;
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+
+/// 内置的 console socket 接收端，相当于 runc 的 recvtty
+pub struct ConsoleCommand {
+    pub socket: PathBuf,
+    /// 只输出终端内容，不转发标准输入
+    pub no_stdin: bool,
+}
+
+impl ConsoleCommand {
+    pub fn new(socket: PathBuf) -> Self {
+        Self {
+            socket,
+            no_stdin: false,
+        }
+    }
+
+    pub fn set_no_stdin(&mut self, no_stdin: bool) {
+        self.no_stdin = no_stdin;
+    }
+}
+
+impl super::Command for ConsoleCommand {
+    /// 在 socket 上等待一个连接，接收 pty 主端并转发到当前终端，直到容器进程关闭终端
+    #[tracing::instrument(name = "console", skip_all, fields(socket = %self.socket.display()))]
+    fn execute(&self) -> Result<()> {
+        // 上次异常退出时留下的 socket 文件
+        if fs::symlink_metadata(&self.socket).is_ok_and(|m| m.file_type().is_socket()) {
+            fs::remove_file(&self.socket)?;
+        }
+        let listener = UnixListener::bind(&self.socket)?;
+        let _cleanup = scopeguard::guard(&self.socket, |socket| {
+            let _ = fs::remove_file(socket);
+        });
+        info!("在 {} 上等待终端", self.socket.display());
+
+        let (stream, _) = listener.accept()?;
+        let master = tty::receive_master(&stream)?
+            .ok_or_else(|| FireError::Generic("console socket 的连接中没有终端描述符".to_string()))?;
+        info!("已收到终端，开始转发");
+
+        let raw_mode = RawMode::enter()?;
+        let result = tty::proxy(master.as_raw_fd(), !self.no_stdin);
+        drop(raw_mode);
+        result
+    }
+}
@@ -1,25 +1,89 @@
-use crate::container::Container;
+use crate::cdi;
+use crate::cgroups;
+use crate::container::{state, Container, EXEC_FIFO};
 use crate::errors::Result;
+use crate::logger;
+use crate::rfc3339;
+use crate::network::etcfiles::{self, DnsConfig};
+use crate::rootfs::{self, OverlayConfig};
+use crate::rootless;
+use crate::sysinfo;
+use crate::tty::{self, Pty};
 use crate::runtime::manager::RUNTIME_MANAGER;
+use crate::runtime::options::RuntimeOptions;
 use log::{error, info, warn};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use oci::Spec;
 use std::fs;
-use std::path::Path;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 
 pub struct CreateCommand {
     pub id: String,
     pub bundle: String,
+    pub dns: DnsConfig,
+    pub rootfs_layers: Vec<PathBuf>,
+    pub cgroup_parent: Option<String>,
+    pub parent_death_signal: Option<Signal>,
+    pub console_socket: Option<PathBuf>,
 }
 
 impl CreateCommand {
     pub fn new(id: String, bundle: Option<String>) -> Self {
         let bundle = bundle.unwrap_or_else(|| ".".to_string());
-        Self { id, bundle }
+        Self {
+            id,
+            bundle,
+            dns: DnsConfig::default(),
+            rootfs_layers: Vec::new(),
+            cgroup_parent: None,
+            parent_death_signal: None,
+            console_socket: None,
+        }
+    }
+
+    /// 设置DNS配置（优先于注解）
+    pub fn set_dns(&mut self, dns: DnsConfig) {
+        self.dns = dns;
+    }
+
+    /// 设置用于组装 overlayfs 根文件系统的镜像层（优先于注解）
+    pub fn set_rootfs_layers(&mut self, layers: Vec<PathBuf>) {
+        self.rootfs_layers = layers;
+    }
+
+    /// 设置容器 cgroup 的父路径（优先于注解）
+    pub fn set_cgroup_parent(&mut self, parent: Option<String>) {
+        self.cgroup_parent = parent;
+    }
+
+    /// 当前进程退出时向 init 进程发送的信号，用于前台运行的容器随 `fire run` 一起退出
+    pub fn set_parent_death_signal(&mut self, signal: Option<Signal>) {
+        self.parent_death_signal = signal;
+    }
+
+    /// process.terminal 为 true 时把 pty 主端发送到该 unix socket（如 `fire console`）
+    pub fn set_console_socket(&mut self, console_socket: Option<PathBuf>) {
+        self.console_socket = console_socket;
+    }
+
+    /// 合并命令行与注解中的 overlayfs 配置
+    fn overlay_config(&self, spec: &Spec) -> Option<OverlayConfig> {
+        if self.rootfs_layers.is_empty() {
+            return OverlayConfig::from_annotations(&spec.annotations);
+        }
+        let mut config = OverlayConfig::new(self.rootfs_layers.clone());
+        config.upper_dir = spec.annotations.get(rootfs::ANNOTATION_ROOTFS_UPPERDIR).map(PathBuf::from);
+        config.work_dir = spec.annotations.get(rootfs::ANNOTATION_ROOTFS_WORKDIR).map(PathBuf::from);
+        Some(config)
     }
 }
 
 impl super::Command for CreateCommand {
+    #[tracing::instrument(name = "create", skip_all, fields(id = %self.id, bundle = %self.bundle))]
     fn execute(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
         info!("创建容器: ID={}, Bundle={}", self.id, self.bundle);
 
         // 验证容器ID
@@ -29,6 +93,14 @@ impl super::Command for CreateCommand {
             ));
         }
 
+        let container_dir = state::root_dir().join(&self.id).to_string_lossy().to_string();
+        if Path::new(&container_dir).join("state.json").exists() {
+            return Err(crate::errors::FireError::AlreadyExists(format!(
+                "容器 {} 已存在",
+                self.id
+            )));
+        }
+
         // 验证bundle目录存在
         let bundle_path = Path::new(&self.bundle);
         if !bundle_path.exists() {
@@ -48,7 +120,7 @@ impl super::Command for CreateCommand {
         }
 
         info!("读取OCI配置文件: {}", config_path.display());
-        let spec = match Spec::load(config_path.to_str().unwrap()) {
+        let mut spec = match Spec::load(config_path.to_str().unwrap()) {
             Ok(spec) => spec,
             Err(e) => {
                 error!("无法读取OCI配置文件: {:?}", e);
@@ -59,71 +131,208 @@ impl super::Command for CreateCommand {
             }
         };
 
+        for platform in spec.other_platforms() {
+            warn!("忽略配置文件中的 {} 段", platform);
+        }
+        if spec.linux.is_none() {
+            return Err(crate::errors::FireError::InvalidSpec(
+                "配置文件缺少 linux 段，fire 只支持 Linux 容器".to_string(),
+            ));
+        }
+
+        // 记录在注解中，之后的命令据此找到容器的 cgroup
+        if let Some(ref parent) = self.cgroup_parent {
+            cgroups::parse_cgroup_parent(parent)?;
+            spec.annotations
+                .insert(cgroups::ANNOTATION_CGROUP_PARENT.to_string(), parent.clone());
+        }
+
+        // 注入注解中引用的 CDI 设备，再按 org.fire.* 注解调整配置
+        cdi::inject(&mut spec)?;
+        RuntimeOptions::from_annotations(&spec.annotations)?.apply(&mut spec);
+        rootless::adapt_spec(&mut spec)?;
+
+        // 由镜像层组装根文件系统时，rootfs 目录仅作为挂载点
+        let overlay = self.overlay_config(&spec);
+        let rootfs_path = bundle_path.join(&spec.root.path);
+        if overlay.is_some() && !spec.root.path.is_empty() {
+            fs::create_dir_all(&rootfs_path)?;
+        }
+
         // 验证配置文件
         self.validate_spec(&spec)?;
+        match (spec.process.terminal, &self.console_socket) {
+            (false, Some(_)) => {
+                return Err(crate::errors::FireError::InvalidSpec(
+                    "console socket 需要 process.terminal 为 true".to_string(),
+                ));
+            }
+            (true, None) => warn!("process.terminal 为 true 但没有指定 console socket，不为容器分配终端"),
+            _ => {}
+        }
+
+        // 不复用其他容器仍在使用的 cgroup，cgroup 在启动 init 进程时创建
+        let cgroup_path = match cgroups::rootless::skip_reason() {
+            None => {
+                let cgroup_path = cgroups::cgroup_path_for_spec(&self.id, &spec)?;
+                cgroups::ensure_cgroup_unused(&cgroup_path)?;
+                Some(cgroup_path)
+            }
+            Some(_) => None,
+        };
 
         // 创建容器运行时目录
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let container_dir = format!("{}/.fire/{}", home_dir, self.id);
         fs::create_dir_all(&container_dir)?;
         info!("创建容器运行时目录: {}", container_dir);
 
+        // 之后的步骤失败时按相反的顺序撤销：结束 init 进程、卸载 overlayfs（容器没有独立的
+        // mount namespace 时挂载在宿主机上）、删除 cgroup 和运行时目录
+        let mut rollback = {
+            let (id, rootfs_path, container_dir) = (self.id.clone(), rootfs_path.clone(), container_dir.clone());
+            scopeguard::guard(None::<i32>, move |pid| {
+                warn!("创建容器 {} 失败，清理已创建的资源", id);
+                if let Some(pid) = pid {
+                    let _ = nix::sys::signal::kill(Pid::from_raw(pid), Signal::SIGKILL);
+                    let _ = nix::sys::wait::waitpid(Pid::from_raw(pid), None);
+                }
+                if rootfs::is_overlay_mounted(&rootfs_path) {
+                    if let Err(e) = rootfs::unmount_overlay(&rootfs_path) {
+                        warn!("卸载 overlayfs 根文件系统失败: {}", e);
+                    }
+                }
+                if let Some(ref path) = cgroup_path {
+                    if let Err(e) = cgroups::remove(path) {
+                        warn!("删除 cgroup {} 失败: {}", path, e);
+                    }
+                }
+                if let Err(e) = fs::remove_dir_all(&container_dir) {
+                    warn!("删除容器运行时目录 {} 失败: {}", container_dir, e);
+                }
+            })
+        };
+
+        // 生成 resolv.conf、hosts、hostname
+        let dns = self.dns.clone().merge(DnsConfig::from_annotations(&spec.annotations));
+        etcfiles::setup(&mut spec, Path::new(&container_dir), &dns)?;
+        spec.save(&format!("{}/{}", container_dir, state::SPEC_FILE)).map_err(|e| {
+            crate::errors::FireError::Generic(format!("无法保存容器配置: {:?}", e))
+        })?;
+
         // 创建容器状态文件
-        let state_file = format!("{}/state.json", container_dir);
+        let state_file = format!("{}/{}", container_dir, state::STATE_FILE);
+        let mut annotations = spec.annotations.clone();
+        if rootless::is_rootless() {
+            annotations.insert(rootless::ANNOTATION_ROOTLESS.to_string(), "true".to_string());
+        }
+        if let Some(reason) = cgroups::rootless::skip_reason() {
+            annotations.insert(
+                cgroups::rootless::ANNOTATION_CGROUP_SKIPPED.to_string(),
+                reason.to_string(),
+            );
+        } else {
+            let skipped = cgroups::skipped_controllers();
+            if !skipped.is_empty() {
+                annotations.insert(
+                    cgroups::controllers::ANNOTATION_SKIPPED_CONTROLLERS.to_string(),
+                    skipped.join(","),
+                );
+            }
+        }
+        // 启动 init 进程，完成 namespace 和 cgroup 配置后阻塞到 start
+        let exec_fifo = Path::new(&container_dir).join(EXEC_FIFO);
+        nix::unistd::mkfifo(&exec_fifo, nix::sys::stat::Mode::S_IRUSR | nix::sys::stat::Mode::S_IWUSR)?;
+        let pty = match self.console_socket {
+            Some(_) => Some(Pty::open()?),
+            None => None,
+        };
+        let mut container = Container::new(self.id.clone(), spec, self.bundle.clone())?;
+        container.set_overlay(overlay);
+        if let Some(ref mut process) = container.main_process {
+            process.set_parent_death_signal(self.parent_death_signal);
+            process.set_terminal(pty.as_ref().map(|pty| pty.slave.as_raw_fd()));
+        }
+        let pid = container.create()?;
+        *rollback = Some(pid);
+        // init 已经持有从端，当前进程只把主端交给 console socket
+        if let (Some(pty), Some(socket)) = (pty, &self.console_socket) {
+            tty::send_master(socket, pty.master.as_raw_fd())?;
+        }
+        annotations.insert(
+            state::ANNOTATION_CREATED.to_string(),
+            rfc3339::format(container.created_at),
+        );
+        if let Some(start_time) = state::process_start_time(pid) {
+            annotations.insert(state::ANNOTATION_INIT_START_TIME.to_string(), start_time.to_string());
+        }
+
         let state = oci::State {
             version: "1.0.0".to_string(),
             id: self.id.clone(),
             status: "created".to_string(),
-            pid: 0,
+            pid,
             bundle: fs::canonicalize(&self.bundle)?
                 .to_string_lossy()
                 .to_string(),
-            annotations: spec.annotations.clone(),
+            annotations,
         };
 
         // 保存状态文件
-        match state.to_string() {
-            Ok(state_json) => {
-                fs::write(&state_file, state_json)?;
-                info!("保存容器状态文件: {}", state_file);
-            }
-            Err(e) => {
-                error!("无法序列化容器状态: {:?}", e);
-                return Err(crate::errors::FireError::Generic(format!(
-                    "无法序列化容器状态: {:?}",
-                    e
-                )));
-            }
-        }
+        state::save(Path::new(&state_file), &state)?;
+        info!("保存容器状态文件: {}", state_file);
 
-        // 创建容器实例并添加到全局管理器
-        let container = Container::new(self.id.clone(), spec, self.bundle.clone())?;
+        // 添加到全局管理器
         RUNTIME_MANAGER.lock().unwrap().create_container(self.id.clone(), container)?;
+        scopeguard::ScopeGuard::into_inner(rollback);
 
-        info!("容器 {} 创建成功", self.id);
+        info!("容器 {} 创建成功，init 进程 PID: {}", self.id, pid);
         Ok(())
     }
 }
 
 impl CreateCommand {
     fn validate_spec(&self, spec: &Spec) -> Result<()> {
-        // 验证OCI版本
-        if spec.version.is_empty() {
-            warn!("OCI版本未设置，使用默认版本");
+        // 不支持的主版本由 spec.validate() 报错，较新的次版本仅提示
+        match oci::Version::parse(&spec.version) {
+            None if spec.version.is_empty() => {
+                warn!("OCI版本未设置，按 {} 处理", oci::VERSION);
+            }
+            Some(version)
+                if version.major == oci::Version::supported().major
+                    && version > oci::Version::supported() =>
+            {
+                warn!(
+                    "OCI版本 {} 高于支持的版本 {}，较新的字段可能被忽略",
+                    spec.version,
+                    oci::VERSION
+                );
+            }
+            _ => {}
         }
 
-        // 验证进程配置
-        if spec.process.args.is_empty() {
-            return Err(crate::errors::FireError::InvalidSpec(
-                "进程参数不能为空".to_string(),
-            ));
+        // 一次报告所有不合法的字段
+        if let Err(errors) = spec.validate() {
+            let details: Vec<String> = errors.iter().map(|e| format!("  {}", e)).collect();
+            return Err(crate::errors::FireError::InvalidSpec(format!(
+                "OCI配置不合法:\n{}",
+                details.join("\n")
+            )));
         }
 
-        // 验证根文件系统
-        if spec.root.path.is_empty() {
-            return Err(crate::errors::FireError::InvalidSpec(
-                "根文件系统路径不能为空".to_string(),
-            ));
+        // 内核不支持的特性在这里报告，而不是在创建 namespace 或加载过滤器时失败
+        if let Some(ref linux) = spec.linux {
+            let info = sysinfo::get();
+            for ns in linux.namespaces.iter().filter(|ns| ns.path.is_empty()) {
+                let name = sysinfo::namespace_name(ns.typ);
+                if !info.has_namespace(name) {
+                    return Err(crate::errors::FireError::KernelUnsupported(format!(
+                        "内核不支持 {} namespace",
+                        name
+                    )));
+                }
+            }
+            if let Some(ref seccomp) = linux.seccomp {
+                crate::seccomp::check_kernel_support(seccomp)?;
+            }
         }
 
         // 验证根文件系统是否存在
@@ -135,6 +344,11 @@ impl CreateCommand {
             )));
         }
 
+        // tmpfs 选项在创建时校验，避免启动时才报 EINVAL
+        for m in spec.mounts.iter().filter(|m| m.typ == "tmpfs") {
+            crate::mounts::normalize_tmpfs_data(&m.options.join(","))?;
+        }
+
         info!("OCI配置验证通过");
         Ok(())
     }
@@ -1,7 +1,17 @@
+use crate::cgroups;
+use crate::container;
 use crate::errors::Result;
+use crate::logger;
+use crate::rootfs;
+use crate::runtime::hooks;
 use crate::runtime::manager::RUNTIME_MANAGER;
-use log::info;
+use crate::signals;
+use log::{info, warn};
+use nix::sys::signal::Signal;
+use nix::sys::wait::waitpid;
+use nix::unistd::Pid;
 use std::fs;
+use std::time::Duration;
 
 pub struct DeleteCommand {
     pub id: String,
@@ -15,16 +25,17 @@ impl DeleteCommand {
 }
 
 impl super::Command for DeleteCommand {
+    #[tracing::instrument(name = "delete", skip_all, fields(id = %self.id))]
     fn execute(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
         info!("删除容器: {}", self.id);
 
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let container_dir = format!("{}/.fire/{}", home_dir, self.id);
+        let container_dir = container::state::root_dir().join(&self.id).to_string_lossy().to_string();
         let state_file = format!("{}/state.json", container_dir);
 
         // 检查容器是否存在
         if !std::path::Path::new(&state_file).exists() {
-            return Err(crate::errors::FireError::Generic(format!(
+            return Err(crate::errors::FireError::NotFound(format!(
                 "容器 {} 不存在",
                 self.id
             )));
@@ -34,33 +45,81 @@ impl super::Command for DeleteCommand {
         let state_content = fs::read_to_string(&state_file)?;
         let state: oci::State = serde_json::from_str(&state_content)?;
 
-        // 检查容器状态，只能删除已停止的容器
-        if state.status == "running" && !self.force {
-            return Err(crate::errors::FireError::Generic(format!(
+        let spec = super::load_spec(&state).ok();
+        let cgroup_path = spec
+            .as_ref()
+            .and_then(|spec| cgroups::cgroup_path_for_spec(&self.id, spec).ok());
+
+        // 状态文件可能已经过时，以 init 进程和 cgroup 中是否还有进程为准
+        let running = is_running(&state, cgroup_path.as_deref())?;
+        if state.status == "created" && state.pid > 0 {
+            // create 后未 start 的 init 进程仍阻塞在 exec.fifo 上，终止后才能删除 cgroup
+            info!("终止容器 {} 等待启动的 init 进程 {}", self.id, state.pid);
+            kill_parked_init(state.pid, container::state::init_start_time(&state));
+        } else if state.status == "paused" && !self.force {
+            return Err(crate::errors::FireError::InvalidState(format!(
+                "容器 {} 已暂停，请先恢复并停止或使用 --force 参数",
+                self.id
+            )));
+        } else if running && !self.force {
+            return Err(crate::errors::FireError::InvalidState(format!(
                 "容器 {} 正在运行，请先停止或使用 --force 参数",
                 self.id
             )));
-        }
-
-        // 如果容器正在运行且使用了 force 参数，先停止容器
-        if state.status == "running" && self.force {
+        } else if running || state.status == "paused" {
             info!("强制停止容器 {}", self.id);
             if let Err(e) = RUNTIME_MANAGER.lock().unwrap().stop_container(&self.id) {
                 info!("停止容器失败，继续删除: {}", e);
             }
+
+            if let Some(ref path) = cgroup_path {
+                // 冻结的进程无法处理信号，先解冻
+                if state.status == "paused" {
+                    if let Err(e) = cgroups::thaw(path) {
+                        warn!("解冻容器 {} 失败: {}", self.id, e);
+                    }
+                }
+                // 先冻结再终止，防止进程 fork 得比被终止得更快
+                if let Err(e) = cgroups::freeze_and_kill(path, libc::SIGKILL) {
+                    info!("终止容器进程失败，继续删除: {}", e);
+                }
+            }
         }
 
         // 清理容器资源
         {
             let mut manager = RUNTIME_MANAGER.lock().unwrap();
-            if let Some(mut container) = manager.remove_container(&self.id) {
+            if let Some(container) = manager.remove_container(&self.id) {
                 info!("清理容器 {} 的资源", self.id);
-                if let Err(e) = container.cleanup() {
+                if let Err(e) = container.lock().unwrap().cleanup() {
                     info!("清理容器资源失败，继续删除: {}", e);
                 }
             }
         }
 
+        if let Some(spec) = spec {
+            // 删除 create 时创建的 cgroup
+            if let (Some(path), None) = (&cgroup_path, cgroups::rootless::skip_reason()) {
+                if let Err(e) = cgroups::remove(path) {
+                    info!("删除容器 cgroup 失败，继续删除: {}", e);
+                }
+            }
+
+            // 卸载由镜像层组装的根文件系统，可写层位于容器目录中
+            let rootfs_path = std::path::Path::new(&state.bundle).join(&spec.root.path);
+            if rootfs::is_overlay_mounted(&rootfs_path) {
+                rootfs::unmount_overlay(&rootfs_path)?;
+            }
+
+            // 容器资源已释放，按 OCI 规范在 delete 返回前执行 poststop 钩子，
+            // 前台运行的容器在 init 退出时已经执行过
+            if !state.annotations.contains_key(container::state::ANNOTATION_EXIT_CODE) {
+                let mut stopped = state.clone();
+                stopped.status = "stopped".to_string();
+                hooks::run_poststop(&spec, &stopped);
+            }
+        }
+
         // 删除容器状态文件
         if std::path::Path::new(&state_file).exists() {
             fs::remove_file(&state_file)?;
@@ -77,3 +136,63 @@ impl super::Command for DeleteCommand {
         Ok(())
     }
 }
+
+/// 终止阻塞在 exec.fifo 上的 init 进程并等待其退出
+///
+/// init 通常不是当前进程的子进程，waitpid 失败时通过 /proc 等待它变为僵尸或消失。
+fn kill_parked_init(pid: i32, start_time: Option<u64>) {
+    if signals::send_signal(pid, start_time, Signal::SIGKILL).is_err() {
+        return;
+    }
+    let pid = Pid::from_raw(pid);
+    if waitpid(pid, None).is_ok() {
+        return;
+    }
+    for _ in 0..100 {
+        if !process_alive(pid.as_raw()) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    warn!("init 进程 {} 未能及时退出", pid);
+}
+
+/// init 进程存活或容器 cgroup 中仍有进程
+fn is_running(state: &oci::State, cgroup_path: Option<&str>) -> Result<bool> {
+    if state.pid > 0 && process_alive(state.pid) {
+        return Ok(true);
+    }
+    match cgroup_path {
+        Some(path) => Ok(!cgroups::get_procs(path)?.is_empty()),
+        None => Ok(false),
+    }
+}
+
+/// 进程存在且不是僵尸进程
+fn process_alive(pid: i32) -> bool {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+    // 状态字段位于进程名的右括号之后
+    !matches!(
+        stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().next()),
+        None | Some("Z") | Some("X")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_alive() {
+        assert!(process_alive(std::process::id() as i32));
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id() as i32;
+        // 未回收时是僵尸进程
+        while process_alive(pid) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        child.wait().unwrap();
+        assert!(!process_alive(pid));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::cgroups::{self, events::{OomEvent, ANNOTATION_OOM_KILLED}};
+use crate::container;
+use crate::errors::Result;
+use crate::logger;
+use log::{info, warn};
+use serde::Serialize;
+
+pub struct EventsCommand {
+    pub id: String,
+}
+
+impl EventsCommand {
+    pub fn new(id: String) -> Self {
+        Self { id }
+    }
+}
+
+/// 以 JSON 行输出的容器事件
+#[derive(Serialize)]
+struct Event<'a> {
+    #[serde(rename = "type")]
+    typ: &'a str,
+    id: &'a str,
+    data: &'a OomEvent,
+}
+
+impl super::Command for EventsCommand {
+    fn execute(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
+        info!("监听容器事件: {}", self.id);
+
+        let container_dir = container::state::root_dir().join(&self.id);
+        let state_file = container_dir.join(container::state::STATE_FILE);
+        if !state_file.exists() {
+            return Err(crate::errors::FireError::NotFound(format!(
+                "容器 {} 不存在",
+                self.id
+            )));
+        }
+
+        let state = container::state::load(&container_dir)?;
+        if state.status != "running" {
+            return Err(crate::errors::FireError::InvalidState(format!(
+                "容器 {} 不在运行状态，当前状态: {}",
+                self.id, state.status
+            )));
+        }
+
+        let spec = super::load_spec(&state)?;
+        let cgroup_path = cgroups::cgroup_path_for_spec(&self.id, &spec)?;
+
+        cgroups::events::watch_oom(&cgroup_path, |event| {
+            warn!("容器 {} 发生 OOM kill，累计 {} 次", self.id, event.oom_kill);
+            if let Err(e) = container::state::annotate(&container_dir, ANNOTATION_OOM_KILLED, "true") {
+                warn!("更新容器状态失败: {}", e);
+            }
+
+            let event = Event {
+                typ: "oom",
+                id: &self.id,
+                data: event,
+            };
+            match serde_json::to_string(&event) {
+                Ok(line) => println!("{}", line),
+                Err(e) => warn!("序列化事件失败: {}", e),
+            }
+            true
+        })?;
+
+        info!("容器 {} 的事件监听结束", self.id);
+        Ok(())
+    }
+}
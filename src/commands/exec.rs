@@ -0,0 +1,308 @@
+use crate::cgroups;
+use crate::container::namespace::{self, NamespaceType};
+use crate::container::process::Process;
+use crate::container::state;
+use crate::errors::{FireError, Result};
+use crate::logger;
+use crate::tty::{self, Pty, RawMode};
+use log::{info, warn};
+use nix::sys::signal::Signal;
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+pub struct ExecCommand {
+    pub id: String,
+    pub args: Vec<String>,
+    pub tty: bool,
+    pub interactive: bool,
+    pub process: Option<PathBuf>,
+    pub user: Option<String>,
+    pub env: Vec<String>,
+    pub cwd: Option<String>,
+    pub detach: bool,
+    pub pid_file: Option<PathBuf>,
+    pub exec_id: Option<String>,
+    pub console_socket: Option<PathBuf>,
+}
+
+impl ExecCommand {
+    pub fn new(id: String, args: Vec<String>) -> Self {
+        Self {
+            id,
+            args,
+            tty: false,
+            interactive: false,
+            process: None,
+            user: None,
+            env: Vec::new(),
+            cwd: None,
+            detach: false,
+            pid_file: None,
+            exec_id: None,
+            console_socket: None,
+        }
+    }
+
+    /// 为进程分配伪终端，当前终端切换到原始模式并转发窗口大小
+    pub fn set_tty(&mut self, tty: bool) {
+        self.tty = tty;
+    }
+
+    /// 保持标准输入打开并交给进程，否则进程的标准输入为 /dev/null
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /// 从 OCI process 文件读取命令、终端、环境变量、工作目录、用户、capabilities、rlimits
+    /// 和安全标签，代替命令行参数和容器 spec 中的 process
+    pub fn set_process(&mut self, process: Option<PathBuf>) {
+        self.process = process;
+    }
+
+    /// 以 `uid[:gid]` 执行，未指定 gid 时为 0
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    /// 追加 `KEY=VALUE` 形式的环境变量，覆盖同名的变量
+    pub fn set_env(&mut self, env: Vec<String>) {
+        self.env = env;
+    }
+
+    pub fn set_cwd(&mut self, cwd: Option<String>) {
+        self.cwd = cwd;
+    }
+
+    /// 进程启动后立即返回，不再等待进程退出，不能与 tty、interactive 同时使用
+    pub fn set_detach(&mut self, detach: bool) {
+        self.detach = detach;
+    }
+
+    /// 进程启动后把 pid 写入该文件
+    pub fn set_pid_file(&mut self, pid_file: Option<PathBuf>) {
+        self.pid_file = pid_file;
+    }
+
+    /// 进程的 exec id，可用于 `fire kill --exec-id`，未指定时为进程的 pid
+    pub fn set_exec_id(&mut self, exec_id: Option<String>) {
+        self.exec_id = exec_id;
+    }
+
+    /// 把 pty 主端发送到该 unix socket（如 `fire console`），而不是转发到当前终端
+    pub fn set_console_socket(&mut self, console_socket: Option<PathBuf>) {
+        self.console_socket = console_socket;
+    }
+
+    /// 在运行中的容器内执行命令，等待其退出并返回退出码，detach 时启动后返回 0
+    ///
+    /// 进程加入容器的全部 namespace 和 cgroup，环境变量、工作目录、用户和权限取自
+    /// 容器 spec 中的 process，指定了 process 文件时取自该文件，再由 user、env、cwd 覆盖。
+    #[tracing::instrument(name = "exec", skip_all, fields(id = %self.id))]
+    pub fn run(&self) -> Result<i32> {
+        let _scope = logger::container_scope(&self.id);
+
+        let container_dir = state::root_dir().join(&self.id);
+        if !container_dir.join(state::STATE_FILE).exists() {
+            return Err(FireError::NotFound(format!("容器 {} 不存在", self.id)));
+        }
+        let state = state::load(&container_dir)?;
+        if state.status != "running" || state.pid <= 0 || !Path::new(&format!("/proc/{}", state.pid)).exists() {
+            return Err(FireError::InvalidState(format!(
+                "容器 {} 不在运行状态，当前状态: {}",
+                self.id, state.status
+            )));
+        }
+        let mut spec = super::load_spec(&state)?;
+        let mut tty = self.tty;
+        let args = match self.process {
+            Some(ref path) => {
+                if !self.args.is_empty() {
+                    return Err(FireError::InvalidSpec("指定 process 文件时不能再指定命令".to_string()));
+                }
+                spec.process = serde_json::from_slice(&std::fs::read(path)?)?;
+                tty |= spec.process.terminal;
+                spec.process.args.clone()
+            }
+            None => self.args.clone(),
+        };
+        if args.is_empty() {
+            return Err(FireError::InvalidSpec("未指定要执行的命令".to_string()));
+        }
+        self.override_process(&mut spec.process)?;
+        if self.console_socket.is_some() && !tty {
+            return Err(FireError::InvalidSpec("console socket 需要与 tty 一起使用".to_string()));
+        }
+        if self.detach && (self.interactive || (tty && self.console_socket.is_none())) {
+            return Err(FireError::InvalidSpec(
+                "后台执行时不能使用 interactive，使用 tty 时需要指定 console socket".to_string(),
+            ));
+        }
+        info!("在容器 {} 中执行: {:?}", self.id, args);
+        if let Some(ref exec_id) = self.exec_id {
+            state::validate_exec_id(exec_id)?;
+            if let Ok(Some(pid)) = state::exec_pid(&container_dir, exec_id) {
+                return Err(FireError::InvalidState(format!(
+                    "exec id {} 已被运行中的进程 {} 使用",
+                    exec_id, pid
+                )));
+            }
+        }
+
+        let namespaces = namespace::open_process_namespaces(state.pid, &namespace::JOIN_ORDER)?;
+        let pid_namespace = namespace::open_process_namespaces(state.pid, &[NamespaceType::Pid])?;
+        let root = File::open(format!("/proc/{}/root", state.pid))?;
+
+        let mut process = Process::from_spec(&spec, args);
+        process.set_join(
+            namespaces.iter().map(|(file, flag)| (file.as_raw_fd(), *flag)).collect(),
+            root.as_raw_fd(),
+        );
+        let pty = if tty { Some(Pty::open()?) } else { None };
+        let null = if tty || self.interactive {
+            None
+        } else {
+            Some(File::open("/dev/null")?)
+        };
+        process.set_terminal(pty.as_ref().map(|pty| pty.slave.as_raw_fd()));
+        process.set_stdin(null.as_ref().map(|file| file.as_raw_fd()));
+
+        // 加入 pid namespace 只影响之后 fork 的子进程，当前进程不受影响
+        for (file, flag) in &pid_namespace {
+            nix::sched::setns(file, *flag)?;
+        }
+
+        let raw_mode = if tty && self.console_socket.is_none() {
+            RawMode::enter()?
+        } else {
+            None
+        };
+        let cgroup_path = cgroups::cgroup_path_for_spec(&self.id, &spec)?;
+        let pid = process.start_with(|pid| {
+            // 容器没有使用 cgroup 时 cgroup 中没有进程
+            if cgroups::get_procs(&cgroup_path)?.is_empty() {
+                return Ok(());
+            }
+            cgroups::join(&cgroup_path, pid)
+        })?;
+        info!("容器 {} 中的进程已启动，PID: {}", self.id, pid);
+
+        let pty = match (pty, &self.console_socket) {
+            (Some(pty), Some(socket)) => {
+                if let Err(e) = tty::send_master(socket, pty.master.as_raw_fd()) {
+                    // 没有人接收终端，进程的输出无处可去
+                    let _ = nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), Signal::SIGKILL);
+                    let _ = process.wait();
+                    return Err(e);
+                }
+                None
+            }
+            (pty, _) => pty,
+        };
+
+        let exec_id = self.exec_id.clone().unwrap_or_else(|| pid.to_string());
+        if let Some(ref path) = self.pid_file {
+            write_pid_file(path, pid)?;
+        }
+        if self.detach {
+            state::record_exec(&container_dir, &exec_id, pid)?;
+            info!("exec 进程 {} 在后台运行，exec id: {}", pid, exec_id);
+            return Ok(0);
+        }
+        if let Err(e) = state::record_exec(&container_dir, &exec_id, pid) {
+            warn!("记录 exec 进程 {} 失败: {}", pid, e);
+        }
+
+        if let Some(Pty { master, slave }) = pty {
+            // 关闭父进程中的从端，进程退出后读主端才会结束
+            drop(slave);
+            if let Err(e) = tty::proxy(master.as_raw_fd(), self.interactive) {
+                warn!("转发终端数据失败: {}", e);
+            }
+        }
+        drop(raw_mode);
+
+        let exit_code = process.wait();
+        state::remove_exec(&container_dir, &exec_id);
+        let exit_code = exit_code?;
+        info!("容器 {} 中的进程 {} 退出，退出码: {}", self.id, pid, exit_code);
+        Ok(exit_code)
+    }
+
+    fn override_process(&self, process: &mut oci::Process) -> Result<()> {
+        if let Some(ref user) = self.user {
+            let (uid, gid) = parse_user(user)?;
+            process.user = oci::User {
+                uid,
+                gid,
+                ..Default::default()
+            };
+        }
+        for env in &self.env {
+            let Some((key, _)) = env.split_once('=') else {
+                return Err(FireError::InvalidSpec(format!("环境变量需为 KEY=VALUE 形式: {}", env)));
+            };
+            process.env.retain(|e| e.split_once('=').map_or(e.as_str(), |(k, _)| k) != key);
+            process.env.push(env.clone());
+        }
+        if let Some(ref cwd) = self.cwd {
+            if !cwd.starts_with('/') {
+                return Err(FireError::InvalidSpec(format!("工作目录需为绝对路径: {}", cwd)));
+            }
+            process.cwd = cwd.clone();
+        }
+        Ok(())
+    }
+}
+
+/// 先写入临时文件再重命名，读取方不会读到不完整的内容
+fn write_pid_file(path: &Path, pid: i32) -> Result<()> {
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    std::fs::write(&tmp, pid.to_string())?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// 解析 `uid[:gid]`
+fn parse_user(user: &str) -> Result<(u32, u32)> {
+    let invalid = || FireError::InvalidSpec(format!("无效的用户，需为 uid[:gid]: {}", user));
+    let (uid, gid) = user.split_once(':').unwrap_or((user, "0"));
+    Ok((uid.parse().map_err(|_| invalid())?, gid.parse().map_err(|_| invalid())?))
+}
+
+impl super::Command for ExecCommand {
+    fn execute(&self) -> Result<()> {
+        self.run().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_process() {
+        let mut cmd = ExecCommand::new("test".to_string(), vec!["sh".to_string()]);
+        cmd.set_user(Some("1000:100".to_string()));
+        cmd.set_env(vec!["PATH=/bin".to_string(), "DEBUG=1".to_string()]);
+        cmd.set_cwd(Some("/tmp".to_string()));
+
+        let mut process = oci::Process {
+            env: vec!["PATH=/usr/bin".to_string(), "HOME=/root".to_string()],
+            cwd: "/".to_string(),
+            ..Default::default()
+        };
+        process.user.additional_gids = vec![10];
+        cmd.override_process(&mut process).unwrap();
+        assert_eq!((process.user.uid, process.user.gid), (1000, 100));
+        assert!(process.user.additional_gids.is_empty());
+        assert_eq!(process.env, ["HOME=/root", "PATH=/bin", "DEBUG=1"]);
+        assert_eq!(process.cwd, "/tmp");
+
+        assert_eq!(parse_user("0").unwrap(), (0, 0));
+        assert!(parse_user("root").is_err());
+        assert!(parse_user("1:x").is_err());
+        cmd.set_env(vec!["DEBUG".to_string()]);
+        assert!(cmd.override_process(&mut process).is_err());
+    }
+}
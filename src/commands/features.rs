@@ -0,0 +1,19 @@
+use crate::errors::Result;
+use crate::sysinfo;
+
+/// 以 JSON 输出探测到的内核特性，编排系统可以据此在创建容器之前选择可用的配置
+#[derive(Default)]
+pub struct FeaturesCommand;
+
+impl FeaturesCommand {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl super::Command for FeaturesCommand {
+    fn execute(&self) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(sysinfo::get())?);
+        Ok(())
+    }
+}
@@ -1,26 +1,126 @@
-use crate::errors::Result;
+use crate::cgroups;
+use crate::container;
+use crate::errors::{FireError, Result};
+use crate::logger;
 use crate::runtime::Runtime;
-use log::info;
+use crate::signals;
+use log::{info, warn};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use std::fs;
+use std::path::Path;
 
 pub struct KillCommand {
     pub id: String,
     pub signal: i32,
+    /// 向容器 cgroup 中的所有进程发送信号，而不只是 init 进程
+    pub all: bool,
+    /// 向 `fire exec` 启动的进程发送信号，而不是 init 进程
+    pub exec_id: Option<String>,
 }
 
 impl KillCommand {
     pub fn new(id: String, signal: i32) -> Self {
-        Self { id, signal }
+        Self {
+            id,
+            signal,
+            all: false,
+            exec_id: None,
+        }
+    }
+
+    pub fn set_all(&mut self, all: bool) {
+        self.all = all;
+    }
+
+    pub fn set_exec_id(&mut self, exec_id: Option<String>) {
+        self.exec_id = exec_id;
     }
 }
 
 impl super::Command for KillCommand {
+    #[tracing::instrument(name = "kill", skip_all, fields(id = %self.id))]
     fn execute(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
         info!("向容器 {} 发送信号 {}", self.id, self.signal);
 
+        let signal = Signal::try_from(self.signal)
+            .map_err(|_| FireError::InvalidSpec(format!("无效的信号: {}", self.signal)))?;
+
+        let state_file = container::state::root_dir().join(&self.id).join(container::state::STATE_FILE);
+        if !state_file.exists() {
+            return Err(FireError::NotFound(format!("容器 {} 不存在", self.id)));
+        }
+        let state: oci::State = serde_json::from_str(&fs::read_to_string(&state_file)?)?;
+
+        // 按 OCI 规范只能向运行中的容器发送信号，--all 可用于清理残留进程
+        match state.status.as_str() {
+            "running" | "paused" => {}
+            _ if self.all => {}
+            status => {
+                return Err(FireError::InvalidState(format!(
+                    "容器 {} 处于 {} 状态，无法发送信号",
+                    self.id, status
+                )));
+            }
+        }
+
         let mut runtime = Runtime::new();
-        runtime.kill_container(&self.id, self.signal)?;
+        let result = if let Some(ref exec_id) = self.exec_id {
+            let container_dir = state_file.parent().unwrap();
+            let (pid, start_time) = container::state::exec_process(container_dir, exec_id)?
+                .ok_or_else(|| FireError::InvalidState(format!("exec 进程 {} 已退出", exec_id)))?;
+            info!("向容器 {} 中的 exec 进程 {}（PID {}）发送信号", self.id, exec_id, pid);
+            // exec 进程退出不代表容器停止
+            signals::send_signal(pid, Some(start_time), signal).map_err(|e| match e {
+                FireError::Nix(Errno::ESRCH) => FireError::InvalidState(format!("exec 进程 {} 已退出", exec_id)),
+                e => e,
+            })
+        } else if self.all {
+            let spec = super::load_spec(&state)?;
+            cgroups::kill_all(&cgroups::cgroup_path_for_spec(&self.id, &spec)?, self.signal)
+        } else if state.pid <= 0 {
+            return Err(FireError::InvalidState(format!("容器 {} 没有 init 进程", self.id)));
+        } else if runtime.get_container(&self.id).is_some() {
+            runtime.kill_container(&self.id, self.signal)
+        } else {
+            signals::send_signal(state.pid, container::state::init_start_time(&state), signal)
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(FireError::Nix(Errno::ESRCH)) => {
+                self.mark_stopped(&state_file, state)?;
+                return Err(FireError::InvalidState(format!("容器 {} 已停止", self.id)));
+            }
+            Err(FireError::Nix(Errno::EPERM)) => {
+                return Err(FireError::PermissionDenied(format!(
+                    "没有权限向容器 {} 发送信号",
+                    self.id
+                )));
+            }
+            Err(FireError::Nix(e)) => {
+                return Err(FireError::Generic(format!(
+                    "向容器 {} 发送信号 {} 失败: {}",
+                    self.id, signal, e
+                )));
+            }
+            Err(e) => return Err(e),
+        }
 
         info!("信号 {} 已发送到容器 {}", self.signal, self.id);
         Ok(())
     }
 }
+
+impl KillCommand {
+    /// init 进程已经不存在，把状态文件更新为 stopped
+    fn mark_stopped(&self, state_file: &Path, state: oci::State) -> Result<()> {
+        if state.status == "stopped" {
+            return Ok(());
+        }
+        warn!("容器 {} 的 init 进程 {} 已退出，更新状态为 stopped", self.id, state.pid);
+        container::state::persist(state_file.parent().unwrap(), "stopped", state.pid)?;
+        Ok(())
+    }
+}
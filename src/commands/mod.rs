@@ -1,15 +1,85 @@
+use crate::container;
 use crate::errors::Result;
+use oci::Spec;
+use std::path::Path;
 
+pub mod console;
 pub mod create;
 pub mod delete;
+pub mod events;
+pub mod exec;
+pub mod features;
 pub mod kill;
 pub mod ps;
+#[cfg(feature = "image")]
+pub mod pull;
 pub mod run;
 pub mod start;
 pub mod state;
+#[cfg(feature = "image")]
+pub mod unpack;
+
+/// 读取容器生效的 OCI 配置，并合并状态文件中记录的注解
+///
+/// create 时生成的注解（如 cgroup 父路径）只保存在状态文件中。
+pub fn load_spec(state: &oci::State) -> Result<Spec> {
+    load_spec_in(&container::state::root_dir().join(&state.id), state)
+}
+
+/// 优先读取 create 时保存到容器目录的配置，其中已经包含 CDI 设备、运行时选项和非特权
+/// 运行的调整；之前版本创建的容器没有保存配置，读取 bundle 中的 config.json。
+fn load_spec_in(container_dir: &Path, state: &oci::State) -> Result<Spec> {
+    let saved = container_dir.join(container::state::SPEC_FILE);
+    let config_path = match saved.exists() {
+        true => saved,
+        false => Path::new(&state.bundle).join("config.json"),
+    };
+    if !config_path.exists() {
+        return Err(crate::errors::FireError::Generic(format!(
+            "配置文件不存在: {}",
+            config_path.display()
+        )));
+    }
+    let mut spec = Spec::load(&config_path.to_string_lossy()).map_err(|e| {
+        crate::errors::FireError::Generic(format!("无法读取OCI配置文件: {:?}", e))
+    })?;
+    spec.annotations.extend(state.annotations.clone());
+    Ok(spec)
+}
 
 /// 命令执行的通用trait
 pub trait Command {
     /// 执行命令
     fn execute(&self) -> Result<()>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_load_spec_prefers_saved() {
+        let dir = std::env::temp_dir().join(format!("fire-load-spec-{}", std::process::id()));
+        let (bundle, container_dir) = (dir.join("bundle"), dir.join("c1"));
+        fs::create_dir_all(&bundle).unwrap();
+        fs::create_dir_all(&container_dir).unwrap();
+        let spec = |hostname: &str| -> Spec {
+            serde_json::from_str(&format!(r#"{{"ociVersion":"1.0.2","hostname":"{}"}}"#, hostname)).unwrap()
+        };
+        spec("bundle").save(&bundle.join("config.json").to_string_lossy()).unwrap();
+        let state = oci::State {
+            id: "c1".to_string(),
+            bundle: bundle.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let from_bundle = load_spec_in(&container_dir, &state).unwrap();
+        spec("saved").save(&container_dir.join(container::state::SPEC_FILE).to_string_lossy()).unwrap();
+        let from_saved = load_spec_in(&container_dir, &state).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(from_bundle.hostname, "bundle");
+        assert_eq!(from_saved.hostname, "saved");
+    }
+}
@@ -1,13 +1,27 @@
 use crate::errors::Result;
 use crate::runtime::manager::RUNTIME_MANAGER;
 use crate::cgroups;
+use crate::container::{state, ContainerState, ContainerSummary};
 use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub struct PsCommand {}
+/// 计算 CPU% 时两次采样的间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct PsCommand {
+    pub stats: bool,
+}
 
 impl PsCommand {
     pub fn new() -> Self {
-        Self {}
+        Self { stats: false }
+    }
+
+    /// 输出 CPU% 和内存使用列，需要两次采样 cgroup 统计
+    pub fn set_stats(&mut self, stats: bool) {
+        self.stats = stats;
     }
 }
 
@@ -15,50 +29,107 @@ impl super::Command for PsCommand {
     fn execute(&self) -> Result<()> {
         info!("列出所有容器");
 
-        let manager = RUNTIME_MANAGER.lock().unwrap();
-        let containers = manager.list_containers();
+        // 只取摘要，采样和输出时不持有管理器和容器的锁
+        let mut containers = RUNTIME_MANAGER.lock().unwrap().list_summaries();
+        // 其他 fire 进程创建的容器只有状态文件
+        let persisted = persisted_summaries(&containers);
+        containers.extend(persisted);
+        containers.sort_by(|a, b| a.id.cmp(&b.id));
 
         if containers.is_empty() {
             println!("没有找到任何容器");
             return Ok(());
         }
 
+        // 运行中容器的 CPU% 和内存使用
+        let mut usage = HashMap::new();
+        if self.stats {
+            let running: Vec<_> = containers.iter().filter(|c| c.pid.is_some()).collect();
+            let before: Vec<_> = running
+                .iter()
+                .map(|c| {
+                    cgroups::stats::collect(&c.cgroup_path)
+                        .ok()
+                        .map(|s| (Instant::now(), s.cpu.usage_usec))
+                })
+                .collect();
+            std::thread::sleep(SAMPLE_INTERVAL);
+            for (container, before) in running.iter().zip(before) {
+                let (Some((begin, before_usec)), Ok(after)) =
+                    (before, cgroups::stats::collect(&container.cgroup_path))
+                else {
+                    continue;
+                };
+                let cpu = cpu_percent(before_usec, after.cpu.usage_usec, begin.elapsed());
+                usage.insert(container.id.as_str(), (cpu, after.memory.current));
+            }
+        }
+
         // 打印表头
-        println!("{:<20} {:<15} {:<10} {:<15} {:<30}", 
-            "CONTAINER ID", "STATE", "PID", "CGROUP", "COMMAND");
-        println!("{}", "-".repeat(90));
+        if self.stats {
+            println!("{:<20} {:<15} {:<10} {:<12} {:<10} {:<8} {:<10} {:<15} {:<30}",
+                "CONTAINER ID", "STATE", "PID", "CREATED", "UPTIME", "CPU%", "MEM", "CGROUP", "COMMAND");
+            println!("{}", "-".repeat(133));
+        } else {
+            println!("{:<20} {:<15} {:<10} {:<12} {:<10} {:<15} {:<30}",
+                "CONTAINER ID", "STATE", "PID", "CREATED", "UPTIME", "CGROUP", "COMMAND");
+            println!("{}", "-".repeat(113));
+        }
 
-        for container in containers {
-            let state = format!("{:?}", container.get_state()).to_lowercase();
-            let pid = container.get_main_process_pid()
+        for container in &containers {
+            let state = container.state.as_str();
+            let pid = container.pid
                 .map(|p| p.to_string())
                 .unwrap_or_else(|| "-".to_string());
             
-            let cgroup_path = container.get_cgroup_path();
+            let cgroup_path = &container.cgroup_path;
             let cgroup_display = if cgroup_path.len() > 25 {
                 format!("...{}", &cgroup_path[cgroup_path.len()-22..])
             } else {
                 cgroup_path.to_string()
             };
             
-            let command = if !container.spec.process.args.is_empty() {
-                container.spec.process.args.join(" ")
+            let command = if !container.command.is_empty() {
+                container.command.as_str()
             } else {
-                "N/A".to_string()
+                "N/A"
             };
             
             let command_display = if command.len() > 25 {
                 format!("{}...", &command[..22])
             } else {
-                command
+                command.to_string()
             };
 
-            println!("{:<20} {:<15} {:<10} {:<15} {:<30}", 
-                container.id, state, pid, cgroup_display, command_display);
+            let now = SystemTime::now();
+            let created = now
+                .duration_since(container.created_at)
+                .map(|d| format!("{} ago", format_uptime(d)))
+                .unwrap_or_else(|_| "-".to_string());
+            // 只有运行中和暂停的容器有运行时长，从 start 开始计算
+            let uptime = match (&container.state, container.started_at) {
+                (ContainerState::Running | ContainerState::Paused, Some(started_at)) => now
+                    .duration_since(started_at)
+                    .map(format_uptime)
+                    .unwrap_or_else(|_| "-".to_string()),
+                _ => "-".to_string(),
+            };
+
+            if self.stats {
+                let (cpu, mem) = match usage.get(container.id.as_str()) {
+                    Some((cpu, mem)) => (format!("{:.1}", cpu), format_bytes(*mem)),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                println!("{:<20} {:<15} {:<10} {:<12} {:<10} {:<8} {:<10} {:<15} {:<30}",
+                    container.id, state, pid, created, uptime, cpu, mem, cgroup_display, command_display);
+            } else {
+                println!("{:<20} {:<15} {:<10} {:<12} {:<10} {:<15} {:<30}",
+                    container.id, state, pid, created, uptime, cgroup_display, command_display);
+            }
             
             // 显示详细的 cgroup 信息
-            if container.get_main_process_pid().is_some() {
-                let cgroup_procs = cgroups::get_procs("cpuset", cgroup_path);
+            if container.pid.is_some() {
+                let cgroup_procs = cgroups::get_procs(cgroup_path).unwrap_or_default();
                 if !cgroup_procs.is_empty() {
                     println!("  └─ Cgroup 进程: {:?}", cgroup_procs);
                 }
@@ -74,3 +145,101 @@ impl Default for PsCommand {
         Self::new()
     }
 }
+
+/// 由状态目录下的状态文件生成摘要，跳过已在管理器中的容器
+fn persisted_summaries(known: &[ContainerSummary]) -> Vec<ContainerSummary> {
+    let Ok(entries) = fs::read_dir(state::root_dir()) else {
+        return Vec::new();
+    };
+
+    let mut summaries = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(state) = state::load(&entry.path()) else {
+            continue;
+        };
+        if known.iter().any(|c| c.id == state.id) {
+            continue;
+        }
+        let Some(container_state) = ContainerState::from_status(&state.status) else {
+            continue;
+        };
+        let spec = super::load_spec(&state).ok();
+        let cgroup_path = spec
+            .as_ref()
+            .and_then(|spec| cgroups::cgroup_path_for_spec(&state.id, spec).ok())
+            .unwrap_or_default();
+        let command = spec.map(|spec| spec.process.args.join(" ")).unwrap_or_default();
+        // 旧版本创建的容器没有记录创建时间，以状态文件目录的修改时间代替
+        let created_at = state::recorded_time(&state, state::ANNOTATION_CREATED)
+            .or_else(|| entry.metadata().and_then(|m| m.modified()).ok())
+            .unwrap_or(UNIX_EPOCH);
+        let pid = match container_state {
+            ContainerState::Stopped => None,
+            _ => Some(state.pid).filter(|&pid| pid > 0),
+        };
+
+        summaries.push(ContainerSummary {
+            id: state.id.clone(),
+            state: container_state,
+            pid,
+            cgroup_path,
+            command,
+            created_at,
+            started_at: state::recorded_time(&state, state::ANNOTATION_STARTED),
+        });
+    }
+    summaries
+}
+
+/// 两次采样之间的 CPU 使用率，100% 表示占满一个 CPU
+fn cpu_percent(before_usec: u64, after_usec: u64, elapsed: Duration) -> f64 {
+    let elapsed_usec = elapsed.as_micros() as f64;
+    if elapsed_usec == 0.0 {
+        return 0.0;
+    }
+    after_usec.saturating_sub(before_usec) as f64 / elapsed_usec * 100.0
+}
+
+/// 以 1d2h、3h4m、5m6s、7s 的形式显示时长
+fn format_uptime(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes, seconds) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+    if days > 0 {
+        format!("{}d{}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m{}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_columns() {
+        assert_eq!(cpu_percent(1_000, 251_000, Duration::from_millis(500)), 50.0);
+        assert_eq!(format_uptime(Duration::from_secs(42)), "42s");
+        assert_eq!(format_uptime(Duration::from_secs(3 * 3600 + 125)), "3h2m");
+        assert_eq!(format_uptime(Duration::from_secs(2 * 86400 + 3600)), "2d1h");
+        assert_eq!(format_bytes(512), "512B");
+        assert_eq!(format_bytes(12 * 1024 * 1024 + 300 * 1024), "12.3MiB");
+    }
+}
@@ -0,0 +1,26 @@
+use crate::errors::Result;
+use crate::image;
+use log::info;
+use std::path::PathBuf;
+
+pub struct PullCommand {
+    pub image: String,
+    pub bundle: PathBuf,
+}
+
+impl PullCommand {
+    pub fn new(image: String, bundle: PathBuf) -> Self {
+        Self { image, bundle }
+    }
+}
+
+impl super::Command for PullCommand {
+    #[tracing::instrument(name = "pull", skip_all, fields(image = %self.image))]
+    fn execute(&self) -> Result<()> {
+        info!("拉取镜像 {} 到 {}", self.image, self.bundle.display());
+        let digest = image::pull(&self.image, &self.bundle)?;
+        // 与 docker pull 一样输出镜像清单的 digest
+        println!("{}", digest);
+        Ok(())
+    }
+}
@@ -1,32 +1,253 @@
+use crate::cgroups::{
+    self,
+    events::{oom_kill_count, ANNOTATION_OOM_KILLED, ANNOTATION_OOM_RESTARTS},
+};
 use crate::commands::create::CreateCommand;
+use crate::commands::delete::DeleteCommand;
 use crate::commands::start::StartCommand;
+use crate::commands::Command;
+use crate::container::state;
 use crate::errors::Result;
-use log::info;
+use crate::{logger, nix_ext};
+use crate::network::etcfiles::DnsConfig;
+use crate::runtime::hooks;
+use crate::runtime::health::{Monitor, ANNOTATION_HEALTH_RESTARTS};
+use crate::runtime::options::{HealthAction, OomPolicy, RuntimeOptions};
+use crate::signals::{self, Reaper};
+use log::{debug, info, warn};
+use nix::sys::signal::Signal;
+use oci::Spec;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// 前台运行时转发终止信号后等待的默认秒数，与 docker stop 相同
+pub const DEFAULT_STOP_TIMEOUT: u64 = 10;
 
 pub struct RunCommand {
     pub id: String,
     pub bundle: Option<String>,
+    pub dns: DnsConfig,
+    pub rootfs_layers: Vec<PathBuf>,
+    pub cgroup_parent: Option<String>,
+    pub detach: bool,
+    pub keep: bool,
+    /// 转发终止信号后等待容器退出的时间，超时后发送 SIGKILL
+    pub stop_timeout: Duration,
+    pub console_socket: Option<PathBuf>,
 }
 
 impl RunCommand {
     pub fn new(id: String, bundle: Option<String>) -> Self {
-        Self { id, bundle }
+        Self {
+            id,
+            bundle,
+            dns: DnsConfig::default(),
+            rootfs_layers: Vec::new(),
+            cgroup_parent: None,
+            detach: false,
+            keep: false,
+            stop_timeout: Duration::from_secs(DEFAULT_STOP_TIMEOUT),
+            console_socket: None,
+        }
     }
-}
 
-impl super::Command for RunCommand {
-    fn execute(&self) -> Result<()> {
+    /// 设置DNS配置（优先于注解）
+    pub fn set_dns(&mut self, dns: DnsConfig) {
+        self.dns = dns;
+    }
+
+    /// 设置用于组装 overlayfs 根文件系统的镜像层（优先于注解）
+    pub fn set_rootfs_layers(&mut self, layers: Vec<PathBuf>) {
+        self.rootfs_layers = layers;
+    }
+
+    /// 设置容器 cgroup 的父路径（优先于注解）
+    pub fn set_cgroup_parent(&mut self, parent: Option<String>) {
+        self.cgroup_parent = parent;
+    }
+
+    /// 启动后立即返回，不等待容器退出
+    pub fn set_detach(&mut self, detach: bool) {
+        self.detach = detach;
+    }
+
+    /// 前台运行的容器退出后保留容器，不自动删除
+    pub fn set_keep(&mut self, keep: bool) {
+        self.keep = keep;
+    }
+
+    pub fn set_stop_timeout(&mut self, timeout: Duration) {
+        self.stop_timeout = timeout;
+    }
+
+    /// process.terminal 为 true 时把 pty 主端发送到该 unix socket（如 `fire console`）
+    pub fn set_console_socket(&mut self, console_socket: Option<PathBuf>) {
+        self.console_socket = console_socket;
+    }
+
+    /// 创建并启动容器，前台运行时等待主进程退出并返回其退出码
+    ///
+    /// 前台运行时容器的 init 进程随当前进程退出（PR_SET_PDEATHSIG），当前进程收到的
+    /// SIGTERM、SIGINT 转发给 init，stop_timeout 后仍未退出时强制终止，之后不再重启。
+    /// 当前进程同时是 subreaper，没有独立 pid namespace 的容器中的孤儿进程由它回收。
+    ///
+    /// 前台运行的容器被 OOM kill 时按 org.fire.oom.policy 注解处理：mark 在状态中
+    /// 记录后保留容器，restart 删除后按原配置重新创建并启动。设置了 org.fire.health.*
+    /// 注解时在后台执行健康检查，持续 unhealthy 的容器同样可以终止或重启。
+    #[tracing::instrument(name = "run", skip_all, fields(id = %self.id, bundle = ?self.bundle))]
+    pub fn run(&self) -> Result<i32> {
+        let _scope = logger::container_scope(&self.id);
         info!("运行容器: {}", self.id);
 
-        // 先创建容器
-        let create_cmd = CreateCommand::new(self.id.clone(), self.bundle.clone());
-        create_cmd.execute()?;
+        if !self.detach {
+            if let Err(e) = nix_ext::set_child_subreaper() {
+                warn!("设置 subreaper 失败: {}", e);
+            }
+        }
+        self.create_and_start()?;
+        if self.detach {
+            return Ok(0);
+        }
+
+        // 前台运行：主进程是当前进程的子进程，退出后更新状态并清理
+        let container_dir = state::root_dir().join(&self.id);
+        let mut oom_restarts = 0;
+        let mut health_restarts = 0;
+        loop {
+            let running = state::load(&container_dir)?;
+            let monitor = self.watch_health(&container_dir, &running);
+            let (exit_code, stopping) = Reaper::install()?.wait_forwarding(running.pid, self.stop_timeout)?;
+            info!("容器 {} 的主进程退出，退出码: {}", self.id, exit_code);
+            // 先停止健康检查，之后只有当前线程写状态文件
+            let unhealthy = monitor.and_then(|(monitor, action)| monitor.stop().then_some(action));
 
-        // 然后启动容器
-        let start_cmd = StartCommand::new(self.id.clone());
-        start_cmd.execute()?;
+            // init 一退出就记录状态并执行 poststop 钩子，不依赖之后的 delete
+            let stopped = state::mark_exited(&container_dir, exit_code)?;
+            let oom_policy = match super::load_spec(&stopped) {
+                Ok(spec) => {
+                    if stopping {
+                        self.kill_remaining(&spec);
+                    }
+                    hooks::run_poststop(&spec, &stopped);
+                    self.oom_policy(&spec, exit_code)
+                }
+                Err(e) => {
+                    warn!("读取容器配置失败，跳过 poststop 钩子: {}", e);
+                    None
+                }
+            };
+            let orphans = signals::reap_orphans();
+            if orphans > 0 {
+                debug!("回收了 {} 个孤儿进程", orphans);
+            }
 
+            if let Some(HealthAction::Restart(policy)) = unhealthy.filter(|_| !stopping) {
+                if let Some(delay) = policy.delay(health_restarts) {
+                    health_restarts += 1;
+                    self.restart(&container_dir, delay, ANNOTATION_HEALTH_RESTARTS, health_restarts)?;
+                    continue;
+                }
+            }
+
+            match oom_policy {
+                Some(policy) if unhealthy.is_none() => {
+                    warn!("容器 {} 被 OOM kill", self.id);
+                    state::annotate(&container_dir, ANNOTATION_OOM_KILLED, "true")?;
+                    if let Some(delay) = policy.restart_delay(oom_restarts).filter(|_| !stopping) {
+                        oom_restarts += 1;
+                        self.restart(&container_dir, delay, ANNOTATION_OOM_RESTARTS, oom_restarts)?;
+                        continue;
+                    }
+                    info!("保留被 OOM kill 的容器 {}", self.id);
+                }
+                _ if self.keep => info!("保留已停止的容器 {}", self.id),
+                _ => DeleteCommand::new(self.id.clone(), false).execute()?,
+            }
+            return Ok(exit_code);
+        }
+    }
+
+    fn create_and_start(&self) -> Result<()> {
+        let mut create_cmd = CreateCommand::new(self.id.clone(), self.bundle.clone());
+        create_cmd.set_dns(self.dns.clone());
+        create_cmd.set_rootfs_layers(self.rootfs_layers.clone());
+        create_cmd.set_cgroup_parent(self.cgroup_parent.clone());
+        create_cmd.set_console_socket(self.console_socket.clone());
+        if !self.detach {
+            create_cmd.set_parent_death_signal(Some(Signal::SIGKILL));
+        }
+        create_cmd.execute()?;
+
+        StartCommand::new(self.id.clone()).execute()?;
         info!("容器 {} 创建并启动成功", self.id);
         Ok(())
     }
+
+    /// 没有独立 pid namespace 时 init 退出不会带走容器中的其他进程，停止时一并终止
+    fn kill_remaining(&self, spec: &Spec) {
+        let result = cgroups::cgroup_path_for_spec(&self.id, spec)
+            .and_then(|path| cgroups::kill_all(&path, Signal::SIGKILL as i32));
+        if let Err(e) = result {
+            warn!("终止容器 {} 中的剩余进程失败: {}", self.id, e);
+        }
+    }
+
+    /// 删除已停止的容器，等待 delay 后按原配置重新创建并启动，并在新容器的状态中记录重启次数
+    fn restart(&self, container_dir: &Path, delay: Duration, annotation: &str, restarts: u32) -> Result<()> {
+        info!("{:?} 后第 {} 次重启容器 {}", delay, restarts, self.id);
+        DeleteCommand::new(self.id.clone(), false).execute()?;
+        std::thread::sleep(delay);
+        self.create_and_start()?;
+        state::annotate(container_dir, annotation, &restarts.to_string())?;
+        Ok(())
+    }
+
+    /// 设置了健康检查时在后台启动检查，返回检查线程和持续 unhealthy 时的处理
+    fn watch_health(&self, container_dir: &Path, running: &oci::State) -> Option<(Monitor, HealthAction)> {
+        let result = super::load_spec(running).and_then(|spec| {
+            let Some(check) = RuntimeOptions::from_annotations(&spec.annotations)?.health_check else {
+                return Ok(None);
+            };
+            let action = check.action;
+            let monitor = Monitor::start(&self.id, container_dir.to_path_buf(), running.pid, &spec, check)?;
+            Ok(Some((monitor, action)))
+        });
+        result.unwrap_or_else(|e| {
+            warn!("启动容器 {} 的健康检查失败: {}", self.id, e);
+            None
+        })
+    }
+
+    /// 容器因 OOM kill 退出且设置了 OOM 策略时返回该策略
+    ///
+    /// init 被 SIGKILL 终止且 cgroup 中有 OOM kill 记录才视为 OOM，此时 cgroup 尚未删除。
+    fn oom_policy(&self, spec: &Spec, exit_code: i32) -> Option<OomPolicy> {
+        if exit_code != 128 + libc::SIGKILL {
+            return None;
+        }
+        let policy = match RuntimeOptions::from_annotations(&spec.annotations) {
+            Ok(options) => options.oom_policy?,
+            Err(e) => {
+                warn!("解析容器注解失败: {}", e);
+                return None;
+            }
+        };
+        if policy == OomPolicy::None {
+            return None;
+        }
+        match cgroups::cgroup_path_for_spec(&self.id, spec).and_then(|path| oom_kill_count(&path)) {
+            Ok(count) if count > 0 => Some(policy),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("读取容器 {} 的 OOM 计数失败: {}", self.id, e);
+                None
+            }
+        }
+    }
+}
+
+impl super::Command for RunCommand {
+    fn execute(&self) -> Result<()> {
+        self.run().map(|_| ())
+    }
 }
@@ -1,10 +1,10 @@
+use crate::container::{process, state, EXEC_FIFO};
 use crate::errors::Result;
+use crate::logger;
+use crate::runtime::hooks;
 use crate::runtime::manager::RUNTIME_MANAGER;
-use crate::container::Container;
-use log::info;
+use log::{info, warn};
 use std::fs;
-use std::path::Path;
-use oci::Spec;
 
 pub struct StartCommand {
     pub id: String,
@@ -17,14 +17,15 @@ impl StartCommand {
 }
 
 impl super::Command for StartCommand {
+    #[tracing::instrument(name = "start", skip_all, fields(id = %self.id))]
     fn execute(&self) -> Result<()> {
+        let _scope = logger::container_scope(&self.id);
         info!("启动容器: {}", self.id);
 
         // 检查容器状态文件是否存在
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let state_file = format!("{}/.fire/{}/state.json", home_dir, self.id);
-        if !std::path::Path::new(&state_file).exists() {
-            return Err(crate::errors::FireError::Generic(format!(
+        let state_file = state::root_dir().join(&self.id).join(state::STATE_FILE);
+        if !state_file.exists() {
+            return Err(crate::errors::FireError::NotFound(format!(
                 "容器 {} 不存在",
                 self.id
             )));
@@ -36,70 +37,34 @@ impl super::Command for StartCommand {
 
         // 检查容器当前状态
         if state.status != "created" {
-            return Err(crate::errors::FireError::Generic(format!(
+            return Err(crate::errors::FireError::InvalidState(format!(
                 "容器 {} 不在创建状态，当前状态: {}",
                 self.id, state.status
             )));
         }
 
-        // 检查容器是否已经在全局管理器中
-        {
-            let manager = RUNTIME_MANAGER.lock().unwrap();
-            if manager.get_container(&self.id).is_none() {
-                // 如果不存在，从状态文件重新创建
-                drop(manager);
-                
-                // 从 bundle 重新读取 OCI 配置
-                let config_path = Path::new(&state.bundle).join("config.json");
-                if !config_path.exists() {
-                    return Err(crate::errors::FireError::Generic(format!(
-                        "配置文件不存在: {}",
-                        config_path.display()
-                    )));
-                }
+        // init 进程在 create 时已经启动，PID 以状态文件为准
+        let pid = state.pid;
+        let container_dir = state_file.parent().unwrap();
 
-                let spec = Spec::load(config_path.to_str().unwrap())
-                    .map_err(|e| crate::errors::FireError::Generic(format!(
-                        "无法读取OCI配置文件: {:?}",
-                        e
-                    )))?;
-
-                // 重新创建容器实例
-                let container = Container::new(self.id.clone(), spec, state.bundle.clone())?;
-                RUNTIME_MANAGER.lock().unwrap().create_container(self.id.clone(), container)?;
-            }
-        }
-
-        // 启动容器
-        RUNTIME_MANAGER.lock().unwrap().start_container(&self.id)?;
-
-        // 获取容器信息以更新状态
-        let pid = {
-            let manager = RUNTIME_MANAGER.lock().unwrap();
-            let container = manager.get_container(&self.id)
-                .ok_or_else(|| crate::errors::FireError::Generic(
-                    format!("容器 {} 未找到", self.id)
-                ))?;
-            container.get_main_process_pid().unwrap_or(0)
+        // create 在同一进程中时由容器实例放行，否则直接通过 exec.fifo 放行 init 进程
+        let managed = RUNTIME_MANAGER.lock().unwrap().get_container(&self.id).is_some();
+        let new_state = if managed {
+            // 容器实例负责写回状态文件
+            RUNTIME_MANAGER.lock().unwrap().start_container(&self.id)?;
+            serde_json::from_str(&fs::read_to_string(&state_file)?)?
+        } else {
+            process::release_exec_fifo(&container_dir.join(EXEC_FIFO), pid)?;
+            state::persist(container_dir, "running", pid)?
         };
 
-        // 更新容器状态为running
-        let new_state = oci::State {
-            version: state.version,
-            id: state.id,
-            status: "running".to_string(),
-            pid,
-            bundle: state.bundle,
-            annotations: state.annotations,
-        };
-
-        // 保存新状态
-        let new_state_json = new_state
-            .to_string()
-            .map_err(|e| crate::errors::FireError::Generic(format!("状态序列化失败: {:?}", e)))?;
-        fs::write(&state_file, new_state_json)?;
+        // 按 OCI 规范在 start 返回前执行 poststart 钩子
+        match super::load_spec(&new_state) {
+            Ok(spec) => hooks::run_poststart(&spec, &new_state),
+            Err(e) => warn!("读取容器配置失败，跳过 poststart 钩子: {}", e),
+        }
 
-        info!("容器 {} 启动成功", self.id);
+        info!("容器 {} 启动成功，init 进程 PID: {}", self.id, pid);
         Ok(())
     }
 }
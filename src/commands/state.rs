@@ -1,29 +1,65 @@
+use crate::cgroups::{self, stats::CgroupStats};
 use crate::errors::Result;
-use crate::container::Container;
+use crate::logger;
+use crate::container::{state as container_state, Container};
 use log::info;
+use std::collections::BTreeMap;
 use std::fs;
-use oci::Spec;
+use std::path::Path;
+
+/// 附加到状态 JSON 中的生命周期时间字段及其来源注解
+const TIMESTAMPS: [(&str, &str); 3] = [
+    ("created", container_state::ANNOTATION_CREATED),
+    ("started", container_state::ANNOTATION_STARTED),
+    ("finished", container_state::ANNOTATION_FINISHED),
+];
 
 pub struct StateCommand {
     pub id: String,
+    pub stats: bool,
+    pub human: bool,
+    /// 输出所有容器的状态，忽略 id
+    pub all: bool,
 }
 
 impl StateCommand {
     pub fn new(id: String) -> Self {
-        Self { id }
+        Self {
+            id,
+            stats: false,
+            human: false,
+            all: false,
+        }
+    }
+
+    pub fn set_stats(&mut self, stats: bool) {
+        self.stats = stats;
+    }
+
+    /// 以易读的列表输出，而不是 OCI 状态 JSON
+    pub fn set_human(&mut self, human: bool) {
+        self.human = human;
+    }
+
+    /// 以 JSON 数组输出状态目录下所有容器的状态，供监控程序一次读取
+    pub fn set_all(&mut self, all: bool) {
+        self.all = all;
     }
 }
 
 impl super::Command for StateCommand {
     fn execute(&self) -> Result<()> {
-        info!("获取容器状态: {}", self.id);
+        if self.all {
+            return self.print_all(&container_state::root_dir());
+        }
 
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let state_file = format!("{}/.fire/{}/state.json", home_dir, self.id);
+        let _scope = logger::container_scope(&self.id);
+        info!("获取容器状态: {}", self.id);
+        let state_file = container_state::root_dir().join(&self.id).join(container_state::STATE_FILE);
 
         // 检查容器状态文件是否存在
-        if !std::path::Path::new(&state_file).exists() {
-            return Err(crate::errors::FireError::Generic(format!(
+        if !state_file.exists() {
+            return Err(crate::errors::FireError::NotFound(format!(
                 "容器 {} 不存在",
                 self.id
             )));
@@ -31,8 +67,75 @@ impl super::Command for StateCommand {
 
         // 读取容器状态
         let state_content = fs::read_to_string(&state_file)?;
-        let state: oci::State = serde_json::from_str(&state_content)?;
+        let state = refresh(serde_json::from_str(&state_content)?);
+
+        if self.human {
+            return self.print_human(state);
+        }
 
+        // 供其他工具解析的 OCI 状态，--stats 时附加 stats 字段
+        let mut output = to_output(&state)?;
+        if self.stats {
+            output["stats"] = serde_json::to_value(self.collect_stats(&state)?)?;
+        }
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        Ok(())
+    }
+}
+
+/// 进程已退出但状态文件尚未更新时视为 stopped
+fn refresh(mut state: oci::State) -> oci::State {
+    if state.status == "running" && !Path::new(&format!("/proc/{}", state.pid)).exists() {
+        state.status = "stopped".to_string();
+    }
+    state
+}
+
+/// OCI 状态 JSON，附加生命周期时间字段
+fn to_output(state: &oci::State) -> Result<serde_json::Value> {
+    let mut output = serde_json::to_value(state)?;
+    for (field, annotation) in TIMESTAMPS {
+        if let Some(time) = state.annotations.get(annotation) {
+            output[field] = time.clone().into();
+        }
+    }
+    Ok(output)
+}
+
+/// 按 id 排序读取目录下所有容器的状态，跳过没有状态文件的目录（如正在创建的容器）
+fn load_all(state_dir: &Path) -> Result<Vec<oci::State>> {
+    let mut states: Vec<oci::State> = match fs::read_dir(state_dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| container_state::load(&entry.path()).ok())
+            .map(refresh)
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    states.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(states)
+}
+
+impl StateCommand {
+    fn print_all(&self, state_dir: &Path) -> Result<()> {
+        let states = load_all(state_dir)?;
+        let mut outputs = Vec::with_capacity(states.len());
+        for state in &states {
+            let mut output = to_output(state)?;
+            // 已停止的容器没有 cgroup，只为能采集到的容器附加 stats
+            if self.stats {
+                if let Ok(stats) = self.collect_stats(state) {
+                    output["stats"] = serde_json::to_value(stats)?;
+                }
+            }
+            outputs.push(output);
+        }
+        println!("{}", serde_json::to_string_pretty(&outputs)?);
+        Ok(())
+    }
+
+    fn print_human(&self, state: oci::State) -> Result<()> {
         // 输出基本状态信息
         println!("容器状态信息:");
         println!("  ID: {}", state.id);
@@ -40,9 +143,18 @@ impl super::Command for StateCommand {
         println!("  进程ID: {}", state.pid);
         println!("  Bundle路径: {}", state.bundle);
         println!("  OCI版本: {}", state.version);
+        for (label, annotation) in [
+            ("创建时间", container_state::ANNOTATION_CREATED),
+            ("启动时间", container_state::ANNOTATION_STARTED),
+            ("结束时间", container_state::ANNOTATION_FINISHED),
+        ] {
+            if let Some(time) = state.annotations.get(annotation) {
+                println!("  {}: {}", label, time);
+            }
+        }
 
         // 尝试获取namespace信息
-        if let Ok(spec) = self.load_container_spec(&state.bundle) {
+        if let Ok(spec) = super::load_spec(&state) {
             if let Ok(container) = Container::new(state.id.clone(), spec, state.bundle.clone()) {
                 let namespace_info = container.get_namespace_info();
                 if !namespace_info.is_empty() {
@@ -53,9 +165,25 @@ impl super::Command for StateCommand {
                 } else {
                     println!("  Namespace信息: 无");
                 }
+
+                // 运行中的容器输出可直接加入的namespace路径
+                if state.pid > 0 && Path::new(&format!("/proc/{}", state.pid)).exists() {
+                    let paths: BTreeMap<_, _> =
+                        container.get_namespace_paths(state.pid).into_iter().collect();
+                    if !paths.is_empty() {
+                        println!("  Namespace路径:");
+                        for (ns_type, path) in paths {
+                            println!("    {}: {}", ns_type, path);
+                        }
+                    }
+                }
             }
         }
 
+        if self.stats {
+            self.print_stats(&state)?;
+        }
+
         // 输出注解信息
         if !state.annotations.is_empty() {
             println!("  注解:");
@@ -66,25 +194,65 @@ impl super::Command for StateCommand {
 
         Ok(())
     }
+
+    fn collect_stats(&self, state: &oci::State) -> Result<CgroupStats> {
+        let spec = super::load_spec(state)?;
+        cgroups::stats::collect(&cgroups::cgroup_path_for_spec(&state.id, &spec)?)
+    }
+
+    fn print_stats(&self, state: &oci::State) -> Result<()> {
+        let stats = self.collect_stats(state)?;
+        let limit = |limit: Option<u64>| limit.map_or("max".to_string(), |l| l.to_string());
+
+        println!("  资源统计:");
+        println!(
+            "    CPU: 使用 {}us, 节流 {} 次",
+            stats.cpu.usage_usec, stats.cpu.nr_throttled
+        );
+        println!(
+            "    内存: {} / {} 字节, OOM kill {} 次",
+            stats.memory.current,
+            limit(stats.memory.limit),
+            stats.memory.events.oom_kill
+        );
+        println!(
+            "    进程数: {} / {}, 峰值 {}, 达到上限 {} 次",
+            stats.pids.current,
+            limit(stats.pids.limit),
+            stats.pids.peak.map_or("-".to_string(), |p| p.to_string()),
+            stats.pids.events.max
+        );
+        Ok(())
+    }
 }
 
-impl StateCommand {
-    fn load_container_spec(&self, bundle_path: &str) -> Result<Spec> {
-        let config_path = format!("{}/config.json", bundle_path);
-        
-        if !std::path::Path::new(&config_path).exists() {
-            return Err(crate::errors::FireError::InvalidSpec(format!(
-                "配置文件不存在: {}",
-                config_path
-            )));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match Spec::load(&config_path) {
-            Ok(spec) => Ok(spec),
-            Err(e) => Err(crate::errors::FireError::InvalidSpec(format!(
-                "无法读取OCI配置文件: {:?}",
-                e
-            ))),
+    #[test]
+    fn test_load_all() {
+        let dir = std::env::temp_dir().join(format!("fire-state-all-{}", std::process::id()));
+        for (id, status) in [("web", "stopped"), ("db", "running")] {
+            fs::create_dir_all(dir.join(id)).unwrap();
+            let state = oci::State {
+                version: "1.0.0".to_string(),
+                id: id.to_string(),
+                status: status.to_string(),
+                pid: i32::MAX,
+                bundle: format!("/srv/bundles/{}", id),
+                annotations: Default::default(),
+            };
+            container_state::save(&dir.join(id).join(container_state::STATE_FILE), &state).unwrap();
         }
+        // 正在创建的容器还没有状态文件
+        fs::create_dir_all(dir.join("creating")).unwrap();
+
+        let states = load_all(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let summary: Vec<_> = states.iter().map(|s| (s.id.as_str(), s.status.as_str())).collect();
+        // init 进程已经不存在的 running 容器视为 stopped
+        assert_eq!(summary, [("db", "stopped"), ("web", "stopped")]);
+        assert!(load_all(&dir).unwrap().is_empty());
     }
 }
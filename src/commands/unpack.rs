@@ -0,0 +1,35 @@
+use crate::errors::Result;
+use crate::image;
+use log::info;
+use std::path::PathBuf;
+
+pub struct UnpackCommand {
+    pub source: PathBuf,
+    pub bundle: PathBuf,
+    pub image: Option<String>,
+}
+
+impl UnpackCommand {
+    pub fn new(source: PathBuf, bundle: PathBuf) -> Self {
+        Self {
+            source,
+            bundle,
+            image: None,
+        }
+    }
+
+    /// 设置要解压的镜像名称，source 中包含多个镜像时需要
+    pub fn set_image(&mut self, image: Option<String>) {
+        self.image = image;
+    }
+}
+
+impl super::Command for UnpackCommand {
+    #[tracing::instrument(name = "unpack", skip_all, fields(source = %self.source.display()))]
+    fn execute(&self) -> Result<()> {
+        info!("解压镜像 {} 到 {}", self.source.display(), self.bundle.display());
+        let digest = image::unpack(&self.source, &self.bundle, self.image.as_deref())?;
+        println!("{}", digest);
+        Ok(())
+    }
+}
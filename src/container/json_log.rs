@@ -0,0 +1,178 @@
+//! Docker json-file 格式的容器日志
+//!
+//! 容器的标准输出和标准错误分别写入两个管道，由 create 时 fork 出的转发进程逐行读取，
+//! 按 Docker 的记录格式追加到日志文件：
+//!
+//! ```text
+//! {"log":"hello\n","stream":"stdout","time":"2023-11-14T22:13:20.000000005Z"}
+//! ```
+//!
+//! 转发进程不在容器的 namespace 和 cgroup 中，容器内所有进程关闭输出后退出。
+
+use crate::errors::Result;
+use crate::rfc3339;
+use nix::unistd::{fork, ForkResult};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// 没有换行时单条记录的最大长度，与 Docker 相同，超出部分拆成多条记录
+const MAX_LINE: usize = 16 * 1024;
+
+#[derive(Serialize)]
+struct Record<'a> {
+    log: &'a str,
+    stream: &'a str,
+    time: String,
+}
+
+/// 容器标准输出和标准错误管道的写端，交给 init 进程 dup2 到 1 和 2
+pub struct Output {
+    pub stdout: OwnedFd,
+    pub stderr: OwnedFd,
+}
+
+/// fork 转发进程，返回容器输出管道的写端
+pub fn spawn_forwarder(path: &Path) -> Result<Output> {
+    // 在父进程中打开，路径或权限有问题时 create 直接报错
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let (stdout_read, stdout_write) = pipe()?;
+    let (stderr_read, stderr_write) = pipe()?;
+
+    match unsafe { fork() }? {
+        ForkResult::Parent { .. } => Ok(Output {
+            stdout: stdout_write,
+            stderr: stderr_write,
+        }),
+        ForkResult::Child => {
+            drop(stdout_write);
+            drop(stderr_write);
+            detach();
+            forward(file, [(stdout_read, "stdout"), (stderr_read, "stderr")]);
+            unsafe { libc::_exit(0) }
+        }
+    }
+}
+
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let (read, write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+    Ok(unsafe { (OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write)) })
+}
+
+/// 脱离 fire 的会话，不占用调用方的终端和输出管道
+fn detach() {
+    let _ = nix::unistd::setsid();
+    if let Ok(null) = OpenOptions::new().read(true).write(true).open("/dev/null") {
+        for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            unsafe { libc::dup2(null.as_raw_fd(), fd) };
+        }
+    }
+}
+
+/// 读取两个管道直到都关闭，把完整的行写成记录
+fn forward(mut file: File, streams: [(OwnedFd, &str); 2]) {
+    let mut buffers = [Vec::new(), Vec::new()];
+    let mut open = [true, true];
+    let mut chunk = [0u8; 8192];
+
+    while open.iter().any(|&o| o) {
+        let mut pollfds: Vec<libc::pollfd> = streams
+            .iter()
+            .map(|(fd, _)| libc::pollfd {
+                fd: fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        for (pollfd, &o) in pollfds.iter_mut().zip(&open) {
+            if !o {
+                // poll 忽略负数的 fd
+                pollfd.fd = -1;
+            }
+        }
+        if unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, -1) } < 0 {
+            if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        }
+
+        for (i, pollfd) in pollfds.iter().enumerate() {
+            if pollfd.fd < 0 || pollfd.revents == 0 {
+                continue;
+            }
+            let n = unsafe {
+                libc::read(pollfd.fd, chunk.as_mut_ptr() as *mut libc::c_void, chunk.len())
+            };
+            let eof = n <= 0;
+            if eof {
+                open[i] = false;
+            } else {
+                buffers[i].extend_from_slice(&chunk[..n as usize]);
+            }
+            for line in take_lines(&mut buffers[i], eof) {
+                let _ = file.write_all(record(&line, streams[i].1, SystemTime::now()).as_bytes());
+            }
+        }
+    }
+}
+
+/// 从缓冲区取出完整的行（包含换行），过长或结束时剩余的内容也作为一行
+fn take_lines(buffer: &mut Vec<u8>, eof: bool) -> Vec<Vec<u8>> {
+    let mut lines = Vec::new();
+    loop {
+        let end = match buffer.iter().position(|&b| b == b'\n') {
+            Some(pos) if pos < MAX_LINE => pos + 1,
+            _ if buffer.len() >= MAX_LINE => MAX_LINE,
+            _ if eof && !buffer.is_empty() => buffer.len(),
+            _ => break,
+        };
+        lines.push(buffer.drain(..end).collect());
+    }
+    lines
+}
+
+/// 一条 json-file 记录，以换行结尾，非 UTF-8 的内容按替换字符处理
+fn record(line: &[u8], stream: &str, time: SystemTime) -> String {
+    let record = Record {
+        log: &String::from_utf8_lossy(line),
+        stream,
+        time: rfc3339::format(time),
+    };
+    // 字段都是字符串，序列化不会失败
+    let mut json = serde_json::to_string(&record).unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_record() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        assert_eq!(
+            record(b"hello \"fire\"\n", "stderr", time),
+            "{\"log\":\"hello \\\"fire\\\"\\n\",\"stream\":\"stderr\",\"time\":\"2023-11-14T22:13:20.000000005Z\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_take_lines() {
+        let mut buffer = b"a\nbc\nd".to_vec();
+        assert_eq!(take_lines(&mut buffer, false), vec![b"a\n".to_vec(), b"bc\n".to_vec()]);
+        assert_eq!(buffer, b"d");
+        assert_eq!(take_lines(&mut buffer, true), vec![b"d".to_vec()]);
+
+        let mut long = vec![b'x'; MAX_LINE + 1];
+        let lines = take_lines(&mut long, false);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].len(), MAX_LINE);
+        assert_eq!(long.len(), 1);
+    }
+}
@@ -1,16 +1,28 @@
+pub mod json_log;
 pub mod namespace;
 pub mod process;
+pub mod setup;
 pub mod state;
 
 use crate::errors::Result;
 use crate::cgroups;
+use crate::rootfs::OverlayConfig;
+use crate::runtime::options::{LogDriver, RuntimeOptions};
+use crate::seccomp;
 use namespace::{NamespaceManager, NamespaceType};
 use oci::Spec;
 use process::Process;
+use setup::ContainerSetup;
 use std::collections::HashMap;
 use log::{info, warn, error};
 
-#[derive(Debug, Clone)]
+/// create 与 start 之间同步用的 FIFO，位于容器运行时目录下
+pub const EXEC_FIFO: &str = "exec.fifo";
+
+/// stop 发送 SIGTERM 后等待主进程退出的时间，超时后发送 SIGKILL
+const STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Debug)]
 pub struct Container {
     pub id: String,
     pub spec: Spec,
@@ -18,9 +30,26 @@ pub struct Container {
     pub state: ContainerState,
     pub processes: HashMap<i32, process::Process>,
     pub created_at: std::time::SystemTime,
+    pub started_at: Option<std::time::SystemTime>,
     pub namespace_manager: Option<NamespaceManager>,
     pub cgroup_path: String,
+    pub cgroups_enabled: bool,
     pub main_process: Option<Process>,
+    /// 由镜像层组装根文件系统时的 overlayfs 配置，由 init 在自己的 mount namespace 中挂载
+    pub overlay: Option<OverlayConfig>,
+}
+
+/// 列出容器时需要的信息，不复制 spec 和进程等完整结构
+#[derive(Debug, Clone)]
+pub struct ContainerSummary {
+    pub id: String,
+    pub state: ContainerState,
+    pub pid: Option<i32>,
+    pub cgroup_path: String,
+    /// 主进程的命令行，以空格连接
+    pub command: String,
+    pub created_at: std::time::SystemTime,
+    pub started_at: Option<std::time::SystemTime>,
 }
 
 #[derive(Debug, Clone)]
@@ -31,24 +60,41 @@ pub enum ContainerState {
     Paused,
 }
 
+impl ContainerState {
+    /// OCI 状态文件中的 status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContainerState::Created => "created",
+            ContainerState::Running => "running",
+            ContainerState::Stopped => "stopped",
+            ContainerState::Paused => "paused",
+        }
+    }
+
+    /// 由状态文件中的 status 解析，未知的状态返回 None
+    pub fn from_status(status: &str) -> Option<Self> {
+        match status {
+            "created" => Some(ContainerState::Created),
+            "running" => Some(ContainerState::Running),
+            "stopped" => Some(ContainerState::Stopped),
+            "paused" => Some(ContainerState::Paused),
+            _ => None,
+        }
+    }
+}
+
 impl Container {
+    #[tracing::instrument(name = "container_new", skip_all, fields(id = %id))]
     pub fn new(id: String, spec: Spec, bundle: String) -> Result<Self> {
         // 生成 cgroup 路径
-        let cgroup_path = if let Some(ref linux) = spec.linux {
-            if !linux.cgroups_path.is_empty() {
-                linux.cgroups_path.clone()
-            } else {
-                cgroups::generate_cgroup_path(&id, None)
-            }
-        } else {
-            cgroups::generate_cgroup_path(&id, None)
-        };
+        let cgroup_path = cgroups::cgroup_path_for_spec(&id, &spec)?;
 
         // 验证 cgroup 路径
         cgroups::validate_cgroup_path(&cgroup_path)?;
         
         // 检查 cgroup 是否可用
         cgroups::check_cgroup_mounted()?;
+        let cgroups_enabled = cgroups::rootless::skip_reason().is_none();
 
         // 创建namespace管理器
         let namespace_manager = if let Some(ref linux) = spec.linux {
@@ -73,12 +119,20 @@ impl Container {
 
         // 创建主进程
         let main_process = {
-            let mut process = Process::new(spec.process.args.clone());
-            process.set_env(spec.process.env.clone());
-            process.set_cwd(spec.process.cwd.clone());
-            
-            // 设置用户和组
-            process.set_uid_gid(Some(spec.process.user.uid), Some(spec.process.user.gid));
+            let mut process = Process::from_spec(&spec, spec.process.args.clone());
+            let container_dir = state::root_dir().join(&id);
+            let options = RuntimeOptions::from_annotations(&spec.annotations)?;
+            process.set_log_file(options.log_file(&container_dir));
+            process.set_sysctl(spec.linux.as_ref().map(|linux| linux.sysctl.clone()).unwrap_or_default());
+            process.set_json_log(options.log_driver == Some(LogDriver::JsonFile));
+            process.set_state(oci::State {
+                version: spec.version.clone(),
+                id: id.clone(),
+                status: "creating".to_string(),
+                pid: 0,
+                bundle: bundle.clone(),
+                annotations: spec.annotations.clone(),
+            });
             
             Some(process)
         };
@@ -90,47 +144,91 @@ impl Container {
             state: ContainerState::Created,
             processes: HashMap::new(),
             created_at: std::time::SystemTime::now(),
+            started_at: None,
             namespace_manager,
             cgroup_path,
+            cgroups_enabled,
             main_process,
+            overlay: None,
         })
     }
 
-    pub fn start(&mut self) -> Result<()> {
-        if !matches!(self.state, ContainerState::Created) {
-            return Err(crate::errors::FireError::Generic(format!(
-                "容器 {} 不在创建状态，无法启动",
+    /// 设置由镜像层组装根文件系统时的 overlayfs 配置
+    pub fn set_overlay(&mut self, overlay: Option<OverlayConfig>) {
+        self.overlay = overlay;
+    }
+
+    /// 创建 namespace 和 cgroup 并启动 init 进程，init 在执行容器命令前阻塞在
+    /// exec.fifo 上，由 start 放行。返回 init 进程的 PID。
+    #[tracing::instrument(name = "container_create", skip_all, fields(id = %self.id))]
+    pub fn create(&mut self) -> Result<i32> {
+        if !matches!(self.state, ContainerState::Created) || self.get_main_process_pid().is_some() {
+            return Err(crate::errors::FireError::InvalidState(format!(
+                "容器 {} 已经创建过 init 进程",
                 self.id
             )));
         }
 
-        info!("启动容器 {}", self.id);
+        info!("创建容器 {} 的 init 进程", self.id);
 
-        // 创建所有namespace
-        if let Some(ref mut namespace_manager) = self.namespace_manager {
-            info!("为容器 {} 创建namespace", self.id);
-            namespace_manager.create_all()?;
-            
-            // 记录创建的namespace类型
+        // namespace 和根文件系统由 init 进程自己准备，当前进程不受影响
+        if let Some(ref namespace_manager) = self.namespace_manager {
             let ns_types = namespace_manager.get_namespace_types();
-            info!("容器 {} 创建的namespace类型: {:?}", self.id, ns_types);
+            info!("容器 {} 的namespace类型: {:?}", self.id, ns_types);
         }
 
-        // 启动主进程
+        let container_dir = self.container_dir();
+        let rootfs = std::fs::canonicalize(&self.bundle)?.join(&self.spec.root.path);
+        let setup = ContainerSetup::new(self.spec.clone(), rootfs, self.overlay.clone(), container_dir.clone());
+        // init 在新的 user namespace 中以容器的 root 身份打开 exec.fifo
+        if let Some((uid, gid)) = setup.root_host_ids() {
+            nix::unistd::chown(
+                &container_dir.join(EXEC_FIFO),
+                Some(nix::unistd::Uid::from_raw(uid)),
+                Some(nix::unistd::Gid::from_raw(gid)),
+            )?;
+        }
+        if let Some(ref mut main_process) = self.main_process {
+            // 按需导出编译后的 seccomp 过滤器，子进程直接加载
+            if seccomp::export_enabled(&self.spec.annotations) {
+                if let Some(ref seccomp) = main_process.seccomp {
+                    let program = seccomp::export_program(seccomp, &container_dir)?;
+                    main_process.set_seccomp_program(program);
+                }
+            }
+            main_process.set_exec_fifo(Some(container_dir.join(EXEC_FIFO)));
+            main_process.set_setup(Some(setup));
+        }
+
+        // 启动主进程，在执行容器命令前加入 cgroup，避免早期子进程逃逸资源限制
+        let cgroup_path = self.cgroup_path.clone();
+        let resources = match self.spec.linux {
+            Some(ref linux) if self.cgroups_enabled => {
+                let mut resources = linux.resources.clone();
+                cgroups::blkio::apply_annotations(&mut resources, &self.spec.annotations)?;
+                Some(resources)
+            }
+            _ => None,
+        };
         let pid = if let Some(ref mut main_process) = self.main_process {
             info!("启动容器 {} 的主进程", self.id);
-            main_process.start()?
+            main_process.start_with(|pid| match resources {
+                Some(ref resources) => {
+                    info!("为容器进程 {} 应用 cgroup 限制，路径: {}", pid, cgroup_path);
+                    cgroups::create(resources, &cgroup_path)?;
+                    cgroups::join(&cgroup_path, pid)?;
+                    cgroups::apply(resources, &cgroup_path)
+                }
+                None => Ok(()),
+            })?
         } else {
-            return Err(crate::errors::FireError::Generic(
+            return Err(crate::errors::FireError::InvalidState(
                 "容器没有主进程".to_string()
             ));
         };
 
-        // 应用 cgroup 限制
-        if let Some(ref linux) = self.spec.linux {
-            info!("为容器 {} 应用 cgroup 限制，路径: {}", self.id, self.cgroup_path);
-            cgroups::apply_pid(&linux.resources, pid, &self.cgroup_path)?;
-            info!("cgroup 限制应用成功");
+        if !self.cgroups_enabled {
+            warn!("容器 {} 未配置 cgroup，资源限制不会生效", self.id);
         }
 
         // 将主进程添加到进程列表
@@ -138,15 +236,37 @@ impl Container {
             self.processes.insert(pid, main_process.clone());
         }
 
+        info!("容器 {} 创建成功，init 进程 PID: {}", self.id, pid);
+        Ok(pid)
+    }
+
+    /// 放行 create 中阻塞的 init 进程，执行容器命令
+    #[tracing::instrument(name = "container_start", skip_all, fields(id = %self.id))]
+    pub fn start(&mut self) -> Result<()> {
+        let pid = match (&self.state, self.get_main_process_pid()) {
+            (ContainerState::Created, Some(pid)) => pid,
+            _ => {
+                return Err(crate::errors::FireError::InvalidState(format!(
+                    "容器 {} 不在创建状态，无法启动",
+                    self.id
+                )))
+            }
+        };
+
+        info!("启动容器 {}", self.id);
+        process::release_exec_fifo(&self.container_dir().join(EXEC_FIFO), pid)?;
+
         // 设置容器状态为运行中
         self.state = ContainerState::Running;
+        self.started_at = Some(std::time::SystemTime::now());
+        self.persist_state()?;
         info!("容器 {} 启动成功，主进程 PID: {}", self.id, pid);
         Ok(())
     }
 
     pub fn stop(&mut self) -> Result<()> {
         if !matches!(self.state, ContainerState::Running) {
-            return Err(crate::errors::FireError::Generic(format!(
+            return Err(crate::errors::FireError::InvalidState(format!(
                 "容器 {} 不在运行状态，无法停止",
                 self.id
             )));
@@ -159,9 +279,16 @@ impl Container {
             if main_process.is_alive() {
                 info!("终止容器 {} 的主进程", self.id);
                 main_process.kill(15)?; // SIGTERM
-                
-                // 等待进程结束
-                match main_process.wait() {
+
+                // 等待进程结束，容器 pid namespace 中的 init 默认忽略 SIGTERM，超时后强制终止
+                let exited = match main_process.wait_timeout(STOP_TIMEOUT) {
+                    Ok(None) => {
+                        warn!("容器 {} 主进程在 {:?} 内没有退出，强制终止", self.id, STOP_TIMEOUT);
+                        main_process.kill(9).and_then(|_| main_process.wait())
+                    }
+                    result => result.map(|code| code.unwrap_or(0)),
+                };
+                match exited {
                     Ok(exit_code) => {
                         info!("容器 {} 主进程已结束，退出码: {}", self.id, exit_code);
                     }
@@ -172,15 +299,21 @@ impl Container {
             }
         }
 
+        // 清理主进程退出后残留在容器中的进程
+        if let Err(e) = cgroups::freeze_and_kill(&self.cgroup_path, libc::SIGKILL) {
+            warn!("终止容器 {} 的残留进程失败: {}", self.id, e);
+        }
+
         // 设置容器状态为停止
         self.state = ContainerState::Stopped;
+        self.persist_state()?;
         info!("容器 {} 停止成功", self.id);
         Ok(())
     }
 
     pub fn pause(&mut self) -> Result<()> {
         if !matches!(self.state, ContainerState::Running) {
-            return Err(crate::errors::FireError::Generic(format!(
+            return Err(crate::errors::FireError::InvalidState(format!(
                 "容器 {} 不在运行状态，无法暂停",
                 self.id
             )));
@@ -192,13 +325,14 @@ impl Container {
         cgroups::freeze(&self.cgroup_path)?;
         
         self.state = ContainerState::Paused;
+        self.persist_state()?;
         info!("容器 {} 暂停成功", self.id);
         Ok(())
     }
 
     pub fn resume(&mut self) -> Result<()> {
         if !matches!(self.state, ContainerState::Paused) {
-            return Err(crate::errors::FireError::Generic(format!(
+            return Err(crate::errors::FireError::InvalidState(format!(
                 "容器 {} 不在暂停状态，无法恢复",
                 self.id
             )));
@@ -206,48 +340,41 @@ impl Container {
 
         info!("恢复容器 {}", self.id);
         
-        // 检测 cgroup 版本并使用相应的恢复方法
-        let cgroup_version = cgroups::detect_cgroup_version()?;
-        match cgroup_version {
-            1 => {
-                // cgroup v1 使用 freezer.state
-                cgroups::write_file(
-                    &format!("/sys/fs/cgroup/freezer{}", self.cgroup_path),
-                    "freezer.state",
-                    "THAWED",
-                )?;
-            }
-            2 => {
-                // cgroup v2 使用 cgroup.freeze
-                cgroups::write_file(
-                    &format!("/sys/fs/cgroup{}", self.cgroup_path),
-                    "cgroup.freeze",
-                    "0",
-                )?;
-            }
-            _ => {
-                return Err(crate::errors::FireError::Generic(
-                    format!("不支持的 cgroup 版本: {}", cgroup_version)
-                ));
-            }
-        }
+        // cgroup v1 使用 freezer.state，cgroup v2 使用 cgroup.freeze
+        cgroups::thaw(&self.cgroup_path)?;
         
         self.state = ContainerState::Running;
+        self.persist_state()?;
         info!("容器 {} 恢复成功", self.id);
         Ok(())
     }
 
+    /// 获取容器 cgroup 的资源使用统计
+    pub fn stats(&self) -> Result<cgroups::stats::CgroupStats> {
+        cgroups::stats::collect(&self.cgroup_path)
+    }
+
+    /// 监听容器的 OOM 事件，callback 返回 false 时停止
+    pub fn watch_oom<F>(&self, callback: F) -> Result<()>
+    where
+        F: FnMut(&cgroups::events::OomEvent) -> bool,
+    {
+        cgroups::events::watch_oom(&self.cgroup_path, callback)
+    }
+
     pub fn cleanup(&mut self) -> Result<()> {
         info!("清理容器 {} 资源", self.id);
 
         // 清理 cgroup
-        match cgroups::remove(&self.cgroup_path) {
-            Ok(_) => {
-                info!("容器 {} 的 cgroup 清理成功", self.id);
-            }
-            Err(e) => {
-                error!("清理容器 {} 的 cgroup 失败: {}", self.id, e);
-                // 不返回错误，继续清理其他资源
+        if self.cgroups_enabled {
+            match cgroups::remove(&self.cgroup_path) {
+                Ok(_) => {
+                    info!("容器 {} 的 cgroup 清理成功", self.id);
+                }
+                Err(e) => {
+                    error!("清理容器 {} 的 cgroup 失败: {}", self.id, e);
+                    // 不返回错误，继续清理其他资源
+                }
             }
         }
 
@@ -259,6 +386,18 @@ impl Container {
         Ok(())
     }
 
+    pub fn summary(&self) -> ContainerSummary {
+        ContainerSummary {
+            id: self.id.clone(),
+            state: self.state.clone(),
+            pid: self.get_main_process_pid(),
+            cgroup_path: self.cgroup_path.clone(),
+            command: self.spec.process.args.join(" "),
+            created_at: self.created_at,
+            started_at: self.started_at,
+        }
+    }
+
     pub fn get_main_process_pid(&self) -> Option<i32> {
         self.main_process.as_ref().and_then(|p| p.pid)
     }
@@ -279,6 +418,22 @@ impl Container {
         &self.bundle
     }
 
+    /// 把当前状态写入状态文件，不是通过 create 命令创建的容器没有状态文件，跳过
+    fn persist_state(&self) -> Result<()> {
+        let container_dir = self.container_dir();
+        if !container_dir.join(state::STATE_FILE).exists() {
+            return Ok(());
+        }
+        let pid = self.get_main_process_pid().unwrap_or(0);
+        state::persist(&container_dir, self.state.as_str(), pid)?;
+        Ok(())
+    }
+
+    /// 容器的运行时目录，见 [`state::root_dir`]
+    pub fn container_dir(&self) -> std::path::PathBuf {
+        state::root_dir().join(&self.id)
+    }
+
     /// 检查容器是否有指定的namespace
     pub fn has_namespace(&self, ns_type: NamespaceType) -> bool {
         self.namespace_manager
@@ -321,10 +476,24 @@ impl Container {
         info
     }
 
+    /// 获取容器各namespace可供外部工具加入的路径
+    pub fn get_namespace_paths(&self, pid: i32) -> HashMap<String, String> {
+        self.namespace_manager
+            .as_ref()
+            .map(|manager| {
+                manager
+                    .get_namespace_paths(pid)
+                    .into_iter()
+                    .map(|(ns_type, path)| (ns_type.proc_path().to_string(), path))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// 执行容器内的命令（需要进入namespace）
     pub fn exec_in_container(&self, command: &[String]) -> Result<()> {
         if !matches!(self.state, ContainerState::Running) {
-            return Err(crate::errors::FireError::Generic(format!(
+            return Err(crate::errors::FireError::InvalidState(format!(
                 "容器 {} 不在运行状态，无法执行命令",
                 self.id
             )));
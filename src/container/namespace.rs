@@ -1,6 +1,6 @@
 use crate::errors::Result;
 use nix::fcntl::{open, OFlag};
-use nix::sched::{clone, unshare, CloneFlags};
+use nix::sched::{unshare, CloneFlags};
 use nix::sys::stat::Mode;
 use nix::unistd::{close, getpid};
 use std::os::unix::io::RawFd;
@@ -66,6 +66,9 @@ impl NamespaceType {
             oci::LinuxNamespaceType::uts => Ok(NamespaceType::Uts),
             oci::LinuxNamespaceType::user => Ok(NamespaceType::User),
             oci::LinuxNamespaceType::cgroup => Ok(NamespaceType::Cgroup),
+            oci::LinuxNamespaceType::time => Err(crate::errors::FireError::InvalidSpec(
+                "不支持的namespace类型: time".to_string(),
+            )),
         }
     }
 
@@ -223,6 +226,12 @@ impl NamespaceManager {
         
         for oci_ns in oci_namespaces {
             let namespace = Namespace::from_oci_namespace(oci_ns)?;
+            if manager.contains_namespace(namespace.ns_type) {
+                return Err(crate::errors::FireError::InvalidSpec(format!(
+                    "重复的namespace配置: {}",
+                    namespace.ns_type.proc_path()
+                )));
+            }
             manager.add_namespace(namespace);
         }
         
@@ -262,6 +271,11 @@ impl NamespaceManager {
         self.namespaces.insert(namespace.ns_type, namespace);
     }
 
+    /// 移除namespace，不再由管理器创建或加入
+    pub fn remove_namespace(&mut self, ns_type: NamespaceType) -> Option<Namespace> {
+        self.namespaces.remove(&ns_type)
+    }
+
     /// 获取namespace
     pub fn get_namespace(&self, ns_type: NamespaceType) -> Option<&Namespace> {
         self.namespaces.get(&ns_type)
@@ -273,6 +287,7 @@ impl NamespaceManager {
     }
 
     /// 创建所有namespace
+    #[tracing::instrument(name = "namespaces", skip_all)]
     pub fn create_all(&mut self) -> Result<()> {
         info!("开始创建所有namespace");
         
@@ -316,6 +331,20 @@ impl NamespaceManager {
         Ok(())
     }
 
+    /// 获取运行中容器各namespace的路径
+    ///
+    /// 加入已有namespace（例如持久化的绑定挂载）时返回配置的路径，
+    /// 否则返回init进程的 `/proc/<pid>/ns/*` 路径。
+    pub fn get_namespace_paths(&self, pid: i32) -> HashMap<NamespaceType, String> {
+        self.namespaces
+            .iter()
+            .map(|(ns_type, ns)| {
+                let path = ns.path.clone().unwrap_or_else(|| ns.process_path(pid));
+                (*ns_type, path)
+            })
+            .collect()
+    }
+
     /// 获取所有namespace类型
     pub fn get_namespace_types(&self) -> Vec<NamespaceType> {
         self.namespaces.keys().cloned().collect()
@@ -351,6 +380,58 @@ impl NamespaceManager {
             }
         }
 
+        // 加入已有user namespace时不能再写入映射
+        if let Some(user_ns) = self.namespaces.get(&NamespaceType::User) {
+            if user_ns.path.is_some() && self.user_mapping.is_some() {
+                return Err(crate::errors::FireError::InvalidSpec(
+                    "加入已有user namespace时不能指定uidMappings/gidMappings".to_string()
+                ));
+            }
+        }
+
+        // 检查内核支持以及namespace路径的类型
+        for (ns_type, namespace) in &self.namespaces {
+            match namespace.path {
+                Some(ref path) => validate_namespace_path(*ns_type, path)?,
+                None => {
+                    let proc_path = format!("/proc/self/ns/{}", ns_type.proc_path());
+                    if !Path::new(&proc_path).exists() {
+                        return Err(crate::errors::FireError::KernelUnsupported(format!(
+                            "内核不支持 {} namespace（{} 不存在）",
+                            ns_type.proc_path(),
+                            proc_path
+                        )));
+                    }
+                }
+            }
+        }
+
+        // 加入已有的user namespace时，被加入的其他namespace必须属于它，否则setns会因缺少权限而失败；
+        // 新建的user namespace由内核在加入时检查权限
+        if let Some(user_path) = self
+            .namespaces
+            .get(&NamespaceType::User)
+            .and_then(|user_ns| user_ns.path.as_ref())
+        {
+            let user = namespace_inode(user_path)?;
+            for (ns_type, namespace) in &self.namespaces {
+                let path = match (ns_type, &namespace.path) {
+                    (NamespaceType::User, _) | (_, None) => continue,
+                    (_, Some(path)) => path,
+                };
+                if let Some(owner) = namespace_owner_inode(path)? {
+                    if owner != user {
+                        return Err(crate::errors::FireError::InvalidSpec(format!(
+                            "{} namespace {} 不属于user namespace {}",
+                            ns_type.proc_path(),
+                            path,
+                            user_path
+                        )));
+                    }
+                }
+            }
+        }
+
         // 检查namespace组合是否有效
         if self.contains_namespace(NamespaceType::Pid) 
             && !self.contains_namespace(NamespaceType::Mount) {
@@ -388,6 +469,68 @@ impl NamespaceManager {
     }
 }
 
+// linux/nsfs.h 中的 ioctl 请求
+const NS_GET_USERNS: libc::c_ulong = 0xb701;
+const NS_GET_NSTYPE: libc::c_ulong = 0xb703;
+
+/// 校验namespace路径确实指向对应类型的namespace文件
+fn validate_namespace_path(ns_type: NamespaceType, path: &str) -> Result<()> {
+    let file = fs::File::open(path).map_err(|e| {
+        crate::errors::FireError::InvalidSpec(format!(
+            "无法打开 {} namespace路径 {}: {}",
+            ns_type.proc_path(),
+            path,
+            e
+        ))
+    })?;
+
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), NS_GET_NSTYPE as _) };
+    if res == -1 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOTTY) {
+            // 旧内核不支持 NS_GET_NSTYPE，或路径不是namespace文件
+            warn!("无法校验namespace路径类型: {}: {}", path, err);
+            return Ok(());
+        }
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "{} 不是有效的namespace文件: {}",
+            path, err
+        )));
+    }
+
+    if res != ns_type.clone_flag().bits() {
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "namespace路径类型不匹配: {} 不是 {} namespace",
+            path,
+            ns_type.proc_path()
+        )));
+    }
+    Ok(())
+}
+
+/// 获取namespace文件的inode
+fn namespace_inode(path: &str) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(path)?.ino())
+}
+
+/// 获取拥有该namespace的user namespace的inode
+fn namespace_owner_inode(path: &str) -> Result<Option<u64>> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::FromRawFd;
+
+    let file = fs::File::open(path)?;
+    let fd = unsafe { libc::ioctl(file.as_raw_fd(), NS_GET_USERNS as _) };
+    if fd == -1 {
+        let err = std::io::Error::last_os_error();
+        warn!("无法查询namespace所属的user namespace: {}: {}", path, err);
+        return Ok(None);
+    }
+
+    let owner = unsafe { fs::File::from_raw_fd(fd) };
+    Ok(Some(owner.metadata()?.ino()))
+}
+
 /// 进入指定的namespace
 pub fn enter_namespace(namespace: &Namespace) -> Result<()> {
     debug!("进入namespace: {:?}", namespace.ns_type);
@@ -476,6 +619,85 @@ pub fn get_process_namespaces(pid: i32) -> Result<HashMap<NamespaceType, String>
     Ok(namespaces)
 }
 
+/// 加入其他进程的 namespace 的顺序，user namespace 必须最先加入，之后才有权限加入其余的
+///
+/// 加入 pid namespace 只对之后创建的子进程生效，需要的调用方在 fork 之前单独加入。
+pub const JOIN_ORDER: [NamespaceType; 6] = [
+    NamespaceType::User,
+    NamespaceType::Ipc,
+    NamespaceType::Uts,
+    NamespaceType::Network,
+    NamespaceType::Cgroup,
+    NamespaceType::Mount,
+];
+
+/// 按 types 的顺序打开进程 pid 与当前进程不同的 namespace
+///
+/// 与当前进程相同的 namespace 不需要加入（加入自己所在的 user namespace 会失败）。
+/// 旧内核不支持 cgroup namespace 时跳过，其余 namespace 缺失时报错，避免在宿主机上执行。
+pub fn open_process_namespaces(pid: i32, types: &[NamespaceType]) -> Result<Vec<(fs::File, CloneFlags)>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut namespaces = Vec::new();
+    for &ns_type in types {
+        let path = format!("/proc/{}/ns/{}", pid, ns_type.proc_path());
+        let target = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && ns_type == NamespaceType::Cgroup => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let same = fs::metadata(format!("/proc/self/ns/{}", ns_type.proc_path()))
+            .is_ok_and(|own| own.dev() == target.dev() && own.ino() == target.ino());
+        if !same {
+            namespaces.push((fs::File::open(&path)?, ns_type.clone_flag()));
+        }
+    }
+    Ok(namespaces)
+}
+
+/// 创建一个带有指定映射、不包含任何进程的user namespace
+///
+/// 通过一个短暂存在的子进程创建namespace并由当前进程写入映射，
+/// 返回的文件保持对该namespace的引用（用于idmapped挂载等场景）。
+pub fn create_detached_user_namespace(mapping: &UserNamespaceMapping) -> Result<fs::File> {
+    let sync = crate::sync::Sync::new()?;
+    let parent = getpid();
+
+    let child = match unsafe { nix::unistd::fork() }? {
+        nix::unistd::ForkResult::Child => {
+            // 当前进程来不及回收就崩溃时随之退出，不会一直停在 pause；fork 之后父进程可能已经退出
+            if crate::nix_ext::set_pdeathsig(Some(nix::sys::signal::SIGKILL)).is_err()
+                || nix::unistd::getppid() != parent
+            {
+                unsafe { libc::_exit(1) };
+            }
+            if unshare(CloneFlags::CLONE_NEWUSER).is_err() || sync.notify_parent().is_err() {
+                unsafe { libc::_exit(1) };
+            }
+            loop {
+                unsafe { libc::pause() };
+            }
+        }
+        nix::unistd::ForkResult::Parent { child } => child,
+    };
+
+    let result = sync
+        .wait_for_child()
+        .and_then(|_| mapping.apply_to_pid(child.as_raw()))
+        .and_then(|_| Ok(fs::File::open(format!("/proc/{}/ns/user", child.as_raw()))?));
+
+    let _ = nix::sys::signal::kill(child, nix::sys::signal::SIGKILL);
+    let _ = nix::sys::wait::waitpid(child, None);
+
+    if result.is_ok() {
+        debug!("创建独立的user namespace，UID映射={}, GID映射={}",
+            mapping.uid_mappings.len(),
+            mapping.gid_mappings.len()
+        );
+    }
+    result
+}
+
 /// 用户namespace映射
 #[derive(Debug, Clone)]
 pub struct UserNamespaceMapping {
@@ -516,7 +738,7 @@ impl UserNamespaceMapping {
         // 应用GID映射
         if !self.gid_mappings.is_empty() {
             // 在写入GID映射之前，需要写入/proc/self/setgroups
-            self.write_setgroups_deny()?;
+            self.write_setgroups_deny("/proc/self/setgroups")?;
             self.write_id_map("/proc/self/gid_map", &self.gid_mappings)?;
             info!("成功应用GID映射，数量: {}", self.gid_mappings.len());
         }
@@ -524,6 +746,22 @@ impl UserNamespaceMapping {
         Ok(())
     }
 
+    /// 为指定进程写入ID映射（由特权父进程调用，目标进程需已创建新的user namespace）
+    pub fn apply_to_pid(&self, pid: i32) -> Result<()> {
+        if !self.uid_mappings.is_empty() {
+            self.write_id_map(&format!("/proc/{}/uid_map", pid), &self.uid_mappings)?;
+        }
+        if !self.gid_mappings.is_empty() {
+            self.write_id_map(&format!("/proc/{}/gid_map", pid), &self.gid_mappings)?;
+        }
+        Ok(())
+    }
+
+    /// 禁止指定进程所在的user namespace调用setgroups，非特权用户写入GID映射前必须设置
+    pub fn deny_setgroups(&self, pid: i32) -> Result<()> {
+        self.write_setgroups_deny(&format!("/proc/{}/setgroups", pid))
+    }
+
     /// 写入ID映射文件
     fn write_id_map(&self, path: &str, mappings: &[oci::LinuxIDMapping]) -> Result<()> {
         let mut content = String::new();
@@ -547,8 +785,7 @@ impl UserNamespaceMapping {
     }
 
     /// 写入setgroups文件
-    fn write_setgroups_deny(&self) -> Result<()> {
-        let path = "/proc/self/setgroups";
+    fn write_setgroups_deny(&self, path: &str) -> Result<()> {
         match fs::write(path, "deny") {
             Ok(_) => {
                 debug!("成功设置setgroups为deny");
@@ -589,4 +826,44 @@ mod tests {
         assert!(manager.contains_namespace(NamespaceType::Pid));
         assert!(!manager.contains_namespace(NamespaceType::Network));
     }
+
+    #[test]
+    fn test_duplicate_namespaces() {
+        let namespaces = vec![
+            oci::LinuxNamespace { typ: oci::LinuxNamespaceType::pid, path: String::new() },
+            oci::LinuxNamespace { typ: oci::LinuxNamespaceType::pid, path: String::new() },
+        ];
+        assert!(NamespaceManager::from_oci_namespaces(&namespaces).is_err());
+    }
+
+    #[test]
+    fn test_new_user_namespace_can_join_existing() {
+        let mut manager = NamespaceManager::new();
+        manager.add_namespace(Namespace::new(NamespaceType::User, None));
+        manager.add_namespace(Namespace::new(
+            NamespaceType::Network,
+            Some("/proc/self/ns/net".to_string()),
+        ));
+        assert!(manager.validate().is_ok());
+    }
+
+    #[test]
+    fn test_namespace_path_type_mismatch() {
+        assert!(validate_namespace_path(NamespaceType::Network, "/proc/self/ns/net").is_ok());
+        assert!(validate_namespace_path(NamespaceType::Ipc, "/proc/self/ns/net").is_err());
+    }
+
+    #[test]
+    fn test_namespace_paths() {
+        let mut manager = NamespaceManager::new();
+        manager.add_namespace(Namespace::new(NamespaceType::Pid, None));
+        manager.add_namespace(Namespace::new(
+            NamespaceType::Network,
+            Some("/var/run/netns/test".to_string()),
+        ));
+
+        let paths = manager.get_namespace_paths(42);
+        assert_eq!(paths[&NamespaceType::Pid], "/proc/42/ns/pid");
+        assert_eq!(paths[&NamespaceType::Network], "/var/run/netns/test");
+    }
 }
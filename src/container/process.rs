@@ -1,9 +1,32 @@
-use crate::errors::Result;
+use super::json_log;
+use super::namespace::UserNamespaceMapping;
+use super::setup::ContainerSetup;
+use crate::errors::{FireError, Result};
+use crate::{apparmor, capabilities, logger, nix_ext, seccomp, selinux, tty};
+use nix::sched::CloneFlags;
+use nix::sys::signal::Signal;
 use nix::sys::wait::{waitpid, WaitStatus};
-use nix::unistd::{fork, ForkResult, Pid};
-use log::{debug, error, info};
+use nix::unistd::Pid;
+use log::{debug, error, info, warn};
+use oci::{LinuxCapabilities, LinuxRlimit, LinuxSeccomp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone)]
+/// 传递 init 配置管道读端的环境变量，存在时说明当前进程是 `fire init`
+pub const INIT_PIPE_ENV: &str = "_FIRE_INIT_PIPE";
+
+/// clone init 进程时使用的栈大小，子进程在其上只执行到 exec
+const CLONE_STACK_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Process {
     pub pid: Option<i32>,
     pub command: Vec<String>,
@@ -12,6 +35,39 @@ pub struct Process {
     pub cwd: String,
     pub uid: Option<u32>,
     pub gid: Option<u32>,
+    pub no_new_privileges: bool,
+    pub capabilities: Option<LinuxCapabilities>,
+    pub rlimits: Vec<LinuxRlimit>,
+    /// init 进程在容器的 namespace 中写入 /proc/sys 的内核参数，exec 时为空
+    pub sysctl: HashMap<String, String>,
+    /// 执行容器命令时切换到的 SELinux 标签和 AppArmor profile，主机未启用时忽略
+    pub selinux_label: String,
+    pub apparmor_profile: String,
+    pub seccomp: Option<LinuxSeccomp>,
+    /// 预先导出的 seccomp BPF 程序，存在时跳过 libseccomp 直接加载
+    pub seccomp_program: Option<Vec<u8>>,
+    /// 交给 seccomp 监听程序的容器状态
+    pub state: Option<oci::State>,
+    /// 标准输出和标准错误重定向到的文件
+    pub log_file: Option<PathBuf>,
+    /// 以 Docker json-file 格式写入 log_file，而不是直接重定向
+    pub json_log: bool,
+    /// 设置后子进程在执行容器命令前阻塞在该 FIFO 上，直到 start 打开读端
+    pub exec_fifo: Option<PathBuf>,
+    /// exec 时子进程依次加入的容器 namespace，描述符由调用方持有
+    #[serde(with = "raw::namespaces")]
+    pub namespaces: Vec<(RawFd, CloneFlags)>,
+    /// exec 时加入 namespace 后 chroot 到的容器根目录
+    pub root: Option<RawFd>,
+    /// 作为控制终端和标准输入输出的 pty 从端
+    pub terminal: Option<RawFd>,
+    /// 替换子进程标准输入的描述符
+    pub stdin: Option<RawFd>,
+    /// 启动进程的线程退出时内核向进程发送的信号（PR_SET_PDEATHSIG）
+    #[serde(with = "raw::signal")]
+    pub parent_death_signal: Option<Signal>,
+    /// create 时 init 进程在执行容器命令前创建 namespace 并准备根文件系统，exec 时为空
+    pub setup: Option<ContainerSetup>,
 }
 
 impl Process {
@@ -32,9 +88,59 @@ impl Process {
             cwd: "/".to_string(),
             uid: None,
             gid: None,
+            no_new_privileges: false,
+            capabilities: None,
+            rlimits: Vec::new(),
+            sysctl: HashMap::new(),
+            selinux_label: String::new(),
+            apparmor_profile: String::new(),
+            seccomp: None,
+            seccomp_program: None,
+            state: None,
+            log_file: None,
+            json_log: false,
+            exec_fifo: None,
+            namespaces: Vec::new(),
+            root: None,
+            terminal: None,
+            stdin: None,
+            parent_death_signal: None,
+            setup: None,
         }
     }
 
+    /// 按 spec 中的 process 设置环境变量、工作目录、用户和权限，执行的命令为 args
+    pub fn from_spec(spec: &oci::Spec, args: Vec<String>) -> Self {
+        let mut process = Process::new(args);
+        process.set_env(spec.process.env.clone());
+        process.set_cwd(spec.process.cwd.clone());
+
+        // 设置用户和组
+        process.set_uid_gid(Some(spec.process.user.uid), Some(spec.process.user.gid));
+
+        // 设置权限相关配置，在 exec 前依次生效
+        process.set_no_new_privileges(spec.process.no_new_privileges);
+        let caps = match spec.process.capabilities {
+            Some(ref caps) => Some(caps.clone()),
+            None => {
+                info!("未配置 capabilities，使用默认集合");
+                let user_namespace = spec.linux.as_ref().is_some_and(|linux| {
+                    linux
+                        .namespaces
+                        .iter()
+                        .any(|ns| matches!(ns.typ, oci::LinuxNamespaceType::user))
+                });
+                capabilities::default_capabilities(spec.process.user.uid, user_namespace)
+            }
+        };
+        process.set_capabilities(caps);
+        process.set_rlimits(spec.process.rlimits.clone());
+        process.set_selinux_label(spec.process.selinux_label.clone());
+        process.set_apparmor_profile(spec.process.apparmor_profile.clone());
+        process.set_seccomp(spec.linux.as_ref().and_then(|l| l.seccomp.clone()));
+        process
+    }
+
     pub fn set_env(&mut self, env: Vec<String>) {
         self.env = env;
     }
@@ -48,30 +154,286 @@ impl Process {
         self.gid = gid;
     }
 
-    /// 启动容器进程
-    pub fn start(&mut self) -> Result<i32> {
+    pub fn set_no_new_privileges(&mut self, no_new_privileges: bool) {
+        self.no_new_privileges = no_new_privileges;
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Option<LinuxCapabilities>) {
+        self.capabilities = capabilities;
+    }
+
+    pub fn set_rlimits(&mut self, rlimits: Vec<LinuxRlimit>) {
+        self.rlimits = rlimits;
+    }
+
+    pub fn set_sysctl(&mut self, sysctl: HashMap<String, String>) {
+        self.sysctl = sysctl;
+    }
+
+    pub fn set_selinux_label(&mut self, label: String) {
+        self.selinux_label = label;
+    }
+
+    pub fn set_apparmor_profile(&mut self, profile: String) {
+        self.apparmor_profile = profile;
+    }
+
+    pub fn set_seccomp(&mut self, seccomp: Option<LinuxSeccomp>) {
+        self.seccomp = seccomp;
+    }
+
+    pub fn set_seccomp_program(&mut self, program: Option<Vec<u8>>) {
+        self.seccomp_program = program;
+    }
+
+    pub fn set_state(&mut self, state: oci::State) {
+        self.state = Some(state);
+    }
+
+    pub fn set_log_file(&mut self, log_file: Option<PathBuf>) {
+        self.log_file = log_file;
+    }
+
+    pub fn set_json_log(&mut self, json_log: bool) {
+        self.json_log = json_log;
+    }
+
+    pub fn set_exec_fifo(&mut self, exec_fifo: Option<PathBuf>) {
+        self.exec_fifo = exec_fifo;
+    }
+
+    /// 在已有容器中执行：子进程先加入 namespaces，再 chroot 到 root
+    pub fn set_join(&mut self, namespaces: Vec<(RawFd, CloneFlags)>, root: RawFd) {
+        self.namespaces = namespaces;
+        self.root = Some(root);
+    }
+
+    pub fn set_terminal(&mut self, terminal: Option<RawFd>) {
+        self.terminal = terminal;
+    }
+
+    pub fn set_stdin(&mut self, stdin: Option<RawFd>) {
+        self.stdin = stdin;
+    }
+
+    pub fn set_parent_death_signal(&mut self, signal: Option<Signal>) {
+        self.parent_death_signal = signal;
+    }
+
+    pub fn set_setup(&mut self, setup: Option<ContainerSetup>) {
+        self.setup = setup;
+    }
+
+    /// 启动容器进程，子进程在 exec 前等待 before_exec 完成
+    ///
+    /// 子进程 clone 后立即重新执行 `/proc/self/exe init`，在干净的地址空间中完成进入
+    /// namespace、组装并切换根文件系统、切换用户、加载 seccomp 等步骤，不受父进程中
+    /// 其他线程持有的锁影响。
+    /// create 时新的 user 和 pid namespace 在 clone 时创建，新建 user namespace 时当前进程
+    /// 写入 ID 映射后子进程才以容器中的 root 身份 exec。
+    /// before_exec 在父进程中以子进程 PID 调用（如将子进程加入 cgroup），成功后才通过
+    /// 管道发送进程配置，失败时子进程不会执行容器命令。
+    ///
+    /// init 完成准备之前设置了 PR_SET_PDEATHSIG 为 SIGKILL，当前进程中途崩溃时不会留下
+    /// 准备到一半的 init；准备完成后 init 恢复 parent_death_signal 的设置并通知当前进程返回。
+    #[tracing::instrument(name = "process_start", skip_all)]
+    pub fn start_with<F>(&mut self, before_exec: F) -> Result<i32>
+    where
+        F: FnOnce(i32) -> Result<()>,
+    {
         info!("启动容器进程: {:?}", self.command);
-        
-        match unsafe { fork() } {
-            Ok(ForkResult::Parent { child }) => {
-                let pid = child.as_raw();
-                self.pid = Some(pid);
-                info!("容器进程启动成功, PID: {}", pid);
-                Ok(pid)
-            }
-            Ok(ForkResult::Child) => {
-                // 子进程中执行容器命令
-                self.exec_in_child()
+
+        // json-file 日志由单独的转发进程写入，init 只拿到管道的写端；分配了终端时输出都写到终端
+        let json_output = match self.log_file {
+            Some(ref log_file) if self.json_log && self.terminal.is_none() => Some(json_log::spawn_forwarder(log_file)?),
+            _ => None,
+        };
+
+        // 进程可能位于新的 pid namespace 中，getppid 无法判断父进程是否已经退出，改用 pidfd；
+        // 内核不支持 pidfd 时只设置 PDEATHSIG
+        let parent = match self.parent_death_signal {
+            Some(_) if crate::sysinfo::get().syscalls.pidfd => {
+                Some(unsafe { OwnedFd::from_raw_fd(nix_ext::pidfd_open(std::process::id() as i32)?) })
             }
-            Err(e) => {
-                error!("fork 失败: {}", e);
-                Err(crate::errors::FireError::Nix(e))
+            _ => None,
+        };
+
+        let (ready_read, ready_write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+        let (ready_read, ready_write) =
+            unsafe { (OwnedFd::from_raw_fd(ready_read), OwnedFd::from_raw_fd(ready_write)) };
+        let config = InitConfig {
+            process: self.clone(),
+            json_output: json_output.as_ref().map(|o| (o.stdout.as_raw_fd(), o.stderr.as_raw_fd())),
+            parent: parent.as_ref().map(|fd| fd.as_raw_fd()),
+            ready: ready_write.as_raw_fd(),
+            log: logger::settings(),
+        };
+        let payload = serde_json::to_vec(&config)?;
+
+        let (config_read, config_write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+        let (config_read, config_write) =
+            unsafe { (OwnedFd::from_raw_fd(config_read), OwnedFd::from_raw_fd(config_write)) };
+        let mut inherited = config.fds();
+        inherited.push(config_read.as_raw_fd());
+
+        // clone 之后子进程只调用异步信号安全的函数，参数和环境变量提前准备好
+        let exe = CString::new("/proc/self/exe")?;
+        let arg0 = std::env::args_os().next().unwrap_or_else(|| "fire".into());
+        let argv = [CString::new(arg0.into_vec())?, CString::new("init")?];
+        let mut envp = Vec::new();
+        for (key, value) in std::env::vars_os().filter(|(key, _)| key != INIT_PIPE_ENV) {
+            let mut entry = key.into_vec();
+            entry.push(b'=');
+            entry.extend(value.into_vec());
+            envp.push(CString::new(entry)?);
+        }
+        envp.push(CString::new(format!("{}={}", INIT_PIPE_ENV, config_read.as_raw_fd()))?);
+        let argv_ptrs = null_terminated(&argv);
+        let envp_ptrs = null_terminated(&envp);
+
+        let flags = self.setup.as_ref().map(|setup| setup.clone_flags()).unwrap_or_else(CloneFlags::empty);
+        let user_mapping = self.setup.as_ref().and_then(|setup| setup.user_mapping());
+        let become_root = user_mapping.as_ref().is_some_and(|mapping| {
+            maps_root(&mapping.uid_mappings) && maps_root(&mapping.gid_mappings)
+        });
+        let sync = match user_mapping {
+            Some(_) => {
+                let (read, write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+                Some(unsafe { (OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write)) })
             }
+            None => None,
+        };
+        let sync_read = sync.as_ref().map(|(read, _)| read.as_raw_fd());
+
+        // 加入 pid namespace 只影响之后创建的子进程，clone 之后恢复
+        let own_pid_namespace = match self.setup.as_ref().and_then(|setup| setup.pid_namespace_path()) {
+            Some(path) => Some(enter_pid_namespace(path)?),
+            None => None,
+        };
+        let mut stack = vec![0u8; CLONE_STACK_SIZE];
+        let child = Box::new(|| unsafe {
+            exec_init(&exe, &argv_ptrs, &envp_ptrs, &inherited, sync_read, become_root)
+        });
+        let cloned = unsafe { nix::sched::clone(child, &mut stack, flags, Some(libc::SIGCHLD)) };
+        if let Some(own) = own_pid_namespace {
+            nix::sched::setns(own, CloneFlags::CLONE_NEWPID)?;
         }
+        let pid = cloned
+            .inspect_err(|e| error!("启动 init 进程失败: {}", e))?
+            .as_raw();
+        // 容器进程退出后转发进程才能读到 EOF
+        drop(json_output);
+        drop(config_read);
+        drop(ready_write);
+        let sync_write = sync.map(|(_, write)| write);
+
+        let result = match (user_mapping, sync_write) {
+            (Some(mapping), Some(sync)) => write_id_mappings(&mapping, pid, sync),
+            _ => Ok(()),
+        }
+        .and_then(|_| before_exec(pid))
+        .and_then(|_| send_config(config_write, &payload))
+        .and_then(|_| wait_ready(ready_read));
+        if let Err(e) = result {
+            error!("容器进程 {} 启动前准备失败: {}", pid, e);
+            let _ = nix::sys::signal::kill(Pid::from_raw(pid), nix::sys::signal::SIGKILL);
+            let _ = waitpid(Pid::from_raw(pid), None);
+            return Err(e);
+        }
+        self.pid = Some(pid);
+        info!("容器进程启动成功, PID: {}", pid);
+        Ok(pid)
+    }
+
+    /// exec 后由 init 继续使用的描述符
+    fn inherited_fds(&self) -> Vec<RawFd> {
+        let mut fds: Vec<RawFd> = self.namespaces.iter().map(|&(fd, _)| fd).collect();
+        fds.extend(self.root);
+        fds.extend(self.terminal);
+        fds.extend(self.stdin);
+        fds
     }
 
-    /// 在子进程中执行命令
-    fn exec_in_child(&self) -> ! {
+    /// init 阶段执行容器命令
+    ///
+    /// create 时先进入 namespace 并切换到容器的根文件系统，之后才写入 sysctl、屏蔽路径。
+    /// 顺序为：切换用户、设置 no_new_privs、配置 capabilities，最后加载
+    /// seccomp 并立即 exec，使过滤器不会拦截运行时自身的系统调用。
+    fn exec_in_child(
+        &self,
+        json_output: Option<&json_log::Output>,
+        parent: Option<&OwnedFd>,
+        ready: OwnedFd,
+    ) -> ! {
+        // 日志文件和 exec_fifo 位于宿主机上，切换根文件系统之前打开
+        let log_file = match self.log_file {
+            Some(ref path) if json_output.is_none() && self.terminal.is_none() => {
+                match OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        error!("打开日志文件 {} 失败: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => None,
+        };
+        let exec_fifo = match self.exec_fifo {
+            Some(ref path) => match open_fifo_dir(path) {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    error!("打开 {} 所在目录失败: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        };
+
+        if let Some(ref setup) = self.setup {
+            if let Err(e) = setup.prepare(self.terminal) {
+                error!("准备容器环境失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Err(e) = self.join_container() {
+            error!("进入容器失败: {}", e);
+            std::process::exit(1);
+        }
+        if let Err(e) = apply_sysctl(&self.sysctl) {
+            error!("设置 sysctl 失败: {}", e);
+            std::process::exit(1);
+        }
+        if let Some(ref setup) = self.setup {
+            if let Err(e) = setup.finish() {
+                error!("完成根文件系统设置失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(fd) = self.stdin {
+            if unsafe { libc::dup2(fd, libc::STDIN_FILENO) } == -1 {
+                std::process::exit(1);
+            }
+        }
+        if let Some(fd) = self.terminal {
+            if let Err(e) = tty::set_controlling_terminal(fd) {
+                error!("设置控制终端失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(output) = json_output {
+            for (fd, target) in [(&output.stdout, libc::STDOUT_FILENO), (&output.stderr, libc::STDERR_FILENO)] {
+                if unsafe { libc::dup2(fd.as_raw_fd(), target) } == -1 {
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(ref file) = log_file {
+            if let Err(e) = redirect_output(file) {
+                error!("重定向容器输出失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+
         // 设置工作目录
         if let Err(e) = std::env::set_current_dir(&self.cwd) {
             error!("设置工作目录失败: {}", e);
@@ -87,6 +449,42 @@ impl Process {
             }
         }
 
+        // 准备完成，父进程可以退出：恢复 parent_death_signal 的设置（没有设置时清除）再通知父进程
+        if let Err(e) = nix_ext::set_pdeathsig(self.parent_death_signal).and_then(|_| notify_ready(ready)) {
+            error!("通知父进程失败: {}", e);
+            std::process::exit(1);
+        }
+
+        // create 到此为止，等待 start 之后再切换用户并执行容器命令
+        if let Some((ref dir, ref name)) = exec_fifo {
+            if let Err(e) = wait_for_start(dir, name) {
+                error!("等待容器启动失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        // 有效集可能被父进程收窄，恢复后再切换用户和设置 capabilities
+        if let Err(e) = capabilities::reset_effective() {
+            warn!("重置有效 capabilities 失败: {}", e);
+        }
+
+        // 提高硬限制需要 CAP_SYS_RESOURCE，标签写入 /proc/self/attr，都在切换用户之前完成
+        for rlimit in &self.rlimits {
+            if let Err(e) = nix_ext::setrlimit(rlimit.typ as libc::c_int, rlimit.soft, rlimit.hard) {
+                error!("设置 {:?} 失败: {}", rlimit.typ, e);
+                std::process::exit(1);
+            }
+        }
+        if let Err(e) = self.apply_labels() {
+            error!("设置安全标签失败: {}", e);
+            std::process::exit(1);
+        }
+
+        // 切换用户后保留 capabilities，由 spec 决定最终的集合
+        if self.capabilities.is_some() {
+            let _ = nix_ext::set_keepcaps();
+        }
+
         // 设置用户和组
         if let Some(gid) = self.gid {
             if let Err(e) = nix::unistd::setgid(nix::unistd::Gid::from_raw(gid)) {
@@ -102,12 +500,107 @@ impl Process {
             }
         }
 
+        // 切换用户会清除 PR_SET_PDEATHSIG，需要重新设置
+        if let Err(e) = self.set_parent_death_signal_in_child(parent) {
+            error!("设置父进程退出信号失败: {}", e);
+            std::process::exit(1);
+        }
+
+        if self.no_new_privileges {
+            if let Err(e) = nix_ext::set_no_new_privs() {
+                error!("设置 no_new_privs 失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(ref caps) = self.capabilities {
+            if let Err(e) = capabilities::drop_privileges(caps) {
+                error!("设置 capabilities 失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(ref seccomp) = self.seccomp {
+            if let Err(e) = self.load_seccomp(seccomp) {
+                error!("加载 seccomp 过滤器失败: {}", e);
+                std::process::exit(1);
+            }
+        }
+
         // 执行命令
         let err = exec_command(&self.command[0], &self.args);
         error!("执行命令失败: {}", err);
         std::process::exit(1);
     }
 
+    /// exec 时加入容器的 namespace 并切换到容器的根目录，环境变量只保留 spec 中的
+    fn join_container(&self) -> Result<()> {
+        let Some(root) = self.root else {
+            return Ok(());
+        };
+        for &(fd, flag) in &self.namespaces {
+            nix::sched::setns(unsafe { BorrowedFd::borrow_raw(fd) }, flag)?;
+        }
+        // 根目录的描述符在加入 mount namespace 之前打开，pivot_root 和 chroot 的容器都适用
+        nix::unistd::fchdir(root)?;
+        nix::unistd::chroot(".")?;
+        for (key, _) in std::env::vars_os() {
+            std::env::remove_var(key);
+        }
+        Ok(())
+    }
+
+    /// 设置父进程退出时收到的信号，parent 为父进程的 pidfd，父进程在设置前已经退出时返回错误
+    fn set_parent_death_signal_in_child(&self, parent: Option<&OwnedFd>) -> Result<()> {
+        if self.parent_death_signal.is_none() {
+            return Ok(());
+        }
+        nix_ext::set_pdeathsig(self.parent_death_signal)?;
+        if let Some(parent) = parent {
+            // 父进程退出后 pidfd 变为可读
+            let mut pollfd = libc::pollfd { fd: parent.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut pollfd, 1, 0) } > 0 {
+                return Err(crate::errors::FireError::Generic("父进程已经退出".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 设置 exec 后生效的 SELinux 标签和 AppArmor profile
+    fn apply_labels(&self) -> Result<()> {
+        if !self.selinux_label.is_empty() {
+            if selinux::enabled() {
+                selinux::setexeccon(&self.selinux_label)?;
+            } else {
+                warn!("主机未启用 SELinux，忽略标签 {}", self.selinux_label);
+            }
+        }
+        if !self.apparmor_profile.is_empty() {
+            if apparmor::enabled() {
+                apparmor::apply_profile(&self.apparmor_profile)?;
+            } else {
+                warn!("主机未启用 AppArmor，忽略 profile {}", self.apparmor_profile);
+            }
+        }
+        Ok(())
+    }
+
+    /// 加载 seccomp 过滤器，并把通知描述符交给监听程序
+    fn load_seccomp(&self, seccomp: &LinuxSeccomp) -> Result<()> {
+        if let Some(ref program) = self.seccomp_program {
+            return seccomp::load_program(program, &seccomp.flags);
+        }
+        let notify_fd = match seccomp::initialize_seccomp(seccomp)? {
+            Some(fd) => fd,
+            None => return Ok(()),
+        };
+        let mut state = self.state.clone().ok_or_else(|| {
+            crate::errors::FireError::Generic("缺少发送给 seccomp 监听程序的容器状态".to_string())
+        })?;
+        state.pid = std::process::id() as i32;
+        seccomp::send_to_listener(seccomp, &notify_fd, state.pid, &state)
+    }
+
     /// 等待进程结束
     pub fn wait(&self) -> Result<i32> {
         if let Some(pid) = self.pid {
@@ -137,6 +630,21 @@ impl Process {
         }
     }
 
+    /// 在 timeout 内等待进程结束，超时返回 None
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<Option<i32>> {
+        let pid = self.pid.ok_or_else(|| FireError::Generic("进程未启动".to_string()))?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match waitpid(Pid::from_raw(pid), Some(nix::sys::wait::WaitPidFlag::WNOHANG))? {
+                WaitStatus::StillAlive if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                WaitStatus::StillAlive => return Ok(None),
+                status => return Ok(Some(exit_code(status).unwrap_or(0))),
+            }
+        }
+    }
+
     /// 杀死进程
     pub fn kill(&self, signal: i32) -> Result<()> {
         if let Some(pid) = self.pid {
@@ -174,6 +682,300 @@ impl Process {
     }
 }
 
+/// 通过管道发送给 `fire init` 的配置，描述符在 exec 后编号不变
+#[derive(Serialize, Deserialize)]
+struct InitConfig {
+    process: Process,
+    /// json-file 日志管道的写端，依次对应标准输出和标准错误
+    json_output: Option<(RawFd, RawFd)>,
+    /// 父进程的 pidfd
+    parent: Option<RawFd>,
+    /// init 完成准备后写入的管道
+    ready: RawFd,
+    log: logger::Settings,
+}
+
+impl InitConfig {
+    fn fds(&self) -> Vec<RawFd> {
+        let mut fds = self.process.inherited_fds();
+        if let Some((stdout, stderr)) = self.json_output {
+            fds.extend([stdout, stderr]);
+        }
+        fds.extend(self.parent);
+        fds.push(self.ready);
+        fds
+    }
+}
+
+/// 写入配置后关闭管道，init 读到 EOF 才开始执行
+fn send_config(pipe: OwnedFd, payload: &[u8]) -> Result<()> {
+    File::from(pipe).write_all(payload)?;
+    Ok(())
+}
+
+/// 等待 init 完成准备，init 在此之前退出时管道直接关闭
+fn wait_ready(pipe: OwnedFd) -> Result<()> {
+    let mut buf = [0u8; 1];
+    if File::from(pipe).read(&mut buf)? == 0 {
+        return Err(FireError::Generic("init 进程在准备过程中退出".to_string()));
+    }
+    Ok(())
+}
+
+/// 通知父进程 init 已完成准备
+fn notify_ready(pipe: OwnedFd) -> Result<()> {
+    File::from(pipe).write_all(&[0])?;
+    Ok(())
+}
+
+/// 以空指针结尾的参数数组，指向的字符串需要在 exec 之前保持有效
+fn null_terminated(strings: &[CString]) -> Vec<*const libc::c_char> {
+    strings.iter().map(|s| s.as_ptr()).chain(std::iter::once(std::ptr::null())).collect()
+}
+
+/// 映射中是否包含容器中的 0
+fn maps_root(mappings: &[oci::LinuxIDMapping]) -> bool {
+    mappings.iter().any(|m| m.container_id == 0 && m.size > 0)
+}
+
+/// 加入 path 所指的 pid namespace，返回当前线程原来的 pid namespace 用于恢复
+fn enter_pid_namespace(path: &str) -> Result<File> {
+    let own = File::open("/proc/thread-self/ns/pid")?;
+    nix::sched::setns(File::open(path)?, CloneFlags::CLONE_NEWPID)?;
+    Ok(own)
+}
+
+/// 为新建 user namespace 的 init 写入 ID 映射，写完后通知 init 继续
+fn write_id_mappings(mapping: &UserNamespaceMapping, pid: i32, sync: OwnedFd) -> Result<()> {
+    // 非特权用户写入 GID 映射之前必须禁用 setgroups
+    if crate::rootless::is_rootless() {
+        mapping.deny_setgroups(pid)?;
+    }
+    mapping.apply_to_pid(pid)?;
+    File::from(sync).write_all(&[0])?;
+    Ok(())
+}
+
+/// clone 出的子进程中执行 `fire init`，只调用异步信号安全的函数，失败时返回退出码
+///
+/// sync 不为空时等待父进程写入 ID 映射，become_root 时随后切换为容器中的 root，
+/// 使 exec 之后仍然拥有新 user namespace 中的 capabilities。
+unsafe fn exec_init(
+    exe: &CString,
+    argv: &[*const libc::c_char],
+    envp: &[*const libc::c_char],
+    inherited: &[RawFd],
+    sync: Option<RawFd>,
+    become_root: bool,
+) -> isize {
+    if nix_ext::set_pdeathsig(Some(Signal::SIGKILL)).is_err() {
+        return 127;
+    }
+    // 与 std::process::Command 一致，不把当前进程屏蔽和忽略的信号带给 init
+    let mut empty: libc::sigset_t = std::mem::zeroed();
+    libc::sigemptyset(&mut empty);
+    libc::sigprocmask(libc::SIG_SETMASK, &empty, std::ptr::null_mut());
+    libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    for &fd in inherited {
+        if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+            return 127;
+        }
+    }
+    if let Some(fd) = sync {
+        let mut buf = 0u8;
+        if libc::read(fd, &mut buf as *mut u8 as *mut libc::c_void, 1) != 1 {
+            return 127;
+        }
+    }
+    if become_root && (libc::setresgid(0, 0, 0) == -1 || libc::setresuid(0, 0, 0) == -1) {
+        return 127;
+    }
+    libc::execve(exe.as_ptr(), argv.as_ptr(), envp.as_ptr());
+    127
+}
+
+/// 当前进程是否由 [`Process::start_with`] 启动，需要进入 init 阶段
+pub fn is_init() -> bool {
+    std::env::var_os(INIT_PIPE_ENV).is_some()
+}
+
+/// init 阶段：读取父进程发送的配置，执行容器命令，成功时不会返回
+///
+/// 父进程在准备失败或异常退出时不发送完整的配置，init 随之退出。
+pub fn init() -> Result<()> {
+    let fd: RawFd = std::env::var(INIT_PIPE_ENV)
+        .ok()
+        .and_then(|fd| fd.parse().ok())
+        .ok_or_else(|| FireError::Generic(format!("{} 无效，init 只能由 fire 启动", INIT_PIPE_ENV)))?;
+    std::env::remove_var(INIT_PIPE_ENV);
+    // ps 中可以区分等待 start 的 init 和 fire 命令本身
+    let _ = nix_ext::set_name(&std::ffi::CString::new("fire:init").unwrap());
+    let config: InitConfig = serde_json::from_reader(unsafe { File::from_raw_fd(fd) })?;
+    if let Err(e) = logger::restore(&config.log) {
+        warn!("恢复日志配置失败: {}", e);
+    }
+
+    // 描述符只在 init 阶段使用，不能泄漏到容器命令中
+    for fd in config.fds() {
+        nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC))?;
+    }
+    let json_output = config.json_output.map(|(stdout, stderr)| unsafe {
+        json_log::Output {
+            stdout: OwnedFd::from_raw_fd(stdout),
+            stderr: OwnedFd::from_raw_fd(stderr),
+        }
+    });
+    let parent = config.parent.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+    let ready = unsafe { OwnedFd::from_raw_fd(config.ready) };
+    config.process.exec_in_child(json_output.as_ref(), parent.as_ref(), ready)
+}
+
+/// CloneFlags 和 Signal 没有实现 serde，按整数发送给 init
+mod raw {
+    pub mod namespaces {
+        use nix::sched::CloneFlags;
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::os::unix::io::RawFd;
+
+        pub fn serialize<S: Serializer>(namespaces: &[(RawFd, CloneFlags)], s: S) -> Result<S::Ok, S::Error> {
+            s.collect_seq(namespaces.iter().map(|(fd, flag)| (fd, flag.bits())))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<(RawFd, CloneFlags)>, D::Error> {
+            let namespaces: Vec<(RawFd, libc::c_int)> = Deserialize::deserialize(d)?;
+            Ok(namespaces
+                .into_iter()
+                .map(|(fd, bits)| (fd, CloneFlags::from_bits_truncate(bits)))
+                .collect())
+        }
+    }
+
+    pub mod signal {
+        use nix::sys::signal::Signal;
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(signal: &Option<Signal>, s: S) -> Result<S::Ok, S::Error> {
+            match signal {
+                Some(signal) => s.serialize_some(&(*signal as i32)),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Signal>, D::Error> {
+            Option::<i32>::deserialize(d)?
+                .map(|signal| Signal::try_from(signal).map_err(D::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+/// start 等待 init 进程响应的最长时间
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 打开 exec_fifo 所在的目录，切换根文件系统后通过它找到 FIFO
+fn open_fifo_dir(exec_fifo: &Path) -> Result<(File, CString)> {
+    let dir = exec_fifo.parent().unwrap_or_else(|| Path::new("/"));
+    let name = exec_fifo
+        .file_name()
+        .ok_or_else(|| FireError::Generic(format!("无效的 exec_fifo 路径: {}", exec_fifo.display())))?;
+    let dir = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_CLOEXEC)
+        .open(dir)?;
+    Ok((dir, CString::new(name.to_os_string().into_vec())?))
+}
+
+/// 阻塞直到 start 打开 FIFO 的读端，写入一个字节表示即将执行容器命令
+fn wait_for_start(dir: &File, name: &CString) -> Result<()> {
+    let fd = nix::fcntl::openat(
+        dir.as_raw_fd(),
+        name.as_c_str(),
+        nix::fcntl::OFlag::O_WRONLY | nix::fcntl::OFlag::O_CLOEXEC,
+        nix::sys::stat::Mode::empty(),
+    )?;
+    let mut fifo = unsafe { File::from_raw_fd(fd) };
+    fifo.write_all(&[0])?;
+    Ok(())
+}
+
+/// 让阻塞在 exec_fifo 上的 init 进程继续执行容器命令，成功后删除 FIFO
+///
+/// 读端以非阻塞方式打开，init 进程已经退出时不会一直等待。
+pub fn release_exec_fifo(exec_fifo: &Path, pid: i32) -> Result<()> {
+    if pid <= 0 {
+        return Err(crate::errors::FireError::InvalidState(
+            "容器没有等待启动的 init 进程".to_string(),
+        ));
+    }
+    let mut fifo = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(exec_fifo)?;
+    let mut pollfd = libc::pollfd {
+        fd: fifo.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        if nix::sys::signal::kill(Pid::from_raw(pid), None).is_err() {
+            return Err(crate::errors::FireError::InvalidState(format!(
+                "容器 init 进程 {} 已退出",
+                pid
+            )));
+        }
+        let ready = unsafe { libc::poll(&mut pollfd, 1, 100) };
+        if ready > 0 && pollfd.revents & libc::POLLIN != 0 {
+            let mut buf = [0u8; 1];
+            if fifo.read(&mut buf)? == 1 {
+                break;
+            }
+        } else if ready < 0 && nix::errno::Errno::last() != nix::errno::Errno::EINTR {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        if Instant::now() >= deadline {
+            return Err(crate::errors::FireError::Generic(format!(
+                "容器 init 进程 {} 未响应 start",
+                pid
+            )));
+        }
+    }
+    std::fs::remove_file(exec_fifo)?;
+    Ok(())
+}
+
+/// 子进程的退出码，被信号终止时为 128 + 信号值，进程尚未退出时为 None
+pub fn exit_code(status: WaitStatus) -> Option<i32> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(code),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+        _ => None,
+    }
+}
+
+/// 写入 /proc/sys 下的内核参数，net.*、kernel.shm* 等只作用于当前进程所在的 namespace
+///
+/// spec 校验时已经确认每个参数都有对应的 namespace，不会修改宿主机的设置。
+fn apply_sysctl(sysctl: &HashMap<String, String>) -> Result<()> {
+    for (key, value) in sysctl {
+        // 替换后路径中不会出现 ..
+        let path = Path::new("/proc/sys").join(key.replace('.', "/"));
+        std::fs::write(&path, value).map_err(|e| FireError::Generic(format!("{} = {}: {}", key, value, e)))?;
+        debug!("设置 sysctl {} = {}", key, value);
+    }
+    Ok(())
+}
+
+/// 用已经打开的日志文件替换标准输出和标准错误
+fn redirect_output(file: &File) -> std::io::Result<()> {
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
 fn exec_command(program: &str, args: &[String]) -> std::io::Error {
     use std::ffi::CString;
     use std::ptr;
@@ -192,3 +994,29 @@ fn exec_command(program: &str, args: &[String]) -> std::io::Error {
 
     std::io::Error::last_os_error()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_config() {
+        let mut process = Process::new(vec!["sh".to_string(), "-c".to_string(), "true".to_string()]);
+        process.set_join(vec![(5, CloneFlags::CLONE_NEWNS), (6, CloneFlags::CLONE_NEWPID)], 7);
+        process.set_terminal(Some(8));
+        process.set_parent_death_signal(Some(Signal::SIGKILL));
+        let config = InitConfig {
+            process,
+            json_output: Some((9, 10)),
+            parent: Some(11),
+            ready: 12,
+            log: logger::Settings::default(),
+        };
+
+        let config: InitConfig = serde_json::from_slice(&serde_json::to_vec(&config).unwrap()).unwrap();
+        assert_eq!(config.process.args, ["-c", "true"]);
+        assert_eq!(config.process.namespaces[1], (6, CloneFlags::CLONE_NEWPID));
+        assert_eq!(config.process.parent_death_signal, Some(Signal::SIGKILL));
+        assert_eq!(config.fds(), [5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+}
@@ -0,0 +1,151 @@
+//! create 时 init 进程在执行容器命令之前完成的准备：进入新的 namespace，在容器自己的
+//! mount namespace 中组装根文件系统并切换过去。
+//!
+//! 新的 user namespace 和 pid namespace 在 clone init 进程时创建，加入已有的 pid
+//! namespace 由父进程完成，其余的 namespace 由 init 自己创建或加入。
+
+use super::namespace::{NamespaceManager, NamespaceType, UserNamespaceMapping};
+use crate::errors::Result;
+use crate::mounts;
+use crate::nix_ext;
+use crate::rootfs::{self, OverlayConfig};
+use log::{info, warn};
+use nix::sched::CloneFlags;
+use oci::{LinuxIDMapping, LinuxNamespaceType, Spec};
+use serde::{Deserialize, Serialize};
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerSetup {
+    pub spec: Spec,
+    /// 宿主机上根文件系统的绝对路径
+    pub rootfs: PathBuf,
+    /// 由镜像层组装根文件系统时的 overlayfs 配置
+    pub overlay: Option<OverlayConfig>,
+    /// 容器运行时目录，overlayfs 的可写层默认位于其中
+    pub container_dir: PathBuf,
+}
+
+impl ContainerSetup {
+    pub fn new(spec: Spec, rootfs: PathBuf, overlay: Option<OverlayConfig>, container_dir: PathBuf) -> Self {
+        Self {
+            spec,
+            rootfs,
+            overlay,
+            container_dir,
+        }
+    }
+
+    /// clone init 进程时新建的 namespace
+    ///
+    /// 新的 pid namespace 只对之后创建的子进程生效，init 必须在其中出生；非特权用户只能在
+    /// 自己的 user namespace 中创建其他 namespace，因此 user namespace 也在 clone 时创建。
+    pub fn clone_flags(&self) -> CloneFlags {
+        let mut flags = CloneFlags::empty();
+        for ns in self.namespaces().filter(|ns| ns.path.is_empty()) {
+            match ns.typ {
+                LinuxNamespaceType::user => flags |= CloneFlags::CLONE_NEWUSER,
+                LinuxNamespaceType::pid => flags |= CloneFlags::CLONE_NEWPID,
+                _ => {}
+            }
+        }
+        flags
+    }
+
+    /// 需要加入的已有 pid namespace，由父进程在 clone 之前加入
+    pub fn pid_namespace_path(&self) -> Option<&str> {
+        self.namespaces()
+            .find(|ns| ns.typ == LinuxNamespaceType::pid && !ns.path.is_empty())
+            .map(|ns| ns.path.as_str())
+    }
+
+    /// clone 时新建 user namespace 后由父进程写入的 ID 映射
+    pub fn user_mapping(&self) -> Option<UserNamespaceMapping> {
+        if !self.clone_flags().contains(CloneFlags::CLONE_NEWUSER) {
+            return None;
+        }
+        let linux = self.spec.linux.as_ref()?;
+        Some(UserNamespaceMapping::from_oci_mappings(&linux.uid_mappings, &linux.gid_mappings))
+    }
+
+    /// 新建 user namespace 时容器中的 root 在宿主机上的 uid 和 gid，没有映射时返回 None
+    pub fn root_host_ids(&self) -> Option<(u32, u32)> {
+        let mapping = self.user_mapping()?;
+        Some((host_id(&mapping.uid_mappings, 0)?, host_id(&mapping.gid_mappings, 0)?))
+    }
+
+    /// 进入其余的 namespace 并切换到准备好的根文件系统
+    ///
+    /// 传播模式设置好之后才挂载 overlayfs 和 spec 中的挂载点，不会传播到宿主机。
+    /// terminal 为分配给容器的 pty 从端，绑定到容器的 /dev/console。
+    #[tracing::instrument(name = "container_setup", skip_all)]
+    pub fn prepare(&self, terminal: Option<RawFd>) -> Result<()> {
+        let mut manager = match self.spec.linux {
+            Some(ref linux) => NamespaceManager::from_oci_namespaces(&linux.namespaces)?,
+            None => NamespaceManager::new(),
+        };
+        // clone 时已经创建或由父进程加入
+        manager.remove_namespace(NamespaceType::Pid);
+        if self.clone_flags().contains(CloneFlags::CLONE_NEWUSER) {
+            manager.remove_namespace(NamespaceType::User);
+        }
+        manager.create_all()?;
+
+        if manager.contains_namespace(NamespaceType::Uts) && !self.spec.hostname.is_empty() {
+            nix_ext::sethostname(&self.spec.hostname)?;
+        }
+
+        let rootfs = self.rootfs.to_string_lossy().to_string();
+        if !manager.contains_namespace(NamespaceType::Mount) {
+            // 没有自己的 mount namespace 时挂载会出现在宿主机上，只挂载 overlayfs 并切换根目录
+            if !self.spec.mounts.is_empty() {
+                warn!("容器没有独立的 mount namespace，忽略 spec 中的 {} 个挂载点", self.spec.mounts.len());
+            }
+            if let Some(ref overlay) = self.overlay {
+                rootfs::mount_overlay(overlay, &self.rootfs, &self.container_dir)?;
+            }
+            nix::unistd::chroot(&self.rootfs)?;
+            std::env::set_current_dir("/")?;
+            info!("容器没有独立的 mount namespace，chroot 到 {}", rootfs);
+            return Ok(());
+        }
+
+        if let Some(ref linux) = self.spec.linux {
+            mounts::setup_rootfs_propagation(&linux.rootfs_propagation)?;
+        }
+        if let Some(ref overlay) = self.overlay {
+            rootfs::mount_overlay(overlay, &self.rootfs, &self.container_dir)?;
+        }
+        // user namespace 中没有 mknod 的权限，设备改为从宿主机绑定挂载
+        let bind_device = self.namespaces().any(|ns| ns.typ == LinuxNamespaceType::user);
+        mounts::mount_to(&self.spec, &rootfs, bind_device)?;
+        if let Some(fd) = terminal {
+            // 描述符所在的挂载属于原来的 mount namespace，不能作为绑定挂载的源，
+            // 改用当前 namespace 中复制过来的 /dev/pts 下的路径
+            let slave = std::fs::read_link(format!("/proc/self/fd/{}", fd))?;
+            mounts::setup_console(&rootfs, &slave.to_string_lossy())?;
+        }
+        mounts::pivot_rootfs(&rootfs)
+    }
+
+    /// 屏蔽和只读化 spec 中的路径，按需将根文件系统重新挂载为只读，在写入 sysctl 之后调用
+    pub fn finish(&self) -> Result<()> {
+        if self.namespaces().any(|ns| ns.typ == LinuxNamespaceType::mount) {
+            mounts::finish_rootfs(&self.spec)?;
+        }
+        Ok(())
+    }
+
+    fn namespaces(&self) -> impl Iterator<Item = &oci::LinuxNamespace> {
+        self.spec.linux.iter().flat_map(|linux| linux.namespaces.iter())
+    }
+}
+
+/// 按映射将容器中的 id 换算为宿主机上的 id
+fn host_id(mappings: &[LinuxIDMapping], id: u32) -> Option<u32> {
+    mappings
+        .iter()
+        .find(|m| id >= m.container_id && id - m.container_id < m.size)
+        .map(|m| m.host_id + (id - m.container_id))
+}
@@ -1,3 +1,10 @@
+use crate::errors::{FireError, Result};
+use crate::rfc3339;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
 #[derive(Debug, Clone)]
 pub enum ContainerState {
     Creating,
@@ -15,3 +22,220 @@ impl ContainerState {
         matches!(self, ContainerState::Stopped(_))
     }
 }
+
+/// 所有容器目录的上级目录，默认为 `$HOME/.fire`，非特权运行且设置了 XDG_RUNTIME_DIR 时
+/// 为 `$XDG_RUNTIME_DIR/fire`
+pub fn root_dir() -> PathBuf {
+    if crate::rootless::is_rootless() {
+        if let Some(dir) = crate::rootless::runtime_dir() {
+            return dir;
+        }
+    }
+    let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+    Path::new(&home_dir).join(".fire")
+}
+
+/// 容器目录下的 OCI 状态文件
+pub const STATE_FILE: &str = "state.json";
+
+/// 容器目录下保存的生效配置，create 时按注解、CDI 设备和运行时选项调整后写入，
+/// 之后的命令以此为准，不再重新读取 bundle
+pub const SPEC_FILE: &str = "config.json";
+
+/// 容器目录下记录 exec 进程的目录，每个进程一个以 exec id 命名的文件，内容为
+/// `<pid> <启动时间>`，启动时间用于识别 pid 被复用
+pub const EXEC_DIR: &str = "exec";
+
+/// init 进程的启动时间，向 init 发送信号前用于识别 pid 被复用
+pub const ANNOTATION_INIT_START_TIME: &str = "org.fire.init.start-time";
+
+/// init 进程的退出码，前台运行时由 reaper 在 init 退出后记录，此时 poststop 钩子已经执行
+pub const ANNOTATION_EXIT_CODE: &str = "org.fire.exit-code";
+
+// 各生命周期阶段的时间，RFC 3339 格式的 UTC 时间
+pub const ANNOTATION_CREATED: &str = "org.fire.created";
+pub const ANNOTATION_STARTED: &str = "org.fire.started";
+pub const ANNOTATION_FINISHED: &str = "org.fire.finished";
+
+/// 读取容器目录下的状态文件
+pub fn load(container_dir: &Path) -> Result<oci::State> {
+    Ok(serde_json::from_str(&fs::read_to_string(container_dir.join(STATE_FILE))?)?)
+}
+
+/// 更新容器目录下状态文件中的 status 和 pid，返回更新后的状态
+///
+/// bundle、注解等 create 时记录的字段保持不变。容器的所有状态变化都经过这里写回
+/// 磁盘，之后的命令以状态文件为准。
+pub fn persist(container_dir: &Path, status: &str, pid: i32) -> Result<oci::State> {
+    let mut state = load(container_dir)?;
+    state.status = status.to_string();
+    state.pid = pid;
+    // 暂停后恢复同样是 running，只记录第一次启动的时间
+    match status {
+        "running" => record_time(&mut state, ANNOTATION_STARTED),
+        "stopped" => record_time(&mut state, ANNOTATION_FINISHED),
+        _ => {}
+    }
+    save(&container_dir.join(STATE_FILE), &state)?;
+    Ok(state)
+}
+
+/// 记录 init 进程退出，状态更新为 stopped 并保存退出码
+pub fn mark_exited(container_dir: &Path, exit_code: i32) -> Result<oci::State> {
+    let mut state = load(container_dir)?;
+    state.status = "stopped".to_string();
+    state
+        .annotations
+        .insert(ANNOTATION_EXIT_CODE.to_string(), exit_code.to_string());
+    record_time(&mut state, ANNOTATION_FINISHED);
+    save(&container_dir.join(STATE_FILE), &state)?;
+    Ok(state)
+}
+
+/// 在状态文件中设置注解，返回更新后的状态
+pub fn annotate(container_dir: &Path, key: &str, value: &str) -> Result<oci::State> {
+    let mut state = load(container_dir)?;
+    state.annotations.insert(key.to_string(), value.to_string());
+    save(&container_dir.join(STATE_FILE), &state)?;
+    Ok(state)
+}
+
+/// 记录当前时间，已经记录过时保持不变
+pub fn record_time(state: &mut oci::State, annotation: &str) {
+    state
+        .annotations
+        .entry(annotation.to_string())
+        .or_insert_with(|| rfc3339::format(SystemTime::now()));
+}
+
+/// 状态文件中记录的时间
+pub fn recorded_time(state: &oci::State, annotation: &str) -> Option<SystemTime> {
+    state.annotations.get(annotation).and_then(|t| rfc3339::parse(t))
+}
+
+/// 记录在容器中执行的进程，之后可以按 exec id 向其发送信号
+pub fn record_exec(container_dir: &Path, exec_id: &str, pid: i32) -> Result<()> {
+    validate_exec_id(exec_id)?;
+    let start_time = process_start_time(pid)
+        .ok_or_else(|| FireError::InvalidState(format!("exec 进程 {} 已退出", pid)))?;
+    let dir = container_dir.join(EXEC_DIR);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(exec_id), format!("{} {}", pid, start_time))?;
+    Ok(())
+}
+
+/// exec id 用作文件名，只能包含字母、数字、`-` 和 `_`
+pub fn validate_exec_id(exec_id: &str) -> Result<()> {
+    if exec_id.is_empty() || !exec_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(FireError::InvalidSpec(format!("无效的 exec id: {}", exec_id)));
+    }
+    Ok(())
+}
+
+/// 状态文件中记录的 init 进程启动时间
+pub fn init_start_time(state: &oci::State) -> Option<u64> {
+    state.annotations.get(ANNOTATION_INIT_START_TIME)?.parse().ok()
+}
+
+/// 按 exec id 查找仍在运行的 exec 进程，进程已经退出时删除记录并返回 None
+pub fn exec_pid(container_dir: &Path, exec_id: &str) -> Result<Option<i32>> {
+    Ok(exec_process(container_dir, exec_id)?.map(|(pid, _)| pid))
+}
+
+/// 与 [`exec_pid`] 相同，同时返回记录的进程启动时间
+pub fn exec_process(container_dir: &Path, exec_id: &str) -> Result<Option<(i32, u64)>> {
+    let path = container_dir.join(EXEC_DIR).join(exec_id);
+    let record = match fs::read_to_string(&path) {
+        Ok(record) => record,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(FireError::NotFound(format!("exec 进程 {} 不存在", exec_id)));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let (pid, start_time) = record
+        .split_once(' ')
+        .and_then(|(pid, time)| Some((pid.parse::<i32>().ok()?, time.trim().parse::<u64>().ok()?)))
+        .ok_or_else(|| FireError::Generic(format!("exec 进程记录损坏: {}", path.display())))?;
+    if process_start_time(pid) == Some(start_time) {
+        return Ok(Some((pid, start_time)));
+    }
+    remove_exec(container_dir, exec_id);
+    Ok(None)
+}
+
+/// 删除 exec 进程的记录
+pub fn remove_exec(container_dir: &Path, exec_id: &str) {
+    let _ = fs::remove_file(container_dir.join(EXEC_DIR).join(exec_id));
+}
+
+/// 进程的启动时间（系统启动后的时钟周期数），进程不存在或已是僵尸进程时返回 None
+pub fn process_start_time(pid: i32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // 进程名之后的第一个字段是状态，启动时间是其后的第 20 个字段
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    if matches!(fields.first(), None | Some(&"Z") | Some(&"X")) {
+        return None;
+    }
+    fields.get(19)?.parse().ok()
+}
+
+/// 写入完整的状态文件
+pub fn save(state_file: &Path, state: &oci::State) -> Result<()> {
+    let state_json = state
+        .to_string()
+        .map_err(|e| FireError::Generic(format!("状态序列化失败: {:?}", e)))?;
+    fs::write(state_file, state_json)?;
+    debug!("容器 {} 的状态更新为 {}", state.id, state.status);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persist_keeps_other_fields() {
+        let dir = std::env::temp_dir().join(format!("fire-state-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut state = oci::State {
+            version: "1.0.0".to_string(),
+            id: "c1".to_string(),
+            status: "created".to_string(),
+            pid: 42,
+            bundle: "/srv/bundles/c1".to_string(),
+            annotations: Default::default(),
+        };
+        state.annotations.insert("org.fire.test".to_string(), "1".to_string());
+        save(&dir.join(STATE_FILE), &state).unwrap();
+
+        let persisted = persist(&dir, "running", 42).unwrap();
+        let loaded: oci::State =
+            serde_json::from_str(&fs::read_to_string(dir.join(STATE_FILE)).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.status, "running");
+        assert_eq!(loaded.bundle, persisted.bundle);
+        assert_eq!(loaded.annotations.get("org.fire.test").map(String::as_str), Some("1"));
+        assert!(recorded_time(&loaded, ANNOTATION_STARTED).is_some());
+        assert!(recorded_time(&loaded, ANNOTATION_FINISHED).is_none());
+    }
+
+    #[test]
+    fn test_exec_record() {
+        let dir = std::env::temp_dir().join(format!("fire-exec-record-{}", std::process::id()));
+        let pid = std::process::id() as i32;
+        record_exec(&dir, "debug-1", pid).unwrap();
+        let found = exec_pid(&dir, "debug-1").unwrap();
+
+        // 记录的启动时间与进程不符时视为已退出
+        fs::write(dir.join(EXEC_DIR).join("stale"), format!("{} 1", pid)).unwrap();
+        let stale = exec_pid(&dir, "stale").unwrap();
+        let removed = !dir.join(EXEC_DIR).join("stale").exists();
+        let invalid = record_exec(&dir, "../x", pid).is_err();
+        let missing = exec_pid(&dir, "missing").is_err();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(found, Some(pid));
+        assert_eq!((stale, removed, invalid, missing), (None, true, true, true));
+    }
+}
@@ -11,6 +11,21 @@ pub enum FireError {
     #[error("Generic error: {0}")]
     Generic(String),
 
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid state: {0}")]
+    InvalidState(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("Kernel unsupported: {0}")]
+    KernelUnsupported(String),
+
     #[error("Nix error: {0}")]
     Nix(#[from] nix::Error),
 
@@ -26,6 +41,43 @@ pub enum FireError {
 
 pub type Result<T> = std::result::Result<T, FireError>;
 
+// 命令行的退出码，脚本可以据此区分失败原因。2 由 clap 用于命令行参数错误。
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_NOT_FOUND: i32 = 3;
+pub const EXIT_ALREADY_EXISTS: i32 = 4;
+pub const EXIT_INVALID_STATE: i32 = 5;
+pub const EXIT_INVALID_SPEC: i32 = 6;
+pub const EXIT_PERMISSION_DENIED: i32 = 7;
+pub const EXIT_KERNEL_UNSUPPORTED: i32 = 8;
+
+impl FireError {
+    /// 错误对应的命令行退出码
+    ///
+    /// 系统调用返回的 EPERM/EACCES 视为权限不足，ENOSYS/EOPNOTSUPP 视为内核不支持。
+    pub fn exit_code(&self) -> i32 {
+        use nix::errno::Errno;
+
+        let errno = match self {
+            FireError::Nix(errno) => Some(*errno),
+            FireError::Io(e) => e.raw_os_error().map(Errno::from_i32),
+            _ => None,
+        };
+        match (self, errno) {
+            (FireError::NotFound(_), _) => EXIT_NOT_FOUND,
+            (FireError::AlreadyExists(_), _) => EXIT_ALREADY_EXISTS,
+            (FireError::InvalidState(_), _) => EXIT_INVALID_STATE,
+            (FireError::InvalidSpec(_), _) => EXIT_INVALID_SPEC,
+            (FireError::PermissionDenied(_), _) | (_, Some(Errno::EPERM | Errno::EACCES)) => {
+                EXIT_PERMISSION_DENIED
+            }
+            (FireError::KernelUnsupported(_), _) | (_, Some(Errno::ENOSYS | Errno::EOPNOTSUPP)) => {
+                EXIT_KERNEL_UNSUPPORTED
+            }
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
 // 兼容性宏
 #[macro_export]
 macro_rules! bail {
@@ -59,3 +111,17 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(FireError::NotFound("c1".to_string()).exit_code(), EXIT_NOT_FOUND);
+        assert_eq!(FireError::Nix(nix::errno::Errno::EPERM).exit_code(), EXIT_PERMISSION_DENIED);
+        let enosys = std::io::Error::from_raw_os_error(libc::ENOSYS);
+        assert_eq!(FireError::Io(enosys).exit_code(), EXIT_KERNEL_UNSUPPORTED);
+        assert_eq!(FireError::Generic("x".to_string()).exit_code(), EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,266 @@
+//! 本地镜像：OCI layout 目录及其 tar 包，以及 `docker save` 生成的 tar 包
+//!
+//! tar 包先解压到 bundle 下的临时目录。同时包含 index.json 和 manifest.json 时
+//! （Docker 25 之后的 docker save）按 OCI layout 处理，可以校验各内容的摘要。
+
+use super::manifest::{
+    select_platform, sha256, sha256_file, verify, Descriptor, Document, ANNOTATION_REF_NAME,
+};
+use super::{layer, Reference};
+use crate::errors::{FireError, Result};
+use crate::mounts::secure_join;
+use log::info;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const OCI_INDEX: &str = "index.json";
+const DOCKER_MANIFEST: &str = "manifest.json";
+/// containerd 和 docker save 在 OCI 索引中记录完整的镜像名
+const ANNOTATION_IMAGE_NAME: &str = "io.containerd.image.name";
+/// tar 包的临时解压目录，位于 bundle 中
+const EXTRACT_DIR: &str = ".archive";
+
+/// docker save 的 manifest.json 中的一项，路径相对于 tar 包根目录
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerManifest {
+    config: String,
+    #[serde(default)]
+    repo_tags: Vec<String>,
+    layers: Vec<String>,
+}
+
+/// 镜像配置、由下至上的镜像层文件和镜像的 digest
+type Image = (Vec<u8>, Vec<PathBuf>, String);
+
+pub fn unpack_into(source: &Path, bundle: &Path, name: Option<&str>) -> Result<String> {
+    if source.is_dir() {
+        return unpack_dir(source, bundle, name);
+    }
+
+    info!("解压 {}", source.display());
+    let dir = bundle.join(EXTRACT_DIR);
+    let result = layer::open(source)
+        .and_then(|mut archive| Ok(archive.unpack(&dir)?))
+        .and_then(|_| unpack_dir(&dir, bundle, name));
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn unpack_dir(dir: &Path, bundle: &Path, name: Option<&str>) -> Result<String> {
+    let (config, layers, digest) = if dir.join(OCI_INDEX).exists() {
+        oci_layout(dir, name)?
+    } else if dir.join(DOCKER_MANIFEST).exists() {
+        docker_archive(dir, name)?
+    } else {
+        return Err(FireError::InvalidSpec(
+            "不是 OCI layout 或 docker save 生成的镜像".to_string(),
+        ));
+    };
+    let image_config = super::parse_config(&config)?;
+
+    let rootfs = bundle.join("rootfs");
+    fs::create_dir(&rootfs)?;
+    for (i, path) in layers.iter().enumerate() {
+        info!("解压镜像层 {}/{}", i + 1, layers.len());
+        layer::unpack(path, &rootfs)?;
+    }
+
+    super::write_spec(&image_config, bundle)?;
+    info!("镜像 {} 已解压到 {}", digest, bundle.display());
+    Ok(digest)
+}
+
+fn oci_layout(dir: &Path, name: Option<&str>) -> Result<Image> {
+    let index: Document = serde_json::from_slice(&fs::read(dir.join(OCI_INDEX))?)?;
+    let candidates: Vec<&Descriptor> = index
+        .manifests
+        .iter()
+        .filter(|d| name.iter().all(|name| matches_name(d, name)))
+        .collect();
+    let mut descriptor = match candidates[..] {
+        [] => {
+            return Err(FireError::NotFound(match name {
+                Some(name) => format!("镜像中没有 {}", name),
+                None => "镜像索引为空".to_string(),
+            }));
+        }
+        [only] => only.clone(),
+        _ if candidates.iter().all(|d| d.platform.is_none()) => {
+            return Err(FireError::InvalidSpec("包含多个镜像，需要指定镜像名称".to_string()));
+        }
+        _ => select_platform(candidates)?.clone(),
+    };
+
+    // 索引可以嵌套，直到找到单一平台的清单
+    loop {
+        let document: Document = serde_json::from_slice(&read_blob(dir, &descriptor)?)?;
+        if document.manifests.is_empty() {
+            let manifest = document.into_manifest()?;
+            let config = read_blob(dir, &manifest.config)?;
+            let mut layers = Vec::new();
+            for layer in &manifest.layers {
+                let path = blob_path(dir, layer)?;
+                let (digest, size) = sha256_file(&path)?;
+                verify(&layer.digest, &digest, size, Some(layer.size))?;
+                layers.push(path);
+            }
+            return Ok((config, layers, descriptor.digest));
+        }
+        descriptor = select_platform(&document.manifests)?.clone();
+    }
+}
+
+fn docker_archive(dir: &Path, name: Option<&str>) -> Result<Image> {
+    let manifests: Vec<DockerManifest> = serde_json::from_slice(&fs::read(dir.join(DOCKER_MANIFEST))?)?;
+    let manifest = match (name, &manifests[..]) {
+        (Some(name), _) => manifests
+            .iter()
+            .find(|m| m.repo_tags.iter().any(|tag| same_image(tag, name)))
+            .ok_or_else(|| FireError::NotFound(format!("镜像中没有 {}", name)))?,
+        (None, [only]) => only,
+        (None, []) => return Err(FireError::InvalidSpec("manifest.json 为空".to_string())),
+        (None, _) => {
+            return Err(FireError::InvalidSpec("包含多个镜像，需要指定镜像名称".to_string()));
+        }
+    };
+
+    // 路径来自 tar 包，限制在解压目录内
+    let config = fs::read(secure_join(dir, &manifest.config)?)?;
+    let layers = manifest
+        .layers
+        .iter()
+        .map(|layer| secure_join(dir, layer))
+        .collect::<Result<Vec<_>>>()?;
+    // docker 的镜像 id 即镜像配置的摘要
+    let digest = sha256(&config);
+    Ok((config, layers, digest))
+}
+
+fn matches_name(descriptor: &Descriptor, name: &str) -> bool {
+    descriptor.annotations.get(ANNOTATION_REF_NAME).is_some_and(|r| r == name)
+        || descriptor
+            .annotations
+            .get(ANNOTATION_IMAGE_NAME)
+            .is_some_and(|image| same_image(image, name))
+}
+
+/// 比较镜像名，`alpine` 与 `docker.io/library/alpine:latest` 视为相同
+fn same_image(a: &str, b: &str) -> bool {
+    a == b
+        || matches!(
+            (Reference::parse(a), Reference::parse(b)),
+            (Ok(a), Ok(b)) if a.to_string() == b.to_string()
+        )
+}
+
+/// blob 在 layout 中的路径为 blobs/<算法>/<摘要>
+fn blob_path(dir: &Path, descriptor: &Descriptor) -> Result<PathBuf> {
+    match descriptor.digest.split_once(':') {
+        Some((algorithm, hex))
+            if !hex.is_empty()
+                && hex.chars().all(|c| c.is_ascii_hexdigit())
+                && algorithm.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            Ok(dir.join("blobs").join(algorithm).join(hex))
+        }
+        _ => Err(FireError::InvalidSpec(format!("无效的摘要: {}", descriptor.digest))),
+    }
+}
+
+fn read_blob(dir: &Path, descriptor: &Descriptor) -> Result<Vec<u8>> {
+    let bytes = fs::read(blob_path(dir, descriptor)?)?;
+    verify(&descriptor.digest, &sha256(&bytes), bytes.len() as u64, Some(descriptor.size))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &[u8] = br#"{"os":"linux","config":{"Cmd":["/hello"]}}"#;
+
+    fn tar(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(fs::File::create(path).unwrap());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_uid(nix::unistd::getuid().as_raw() as u64);
+            header.set_gid(nix::unistd::getgid().as_raw() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    fn unpacked(bundle: &Path) -> (bool, Vec<String>) {
+        let spec = oci::Spec::load(&bundle.join("config.json").to_string_lossy()).unwrap();
+        (bundle.join("rootfs/hello").exists(), spec.process.args)
+    }
+
+    #[test]
+    fn test_unpack_oci_layout() {
+        let dir = std::env::temp_dir().join(format!("fire-oci-layout-{}", std::process::id()));
+        let layout = dir.join("layout");
+        fs::create_dir_all(layout.join("blobs/sha256")).unwrap();
+        let add = |bytes: &[u8]| {
+            let digest = sha256(bytes);
+            fs::write(layout.join("blobs/sha256").join(&digest[7..]), bytes).unwrap();
+            (digest, bytes.len())
+        };
+        let descriptor = |(digest, size): (String, usize)| format!(r#"{{"digest":"{}","size":{}}}"#, digest, size);
+
+        let layer_path = dir.join("layer.tar");
+        tar(&layer_path, &[("hello", b"#!/bin/sh\n")]);
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"config":{},"layers":[{}]}}"#,
+            descriptor(add(CONFIG)),
+            descriptor(add(&fs::read(&layer_path).unwrap()))
+        );
+        let (digest, size) = add(manifest.as_bytes());
+        fs::write(
+            layout.join(OCI_INDEX),
+            format!(
+                r#"{{"manifests":[{{"digest":"{}","size":{},"annotations":{{"{}":"v1"}}}}]}}"#,
+                digest, size, ANNOTATION_REF_NAME
+            ),
+        )
+        .unwrap();
+
+        let missing = super::super::unpack(&layout, &dir.join("missing"), Some("v2"));
+        let result = super::super::unpack(&layout, &dir.join("bundle"), Some("v1"))
+            .map(|_| unpacked(&dir.join("bundle")));
+        let cleaned = !dir.join("missing").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(missing.is_err() && cleaned);
+        assert_eq!(result.unwrap(), (true, vec!["/hello".to_string()]));
+    }
+
+    #[test]
+    fn test_unpack_docker_archive() {
+        let dir = std::env::temp_dir().join(format!("fire-docker-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let layer_path = dir.join("layer.tar");
+        tar(&layer_path, &[("hello", b"#!/bin/sh\n")]);
+        let manifest = br#"[{"Config":"config.json","RepoTags":["hello:latest"],"Layers":["abc/layer.tar"]}]"#;
+        let archive = dir.join("hello.tar");
+        tar(
+            &archive,
+            &[
+                ("manifest.json", manifest),
+                ("config.json", CONFIG),
+                ("abc/layer.tar", &fs::read(&layer_path).unwrap()),
+            ],
+        );
+
+        let bundle = dir.join("bundle");
+        let result = super::super::unpack(&archive, &bundle, Some("docker.io/library/hello"))
+            .map(|digest| (digest, unpacked(&bundle), bundle.join(EXTRACT_DIR).exists()));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap(), (sha256(CONFIG), (true, vec!["/hello".to_string()]), false));
+    }
+}
@@ -0,0 +1,231 @@
+//! 由镜像配置生成 OCI 运行时配置
+//!
+//! 字段对应关系参考 OCI image-spec 的 conversion.md：Entrypoint 和 Cmd 拼接为进程参数，
+//! Env、WorkingDir 原样使用，User 在镜像的 /etc/passwd 和 /etc/group 中解析。
+//! 其余部分与 `runc spec` 生成的默认配置一致。
+
+use crate::errors::{FireError, Result};
+use crate::mounts::secure_join;
+use oci::{LinuxCapabilities, LinuxCapabilityType, LinuxNamespaceType, Mount, Spec};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_PATH: &str = "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const ANNOTATION_STOP_SIGNAL: &str = "org.opencontainers.image.stopSignal";
+
+/// 镜像配置，只包含生成运行时配置需要的字段
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub architecture: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default)]
+    pub config: Option<ContainerConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub stop_signal: Option<String>,
+}
+
+impl ImageConfig {
+    /// 生成 root.path 为 rootfs 的运行时配置，rootfs 用于解析镜像中的用户
+    pub fn to_spec(&self, rootfs: &Path) -> Result<Spec> {
+        let config = self.config.clone().unwrap_or_default();
+
+        let args: Vec<String> = config
+            .entrypoint
+            .into_iter()
+            .flatten()
+            .chain(config.cmd.into_iter().flatten())
+            .collect();
+        if args.is_empty() {
+            return Err(FireError::InvalidSpec("镜像没有指定 Entrypoint 或 Cmd".to_string()));
+        }
+
+        let mut env = config.env.unwrap_or_default();
+        if !env.iter().any(|e| e.starts_with("PATH=")) {
+            env.insert(0, DEFAULT_PATH.to_string());
+        }
+
+        let (uid, gid, additional_gids) =
+            resolve_user(config.user.as_deref().unwrap_or_default(), rootfs)?;
+
+        let default_caps = vec![
+            LinuxCapabilityType::CAP_AUDIT_WRITE,
+            LinuxCapabilityType::CAP_KILL,
+            LinuxCapabilityType::CAP_NET_BIND_SERVICE,
+        ];
+        let mut builder = Spec::builder()
+            .root("rootfs", false)
+            .args(args)
+            .env(env)
+            .cwd(config.working_dir.filter(|d| !d.is_empty()).unwrap_or_else(|| "/".to_string()))
+            .user(uid, gid)
+            .capabilities(LinuxCapabilities {
+                bounding: default_caps.clone(),
+                effective: default_caps.clone(),
+                permitted: default_caps,
+                ..Default::default()
+            })
+            .no_new_privileges(true)
+            .mounts(default_mounts())
+            .namespaces([
+                LinuxNamespaceType::pid,
+                LinuxNamespaceType::network,
+                LinuxNamespaceType::ipc,
+                LinuxNamespaceType::uts,
+                LinuxNamespaceType::mount,
+            ]);
+        if let Some(signal) = config.stop_signal.filter(|s| !s.is_empty()) {
+            builder = builder.annotation(ANNOTATION_STOP_SIGNAL, signal);
+        }
+
+        let mut spec = builder.build();
+        spec.process.user.additional_gids = additional_gids;
+        Ok(spec)
+    }
+}
+
+fn default_mounts() -> Vec<Mount> {
+    let mount = |destination: &str, typ: &str, source: &str, options: &[&str]| Mount {
+        destination: destination.to_string(),
+        typ: typ.to_string(),
+        source: source.to_string(),
+        options: options.iter().map(|o| o.to_string()).collect(),
+        ..Default::default()
+    };
+    vec![
+        mount("/proc", "proc", "proc", &[]),
+        mount("/dev", "tmpfs", "tmpfs", &["nosuid", "strictatime", "mode=755", "size=65536k"]),
+        mount(
+            "/dev/pts",
+            "devpts",
+            "devpts",
+            &["nosuid", "noexec", "newinstance", "ptmxmode=0666", "mode=0620", "gid=5"],
+        ),
+        mount("/dev/shm", "tmpfs", "shm", &["nosuid", "noexec", "nodev", "mode=1777", "size=65536k"]),
+        mount("/dev/mqueue", "mqueue", "mqueue", &["nosuid", "noexec", "nodev"]),
+        mount("/sys", "sysfs", "sysfs", &["nosuid", "noexec", "nodev", "ro"]),
+    ]
+}
+
+/// 解析 `user[:group]`，名称在镜像的 /etc/passwd 和 /etc/group 中查找
+///
+/// 只给出 uid 且 passwd 中没有对应条目时 gid 为 0。附加组为 /etc/group 中列出该用户的组。
+fn resolve_user(user: &str, rootfs: &Path) -> Result<(u32, u32, Vec<u32>)> {
+    let (user, group) = match user.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (user, None),
+    };
+    let passwd = read_rootfs_file(rootfs, "/etc/passwd");
+    let groups = read_rootfs_file(rootfs, "/etc/group");
+
+    // name:password:uid:gid:...
+    let entry = passwd.lines().map(|l| l.split(':').collect::<Vec<_>>()).find(|f| {
+        f.len() > 3 && if user.is_empty() { f[2] == "0" } else { f[0] == user || f[2] == user }
+    });
+    let (uid, mut gid, name) = match entry {
+        Some(f) => (parse_id(f[2], user)?, parse_id(f[3], user)?, Some(f[0].to_string())),
+        None if user.is_empty() => (0, 0, None),
+        None => match user.parse() {
+            Ok(uid) => (uid, 0, None),
+            Err(_) => {
+                return Err(FireError::InvalidSpec(format!("镜像中不存在用户 {}", user)));
+            }
+        },
+    };
+
+    // name:password:gid:member,member
+    let group_entries: Vec<Vec<&str>> = groups
+        .lines()
+        .map(|l| l.split(':').collect::<Vec<_>>())
+        .filter(|f| f.len() > 3)
+        .collect();
+    if let Some(group) = group.filter(|g| !g.is_empty()) {
+        gid = match group_entries.iter().find(|f| f[0] == group || f[2] == group) {
+            Some(f) => parse_id(f[2], group)?,
+            None => group
+                .parse()
+                .map_err(|_| FireError::InvalidSpec(format!("镜像中不存在用户组 {}", group)))?,
+        };
+    }
+
+    let mut additional_gids = Vec::new();
+    if let Some(name) = name {
+        for f in &group_entries {
+            if f[3].split(',').any(|member| member == name) {
+                let id = parse_id(f[2], f[0])?;
+                if id != gid && !additional_gids.contains(&id) {
+                    additional_gids.push(id);
+                }
+            }
+        }
+    }
+    Ok((uid, gid, additional_gids))
+}
+
+fn parse_id(id: &str, name: &str) -> Result<u32> {
+    id.parse()
+        .map_err(|_| FireError::InvalidSpec(format!("镜像中 {} 的 id 无效: {}", name, id)))
+}
+
+/// 读取 rootfs 中的文件，符号链接在 rootfs 内解析，文件不存在时返回空字符串
+fn read_rootfs_file(rootfs: &Path, path: &str) -> String {
+    secure_join(rootfs, path)
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_spec() {
+        let rootfs = std::env::temp_dir().join(format!("fire-image-config-{}", std::process::id()));
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        fs::write(
+            rootfs.join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\nnginx:x:101:101:nginx:/var/cache/nginx:/sbin/nologin\n",
+        )
+        .unwrap();
+        fs::write(rootfs.join("etc/group"), "root:x:0:\nnginx:x:101:\nwww:x:33:nginx\n").unwrap();
+
+        let config: ImageConfig = serde_json::from_str(
+            r#"{"architecture":"amd64","os":"linux","config":{"User":"nginx","Env":["A=1"],
+                "Entrypoint":["/docker-entrypoint.sh"],"Cmd":["nginx","-g","daemon off;"],
+                "WorkingDir":"","StopSignal":"SIGQUIT"}}"#,
+        )
+        .unwrap();
+        let spec = config.to_spec(&rootfs);
+        let by_uid = resolve_user("101:www", &rootfs);
+        let missing = resolve_user("nobody", &rootfs);
+        fs::remove_dir_all(&rootfs).unwrap();
+
+        let spec = spec.unwrap();
+        assert_eq!(spec.process.args, ["/docker-entrypoint.sh", "nginx", "-g", "daemon off;"]);
+        assert_eq!(spec.process.env, [DEFAULT_PATH, "A=1"]);
+        assert_eq!(spec.process.cwd, "/");
+        assert_eq!((spec.process.user.uid, spec.process.user.gid), (101, 101));
+        assert_eq!(spec.process.user.additional_gids, [33]);
+        assert_eq!(spec.root.path, "rootfs");
+        assert_eq!(spec.annotations[ANNOTATION_STOP_SIGNAL], "SIGQUIT");
+        assert_eq!(by_uid.unwrap(), (101, 33, vec![]));
+        assert!(missing.is_err());
+    }
+}
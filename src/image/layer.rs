@@ -0,0 +1,143 @@
+//! 镜像层解压
+//!
+//! 镜像层是（可能经过 gzip 压缩的）tar 包，删除下层文件通过 whiteout 表示：
+//! `.wh.<name>` 删除同目录下的 name，`.wh..wh..opq` 清空所在目录中来自下层的内容。
+
+use crate::errors::{FireError, Result};
+use crate::mounts::secure_join;
+use flate2::read::MultiGzDecoder;
+use log::debug;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// 把镜像层解压到 rootfs
+///
+/// 先按 whiteout 删除下层的内容，再解压整个 tar 包，最后删除解压出的 whiteout 文件。
+/// 非 root 用户解压时不保留文件属主。
+pub fn unpack(path: &Path, rootfs: &Path) -> Result<()> {
+    let mut markers = Vec::new();
+    for entry in open(path)?.entries()? {
+        let entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        if let Some(marker) = apply_whiteout(rootfs, &entry_path)? {
+            markers.push(marker);
+        }
+    }
+
+    let mut archive = open(path)?;
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_ownerships(nix::unistd::geteuid().is_root());
+    archive.set_overwrite(true);
+    archive.unpack(rootfs)?;
+
+    for marker in markers {
+        let _ = fs::remove_file(marker);
+    }
+    Ok(())
+}
+
+/// 打开 tar 包，按文件头识别 gzip 压缩
+pub(super) fn open(path: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+    let mut magic = [0u8; 4];
+    let n = File::open(path)?.read(&mut magic)?;
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = if magic[..n].starts_with(&GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(file))
+    } else if magic[..n] == ZSTD_MAGIC {
+        return Err(FireError::InvalidSpec(format!("不支持 zstd 压缩: {}", path.display())));
+    } else {
+        Box::new(file)
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// 执行 whiteout，返回解压后需要删除的 whiteout 文件路径，不是 whiteout 时返回 None
+fn apply_whiteout(rootfs: &Path, entry_path: &Path) -> Result<Option<PathBuf>> {
+    let Some(name) = entry_path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(None);
+    };
+    if !name.starts_with(WHITEOUT_PREFIX) {
+        return Ok(None);
+    }
+    // 上层镜像层中的符号链接按 rootfs 内的路径解析，不会删除 rootfs 之外的文件
+    let parent = entry_path.parent().unwrap_or(Path::new(""));
+    let dir = secure_join(rootfs, &parent.to_string_lossy())?;
+
+    if name == OPAQUE_WHITEOUT {
+        debug!("清空目录 {}", dir.display());
+        if fs::symlink_metadata(&dir).is_ok_and(|m| m.is_dir()) {
+            for entry in fs::read_dir(&dir)? {
+                remove(&entry?.path())?;
+            }
+        }
+    } else {
+        let target = dir.join(&name[WHITEOUT_PREFIX.len()..]);
+        debug!("删除 {}", target.display());
+        if fs::symlink_metadata(&target).is_ok() {
+            remove(&target)?;
+        }
+    }
+    Ok(Some(dir.join(name)))
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_uid(nix::unistd::getuid().as_raw() as u64);
+            header.set_gid(nix::unistd::getgid().as_raw() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_unpack_whiteouts() {
+        let dir = std::env::temp_dir().join(format!("fire-layer-{}", std::process::id()));
+        let rootfs = dir.join("rootfs");
+        fs::create_dir_all(&rootfs).unwrap();
+
+        let lower = dir.join("lower.tar");
+        layer(&lower, &[("etc/a", b"a"), ("etc/b", b"b"), ("opt/c", b"c")]);
+        unpack(&lower, &rootfs).unwrap();
+
+        let upper = dir.join("upper.tar");
+        layer(&upper, &[("etc/.wh.a", b""), ("opt/.wh..wh..opq", b""), ("opt/d", b"d")]);
+        unpack(&upper, &rootfs).unwrap();
+
+        let exists = |p: &str| rootfs.join(p).exists();
+        let result = (
+            exists("etc/a"),
+            exists("etc/b"),
+            exists("etc/.wh.a"),
+            exists("opt/c"),
+            exists("opt/d"),
+            exists("opt/.wh..wh..opq"),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result, (false, true, false, false, true, false));
+    }
+}
@@ -0,0 +1,127 @@
+//! OCI 镜像清单、索引和内容摘要
+//!
+//! 镜像仓库和本地 OCI layout 共用，也兼容 Docker 的 schema2 清单和清单列表。
+
+use crate::errors::{FireError, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// 镜像在 OCI layout 中的名称，通常为 tag
+pub const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// 内容描述符，引用清单、镜像配置或镜像层
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    #[serde(default)]
+    pub media_type: String,
+    pub digest: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub platform: Option<Platform>,
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+}
+
+/// 单一平台的镜像清单
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub config: Descriptor,
+    /// 由下至上的镜像层
+    pub layers: Vec<Descriptor>,
+}
+
+/// 清单和多平台索引共用的反序列化结构
+#[derive(Deserialize)]
+pub(super) struct Document {
+    #[serde(default)]
+    pub config: Option<Descriptor>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+}
+
+impl Document {
+    pub fn into_manifest(self) -> Result<Manifest> {
+        let config = self
+            .config
+            .ok_or_else(|| FireError::InvalidSpec("不支持的镜像清单格式".to_string()))?;
+        Ok(Manifest {
+            config,
+            layers: self.layers,
+        })
+    }
+}
+
+/// 在索引中选择本机平台的清单
+pub(super) fn select_platform<'a>(
+    manifests: impl IntoIterator<Item = &'a Descriptor>,
+) -> Result<&'a Descriptor> {
+    let arch = go_arch();
+    manifests
+        .into_iter()
+        .find(|d| d.platform.as_ref().is_some_and(|p| p.os == "linux" && p.architecture == arch))
+        .ok_or_else(|| FireError::NotFound(format!("镜像没有 linux/{} 平台的清单", arch)))
+}
+
+/// 镜像清单使用 Go 的架构名
+pub(super) fn go_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        "loongarch64" => "loong64",
+        arch => arch,
+    }
+}
+
+pub(super) fn sha256(bytes: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(bytes))
+}
+
+/// 计算文件的摘要和长度
+pub(super) fn sha256_file(path: &Path) -> Result<(String, u64)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
+
+/// 校验内容的摘要和长度，只支持 sha256
+pub(super) fn verify(expected: &str, actual: &str, size: u64, expected_size: Option<u64>) -> Result<()> {
+    if !expected.starts_with("sha256:") {
+        return Err(FireError::InvalidSpec(format!("不支持的摘要算法: {}", expected)));
+    }
+    if let Some(expected_size) = expected_size.filter(|&s| s > 0 && s != size) {
+        return Err(FireError::Generic(format!(
+            "{} 的长度为 {}，应为 {}",
+            expected, size, expected_size
+        )));
+    }
+    if expected != actual {
+        return Err(FireError::Generic(format!("摘要不匹配: 应为 {}，实际为 {}", expected, actual)));
+    }
+    Ok(())
+}
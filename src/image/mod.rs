@@ -0,0 +1,268 @@
+//! 从镜像仓库拉取 OCI 镜像并生成 bundle
+//!
+//! 按 Docker Registry HTTP API v2 获取镜像清单（多平台镜像选择本机架构）、镜像配置和
+//! 各镜像层，校验 sha256 摘要后依次解压到 `<bundle>/rootfs`，再由镜像配置生成
+//! `<bundle>/config.json`，得到的目录可以直接用于 `fire create` 和 `fire run`。
+//!
+//! 仓库要求认证时使用 WWW-Authenticate 中给出的 Bearer token 服务匿名获取 token。
+//! localhost 上的仓库使用明文 HTTP，其余使用 HTTPS。
+//!
+//! 无法访问仓库时，可以用 [`unpack`] 从本地的 OCI layout 或 `docker save` 生成的 tar 包
+//! 生成 bundle。
+
+mod archive;
+mod config;
+mod layer;
+mod manifest;
+mod registry;
+
+use crate::errors::{FireError, Result};
+use config::ImageConfig;
+use log::{info, warn};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Docker Hub 的镜像名省略仓库地址
+const DEFAULT_REGISTRY: &str = "docker.io";
+/// Docker Hub 实际提供 API 的地址
+const DEFAULT_REGISTRY_HOST: &str = "registry-1.docker.io";
+const DEFAULT_TAG: &str = "latest";
+
+/// 镜像引用，如 `alpine`、`ghcr.io/org/app:v1`、`localhost:5000/app@sha256:...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    pub fn parse(image: &str) -> Result<Self> {
+        let invalid = || FireError::InvalidSpec(format!("无效的镜像引用: {}", image));
+
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+        // 端口号中的冒号出现在最后一个斜杠之前
+        let (name, tag) = match name.rfind(':') {
+            Some(pos) if !name[pos..].contains('/') => (&name[..pos], Some(name[pos + 1..].to_string())),
+            _ => (name, None),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host.to_string(), rest.to_string())
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+
+        let valid_repository = !repository.is_empty()
+            && repository.split('/').all(|part| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+            });
+        let valid_tag = tag.iter().all(|tag| {
+            !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        });
+        let valid_digest = digest.iter().all(|digest| {
+            digest.split_once(':').is_some_and(|(algorithm, hex)| {
+                !algorithm.is_empty() && !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())
+            })
+        });
+        if !valid_repository || !valid_tag || !valid_digest {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// 获取清单时使用的 digest 或 tag，都未指定时为 latest
+    pub fn reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or(DEFAULT_TAG)
+    }
+
+    /// 仓库 API 的基础 URL
+    fn base_url(&self) -> String {
+        let host = if self.registry == DEFAULT_REGISTRY {
+            DEFAULT_REGISTRY_HOST
+        } else {
+            self.registry.as_str()
+        };
+        let local = ["localhost", "127.0.0.1", "[::1]"]
+            .iter()
+            .any(|name| host == *name || host.starts_with(&format!("{}:", name)));
+        format!("{}://{}", if local { "http" } else { "https" }, host)
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(ref tag) = self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        match self.digest {
+            Some(ref digest) => write!(f, "@{}", digest),
+            None if self.tag.is_none() => write!(f, ":{}", DEFAULT_TAG),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 拉取镜像并在 bundle 目录中生成 rootfs 和 config.json，返回镜像清单的 digest
+///
+/// bundle 目录可以不存在或为空目录。失败时清理已经生成的内容。
+pub fn pull(image: &str, bundle: &Path) -> Result<String> {
+    let reference = Reference::parse(image)?;
+    into_bundle(bundle, |bundle| pull_into(&reference, bundle))
+}
+
+/// 把本地的 OCI layout（目录或 tar 包）或 `docker save` 生成的 tar 包解压为 bundle
+///
+/// 包含多个镜像时用 name 选择，可以是镜像名（如 `alpine:3.19`）或 OCI layout 中的
+/// ref.name。返回 OCI 镜像清单的 digest，docker save 格式返回镜像 id。
+pub fn unpack(source: &Path, bundle: &Path, name: Option<&str>) -> Result<String> {
+    if !source.exists() {
+        return Err(FireError::NotFound(format!("{} 不存在", source.display())));
+    }
+    into_bundle(bundle, |bundle| archive::unpack_into(source, bundle, name))
+}
+
+/// 在不存在或为空的 bundle 目录中执行 f，失败时清理已经生成的内容
+fn into_bundle<F>(bundle: &Path, f: F) -> Result<String>
+where
+    F: FnOnce(&Path) -> Result<String>,
+{
+    let created = !bundle.exists();
+    if created {
+        fs::create_dir_all(bundle)?;
+    } else if fs::read_dir(bundle)?.next().is_some() {
+        return Err(FireError::AlreadyExists(format!(
+            "bundle 目录 {} 不为空",
+            bundle.display()
+        )));
+    }
+
+    let result = f(bundle);
+    if result.is_err() {
+        let _ = if created {
+            fs::remove_dir_all(bundle)
+        } else {
+            fs::read_dir(bundle).and_then(|entries| {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.is_dir() {
+                        fs::remove_dir_all(path)?;
+                    } else {
+                        fs::remove_file(path)?;
+                    }
+                }
+                Ok(())
+            })
+        };
+    }
+    result
+}
+
+fn pull_into(reference: &Reference, bundle: &Path) -> Result<String> {
+    info!("拉取镜像: {}", reference);
+    let mut client = registry::Client::new(reference);
+    let (manifest, digest) = client.manifest(reference)?;
+    let image_config = parse_config(&client.blob(&manifest.config)?)?;
+
+    let rootfs = bundle.join("rootfs");
+    fs::create_dir(&rootfs)?;
+    for (i, descriptor) in manifest.layers.iter().enumerate() {
+        info!(
+            "拉取镜像层 {}/{}: {} ({}, {} 字节)",
+            i + 1,
+            manifest.layers.len(),
+            descriptor.digest,
+            descriptor.media_type,
+            descriptor.size
+        );
+        // 先完整下载并校验摘要，再解压
+        let blob_path = bundle.join(format!(".layer-{}", i));
+        let result = client
+            .blob_to_file(descriptor, &blob_path)
+            .and_then(|_| layer::unpack(&blob_path, &rootfs));
+        let _ = fs::remove_file(&blob_path);
+        result?;
+    }
+
+    write_spec(&image_config, bundle)?;
+    info!("镜像 {} 已解压到 {}", reference, bundle.display());
+    Ok(digest)
+}
+
+/// 解析镜像配置，拒绝非 Linux 平台的镜像
+fn parse_config(bytes: &[u8]) -> Result<ImageConfig> {
+    let image_config: ImageConfig = serde_json::from_slice(bytes)?;
+    if !image_config.os.is_empty() && image_config.os != "linux" {
+        return Err(FireError::InvalidSpec(format!(
+            "不支持 {} 平台的镜像",
+            image_config.os
+        )));
+    }
+    if !image_config.architecture.is_empty() && image_config.architecture != manifest::go_arch() {
+        warn!("镜像的架构为 {}，与本机不同", image_config.architecture);
+    }
+    Ok(image_config)
+}
+
+/// 由镜像配置生成 bundle 的 config.json，rootfs 需要已经解压完成
+fn write_spec(image_config: &ImageConfig, bundle: &Path) -> Result<()> {
+    let spec = image_config.to_spec(&bundle.join("rootfs"))?;
+    let config_path = bundle.join("config.json");
+    spec.save(&config_path.to_string_lossy())
+        .map_err(|e| FireError::Generic(format!("写入 {} 失败: {:?}", config_path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        let r = Reference::parse("alpine").unwrap();
+        assert_eq!(r.registry, "docker.io");
+        assert_eq!(r.repository, "library/alpine");
+        assert_eq!(r.reference(), "latest");
+        assert_eq!(r.base_url(), "https://registry-1.docker.io");
+        assert_eq!(r.to_string(), "docker.io/library/alpine:latest");
+
+        let r = Reference::parse("localhost:5000/team/app:v1.2").unwrap();
+        assert_eq!(r.registry, "localhost:5000");
+        assert_eq!(r.repository, "team/app");
+        assert_eq!(r.reference(), "v1.2");
+        assert_eq!(r.base_url(), "http://localhost:5000");
+
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let r = Reference::parse(&format!("ghcr.io/org/app:v1@{}", digest)).unwrap();
+        assert_eq!(r.registry, "ghcr.io");
+        assert_eq!(r.tag.as_deref(), Some("v1"));
+        assert_eq!(r.reference(), digest);
+
+        assert!(Reference::parse("Alpine").is_err());
+        assert!(Reference::parse("alpine:").is_err());
+        assert!(Reference::parse("alpine@latest").is_err());
+    }
+}
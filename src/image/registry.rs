@@ -0,0 +1,221 @@
+//! Registry HTTP API v2 客户端
+
+use super::manifest::{select_platform, sha256, verify, Descriptor, Document, Manifest};
+use super::Reference;
+use crate::errors::{FireError, Result};
+use log::debug;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// 获取清单时接受的格式，包括多平台索引
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+     application/vnd.oci.image.manifest.v1+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.docker.distribution.manifest.v2+json";
+
+/// 清单和镜像配置的最大长度，镜像层不受限制
+const MAX_DOCUMENT: u64 = 8 * 1024 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+pub struct Client {
+    agent: ureq::Agent,
+    base_url: String,
+    repository: String,
+    token: Option<String>,
+}
+
+impl Client {
+    pub fn new(reference: &Reference) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .user_agent(concat!("fire/", env!("CARGO_PKG_VERSION")))
+                .build(),
+            base_url: reference.base_url(),
+            repository: reference.repository.clone(),
+            token: None,
+        }
+    }
+
+    /// 获取本机平台的镜像清单及其 digest
+    pub fn manifest(&mut self, reference: &Reference) -> Result<(Manifest, String)> {
+        let bytes = self.document(&format!("manifests/{}", reference.reference()), MANIFEST_ACCEPT)?;
+        let digest = match reference.digest {
+            Some(ref expected) => {
+                verify(expected, &sha256(&bytes), bytes.len() as u64, None)?;
+                expected.clone()
+            }
+            None => sha256(&bytes),
+        };
+        let document: Document = serde_json::from_slice(&bytes)?;
+        if document.manifests.is_empty() {
+            return Ok((document.into_manifest()?, digest));
+        }
+
+        let descriptor = select_platform(&document.manifests)?;
+        debug!("选择平台清单 {}", descriptor.digest);
+        let bytes = self.document(&format!("manifests/{}", descriptor.digest), MANIFEST_ACCEPT)?;
+        verify(&descriptor.digest, &sha256(&bytes), bytes.len() as u64, Some(descriptor.size))?;
+        let document: Document = serde_json::from_slice(&bytes)?;
+        Ok((document.into_manifest()?, descriptor.digest.clone()))
+    }
+
+    /// 读取较小的 blob，如镜像配置
+    pub fn blob(&mut self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        let bytes = self.document(&format!("blobs/{}", descriptor.digest), "*/*")?;
+        verify(&descriptor.digest, &sha256(&bytes), bytes.len() as u64, Some(descriptor.size))?;
+        Ok(bytes)
+    }
+
+    /// 下载 blob 到文件，边写边计算摘要
+    pub fn blob_to_file(&mut self, descriptor: &Descriptor, path: &Path) -> Result<()> {
+        let mut reader = self.get(&format!("blobs/{}", descriptor.digest), "*/*")?.into_reader();
+        let mut file = File::create(path)?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])?;
+            size += n as u64;
+        }
+        file.sync_all()?;
+        verify(
+            &descriptor.digest,
+            &format!("sha256:{:x}", hasher.finalize()),
+            size,
+            Some(descriptor.size),
+        )
+    }
+
+    fn document(&mut self, path: &str, accept: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.get(path, accept)?
+            .into_reader()
+            .take(MAX_DOCUMENT + 1)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > MAX_DOCUMENT {
+            return Err(FireError::Generic(format!("{} 超过 {} 字节", path, MAX_DOCUMENT)));
+        }
+        Ok(bytes)
+    }
+
+    /// 发送 GET 请求，收到 401 时按质询获取 token 后重试一次
+    fn get(&mut self, path: &str, accept: &str) -> Result<ureq::Response> {
+        let url = format!("{}/v2/{}/{}", self.base_url, self.repository, path);
+        let mut authenticated = false;
+        loop {
+            let mut request = self.agent.get(&url).set("Accept", accept);
+            if let Some(ref token) = self.token {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            match request.call() {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(401, response)) if !authenticated => {
+                    let challenge = response
+                        .header("WWW-Authenticate")
+                        .and_then(parse_challenge)
+                        .ok_or_else(|| FireError::PermissionDenied(format!("{} 需要认证", url)))?;
+                    self.token = Some(self.fetch_token(&challenge)?);
+                    authenticated = true;
+                }
+                Err(e) => return Err(http_error(&url, e)),
+            }
+        }
+    }
+
+    /// 向质询中的 realm 匿名申请拉取权限的 token
+    fn fetch_token(&self, challenge: &HashMap<String, String>) -> Result<String> {
+        let realm = challenge
+            .get("realm")
+            .ok_or_else(|| FireError::Generic("认证质询缺少 realm".to_string()))?;
+        let scope = challenge
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.repository));
+        debug!("从 {} 获取 {} 的 token", realm, scope);
+
+        let mut request = self.agent.get(realm).query("scope", &scope);
+        if let Some(service) = challenge.get("service") {
+            request = request.query("service", service);
+        }
+        let response = request.call().map_err(|e| http_error(realm, e))?;
+        let response: TokenResponse = serde_json::from_reader(response.into_reader())?;
+        response
+            .token
+            .or(response.access_token)
+            .ok_or_else(|| FireError::PermissionDenied(format!("{} 没有返回 token", realm)))
+    }
+}
+
+fn http_error(url: &str, error: ureq::Error) -> FireError {
+    match error {
+        ureq::Error::Status(404, _) => FireError::NotFound(url.to_string()),
+        ureq::Error::Status(code @ (401 | 403), _) => {
+            FireError::PermissionDenied(format!("{} 返回 {}", url, code))
+        }
+        ureq::Error::Status(code, _) => FireError::Generic(format!("{} 返回 {}", url, code)),
+        ureq::Error::Transport(e) => FireError::Generic(format!("请求 {} 失败: {}", url, e)),
+    }
+}
+
+/// 解析 `Bearer realm="...",service="...",scope="..."` 形式的质询参数
+fn parse_challenge(header: &str) -> Option<HashMap<String, String>> {
+    let (scheme, params) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    let mut challenge = HashMap::new();
+    let mut rest = params.trim();
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let (value, next) = match value.strip_prefix('"') {
+            // scope 可能包含逗号，引号内的内容原样保留
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => value.split_once(',').map_or((value, ""), |(v, n)| (v, n)),
+        };
+        challenge.insert(key.trim().to_ascii_lowercase(), value.to_string());
+        rest = next.trim_start_matches([',', ' ']);
+    }
+    Some(challenge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_challenge() {
+        let challenge = parse_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull,push""#,
+        )
+        .unwrap();
+        assert_eq!(challenge["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge["service"], "registry.docker.io");
+        assert_eq!(challenge["scope"], "repository:library/alpine:pull,push");
+
+        assert!(parse_challenge(r#"Basic realm="registry""#).is_none());
+    }
+}
@@ -1,19 +1,30 @@
 #![allow(unknown_lints)]
 #![recursion_limit = "1024"]
 
+pub mod api;
+pub mod apparmor;
 pub mod capabilities;
+pub mod cdi;
 pub mod cgroups;
 pub mod commands;
 pub mod container;
 pub mod errors;
+#[cfg(feature = "image")]
+pub mod image;
 pub mod logger;
 pub mod mounts;
+pub mod network;
 pub mod nix_ext;
+pub mod rfc3339;
+pub mod rootfs;
+pub mod rootless;
 pub mod runtime;
 pub mod seccomp;
 pub mod selinux;
 pub mod signals;
 pub mod sync;
+pub mod sysinfo;
+pub mod tty;
 
 // 重新导出主要的类型和函数
 pub use container::namespace::{NamespaceManager, NamespaceType, Namespace, UserNamespaceMapping};
@@ -1,6 +1,43 @@
-use log::{Level, Log, Metadata, Record};
+use lazy_static::lazy_static;
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use log::{Log, Metadata, Record};
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use serde::{Deserialize, Serialize};
 
-use std::io::{stderr, Write};
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, stderr, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+/// 日志过滤规则的环境变量，语法与 RUST_LOG 相同，如 `info,fire::cgroups=debug`
+pub const LOG_ENV: &str = "FIRE_LOG";
+/// 没有配置时的日志过滤规则
+pub const DEFAULT_FILTER: &str = "info";
+
+/// 日志文件超过该大小后轮转
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// 轮转后保留的旧日志文件数
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+lazy_static! {
+    static ref FILTER: RwLock<Filter> = RwLock::new(FilterBuilder::new().parse(DEFAULT_FILTER).build());
+    static ref FILE_SINK: Mutex<Option<FileSink>> = Mutex::new(None);
+    static ref CONTAINER_ID: Mutex<Option<String>> = Mutex::new(None);
+}
+
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// 当前线程正在操作的容器，优先于进程级的 CONTAINER_ID
+    static SCOPED_CONTAINER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// 收到 SIGHUP 后置位，下一条日志写入前重新打开日志文件
+static REOPEN: AtomicBool = AtomicBool::new(false);
 
 pub struct SimpleLogger;
 
@@ -8,23 +45,348 @@ pub static SIMPLE_LOGGER: SimpleLogger = SimpleLogger;
 
 impl Log for SimpleLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Debug
+        FILTER.read().unwrap_or_else(|e| e.into_inner()).enabled(metadata)
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            let _ = writeln!(&mut stderr(), "{} - {}", record.level(), record.args());
+        if !FILTER.read().unwrap_or_else(|e| e.into_inner()).matches(record) {
+            return;
+        }
+        let line = format_record(record);
+        if let Some(ref mut sink) = *FILE_SINK.lock().unwrap_or_else(|e| e.into_inner()) {
+            match sink.write(&line) {
+                Ok(()) => return,
+                Err(e) => {
+                    let _ = writeln!(&mut stderr(), "写入日志文件 {} 失败: {}", sink.path.display(), e);
+                }
+            }
         }
+        let _ = stderr().write_all(line.as_bytes());
     }
 
     fn flush(&self) {
+        if let Some(ref mut sink) = *FILE_SINK.lock().unwrap_or_else(|e| e.into_inner()) {
+            let _ = sink.file.flush();
+        }
         stderr().flush().expect("Failed to flush");
     }
 }
 
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `LEVEL [容器 id] - msg`，没有对应容器时省略 id
+    #[default]
+    Text,
+    /// 每行一个 JSON 对象，字段为 level、ts、msg、container_id
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("不支持的日志格式: {}，可选 text 或 json", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    level: String,
+    ts: String,
+    msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container_id: Option<&'a str>,
+}
+
+/// 当前线程的日志归属的容器
+fn current_container_id() -> Option<String> {
+    SCOPED_CONTAINER
+        .with(|c| c.borrow().clone())
+        .or_else(|| CONTAINER_ID.lock().unwrap_or_else(|e| e.into_inner()).clone())
+}
+
+fn format_record(record: &Record) -> String {
+    let container_id = current_container_id();
+    if !JSON_FORMAT.load(Ordering::Relaxed) {
+        return match container_id {
+            Some(id) => format!("{} [{}] - {}\n", record.level(), id, record.args()),
+            None => format!("{} - {}\n", record.level(), record.args()),
+        };
+    }
+    let json = JsonRecord {
+        level: record.level().as_str().to_lowercase(),
+        ts: crate::rfc3339::format(SystemTime::now()),
+        msg: record.args().to_string(),
+        container_id: container_id.as_deref(),
+    };
+    // 只包含字符串字段，序列化不会失败
+    let mut line = serde_json::to_string(&json).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+/// 按大小轮转的日志文件，旧文件依次重命名为 log.1、log.2 ...
+struct FileSink {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl FileSink {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let (file, size) = Self::open_file(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<(File, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    /// 先打开新文件再替换，打开失败时继续写旧文件
+    fn reopen(&mut self) -> io::Result<()> {
+        let (file, size) = Self::open_file(&self.path)?;
+        self.file = file;
+        self.size = size;
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            self.file.set_len(0)?;
+            self.size = 0;
+            return Ok(());
+        }
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.reopen()
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        // 日志文件可能已被 logrotate 等外部工具移走
+        if REOPEN.swap(false, Ordering::SeqCst) {
+            self.reopen()?;
+        }
+        let len = line.len() as u64;
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+extern "C" fn handle_sighup(_: libc::c_int) {
+    REOPEN.store(true, Ordering::SeqCst);
+}
+
 /// 初始化日志系统
 pub fn init() -> Result<(), log::SetLoggerError> {
     log::set_logger(&SIMPLE_LOGGER)?;
-    log::set_max_level(log::LevelFilter::Info);
+    set_filter(DEFAULT_FILTER);
     Ok(())
 }
+
+/// 设置日志过滤规则，如 `debug` 或 `warn,fire::cgroups=trace`
+///
+/// FIRE_LOG 或 RUST_LOG 环境变量存在时以环境变量为准，default 只在两者都未设置时生效。
+pub fn set_filter(default: &str) {
+    let directives = std::env::var(LOG_ENV)
+        .or_else(|_| std::env::var("RUST_LOG"))
+        .unwrap_or_else(|_| default.to_string());
+    let filter = FilterBuilder::new().parse(&directives).build();
+    log::set_max_level(filter.filter());
+    *FILTER.write().unwrap_or_else(|e| e.into_inner()) = filter;
+}
+
+/// 设置日志输出格式
+pub fn set_format(format: LogFormat) {
+    JSON_FORMAT.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// 设置当前操作的容器 id，JSON 格式的日志会带上 container_id 字段
+pub fn set_container_id(id: &str) {
+    *CONTAINER_ID.lock().unwrap_or_else(|e| e.into_inner()) = Some(id.to_string());
+}
+
+/// 当前线程的日志归属于某个容器，释放时恢复进入前的容器
+pub struct ContainerScope {
+    previous: Option<String>,
+}
+
+impl Drop for ContainerScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        SCOPED_CONTAINER.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// 之后当前线程的日志都带上容器 id，直到返回的 ContainerScope 被释放
+///
+/// 同一进程中交错操作多个容器时（如嵌入 fire 的 daemon），用于区分日志来源。
+pub fn container_scope(id: &str) -> ContainerScope {
+    let previous = SCOPED_CONTAINER.with(|c| c.replace(Some(id.to_string())));
+    ContainerScope { previous }
+}
+
+/// 将日志写入文件而不是 stderr
+///
+/// 文件超过 max_size 字节时轮转，最多保留 max_files 个旧文件，max_size 为 0 时不轮转。
+/// 同时安装 SIGHUP 处理，收到信号后重新打开日志文件，配合外部的日志轮转工具使用。
+pub fn set_log_file(path: &Path, max_size: u64, max_files: usize) -> io::Result<()> {
+    let sink = FileSink::open(path, max_size, max_files)?;
+    *FILE_SINK.lock().unwrap_or_else(|e| e.into_inner()) = Some(sink);
+
+    let action = SigAction::new(SigHandler::Handler(handle_sighup), SaFlags::SA_RESTART, SigSet::empty());
+    unsafe { signal::sigaction(Signal::SIGHUP, &action) }.map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// 日志配置，重新执行 fire 自身（如容器进程的 init 阶段）时交给新进程恢复
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Settings {
+    json: bool,
+    /// 日志文件的路径、轮转大小和保留的旧文件数
+    file: Option<(PathBuf, u64, usize)>,
+    container_id: Option<String>,
+}
+
+/// 当前线程的日志配置
+pub fn settings() -> Settings {
+    let file = FILE_SINK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|sink| (sink.path.clone(), sink.max_size, sink.max_files));
+    Settings {
+        json: JSON_FORMAT.load(Ordering::Relaxed),
+        file,
+        container_id: current_container_id(),
+    }
+}
+
+/// 恢复 [`settings`] 得到的日志配置
+pub fn restore(settings: &Settings) -> io::Result<()> {
+    JSON_FORMAT.store(settings.json, Ordering::Relaxed);
+    if let Some(ref id) = settings.container_id {
+        set_container_id(id);
+    }
+    if let Some((ref path, max_size, max_files)) = settings.file {
+        set_log_file(path, max_size, max_files)?;
+    }
+    Ok(())
+}
+
+/// 析构时将缓冲的 span 写入 trace 文件，必须在进程退出前释放
+#[derive(Default)]
+pub struct TraceGuard {
+    #[cfg(feature = "chrome-trace")]
+    _flush: Option<tracing_chrome::FlushGuard>,
+}
+
+impl TraceGuard {
+    /// 写完并关闭 trace 文件
+    pub fn finish(self) {}
+}
+
+/// 将 create、start 等操作的 span 及耗时写入 chrome://tracing 格式的文件
+///
+/// span 在 fork 出的容器进程中不会被记录。需要启用 chrome-trace 特性。
+pub fn init_trace(path: Option<&Path>) -> TraceGuard {
+    let Some(path) = path else {
+        return TraceGuard::default();
+    };
+
+    #[cfg(feature = "chrome-trace")]
+    {
+        use tracing_subscriber::prelude::*;
+
+        let (layer, flush) = tracing_chrome::ChromeLayerBuilder::new()
+            .file(path)
+            .include_args(true)
+            .build();
+        if let Err(e) = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer)) {
+            log::warn!("初始化 trace 失败: {}", e);
+            return TraceGuard::default();
+        }
+        TraceGuard { _flush: Some(flush) }
+    }
+
+    #[cfg(not(feature = "chrome-trace"))]
+    {
+        log::warn!("未启用 chrome-trace 特性，忽略 trace 文件 {}", path.display());
+        TraceGuard::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_container_scope() {
+        let format = |level| {
+            format_record(&Record::builder().args(format_args!("创建namespace")).level(level).build())
+        };
+        {
+            let _outer = container_scope("web");
+            {
+                let _inner = container_scope("db");
+                assert_eq!(format(Level::Info), "INFO [db] - 创建namespace\n");
+            }
+            assert_eq!(format(Level::Warn), "WARN [web] - 创建namespace\n");
+        }
+        assert_eq!(format(Level::Info), "INFO - 创建namespace\n");
+    }
+
+    #[test]
+    fn test_file_sink_rotate() {
+        let dir = std::env::temp_dir().join(format!("fire-log-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fire.log");
+
+        let mut sink = FileSink::open(&path, 16, 2).unwrap();
+        for line in ["first line\n", "second line\n", "third line\n", "fourth line\n"] {
+            sink.write(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(fs::read_to_string(sink.rotated(1)).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(sink.rotated(2)).unwrap(), "second line\n");
+        assert!(!sink.rotated(3).exists());
+
+        // 模拟 logrotate 移走文件后发送 SIGHUP
+        fs::rename(&path, dir.join("moved.log")).unwrap();
+        REOPEN.store(true, Ordering::SeqCst);
+        sink.write("fifth line\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fifth line\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
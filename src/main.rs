@@ -4,19 +4,29 @@
 use clap::{Parser, Subcommand};
 use std::process;
 
+mod apparmor;
 mod capabilities;
+mod cdi;
 mod cgroups;
 mod commands;
 mod container;
 mod errors;
+#[cfg(feature = "image")]
+mod image;
 mod logger;
 mod mounts;
+mod network;
 mod nix_ext;
+mod rfc3339;
+mod rootfs;
+mod rootless;
 mod runtime;
 mod seccomp;
 mod selinux;
 mod signals;
 mod sync;
+mod sysinfo;
+mod tty;
 
 use commands::Command;
 
@@ -27,6 +37,15 @@ use commands::Command;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Write a chrome://tracing profile of the operation to FILE (requires the chrome-trace feature)
+    #[arg(long, global = true, value_name = "FILE")]
+    trace: Option<std::path::PathBuf>,
+    /// Write logs to FILE instead of stderr; rotated by size and reopened on SIGHUP
+    #[arg(long, global = true, value_name = "FILE")]
+    log: Option<std::path::PathBuf>,
+    /// Log format: text or json (one record per line, for containerd)
+    #[arg(long = "log-format", global = true, value_name = "FORMAT", default_value = "text")]
+    log_format: logger::LogFormat,
 }
 
 #[derive(Subcommand)]
@@ -37,6 +56,17 @@ enum Commands {
         id: String,
         /// Bundle path
         bundle: Option<String>,
+        #[command(flatten)]
+        dns: DnsArgs,
+        /// Image layer directories for an overlayfs rootfs (colon separated, topmost first)
+        #[arg(long = "rootfs-layers", value_delimiter = ':')]
+        rootfs_layers: Vec<std::path::PathBuf>,
+        /// Parent cgroup path or systemd slice (default /fire)
+        #[arg(long = "cgroup-parent")]
+        cgroup_parent: Option<String>,
+        /// Send the pty master to this unix socket when process.terminal is true
+        #[arg(long, value_name = "SOCKET")]
+        console_socket: Option<std::path::PathBuf>,
     },
     /// Start a container
     Start {
@@ -50,6 +80,12 @@ enum Commands {
         /// Signal to send
         #[arg(short, long, default_value = "15")]
         signal: i32,
+        /// Send the signal to all processes in the container
+        #[arg(short, long, conflicts_with = "exec_id")]
+        all: bool,
+        /// Send the signal to a process started by `fire exec` instead of the init process
+        #[arg(long, value_name = "EXEC-ID")]
+        exec_id: Option<String>,
     },
     /// Delete a container
     Delete {
@@ -62,7 +98,17 @@ enum Commands {
     /// Get container state
     State {
         /// Container ID
-        id: String,
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        id: Option<String>,
+        /// Print a JSON array with the state of every container
+        #[arg(long, conflicts_with = "human")]
+        all: bool,
+        /// Show cgroup resource usage
+        #[arg(long)]
+        stats: bool,
+        /// Print a human readable listing instead of the OCI state JSON
+        #[arg(long)]
+        human: bool,
     },
     /// Run a container
     Run {
@@ -70,6 +116,74 @@ enum Commands {
         id: String,
         /// Bundle path
         bundle: Option<String>,
+        #[command(flatten)]
+        dns: DnsArgs,
+        /// Image layer directories for an overlayfs rootfs (colon separated, topmost first)
+        #[arg(long = "rootfs-layers", value_delimiter = ':')]
+        rootfs_layers: Vec<std::path::PathBuf>,
+        /// Parent cgroup path or systemd slice (default /fire)
+        #[arg(long = "cgroup-parent")]
+        cgroup_parent: Option<String>,
+        /// Return after the container starts instead of waiting for it to exit
+        #[arg(short, long)]
+        detach: bool,
+        /// Keep the stopped container after a foreground run exits instead of deleting it
+        #[arg(long)]
+        keep: bool,
+        /// Seconds to wait after forwarding SIGTERM/SIGINT before killing the container
+        #[arg(long, value_name = "SECONDS", default_value_t = commands::run::DEFAULT_STOP_TIMEOUT)]
+        stop_timeout: u64,
+        /// Send the pty master to this unix socket when process.terminal is true
+        #[arg(long, value_name = "SOCKET")]
+        console_socket: Option<std::path::PathBuf>,
+    },
+    /// Run a command in a running container
+    Exec {
+        /// Container ID
+        id: String,
+        /// Allocate a pseudo-terminal
+        #[arg(short, long)]
+        tty: bool,
+        /// Keep stdin open and forward it to the process
+        #[arg(short, long)]
+        interactive: bool,
+        /// OCI process JSON to execute instead of the command line
+        /// (terminal, env, cwd, user, capabilities, rlimits, labels)
+        #[arg(short, long, value_name = "FILE")]
+        process: Option<std::path::PathBuf>,
+        /// Run as uid[:gid] instead of the container's user (gid defaults to 0)
+        #[arg(short, long, value_name = "UID[:GID]")]
+        user: Option<String>,
+        /// Set an environment variable, overriding the container's (repeatable)
+        #[arg(short, long, value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Working directory inside the container
+        #[arg(long, value_name = "PATH")]
+        cwd: Option<String>,
+        /// Return once the process has started instead of waiting for it
+        #[arg(short, long, conflicts_with = "interactive")]
+        detach: bool,
+        /// Write the process pid to FILE
+        #[arg(long, value_name = "FILE")]
+        pid_file: Option<std::path::PathBuf>,
+        /// ID for `fire kill --exec-id` (defaults to the pid)
+        #[arg(long, value_name = "EXEC-ID")]
+        exec_id: Option<String>,
+        /// Send the pty master to this unix socket instead of attaching (requires a tty)
+        #[arg(long, value_name = "SOCKET")]
+        console_socket: Option<std::path::PathBuf>,
+        /// Command and its arguments
+        #[arg(required_unless_present = "process", conflicts_with = "process", trailing_var_arg = true,
+              allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Receive a pty master on a console socket and attach it to this terminal
+    Console {
+        /// Unix socket path to listen on (pass it as --console-socket)
+        socket: std::path::PathBuf,
+        /// Only show the output, do not forward stdin
+        #[arg(long)]
+        no_stdin: bool,
     },
     /// Pause a container
     Pause {
@@ -82,7 +196,77 @@ enum Commands {
         id: String,
     },
     /// List containers
-    Ps,
+    Ps {
+        /// Sample cgroup usage and show CPU% and MEM columns
+        #[arg(long)]
+        stats: bool,
+    },
+    /// Print the kernel features detected on this host as JSON
+    Features,
+    /// Stream container events (OOM kills) as JSON lines
+    Events {
+        /// Container ID
+        id: String,
+    },
+    /// Pull an image from a registry and unpack it into a bundle
+    #[cfg(feature = "image")]
+    Pull {
+        /// Image reference, e.g. alpine:3.19 or ghcr.io/org/app@sha256:...
+        image: String,
+        /// Bundle directory to create (must be empty or not exist)
+        bundle: std::path::PathBuf,
+    },
+    /// Unpack a local OCI layout or docker-archive tar into a bundle
+    #[cfg(feature = "image")]
+    Unpack {
+        /// OCI layout directory, or a tar of one, or a `docker save` tar
+        source: std::path::PathBuf,
+        /// Bundle directory to create (must be empty or not exist)
+        bundle: std::path::PathBuf,
+        /// Image to unpack when the source holds several (image name or OCI ref.name)
+        #[arg(long)]
+        image: Option<String>,
+    },
+}
+
+impl Commands {
+    /// 命令操作的容器 id，用于日志
+    fn container_id(&self) -> Option<&str> {
+        match self {
+            Commands::Create { id, .. }
+            | Commands::Start { id }
+            | Commands::Kill { id, .. }
+            | Commands::Delete { id, .. }
+            | Commands::Run { id, .. }
+            | Commands::Exec { id, .. }
+            | Commands::Pause { id }
+            | Commands::Resume { id }
+            | Commands::Events { id } => Some(id),
+            Commands::State { id, .. } => id.as_deref(),
+            Commands::Ps { .. } | Commands::Console { .. } | Commands::Features => None,
+            #[cfg(feature = "image")]
+            Commands::Pull { .. } | Commands::Unpack { .. } => None,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct DnsArgs {
+    /// DNS server for the container's resolv.conf (repeatable)
+    #[arg(long = "dns")]
+    servers: Vec<String>,
+    /// DNS search domain (repeatable)
+    #[arg(long = "dns-search")]
+    searches: Vec<String>,
+    /// resolv.conf option (repeatable)
+    #[arg(long = "dns-option")]
+    options: Vec<String>,
+}
+
+impl From<DnsArgs> for network::etcfiles::DnsConfig {
+    fn from(args: DnsArgs) -> Self {
+        Self::new(args.servers, args.searches, args.options)
+    }
 }
 
 fn main() {
@@ -92,37 +276,109 @@ fn main() {
         process::exit(1);
     });
 
+    // create 和 exec 以 `/proc/self/exe init` 启动容器进程，不解析命令行也不初始化运行时，
+    // 成功时执行容器命令不再返回
+    if container::process::is_init() {
+        if let Err(e) = container::process::init() {
+            log::error!("容器进程初始化失败: {}", e);
+        }
+        process::exit(1);
+    }
+
+    let cli = Cli::parse();
+    logger::set_format(cli.log_format);
+    if let Some(id) = cli.command.container_id() {
+        logger::set_container_id(id);
+    }
+
+    // 日志尚未配置，配置文件的错误直接输出到 stderr
+    let mut config = runtime::config::RuntimeConfig::load().unwrap_or_else(|e| {
+        eprintln!("读取运行时配置失败，使用默认配置: {}", e);
+        runtime::config::RuntimeConfig::default()
+    });
+    logger::set_filter(&config.log_level);
+    if cli.log.is_some() {
+        config.log_file = cli.log.clone();
+    }
+    if let Some(ref log_file) = config.log_file {
+        if let Err(e) = logger::set_log_file(log_file, config.log_max_size, config.log_max_files) {
+            eprintln!("打开日志文件 {} 失败: {}", log_file.display(), e);
+            process::exit(1);
+        }
+    }
+
     // 初始化运行时
     if let Err(e) = runtime::init() {
         eprintln!("初始化运行时失败: {}", e);
         process::exit(1);
     }
 
-    let cli = Cli::parse();
+    let trace = logger::init_trace(cli.trace.as_deref());
 
+    let mut exit_code = 0;
     let result = match cli.command {
-        Commands::Create { id, bundle } => {
-            let cmd = commands::create::CreateCommand::new(id, bundle);
+        Commands::Create { id, bundle, dns, rootfs_layers, cgroup_parent, console_socket } => {
+            let mut cmd = commands::create::CreateCommand::new(id, bundle);
+            cmd.set_dns(dns.into());
+            cmd.set_rootfs_layers(rootfs_layers);
+            cmd.set_cgroup_parent(cgroup_parent);
+            cmd.set_console_socket(console_socket);
             cmd.execute()
         }
         Commands::Start { id } => {
             let cmd = commands::start::StartCommand::new(id);
             cmd.execute()
         }
-        Commands::Kill { id, signal } => {
-            let cmd = commands::kill::KillCommand::new(id, signal);
+        Commands::Kill { id, signal, all, exec_id } => {
+            let mut cmd = commands::kill::KillCommand::new(id, signal);
+            cmd.set_all(all);
+            cmd.set_exec_id(exec_id);
             cmd.execute()
         }
         Commands::Delete { id, force } => {
             let cmd = commands::delete::DeleteCommand::new(id, force);
             cmd.execute()
         }
-        Commands::State { id } => {
-            let cmd = commands::state::StateCommand::new(id);
+        Commands::State { id, all, stats, human } => {
+            let mut cmd = commands::state::StateCommand::new(id.unwrap_or_default());
+            cmd.set_stats(stats);
+            cmd.set_human(human);
+            cmd.set_all(all);
             cmd.execute()
         }
-        Commands::Run { id, bundle } => {
-            let cmd = commands::run::RunCommand::new(id, bundle);
+        Commands::Run {
+            id, bundle, dns, rootfs_layers, cgroup_parent, detach, keep, stop_timeout, console_socket,
+        } => {
+            let mut cmd = commands::run::RunCommand::new(id, bundle);
+            cmd.set_dns(dns.into());
+            cmd.set_rootfs_layers(rootfs_layers);
+            cmd.set_cgroup_parent(cgroup_parent);
+            cmd.set_detach(detach);
+            cmd.set_keep(keep);
+            cmd.set_stop_timeout(std::time::Duration::from_secs(stop_timeout));
+            cmd.set_console_socket(console_socket);
+            // 与 runc 一致，前台运行时以容器的退出码退出
+            cmd.run().map(|code| exit_code = code)
+        }
+        Commands::Exec {
+            id, tty, interactive, process, user, env, cwd, detach, pid_file, exec_id, console_socket, command,
+        } => {
+            let mut cmd = commands::exec::ExecCommand::new(id, command);
+            cmd.set_tty(tty);
+            cmd.set_interactive(interactive);
+            cmd.set_process(process);
+            cmd.set_user(user);
+            cmd.set_env(env);
+            cmd.set_cwd(cwd);
+            cmd.set_detach(detach);
+            cmd.set_pid_file(pid_file);
+            cmd.set_exec_id(exec_id);
+            cmd.set_console_socket(console_socket);
+            cmd.run().map(|code| exit_code = code)
+        }
+        Commands::Console { socket, no_stdin } => {
+            let mut cmd = commands::console::ConsoleCommand::new(socket);
+            cmd.set_no_stdin(no_stdin);
             cmd.execute()
         }
         Commands::Pause { id } => {
@@ -133,15 +389,42 @@ fn main() {
             let mut runtime = runtime::Runtime::new();
             runtime.resume_container(&id)
         }
-        Commands::Ps => {
-            let cmd = commands::ps::PsCommand::new();
+        Commands::Ps { stats } => {
+            let mut cmd = commands::ps::PsCommand::new();
+            cmd.set_stats(stats);
+            cmd.execute()
+        }
+        Commands::Features => {
+            let cmd = commands::features::FeaturesCommand::new();
+            cmd.execute()
+        }
+        Commands::Events { id } => {
+            let cmd = commands::events::EventsCommand::new(id);
+            cmd.execute()
+        }
+        #[cfg(feature = "image")]
+        Commands::Pull { image, bundle } => {
+            let cmd = commands::pull::PullCommand::new(image, bundle);
+            cmd.execute()
+        }
+        #[cfg(feature = "image")]
+        Commands::Unpack { source, bundle, image } => {
+            let mut cmd = commands::unpack::UnpackCommand::new(source, bundle);
+            cmd.set_image(image);
             cmd.execute()
         }
     };
+    // process::exit 不会运行析构函数，先写完 trace 文件
+    trace.finish();
 
     if let Err(e) = result {
-        eprintln!("错误: {}", e);
-        process::exit(1);
+        // containerd 从 JSON 日志中读取运行时的错误信息
+        if cli.log_format == logger::LogFormat::Json {
+            log::error!("{}", e);
+        } else {
+            eprintln!("错误: {}", e);
+        }
+        process::exit(e.exit_code());
     }
 
     // 清理运行时
@@ -149,4 +432,5 @@ fn main() {
         eprintln!("清理运行时失败: {}", e);
         process::exit(1);
     }
+    process::exit(exit_code);
 }
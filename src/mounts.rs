@@ -1,12 +1,19 @@
 use crate::errors::*;
+use crate::container::namespace::{create_detached_user_namespace, UserNamespaceMapping};
+use crate::nix_ext;
+use crate::selinux;
 use lazy_static::lazy_static;
-use log::{warn, info};
+use log::{debug, warn, info};
 use oci::{LinuxDevice, LinuxDeviceType, Mount, Spec};
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fs::{create_dir_all, File};
-use std::os::unix::fs::symlink;
-use std::path::Path;
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Component, Path, PathBuf};
 
+/// 在 rootfs 中挂载 spec 中的文件系统并创建设备，调用前需要先设置传播模式，
+/// 避免挂载传播到宿主机
 pub fn mount_to(spec: &Spec, rootfs: &str, bind_device: bool) -> Result<()> {
     let olddir = std::env::current_dir()?;
     std::env::set_current_dir(rootfs)?;
@@ -24,37 +31,64 @@ pub fn mount_to(spec: &Spec, rootfs: &str, bind_device: bool) -> Result<()> {
         )));
     }
 
-    // 处理根文件系统传播模式
-    if let Some(ref linux) = spec.linux {
-        setup_rootfs_propagation(&linux.rootfs_propagation)?;
-    }
-
     // 挂载根文件系统
     mount_rootfs(rootfs)?;
+    let mount_label = selinux::mount_label(spec);
+
+    // spec 未提供 /dev 时挂载 tmpfs 并创建默认设备
+    let default_dev = !has_mount(spec, "/dev");
+    if default_dev {
+        mount_dev(rootfs, mount_label)?;
+    }
 
     // 挂载所有指定的挂载点
+    let cgroupns = has_namespace(spec, oci::LinuxNamespaceType::cgroup);
     for m in &spec.mounts {
-        if let Err(e) = mount_entry(m, bind_device) {
-            warn!("挂载失败，但继续执行: {} -> {}: {}", m.source, m.destination, e);
+        if m.typ == "cgroup" {
+            mount_cgroup(m, Path::new(rootfs), cgroupns)?;
+        } else if idmap_option(m).is_some() {
+            mount_idmapped_entry(m, spec, rootfs)?;
+        } else {
+            mount_entry(m, rootfs, bind_device, mount_label)?;
         }
     }
 
+    // spec 未提供 /dev/shm 时挂载默认的共享内存
+    if !has_mount(spec, "/dev/shm") {
+        mount_shm(spec, rootfs)?;
+    }
+
+    // 独立的 IPC namespace 需要自己的 mqueue 文件系统
+    if has_namespace(spec, oci::LinuxNamespaceType::ipc) && !has_mount(spec, "/dev/mqueue") {
+        mount_mqueue(rootfs)?;
+    }
+
     // 创建默认符号链接
-    default_symlinks()?;
+    default_symlinks(rootfs)?;
+    crate::network::etcfiles::ensure_mtab(spec, Path::new(rootfs))?;
     
-    // 创建设备文件
-    if let Some(ref linux) = spec.linux {
-        create_devices(&linux.devices, bind_device)?;
+    // 创建设备文件，spec 中同路径的设备覆盖默认设备
+    let mut devices = Vec::new();
+    let spec_devices = spec.linux.as_ref().map(|l| l.devices.as_slice()).unwrap_or(&[]);
+    if default_dev {
+        devices.extend(
+            default_devices()
+                .into_iter()
+                .filter(|d| !spec_devices.iter().any(|s| s.path == d.path)),
+        );
     }
+    devices.extend(spec_devices.iter().cloned());
+    create_devices(rootfs, &devices, bind_device)?;
     
     // 确保ptmx存在
-    ensure_ptmx()?;
+    ensure_ptmx(rootfs)?;
 
     info!("文件系统挂载完成");
     Ok(())
 }
 
-fn setup_rootfs_propagation(propagation: &str) -> Result<()> {
+/// 按 linux.rootfsPropagation 设置根目录的传播模式，未设置时为 slave
+pub fn setup_rootfs_propagation(propagation: &str) -> Result<()> {
     let flags = match propagation {
         "shared" => libc::MS_SHARED | libc::MS_REC,
         "private" => libc::MS_PRIVATE | libc::MS_REC,
@@ -86,6 +120,343 @@ fn setup_rootfs_propagation(propagation: &str) -> Result<()> {
     Ok(())
 }
 
+/// 在 rootfs 内解析并创建路径的父目录，返回待创建条目在宿主机上的路径
+///
+/// 最后一个分量不跟随符号链接，已存在的同名条目由调用方处理。
+fn create_parent_in_rootfs(rootfs: &Path, path: &str) -> Result<PathBuf> {
+    let name = Path::new(path).file_name().ok_or_else(|| {
+        crate::errors::FireError::InvalidSpec(format!("无效的容器路径: {}", path))
+    })?;
+    let parent = Path::new(path).parent().unwrap_or_else(|| Path::new("/"));
+    let parent = secure_join(rootfs, &parent.to_string_lossy())?;
+    create_dir_all(&parent)?;
+    Ok(parent.join(name))
+}
+
+/// 解析符号链接的最大层数，与内核的 MAXSYMLINKS 保持一致
+const MAX_SYMLINKS: usize = 40;
+
+/// 在 rootfs 内安全地解析容器路径
+///
+/// 逐级解析路径分量：符号链接以 rootfs 为根重新解释，`..` 不会越过 rootfs，
+/// 因此结果始终位于 rootfs 之下。不存在的分量按原样拼接。
+pub(crate) fn secure_join(rootfs: &Path, path: &str) -> Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<OsString> = Vec::new();
+    let mut links = 0;
+
+    let push_components = |pending: &mut Vec<OsString>, path: &Path| {
+        // 逆序压栈，便于从末尾弹出下一个分量
+        for component in path.components().rev() {
+            match component {
+                Component::Normal(name) => pending.push(name.to_os_string()),
+                Component::ParentDir => pending.push(OsString::from("..")),
+                _ => {}
+            }
+        }
+    };
+    push_components(&mut pending, Path::new(path));
+
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let candidate = resolved.join(&name);
+        let full = rootfs.join(&candidate);
+        match std::fs::symlink_metadata(&full) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(crate::errors::FireError::Generic(format!(
+                        "解析 {} 时符号链接层数过多",
+                        path
+                    )));
+                }
+                let target = std::fs::read_link(&full)?;
+                if target.is_absolute() {
+                    resolved = PathBuf::new();
+                }
+                push_components(&mut pending, &target);
+            }
+            _ => resolved = candidate,
+        }
+    }
+
+    Ok(rootfs.join(resolved))
+}
+
+/// 以 O_PATH 打开 rootfs 内已存在的路径
+///
+/// 优先使用 openat2(RESOLVE_BENEATH|RESOLVE_NO_MAGICLINKS)；内核不支持或
+/// 路径中含有绝对符号链接时，回退到 secure_join 并校验打开结果仍在 rootfs 内。
+fn open_in_rootfs(rootfs: &Path, path: &str) -> Result<OwnedFd> {
+    let root = File::open(rootfs)?;
+    let relative = match path.trim_start_matches('/') {
+        "" => ".",
+        p => p,
+    };
+    let relative_cstr = std::ffi::CString::new(relative)?;
+
+    if crate::sysinfo::get().syscalls.openat2 {
+        match nix_ext::openat2(
+            root.as_raw_fd(),
+            &relative_cstr,
+            libc::O_PATH | libc::O_CLOEXEC,
+            libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS,
+        ) {
+            Ok(fd) => return Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+            Err(crate::errors::FireError::Nix(nix::errno::Errno::EXDEV)) => {}
+            Err(e) => {
+                return Err(crate::errors::FireError::Generic(format!(
+                    "打开挂载目标 {} 失败: {}",
+                    path, e
+                )))
+            }
+        }
+    }
+
+    let resolved = secure_join(rootfs, path)?;
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(&resolved)?;
+
+    // 解析和打开之间路径可能被替换，确认打开的仍是 rootfs 内的文件
+    let actual = std::fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd()))?;
+    if !actual.starts_with(std::fs::canonicalize(rootfs)?) {
+        return Err(crate::errors::FireError::Generic(format!(
+            "挂载目标 {} 逃逸出 rootfs: {}",
+            path,
+            actual.display()
+        )));
+    }
+    Ok(OwnedFd::from(file))
+}
+
+/// 在 rootfs 内创建挂载目标（目录或空文件）并打开
+fn prepare_target(rootfs: &Path, path: &str, is_file: bool) -> Result<OwnedFd> {
+    let resolved = secure_join(rootfs, path)?;
+    if is_file {
+        if let Some(parent) = resolved.parent() {
+            create_dir_all(parent)?;
+        }
+        if !resolved.exists() {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&resolved)?;
+        }
+    } else {
+        create_dir_all(&resolved)?;
+    }
+    open_in_rootfs(rootfs, path)
+}
+
+/// 通过 /proc/self/fd/N 引用已打开的挂载目标
+fn fd_path(fd: &OwnedFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()))
+}
+
+/// 通过注解配置 /dev/shm 的大小
+pub const ANNOTATION_SHM_SIZE: &str = "org.fire.shm.size";
+const DEFAULT_SHM_SIZE: &str = "64m";
+
+fn mount_tmpfs(rootfs: &Path, dest: &str, flags: u64, data: &str) -> Result<()> {
+    let target = prepare_target(rootfs, dest, false)?;
+
+    let source = std::ffi::CString::new("tmpfs")?;
+    let target_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
+    let data_cstr = std::ffi::CString::new(data)?;
+    unsafe {
+        if libc::mount(
+            source.as_ptr(),
+            target_cstr.as_ptr(),
+            source.as_ptr(),
+            flags,
+            data_cstr.as_ptr() as *const libc::c_void,
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "挂载 tmpfs 到 {} 失败: {}",
+                dest,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 在容器的 /dev 挂载 tmpfs
+fn mount_dev(rootfs: &str, mount_label: &str) -> Result<()> {
+    mount_tmpfs(
+        Path::new(rootfs),
+        "/dev",
+        libc::MS_NOSUID | libc::MS_STRICTATIME,
+        &selinux::with_mount_label("mode=755,size=65536k", mount_label),
+    )?;
+    info!("成功挂载 /dev tmpfs");
+    Ok(())
+}
+
+/// 校验并规范化 tmpfs 的数据选项
+///
+/// size、nr_blocks、nr_inodes 支持 k/m/g/t 后缀（size 还支持百分比），转换为字节数；
+/// mode 必须是八进制权限，uid/gid 必须是数字。其他选项原样保留。
+pub fn normalize_tmpfs_data(data: &str) -> Result<String> {
+    let invalid = |option: &str| {
+        crate::errors::FireError::InvalidSpec(format!("无效的 tmpfs 选项: {}", option))
+    };
+
+    let mut options = Vec::new();
+    for option in data.split(',').filter(|o| !o.is_empty()) {
+        let normalized = match option.split_once('=') {
+            Some(("size", value)) if value.ends_with('%') => {
+                let percent: u64 = value[..value.len() - 1].parse().map_err(|_| invalid(option))?;
+                if percent == 0 || percent > 100 {
+                    return Err(invalid(option));
+                }
+                format!("size={}%", percent)
+            }
+            Some((key @ ("size" | "nr_blocks" | "nr_inodes"), value)) => {
+                format!("{}={}", key, parse_size(value).ok_or_else(|| invalid(option))?)
+            }
+            Some(("mode", value)) => {
+                let mode = u32::from_str_radix(value, 8).map_err(|_| invalid(option))?;
+                if mode > 0o7777 {
+                    return Err(invalid(option));
+                }
+                format!("mode={:o}", mode)
+            }
+            Some((key @ ("uid" | "gid"), value)) => {
+                let id: u32 = value.parse().map_err(|_| invalid(option))?;
+                format!("{}={}", key, id)
+            }
+            _ => option.to_string(),
+        };
+        options.push(normalized);
+    }
+    Ok(options.join(","))
+}
+
+/// 将 64m、1g 这类大小转换为字节数
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1u64 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        't' => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// 在容器的 /dev/shm 挂载 tmpfs，大小可通过注解配置
+fn mount_shm(spec: &Spec, rootfs: &str) -> Result<()> {
+    let size = spec
+        .annotations
+        .get(ANNOTATION_SHM_SIZE)
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_SHM_SIZE);
+    let data = normalize_tmpfs_data(&format!("mode=1777,size={}", size))?;
+    let data = selinux::with_mount_label(&data, selinux::mount_label(spec));
+    mount_tmpfs(
+        Path::new(rootfs),
+        "/dev/shm",
+        libc::MS_NOSUID | libc::MS_NOEXEC | libc::MS_NODEV,
+        &data,
+    )?;
+    info!("成功挂载 /dev/shm tmpfs，大小: {}", size);
+    Ok(())
+}
+
+/// 为独立的 IPC namespace 挂载 /dev/mqueue
+fn mount_mqueue(rootfs: &str) -> Result<()> {
+    let target = prepare_target(Path::new(rootfs), "/dev/mqueue", false)?;
+
+    let source = std::ffi::CString::new("mqueue")?;
+    let target_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
+    unsafe {
+        if libc::mount(
+            source.as_ptr(),
+            target_cstr.as_ptr(),
+            source.as_ptr(),
+            libc::MS_NOSUID | libc::MS_NOEXEC | libc::MS_NODEV,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "挂载 /dev/mqueue 失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    info!("成功挂载 /dev/mqueue");
+    Ok(())
+}
+
+fn has_namespace(spec: &Spec, typ: oci::LinuxNamespaceType) -> bool {
+    spec.linux
+        .as_ref()
+        .map(|linux| linux.namespaces.iter().any(|ns| ns.typ == typ))
+        .unwrap_or(false)
+}
+
+fn has_mount(spec: &Spec, destination: &str) -> bool {
+    spec.mounts
+        .iter()
+        .any(|m| Path::new(&m.destination) == Path::new(destination))
+}
+
+/// OCI 规范要求容器内默认提供的设备
+fn default_devices() -> Vec<LinuxDevice> {
+    let device = |path: &str, major: u64, minor: u64| LinuxDevice {
+        path: path.to_string(),
+        typ: LinuxDeviceType::c,
+        major,
+        minor,
+        file_mode: Some(0o666),
+        uid: Some(0),
+        gid: Some(0),
+    };
+    vec![
+        device("/dev/null", 1, 3),
+        device("/dev/zero", 1, 5),
+        device("/dev/full", 1, 7),
+        device("/dev/random", 1, 8),
+        device("/dev/urandom", 1, 9),
+        device("/dev/tty", 5, 0),
+    ]
+}
+
+/// 将分配给容器的 pty 从设备绑定挂载到 /dev/console
+pub fn setup_console(rootfs: &str, pty_slave: &str) -> Result<()> {
+    let console = prepare_target(Path::new(rootfs), "/dev/console", true)?;
+
+    let source = std::ffi::CString::new(pty_slave)?;
+    let target = std::ffi::CString::new(fd_path(&console).to_string_lossy().as_bytes())?;
+    unsafe {
+        if libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "绑定 /dev/console 到 {} 失败: {}",
+                pty_slave,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    info!("成功绑定 /dev/console -> {}", pty_slave);
+    Ok(())
+}
+
 fn mount_rootfs(rootfs: &str) -> Result<()> {
     let rootfs_cstr = std::ffi::CString::new(rootfs)?;
     
@@ -109,42 +480,61 @@ fn mount_rootfs(rootfs: &str) -> Result<()> {
     Ok(())
 }
 
-fn mount_entry(m: &Mount, _bind_device: bool) -> Result<()> {
-    let dest = Path::new(&m.destination);
-    let parent = dest.parent().unwrap();
-    create_dir_all(parent)?;
+fn mount_entry(m: &Mount, rootfs: &str, _bind_device: bool, mount_label: &str) -> Result<()> {
+    let rootfs = Path::new(rootfs);
+
+    // proc 的挂载点不能是符号链接，否则后续对 /proc 的写入可能落到宿主机文件上
+    if m.typ == "proc" {
+        ensure_not_symlink(rootfs, &m.destination)?;
+    }
 
     // 解析挂载选项
     let (flags, data) = parse_mount_options(m);
+    let data = if m.typ == "tmpfs" {
+        normalize_tmpfs_data(&data)?
+    } else {
+        data
+    };
+    // tmpfs 和 devpts 没有持久化的标签，挂载时通过 context= 指定
+    let data = if m.typ == "tmpfs" || m.typ == "devpts" {
+        selinux::with_mount_label(&data, mount_label)
+    } else {
+        data
+    };
     
-    // 准备源路径
-    let src = if m.typ == "bind" {
+    // 准备源路径和目标，目标在 rootfs 内解析，防止符号链接将挂载重定向到宿主机
+    let (src, target) = if m.typ == "bind" {
         // 对于bind挂载，需要处理源路径
         let source = std::fs::canonicalize(&m.source).map_err(|e| {
             crate::errors::FireError::Generic(format!("无法解析源路径 {}: {}", m.source, e))
         })?;
-        
-        // 确保目标目录存在
-        let dir = if source.is_file() {
-            dest.parent().unwrap()
-        } else {
-            dest
-        };
-        create_dir_all(dir)?;
-        
-        // 如果源是文件，确保目标文件存在
-        if source.is_file() {
-            let _ = File::create(dest);
+        // z/Z 选项要求按容器的标签重新标记源路径
+        if let Some(shared) = relabel_option(m) {
+            selinux::relabel(&source, mount_label, shared)?;
         }
-        
-        source
+        // 如果源是文件，目标也创建为文件
+        let target = prepare_target(rootfs, &m.destination, source.is_file())?;
+        (source, target)
     } else {
-        create_dir_all(dest)?;
-        std::path::PathBuf::from(&m.source)
+        let target = prepare_target(rootfs, &m.destination, false)?;
+        (PathBuf::from(&m.source), target)
     };
 
+    // 新挂载 API 能报告文件系统给出的具体错误信息
+    if m.typ != "bind" && flags & !NEW_MOUNT_API_FLAGS == 0 && *NEW_MOUNT_API {
+        mount_fs(&m.source, &target, &m.typ, flags, &data).map_err(|e| {
+            crate::errors::FireError::Generic(format!(
+                "挂载失败 {} -> {}: {}",
+                m.source, m.destination, e
+            ))
+        })?;
+        check_procfs_mount(m, rootfs)?;
+        info!("成功挂载 {} -> {} (类型: {}, 标志: {}, 新挂载API)", m.source, m.destination, m.typ, flags);
+        return Ok(());
+    }
+
     // 执行挂载
-    let dest_cstr = std::ffi::CString::new(dest.to_str().unwrap())
+    let dest_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())
         .map_err(|e| crate::errors::FireError::Generic(format!("路径转换失败: {}", e)))?;
     let src_cstr = std::ffi::CString::new(src.to_str().unwrap())
         .map_err(|e| crate::errors::FireError::Generic(format!("路径转换失败: {}", e)))?;
@@ -190,6 +580,9 @@ fn mount_entry(m: &Mount, _bind_device: bool) -> Result<()> {
     if flags & libc::MS_BIND != 0 {
         let remount_flags = flags & !(libc::MS_BIND | libc::MS_REC);
         if remount_flags != 0 {
+            // 原描述符仍指向被覆盖的目录，需要重新打开以引用新的挂载
+            let target = open_in_rootfs(rootfs, &m.destination)?;
+            let dest_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
             unsafe {
                 if libc::mount(
                     dest_cstr.as_ptr(),
@@ -204,19 +597,483 @@ fn mount_entry(m: &Mount, _bind_device: bool) -> Result<()> {
         }
     }
 
+    check_procfs_mount(m, rootfs)?;
+
     info!("成功挂载 {} -> {} (类型: {}, 标志: {})", m.source, m.destination, m.typ, flags);
     Ok(())
 }
 
-pub fn pivot_rootfs(path: &str) -> Result<()> {
-    let oldroot = Path::new("/.pivot_root");
-    create_dir_all(&oldroot)?;
+/// proc 挂载完成后确认目标确实是 procfs，防止被替换的挂载点骗过之后对 /proc 的写入
+fn check_procfs_mount(m: &Mount, rootfs: &Path) -> Result<()> {
+    if m.typ == "proc" {
+        let target = open_in_rootfs(rootfs, &m.destination)?;
+        ensure_procfs(&fd_path(&target))?;
+    }
+    Ok(())
+}
+
+/// 绑定挂载的重新标记选项：`z` 返回 Some(true)（共享），`Z` 返回 Some(false)
+fn relabel_option(m: &Mount) -> Option<bool> {
+    m.options.iter().rev().find_map(|o| match o.as_str() {
+        "z" => Some(true),
+        "Z" => Some(false),
+        _ => None,
+    })
+}
+
+/// 确认挂载点在 rootfs 中不是符号链接
+fn ensure_not_symlink(rootfs: &Path, dest: &str) -> Result<()> {
+    let dest_path = Path::new(dest);
+    let (parent, name) = match (dest_path.parent(), dest_path.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        _ => return Ok(()),
+    };
+    // 仅解析父目录，保留最后一个分量本身以便检查
+    let target = secure_join(rootfs, &parent.to_string_lossy())?.join(name);
+    if let Ok(metadata) = std::fs::symlink_metadata(&target) {
+        if metadata.file_type().is_symlink() {
+            return Err(crate::errors::FireError::InvalidSpec(format!(
+                "挂载点 {} 是符号链接",
+                dest
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 确认路径位于真正的 procfs 上
+fn ensure_procfs(path: &Path) -> Result<()> {
+    let path_cstr = std::ffi::CString::new(path.to_string_lossy().as_bytes())?;
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path_cstr.as_ptr(), &mut buf) } == -1 {
+        return Err(crate::errors::FireError::Generic(format!(
+            "statfs {} 失败: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        )));
+    }
+    if buf.f_type != libc::PROC_SUPER_MAGIC {
+        return Err(crate::errors::FireError::Generic(format!(
+            "{} 不在 procfs 上 (f_type: {:#x})",
+            path.display(),
+            buf.f_type
+        )));
+    }
+    Ok(())
+}
+
+lazy_static! {
+    /// 内核是否支持 fsopen/fsconfig/fsmount（Linux 5.2+，且需要 CAP_SYS_ADMIN）
+    static ref NEW_MOUNT_API: bool = {
+        let probe = std::ffi::CString::new("tmpfs").unwrap();
+        match nix_ext::fsopen(&probe, nix_ext::FSOPEN_CLOEXEC) {
+            Ok(fd) => {
+                unsafe { libc::close(fd) };
+                true
+            }
+            Err(e) => {
+                debug!("新挂载 API 不可用，使用 mount(2): {}", e);
+                false
+            }
+        }
+    };
+}
+
+/// 可以转换为 fsmount 挂载属性的标志
+const NEW_MOUNT_API_FLAGS: u64 = libc::MS_RDONLY
+    | libc::MS_NOSUID
+    | libc::MS_NODEV
+    | libc::MS_NOEXEC
+    | libc::MS_NOATIME
+    | libc::MS_NODIRATIME
+    | libc::MS_STRICTATIME
+    | libc::MS_RELATIME
+    | nix_ext::MS_NOSYMFOLLOW;
+
+/// 通过 fsopen/fsconfig/fsmount/move_mount 挂载文件系统
+///
+/// 失败时附带内核通过文件系统上下文返回的错误信息。
+fn mount_fs(source: &str, target: &OwnedFd, typ: &str, flags: u64, data: &str) -> Result<()> {
+    let typ_cstr = std::ffi::CString::new(typ)?;
+    let fs = nix_ext::fsopen(&typ_cstr, nix_ext::FSOPEN_CLOEXEC)?;
+    let fs = unsafe { OwnedFd::from_raw_fd(fs) };
+
+    let configure = || -> Result<()> {
+        if !source.is_empty() {
+            let key = std::ffi::CString::new("source")?;
+            let value = std::ffi::CString::new(source)?;
+            nix_ext::fsconfig(fs.as_raw_fd(), nix_ext::FSCONFIG_SET_STRING, Some(&key), Some(&value))?;
+        }
+        for option in data.split(',').filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                Some((key, value)) => {
+                    let key = std::ffi::CString::new(key)?;
+                    let value = std::ffi::CString::new(value)?;
+                    nix_ext::fsconfig(fs.as_raw_fd(), nix_ext::FSCONFIG_SET_STRING, Some(&key), Some(&value))?;
+                }
+                None => {
+                    let key = std::ffi::CString::new(option)?;
+                    nix_ext::fsconfig(fs.as_raw_fd(), nix_ext::FSCONFIG_SET_FLAG, Some(&key), None)?;
+                }
+            }
+        }
+        nix_ext::fsconfig(fs.as_raw_fd(), nix_ext::FSCONFIG_CMD_CREATE, None, None)
+    };
+    if let Err(e) = configure() {
+        let messages = fs_context_messages(&fs);
+        if messages.is_empty() {
+            return Err(e);
+        }
+        return Err(crate::errors::FireError::Generic(format!("{} ({})", e, messages.join("; "))));
+    }
+
+    let mut attr_flags = 0;
+    for (flag, mount_attr) in [
+        (libc::MS_RDONLY, libc::MOUNT_ATTR_RDONLY),
+        (libc::MS_NOSUID, libc::MOUNT_ATTR_NOSUID),
+        (libc::MS_NODEV, libc::MOUNT_ATTR_NODEV),
+        (libc::MS_NOEXEC, libc::MOUNT_ATTR_NOEXEC),
+        (libc::MS_NOATIME, libc::MOUNT_ATTR_NOATIME),
+        (libc::MS_NODIRATIME, libc::MOUNT_ATTR_NODIRATIME),
+        (libc::MS_STRICTATIME, libc::MOUNT_ATTR_STRICTATIME),
+        (nix_ext::MS_NOSYMFOLLOW, libc::MOUNT_ATTR_NOSYMFOLLOW),
+    ] {
+        if flags & flag != 0 {
+            attr_flags |= mount_attr;
+        }
+    }
+
+    let mnt = nix_ext::fsmount(fs.as_raw_fd(), nix_ext::FSMOUNT_CLOEXEC, attr_flags as libc::c_uint)?;
+    let mnt = unsafe { OwnedFd::from_raw_fd(mnt) };
+    let empty = std::ffi::CString::new("")?;
+    nix_ext::move_mount(
+        mnt.as_raw_fd(),
+        &empty,
+        target.as_raw_fd(),
+        &empty,
+        nix_ext::MOVE_MOUNT_F_EMPTY_PATH | nix_ext::MOVE_MOUNT_T_EMPTY_PATH,
+    )
+}
+
+/// 读取文件系统上下文中记录的错误和警告信息
+fn fs_context_messages(fs: &OwnedFd) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = unsafe { libc::read(fs.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        messages.push(String::from_utf8_lossy(&buf[..n as usize]).trim_end().to_string());
+    }
+    messages
+}
+
+/// 处理 `type: cgroup` 挂载
+///
+/// 只暴露容器自己的 cgroup：在 cgroup namespace 中直接挂载新的 cgroup 文件系统，
+/// 根目录就是容器的 cgroup；否则按 /proc/self/cgroup 绑定挂载容器所在的子树。
+/// cgroup v1 先挂载 tmpfs，再逐个层级处理。除非显式指定 rw，否则以只读方式挂载。
+fn mount_cgroup(m: &Mount, rootfs: &Path, cgroupns: bool) -> Result<()> {
+    let readonly = !m.options.iter().any(|o| o == "rw");
+    let flags = libc::MS_NOSUID | libc::MS_NOEXEC | libc::MS_NODEV;
+    let cgroup_root = Path::new("/sys/fs/cgroup");
+    let dest = m.destination.as_str();
+    let own = parse_proc_cgroup(&std::fs::read_to_string("/proc/self/cgroup")?);
+    // 不在 cgroup namespace 中时绑定挂载的源目录
+    let source = |mountpoint: &Path, controllers: &str| -> Result<Option<PathBuf>> {
+        if cgroupns {
+            return Ok(None);
+        }
+        own.iter()
+            .find(|(c, _)| c == controllers)
+            .map(|(_, path)| Some(mountpoint.join(path.trim_start_matches('/'))))
+            .ok_or_else(|| {
+                crate::errors::FireError::Generic(format!(
+                    "/proc/self/cgroup 中没有 {} 的记录",
+                    mountpoint.display()
+                ))
+            })
+    };
+
+    match crate::cgroups::detect_cgroup_version()? {
+        2 => {
+            let source = source(cgroup_root, "")?;
+            mount_cgroup_hierarchy(rootfs, dest, source.as_deref(), "", flags, readonly)?;
+        }
+        _ => {
+            mount_tmpfs(rootfs, dest, flags, "mode=755")?;
+            let unified = crate::cgroups::controllers::hybrid_mountpoint().map(Path::new);
+            for entry in std::fs::read_dir(cgroup_root)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                let target = format!("{}/{}", dest.trim_end_matches('/'), name);
+                let file_type = entry.file_type()?;
+                if file_type.is_symlink() {
+                    // 合并挂载的控制器（如 cpu -> cpu,cpuacct）以符号链接呈现
+                    let link = std::fs::read_link(entry.path())?;
+                    symlink(link, secure_join(rootfs, &target)?)?;
+                    continue;
+                }
+                if !file_type.is_dir() {
+                    continue;
+                }
+                // 混合模式下的 cgroup2 对应 /proc/self/cgroup 中层级 0 的记录
+                let controllers = match unified {
+                    Some(unified) if unified == entry.path() => String::new(),
+                    _ => match v1_controllers(&own, &name) {
+                        Some(controllers) => controllers,
+                        None => {
+                            warn!("{} 不是容器所在的 cgroup 层级，跳过", entry.path().display());
+                            continue;
+                        }
+                    },
+                };
+                let source = source(&entry.path(), &controllers)?;
+                mount_cgroup_hierarchy(rootfs, &target, source.as_deref(), &controllers, flags, readonly)?;
+            }
+            if readonly {
+                remount(rootfs, dest, flags | libc::MS_RDONLY)?;
+            }
+        }
+    }
+
+    info!("成功挂载 cgroup 到 {} (只读: {})", m.destination, readonly);
+    Ok(())
+}
+
+/// 把一个 cgroup 层级中容器所在的部分挂载到 dest
+///
+/// 有 source 时绑定挂载该目录，否则挂载新的 cgroup 文件系统。controllers 为空表示 cgroup2，
+/// 否则是 v1 层级的控制器列表（如 cpu,cpuacct 或 name=systemd）。
+fn mount_cgroup_hierarchy(
+    rootfs: &Path,
+    dest: &str,
+    source: Option<&Path>,
+    controllers: &str,
+    flags: u64,
+    readonly: bool,
+) -> Result<()> {
+    let target = prepare_target(rootfs, dest, false)?;
+    if let Some(source) = source {
+        return bind_mount(source, rootfs, dest, flags, readonly);
+    }
+
+    let (typ, data) = match controllers {
+        "" => ("cgroup2", String::new()),
+        c if c.starts_with("name=") => ("cgroup", format!("none,{}", c)),
+        c => ("cgroup", c.to_string()),
+    };
+    let flags = if readonly { flags | libc::MS_RDONLY } else { flags };
+    let typ_cstr = std::ffi::CString::new(typ)?;
+    let target_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
+    let data_cstr = std::ffi::CString::new(data)?;
+    unsafe {
+        if libc::mount(
+            typ_cstr.as_ptr(),
+            target_cstr.as_ptr(),
+            typ_cstr.as_ptr(),
+            flags,
+            data_cstr.as_ptr() as *const libc::c_void,
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "挂载 {} 到 {} 失败: {}",
+                typ,
+                dest,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 解析 /proc/self/cgroup，返回每个层级的控制器列表和进程所在的路径
+///
+/// cgroup2 的控制器列表为空。
+fn parse_proc_cgroup(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let _id = fields.next()?;
+            let controllers = fields.next()?;
+            let path = fields.next()?;
+            Some((controllers.to_string(), path.to_string()))
+        })
+        .collect()
+}
+
+/// 按 /sys/fs/cgroup 下的目录名找到对应 v1 层级在 /proc/self/cgroup 中的控制器列表
+///
+/// 目录名与控制器的顺序可能不同（如 net_prio,net_cls），按集合比较；
+/// 具名层级的目录名不带 name= 前缀。
+fn v1_controllers(own: &[(String, String)], dir_name: &str) -> Option<String> {
+    let mut wanted: Vec<&str> = dir_name.split(',').collect();
+    wanted.sort_unstable();
+    own.iter()
+        .map(|(controllers, _)| controllers)
+        .filter(|controllers| !controllers.is_empty())
+        .find(|controllers| {
+            let mut names: Vec<&str> =
+                controllers.split(',').map(|c| c.trim_start_matches("name=")).collect();
+            names.sort_unstable();
+            names == wanted
+        })
+        .cloned()
+}
+
+/// 将 source 绑定挂载到 rootfs 内已存在的 dest，并按 flags 重新挂载
+fn bind_mount(source: &Path, rootfs: &Path, dest: &str, flags: u64, readonly: bool) -> Result<()> {
+    let target = open_in_rootfs(rootfs, dest)?;
+    let source_cstr = std::ffi::CString::new(source.to_string_lossy().as_bytes())?;
+    let target_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
+    unsafe {
+        if libc::mount(
+            source_cstr.as_ptr(),
+            target_cstr.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND | libc::MS_REC,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "绑定挂载失败 {} -> {}: {}",
+                source.display(),
+                dest,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    let remount_flags = if readonly { flags | libc::MS_RDONLY } else { flags };
+    remount(rootfs, dest, libc::MS_BIND | remount_flags)
+}
+
+fn remount(rootfs: &Path, dest: &str, flags: u64) -> Result<()> {
+    let target = open_in_rootfs(rootfs, dest)?;
+    let target_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
+    unsafe {
+        if libc::mount(
+            std::ptr::null(),
+            target_cstr.as_ptr(),
+            std::ptr::null(),
+            flags | libc::MS_REMOUNT,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "重新挂载失败 {}: {}",
+                dest,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 解析 idmap/ridmap 选项，返回是否需要递归映射
+fn idmap_option(m: &Mount) -> Option<bool> {
+    if m.options.iter().any(|o| o == "ridmap") {
+        Some(true)
+    } else if m.options.iter().any(|o| o == "idmap") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// 执行 idmapped 绑定挂载
+///
+/// 挂载自身的 uidMappings/gidMappings 优先；未指定时使用容器的映射。
+/// 需要在宿主机的 user namespace 中执行。
+fn mount_idmapped_entry(m: &Mount, spec: &Spec, rootfs: &str) -> Result<()> {
+    let (uid_mappings, gid_mappings) = if !m.uid_mappings.is_empty() || !m.gid_mappings.is_empty() {
+        (m.uid_mappings.as_slice(), m.gid_mappings.as_slice())
+    } else if let Some(ref linux) = spec.linux {
+        (linux.uid_mappings.as_slice(), linux.gid_mappings.as_slice())
+    } else {
+        (&[][..], &[][..])
+    };
+    if uid_mappings.is_empty() || gid_mappings.is_empty() {
+        return Err(crate::errors::FireError::InvalidSpec(format!(
+            "idmap 挂载 {} 需要 uid 和 gid 映射",
+            m.destination
+        )));
+    }
+    if !crate::sysinfo::get().syscalls.mount_setattr {
+        return Err(crate::errors::FireError::KernelUnsupported(format!(
+            "内核不支持 mount_setattr，无法进行 idmap 挂载 {}",
+            m.destination
+        )));
+    }
+
+    let source = std::fs::canonicalize(&m.source).map_err(|e| {
+        crate::errors::FireError::Generic(format!("无法解析源路径 {}: {}", m.source, e))
+    })?;
+    let target = prepare_target(Path::new(rootfs), &m.destination, source.is_file())?;
+
+    let mapping = UserNamespaceMapping::from_oci_mappings(uid_mappings, gid_mappings);
+    let userns = create_detached_user_namespace(&mapping)?;
+    let recursive = idmap_option(m).unwrap_or(false);
+    let (flags, _) = parse_mount_options(m);
+    mount_idmapped(&source, &target, &userns, recursive, flags)?;
+
+    info!("成功挂载 idmapped {} -> {} (递归: {})", m.source, m.destination, recursive);
+    Ok(())
+}
 
+fn mount_idmapped(
+    source: &Path,
+    target: &OwnedFd,
+    userns: &File,
+    recursive: bool,
+    flags: u64,
+) -> Result<()> {
+    let source_cstr = std::ffi::CString::new(source.to_string_lossy().as_bytes())?;
+    let empty = std::ffi::CString::new("")?;
+
+    let mut tree_flags = nix_ext::OPEN_TREE_CLONE | nix_ext::OPEN_TREE_CLOEXEC;
+    let mut setattr_flags = libc::AT_EMPTY_PATH as libc::c_uint;
+    if recursive {
+        tree_flags |= libc::AT_RECURSIVE as libc::c_uint;
+        setattr_flags |= libc::AT_RECURSIVE as libc::c_uint;
+    }
+    let tree = nix_ext::open_tree(libc::AT_FDCWD, &source_cstr, tree_flags)?;
+    let tree = unsafe { OwnedFd::from_raw_fd(tree) };
+
+    let mut attr: libc::mount_attr = unsafe { std::mem::zeroed() };
+    attr.attr_set = libc::MOUNT_ATTR_IDMAP;
+    for (flag, mount_attr) in [
+        (libc::MS_RDONLY, libc::MOUNT_ATTR_RDONLY),
+        (libc::MS_NOSUID, libc::MOUNT_ATTR_NOSUID),
+        (libc::MS_NODEV, libc::MOUNT_ATTR_NODEV),
+        (libc::MS_NOEXEC, libc::MOUNT_ATTR_NOEXEC),
+        (nix_ext::MS_NOSYMFOLLOW, libc::MOUNT_ATTR_NOSYMFOLLOW),
+    ] {
+        if flags & flag != 0 {
+            attr.attr_set |= mount_attr;
+        }
+    }
+    attr.userns_fd = userns.as_raw_fd() as u64;
+    nix_ext::mount_setattr(tree.as_raw_fd(), &empty, setattr_flags, &attr)?;
+
+    nix_ext::move_mount(
+        tree.as_raw_fd(),
+        &empty,
+        target.as_raw_fd(),
+        &empty,
+        nix_ext::MOVE_MOUNT_F_EMPTY_PATH | nix_ext::MOVE_MOUNT_T_EMPTY_PATH,
+    )
+}
+
+/// 将根目录切换到 rootfs 并卸载旧的根目录
+///
+/// 以 pivot_root(".", ".") 将旧的根目录叠放在新根目录之下，不需要在 rootfs 或宿主机上
+/// 创建临时目录。
+pub fn pivot_rootfs(path: &str) -> Result<()> {
     // 打开旧的根目录文件描述符
     let olddir_fd = unsafe {
         libc::open(
             std::ffi::CString::new("/")?.as_ptr(),
-            libc::O_DIRECTORY | libc::O_RDONLY,
+            libc::O_DIRECTORY | libc::O_RDONLY | libc::O_CLOEXEC,
         )
     };
     if olddir_fd < 0 {
@@ -230,7 +1087,7 @@ pub fn pivot_rootfs(path: &str) -> Result<()> {
     let newdir_fd = unsafe {
         libc::open(
             std::ffi::CString::new(path)?.as_ptr(),
-            libc::O_DIRECTORY | libc::O_RDONLY,
+            libc::O_DIRECTORY | libc::O_RDONLY | libc::O_CLOEXEC,
         )
     };
     if newdir_fd < 0 {
@@ -240,20 +1097,27 @@ pub fn pivot_rootfs(path: &str) -> Result<()> {
             std::io::Error::last_os_error()
         )));
     }
+    let _fds = scopeguard::guard((olddir_fd, newdir_fd), |(old, new)| unsafe {
+        libc::close(old);
+        libc::close(new);
+    });
 
-    // 执行pivot_root系统调用
-    let path_cstr = std::ffi::CString::new(path)?;
-    let oldroot_cstr = std::ffi::CString::new("/.pivot_root")?;
-    
+    // 在新根目录中执行pivot_root系统调用
+    let dot_cstr = std::ffi::CString::new(".")?;
     unsafe {
-        if libc::syscall(
-            libc::SYS_pivot_root,
-            path_cstr.as_ptr(),
-            oldroot_cstr.as_ptr(),
-        ) == -1 {
+        if libc::fchdir(newdir_fd) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "切换到新根目录失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        if libc::syscall(libc::SYS_pivot_root, dot_cstr.as_ptr(), dot_cstr.as_ptr()) == -1 {
             let errno = std::io::Error::last_os_error();
-            libc::close(olddir_fd);
-            libc::close(newdir_fd);
+            // rootfs 位于 initramfs 等无法 pivot_root 的文件系统上
+            if errno.raw_os_error() == Some(libc::EINVAL) {
+                warn!("pivot_root 不可用 ({})，回退到 MS_MOVE + chroot", errno);
+                return move_root(path);
+            }
             return Err(crate::errors::FireError::Generic(format!(
                 "pivot_root 系统调用失败: {}",
                 errno
@@ -261,39 +1125,95 @@ pub fn pivot_rootfs(path: &str) -> Result<()> {
         }
     }
 
-    // 卸载旧根目录
+    // 旧根目录叠放在新根目录之下，回到其中卸载，卸载前改为 slave 避免传播到宿主机
     unsafe {
-        let flags = libc::MNT_DETACH;
-        if libc::umount2(oldroot_cstr.as_ptr(), flags) == -1 {
-            warn!("卸载旧根目录失败: {}", std::io::Error::last_os_error());
+        if libc::fchdir(olddir_fd) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "切换到旧根目录失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        if libc::mount(
+            std::ptr::null(),
+            dot_cstr.as_ptr(),
+            std::ptr::null(),
+            libc::MS_SLAVE | libc::MS_REC,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "设置旧根目录为 slave 失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        if libc::umount2(dot_cstr.as_ptr(), libc::MNT_DETACH) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "卸载旧根目录失败: {}",
+                std::io::Error::last_os_error()
+            )));
         }
     }
 
     // 切换到新根目录
+    std::env::set_current_dir("/")?;
+
+    info!("成功执行 pivot_root 到: {}", path);
+    Ok(())
+}
+
+/// 通过 MS_MOVE 将 rootfs 移动到 / 并 chroot，用于无法 pivot_root 的场景
+///
+/// 旧的根目录仍然存在于挂载树中，因此隔离性弱于 pivot_root。
+fn move_root(path: &str) -> Result<()> {
+    let path_cstr = std::ffi::CString::new(path)?;
+    let root_cstr = std::ffi::CString::new("/")?;
+    let dot_cstr = std::ffi::CString::new(".")?;
+
     unsafe {
-        if libc::fchdir(newdir_fd) == -1 {
-            let errno = std::io::Error::last_os_error();
-            libc::close(olddir_fd);
-            libc::close(newdir_fd);
+        if libc::chdir(path_cstr.as_ptr()) == -1 {
             return Err(crate::errors::FireError::Generic(format!(
                 "切换到新根目录失败: {}",
-                errno
+                std::io::Error::last_os_error()
+            )));
+        }
+        if libc::mount(
+            path_cstr.as_ptr(),
+            root_cstr.as_ptr(),
+            std::ptr::null(),
+            libc::MS_MOVE,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "移动 rootfs 挂载失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        if libc::chroot(dot_cstr.as_ptr()) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "chroot 失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        if libc::chdir(root_cstr.as_ptr()) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "切换到根目录失败: {}",
+                std::io::Error::last_os_error()
             )));
         }
     }
 
-    // 清理文件描述符
-    unsafe {
-        libc::close(olddir_fd);
-        libc::close(newdir_fd);
-    }
-
-    info!("成功执行 pivot_root 到: {}", path);
+    info!("成功通过 MS_MOVE + chroot 切换根目录到: {}", path);
     Ok(())
 }
 
 pub fn finish_rootfs(spec: &Spec) -> Result<()> {
     if let Some(ref linux) = spec.linux {
+        // 屏蔽或只读化 /proc 下的路径前，确认它们确实由 procfs 提供
+        for path in linux.masked_paths.iter().chain(linux.readonly_paths.iter()) {
+            if path.starts_with("/proc/") && Path::new(path).exists() {
+                ensure_procfs(Path::new(path))?;
+            }
+        }
+
         for path in &linux.masked_paths {
             mask_path(path)?;
         }
@@ -301,9 +1221,70 @@ pub fn finish_rootfs(spec: &Spec) -> Result<()> {
             readonly_path(path)?;
         }
     }
+
+    if spec.root.readonly {
+        remount_root_readonly()?;
+    }
+    Ok(())
+}
+
+/// 以只读方式重新挂载根文件系统
+///
+/// 非特权 user namespace 中 nosuid/nodev 等标志被锁定，重新挂载时必须保留，
+/// 否则内核会返回 EPERM，因此先从 /proc/self/mountinfo 读取现有标志。
+fn remount_root_readonly() -> Result<()> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    let flags = mountinfo_flags(&mountinfo, "/").unwrap_or(0);
+
+    let root = std::ffi::CString::new("/")?;
+    unsafe {
+        if libc::mount(
+            std::ptr::null(),
+            root.as_ptr(),
+            std::ptr::null(),
+            flags | libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+            std::ptr::null(),
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "以只读方式重新挂载根文件系统失败: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    info!("根文件系统已重新挂载为只读");
     Ok(())
 }
 
+/// 从 mountinfo 中解析指定挂载点的挂载标志，同一挂载点以最上层的挂载为准
+fn mountinfo_flags(mountinfo: &str, mountpoint: &str) -> Option<u64> {
+    let options = mountinfo
+        .lines()
+        .rev()
+        .find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match (fields.get(4), fields.get(5)) {
+                (Some(point), Some(options)) if *point == mountpoint => Some(*options),
+                _ => None,
+            }
+        })?;
+
+    let flags = options.split(',').fold(0, |flags, option| {
+        flags
+            | match option {
+                "nosuid" => libc::MS_NOSUID,
+                "nodev" => libc::MS_NODEV,
+                "noexec" => libc::MS_NOEXEC,
+                "noatime" => libc::MS_NOATIME,
+                "nodiratime" => libc::MS_NODIRATIME,
+                "relatime" => libc::MS_RELATIME,
+                "strictatime" => libc::MS_STRICTATIME,
+                _ => 0,
+            }
+    });
+    Some(flags)
+}
+
 #[rustfmt::skip]
 lazy_static! {
     static ref OPTIONS: HashMap<&'static str, (bool, u64)> = {
@@ -328,6 +1309,8 @@ lazy_static! {
         m.insert("diratime",      (true,  libc::MS_NODIRATIME));
         m.insert("nodiratime",    (false, libc::MS_NODIRATIME));
         m.insert("bind",          (false, libc::MS_BIND));
+        m.insert("idmap",         (false, 0));
+        m.insert("ridmap",        (false, 0));
         m.insert("rbind",         (false, libc::MS_BIND | libc::MS_REC));
         m.insert("unbindable",    (false, libc::MS_UNBINDABLE));
         m.insert("runbindable",   (false, libc::MS_UNBINDABLE | libc::MS_REC));
@@ -341,6 +1324,10 @@ lazy_static! {
         m.insert("norelatime",    (true,  libc::MS_RELATIME));
         m.insert("strictatime",   (false, libc::MS_STRICTATIME));
         m.insert("nostrictatime", (true,  libc::MS_STRICTATIME));
+        m.insert("lazytime",      (false, libc::MS_LAZYTIME));
+        m.insert("nolazytime",    (true,  libc::MS_LAZYTIME));
+        m.insert("nosymfollow",   (false, nix_ext::MS_NOSYMFOLLOW));
+        m.insert("symfollow",     (true,  nix_ext::MS_NOSYMFOLLOW));
         m
     };
 }
@@ -358,8 +1345,18 @@ fn parse_mount_options(m: &Mount) -> (u64, String) {
                     flags |= flag;
                 }
             }
+            // x- 前缀的选项供用户态工具使用（如 x-systemd.*），不传给内核
+            None if option.starts_with("x-") => {
+                debug!("忽略用户态挂载选项: {}", option);
+            }
+            // SELinux 重新标记选项，由 mount_entry 处理
+            None if option == "z" || option == "Z" => {}
+            // noswap 是 tmpfs 的数据选项，其他文件系统不认识
+            None if option == "noswap" && m.typ != "tmpfs" => {
+                warn!("noswap 仅适用于 tmpfs，忽略 {} 上的该选项", m.destination);
+            }
             None => {
-                // 未知选项加入数据字符串
+                // 文件系统特定的选项加入数据字符串
                 data.push(option.clone());
             }
         }
@@ -368,16 +1365,17 @@ fn parse_mount_options(m: &Mount) -> (u64, String) {
     (flags, data.join(","))
 }
 
-fn default_symlinks() -> Result<()> {
+fn default_symlinks(rootfs: &str) -> Result<()> {
     let links = [
         ("/proc/self/fd", "/dev/fd"),
         ("/proc/self/fd/0", "/dev/stdin"),
         ("/proc/self/fd/1", "/dev/stdout"),
         ("/proc/self/fd/2", "/dev/stderr"),
+        ("/proc/kcore", "/dev/core"),
     ];
 
     for (target, link) in &links {
-        if let Err(e) = symlink(target, link) {
+        if let Err(e) = symlink(target, create_parent_in_rootfs(Path::new(rootfs), link)?) {
             if e.kind() != std::io::ErrorKind::AlreadyExists {
                 return Err(e.into());
             }
@@ -386,19 +1384,25 @@ fn default_symlinks() -> Result<()> {
     Ok(())
 }
 
-fn create_devices(devices: &[LinuxDevice], bind: bool) -> Result<()> {
-    let op: fn(&LinuxDevice) -> Result<()> = if bind { bind_dev } else { mknod_dev };
-
+fn create_devices(rootfs: &str, devices: &[LinuxDevice], bind: bool) -> Result<()> {
     for dev in devices {
-        op(dev)?;
+        if bind {
+            bind_dev(rootfs, dev)?;
+            continue;
+        }
+        // 在user namespace中没有 mknod 权限，退化为绑定挂载
+        if let Err(e) = mknod_dev(rootfs, dev) {
+            warn!("创建设备节点 {} 失败，改用绑定挂载: {}", dev.path, e);
+            bind_dev(rootfs, dev)?;
+        }
     }
     Ok(())
 }
 
-fn ensure_ptmx() -> Result<()> {
-    let ptmx = Path::new("/dev/ptmx");
-    if !ptmx.exists() {
-        if let Err(e) = symlink("pts/ptmx", ptmx) {
+fn ensure_ptmx(rootfs: &str) -> Result<()> {
+    let ptmx = create_parent_in_rootfs(Path::new(rootfs), "/dev/ptmx")?;
+    if ptmx.symlink_metadata().is_err() {
+        if let Err(e) = symlink("pts/ptmx", &ptmx) {
             let msg = format!("failed to create /dev/ptmx symlink: {}", e);
             return Err(crate::errors::FireError::Generic(msg));
         }
@@ -423,16 +1427,14 @@ fn makedev(major: u64, minor: u64) -> u64 {
     (minor & 0xff) | ((major & 0xfff) << 8) | ((minor & !0xff) << 12) | ((major & !0xfff) << 32)
 }
 
-fn mknod_dev(dev: &LinuxDevice) -> Result<()> {
-    let path = Path::new(&dev.path);
-    let parent = path.parent().unwrap();
-    create_dir_all(parent)?;
+fn mknod_dev(rootfs: &str, dev: &LinuxDevice) -> Result<()> {
+    let path = create_parent_in_rootfs(Path::new(rootfs), &dev.path)?;
 
     let mode = dev.file_mode.unwrap_or(0o644);
     let dev_type = to_sflag(dev.typ)?;
     let device = makedev(dev.major as u64, dev.minor as u64);
 
-    let path_cstr = std::ffi::CString::new(dev.path.as_str())
+    let path_cstr = std::ffi::CString::new(path.to_string_lossy().as_bytes())
         .map_err(|e| crate::errors::FireError::Generic(format!("Invalid path: {}", e)))?;
 
     unsafe {
@@ -459,31 +1461,12 @@ fn mknod_dev(dev: &LinuxDevice) -> Result<()> {
     Ok(())
 }
 
-fn bind_dev(dev: &LinuxDevice) -> Result<()> {
-    let path = Path::new(&dev.path);
-    let parent = path.parent().unwrap();
-    create_dir_all(parent)?;
-
-    // 打开/创建目标文件
-    let fd = unsafe {
-        libc::open(
-            std::ffi::CString::new(dev.path.as_str())?.as_ptr(),
-            libc::O_RDWR | libc::O_CREAT,
-            0o644,
-        )
-    };
-    if fd < 0 {
-        return Err(crate::errors::FireError::Generic(format!(
-            "创建设备文件失败 {}: {}",
-            dev.path,
-            std::io::Error::last_os_error()
-        )));
-    }
-    unsafe { libc::close(fd) };
+fn bind_dev(rootfs: &str, dev: &LinuxDevice) -> Result<()> {
+    let target = prepare_target(Path::new(rootfs), &dev.path, true)?;
 
-    // 执行绑定挂载
+    // 将宿主机上的设备绑定挂载到容器内
     let source_cstr = std::ffi::CString::new(dev.path.as_str())?;
-    let dest_cstr = std::ffi::CString::new(dev.path.as_str())?;
+    let dest_cstr = std::ffi::CString::new(fd_path(&target).to_string_lossy().as_bytes())?;
     
     unsafe {
         if libc::mount(
@@ -613,7 +1596,6 @@ fn readonly_path(path: &str) -> Result<()> {
 mod tests {
     use super::*;
     use std::fs;
-    use std::path::PathBuf;
     
     #[test]
     fn test_parse_mount_options() {
@@ -622,6 +1604,8 @@ mod tests {
             source: "/source".to_string(),
             typ: "bind".to_string(),
             options: vec!["ro".to_string(), "nosuid".to_string()],
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
         };
         
         let (flags, data) = parse_mount_options(&mount);
@@ -639,6 +1623,115 @@ mod tests {
         assert!(to_sflag(LinuxDeviceType::a).is_err());
     }
     
+    #[test]
+    fn test_create_parent_in_rootfs() {
+        let rootfs = std::env::temp_dir().join(format!("fire-create-parent-{}", std::process::id()));
+        create_dir_all(&rootfs).unwrap();
+        symlink("/run", rootfs.join("dev")).unwrap();
+
+        // 符号链接的父目录按 rootfs 解析，不会落到宿主机的 /run
+        assert_eq!(
+            create_parent_in_rootfs(&rootfs, "/dev/null").unwrap(),
+            rootfs.join("run/null")
+        );
+        assert!(rootfs.join("run").is_dir());
+        assert!(create_parent_in_rootfs(&rootfs, "/").is_err());
+
+        std::fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn test_secure_join() {
+        let rootfs = std::env::temp_dir().join(format!("fire-secure-join-{}", std::process::id()));
+        create_dir_all(rootfs.join("etc")).unwrap();
+        symlink("/etc", rootfs.join("abs")).unwrap();
+        symlink("../../../..", rootfs.join("etc/up")).unwrap();
+
+        assert_eq!(secure_join(&rootfs, "/abs/passwd").unwrap(), rootfs.join("etc/passwd"));
+        assert_eq!(secure_join(&rootfs, "/etc/up/tmp").unwrap(), rootfs.join("tmp"));
+        assert_eq!(secure_join(&rootfs, "/../../x").unwrap(), rootfs.join("x"));
+
+        std::fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn test_mountinfo_flags() {
+        let mountinfo = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+98 22 0:50 / / rw,nosuid,nodev,noatime master:2 - overlay overlay rw,lowerdir=/l
+99 98 0:51 / /proc rw,nosuid,nodev,noexec - proc proc rw
+";
+        assert_eq!(
+            mountinfo_flags(mountinfo, "/"),
+            Some(libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOATIME)
+        );
+        assert_eq!(
+            mountinfo_flags(mountinfo, "/proc"),
+            Some(libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC)
+        );
+        assert_eq!(mountinfo_flags(mountinfo, "/sys"), None);
+    }
+
+    #[test]
+    fn test_v1_controllers() {
+        let own = parse_proc_cgroup(
+            "12:memory:/fire/abc\n4:net_cls,net_prio:/fire/abc\n1:name=systemd:/user.slice\n0::/fire/abc\n",
+        );
+        assert_eq!(own[3], (String::new(), "/fire/abc".to_string()));
+        assert_eq!(v1_controllers(&own, "memory").as_deref(), Some("memory"));
+        assert_eq!(v1_controllers(&own, "net_prio,net_cls").as_deref(), Some("net_cls,net_prio"));
+        assert_eq!(v1_controllers(&own, "systemd").as_deref(), Some("name=systemd"));
+        assert_eq!(v1_controllers(&own, "unified"), None);
+    }
+
+    #[test]
+    fn test_ensure_procfs() {
+        assert!(ensure_procfs(Path::new("/proc")).is_ok());
+        assert!(ensure_procfs(&std::env::temp_dir()).is_err());
+    }
+
+    #[test]
+    fn test_ensure_not_symlink() {
+        let rootfs = std::env::temp_dir().join(format!("fire-not-symlink-{}", std::process::id()));
+        create_dir_all(rootfs.join("etc")).unwrap();
+        symlink("/etc", rootfs.join("proc")).unwrap();
+
+        assert!(ensure_not_symlink(&rootfs, "/proc").is_err());
+        assert!(ensure_not_symlink(&rootfs, "/etc").is_ok());
+        assert!(ensure_not_symlink(&rootfs, "/sys").is_ok());
+
+        std::fs::remove_dir_all(&rootfs).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_tmpfs_data() {
+        assert_eq!(
+            normalize_tmpfs_data("size=64m,mode=1777,uid=0,noswap").unwrap(),
+            "size=67108864,mode=1777,uid=0,noswap"
+        );
+        assert_eq!(normalize_tmpfs_data("size=50%,nr_inodes=1k").unwrap(), "size=50%,nr_inodes=1024");
+        assert!(normalize_tmpfs_data("size=64mb").is_err());
+        assert!(normalize_tmpfs_data("mode=999").is_err());
+        assert!(normalize_tmpfs_data("uid=root").is_err());
+    }
+
+    #[test]
+    fn test_idmap_option() {
+        let mut mount = Mount {
+            destination: "/data".to_string(),
+            source: "/srv/data".to_string(),
+            typ: "bind".to_string(),
+            options: vec!["rbind".to_string(), "idmap".to_string()],
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
+        };
+        assert_eq!(idmap_option(&mount), Some(false));
+        assert!(parse_mount_options(&mount).1.is_empty());
+
+        mount.options.push("ridmap".to_string());
+        assert_eq!(idmap_option(&mount), Some(true));
+    }
+
     #[test]
     fn test_makedev() {
         let dev = makedev(1, 5);
@@ -652,10 +1745,36 @@ mod tests {
             source: "/source".to_string(),
             typ: "ext4".to_string(),
             options: vec!["ro".to_string(), "user_xattr".to_string()],
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
         };
         
         let (flags, data) = parse_mount_options(&mount);
         assert!(flags & libc::MS_RDONLY != 0);
         assert_eq!(data, "user_xattr");
     }
+
+    #[test]
+    fn test_mount_options_passthrough() {
+        let mut mount = Mount {
+            destination: "/tmp".to_string(),
+            source: "tmpfs".to_string(),
+            typ: "tmpfs".to_string(),
+            options: vec![
+                "nosymfollow".to_string(),
+                "lazytime".to_string(),
+                "x-systemd.automount".to_string(),
+                "noswap".to_string(),
+            ],
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
+        };
+
+        let (flags, data) = parse_mount_options(&mount);
+        assert_eq!(flags, nix_ext::MS_NOSYMFOLLOW | libc::MS_LAZYTIME);
+        assert_eq!(data, "noswap");
+
+        mount.typ = "ext4".to_string();
+        assert!(parse_mount_options(&mount).1.is_empty());
+    }
 }
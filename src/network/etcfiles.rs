@@ -0,0 +1,321 @@
+use crate::errors::Result;
+use crate::selinux;
+use log::{debug, info, warn};
+use oci::{Mount, Spec};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// 通过注解配置DNS服务器（逗号分隔）
+pub const ANNOTATION_DNS_SERVERS: &str = "org.fire.dns.servers";
+/// 通过注解配置DNS搜索域（逗号分隔）
+pub const ANNOTATION_DNS_SEARCH: &str = "org.fire.dns.search";
+/// 通过注解配置resolv.conf选项（逗号分隔）
+pub const ANNOTATION_DNS_OPTIONS: &str = "org.fire.dns.options";
+
+const HOST_RESOLV_CONF: &str = "/etc/resolv.conf";
+const SYSTEMD_RESOLV_CONF: &str = "/run/systemd/resolve/resolv.conf";
+
+/// 运行时生成的 /etc 文件
+const ETC_FILES: [&str; 3] = ["resolv.conf", "hosts", "hostname"];
+
+const MTAB: &str = "/etc/mtab";
+const MTAB_TARGET: &str = "/proc/self/mounts";
+
+/// 容器的DNS配置
+#[derive(Debug, Clone, Default)]
+pub struct DnsConfig {
+    pub servers: Vec<String>,
+    pub searches: Vec<String>,
+    pub options: Vec<String>,
+}
+
+impl DnsConfig {
+    pub fn new(servers: Vec<String>, searches: Vec<String>, options: Vec<String>) -> Self {
+        Self {
+            servers,
+            searches,
+            options,
+        }
+    }
+
+    /// 从容器注解中读取DNS配置
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Self {
+        let split = |key: &str| -> Vec<String> {
+            annotations
+                .get(key)
+                .map(|v| {
+                    v.split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        Self {
+            servers: split(ANNOTATION_DNS_SERVERS),
+            searches: split(ANNOTATION_DNS_SEARCH),
+            options: split(ANNOTATION_DNS_OPTIONS),
+        }
+    }
+
+    /// 合并配置，命令行参数优先于注解
+    pub fn merge(self, fallback: DnsConfig) -> Self {
+        Self {
+            servers: if self.servers.is_empty() { fallback.servers } else { self.servers },
+            searches: if self.searches.is_empty() { fallback.searches } else { self.searches },
+            options: if self.options.is_empty() { fallback.options } else { self.options },
+        }
+    }
+}
+
+/// 渲染 resolv.conf
+///
+/// 未配置的部分沿用宿主机的 resolv.conf；使用独立网络namespace时
+/// 宿主机的回环地址在容器内不可达，会被过滤掉。
+pub fn render_resolv_conf(dns: &DnsConfig, host: &str, own_netns: bool) -> String {
+    let mut servers = Vec::new();
+    let mut searches = Vec::new();
+    let mut options = Vec::new();
+    for line in host.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => servers.extend(fields.map(|s| s.to_string())),
+            Some("search") | Some("domain") => searches.extend(fields.map(|s| s.to_string())),
+            Some("options") => options.extend(fields.map(|s| s.to_string())),
+            _ => {}
+        }
+    }
+    if own_netns {
+        servers.retain(|s| !is_loopback(s));
+    }
+
+    let dns = dns.clone().merge(DnsConfig::new(servers, searches, options));
+
+    let mut content = String::new();
+    if !dns.searches.is_empty() {
+        content.push_str(&format!("search {}\n", dns.searches.join(" ")));
+    }
+    for server in &dns.servers {
+        content.push_str(&format!("nameserver {}\n", server));
+    }
+    if !dns.options.is_empty() {
+        content.push_str(&format!("options {}\n", dns.options.join(" ")));
+    }
+    content
+}
+
+/// 渲染 /etc/hosts
+pub fn render_hosts(hostname: &str) -> String {
+    let mut content = String::from("127.0.0.1\tlocalhost\n");
+    content.push_str("::1\tlocalhost ip6-localhost ip6-loopback\n");
+    if !hostname.is_empty() {
+        content.push_str(&format!("127.0.1.1\t{}\n", hostname));
+    }
+    content
+}
+
+/// 渲染 /etc/hostname
+pub fn render_hostname(hostname: &str) -> String {
+    format!("{}\n", hostname)
+}
+
+fn is_loopback(server: &str) -> bool {
+    server
+        .parse::<std::net::IpAddr>()
+        .map(|ip| ip.is_loopback())
+        .unwrap_or(false)
+}
+
+fn read_host_resolv_conf() -> String {
+    let content = fs::read_to_string(HOST_RESOLV_CONF).unwrap_or_default();
+    // systemd-resolved 的存根解析器只监听宿主机回环地址
+    if content.contains("127.0.0.53") {
+        if let Ok(upstream) = fs::read_to_string(SYSTEMD_RESOLV_CONF) {
+            return upstream;
+        }
+    }
+    content
+}
+
+fn has_network_namespace(spec: &Spec) -> bool {
+    spec.linux
+        .as_ref()
+        .map(|linux| {
+            linux
+                .namespaces
+                .iter()
+                .any(|ns| matches!(ns.typ, oci::LinuxNamespaceType::network))
+        })
+        .unwrap_or(false)
+}
+
+fn has_spec_mount(spec: &Spec, destination: &str) -> bool {
+    spec.mounts
+        .iter()
+        .any(|m| Path::new(&m.destination) == Path::new(destination))
+}
+
+/// 在容器状态目录中生成 /etc 文件
+///
+/// 已由spec挂载提供的文件不会生成，返回实际生成的文件名列表。
+pub fn generate(spec: &Spec, container_dir: &Path, dns: &DnsConfig) -> Result<Vec<String>> {
+    let mut generated = Vec::new();
+
+    for name in ETC_FILES {
+        let destination = format!("/etc/{}", name);
+        if has_spec_mount(spec, &destination) {
+            debug!("spec 已挂载 {}，跳过生成", destination);
+            continue;
+        }
+
+        let content = match name {
+            "resolv.conf" => render_resolv_conf(
+                dns,
+                &read_host_resolv_conf(),
+                has_network_namespace(spec),
+            ),
+            "hosts" => render_hosts(&spec.hostname),
+            _ => {
+                if spec.hostname.is_empty() {
+                    continue;
+                }
+                render_hostname(&spec.hostname)
+            }
+        };
+
+        let path = container_dir.join(name);
+        fs::write(&path, content)?;
+        // 绑定挂载保留源文件的标签，需要与容器的挂载标签一致
+        selinux::setfilecon(&path.to_string_lossy(), selinux::mount_label(spec))?;
+        info!("生成容器文件: {}", path.display());
+        generated.push(name.to_string());
+    }
+
+    Ok(generated)
+}
+
+/// 将状态目录中已生成的 /etc 文件作为绑定挂载追加到spec
+///
+/// spec中已有的同名挂载点保持不变。
+pub fn inject_mounts(spec: &mut Spec, container_dir: &Path) {
+    for name in ETC_FILES {
+        let destination = format!("/etc/{}", name);
+        let source = container_dir.join(name);
+        if !source.exists() || has_spec_mount(spec, &destination) {
+            continue;
+        }
+
+        spec.mounts.push(Mount {
+            destination,
+            typ: "bind".to_string(),
+            source: source.to_string_lossy().to_string(),
+            options: vec!["rbind".to_string()],
+            uid_mappings: Vec::new(),
+            gid_mappings: Vec::new(),
+        });
+    }
+}
+
+/// 在 rootfs 中创建 /etc/mtab -> /proc/self/mounts 符号链接
+///
+/// spec 已挂载或镜像中已存在 /etc/mtab 时不做处理。
+pub fn ensure_mtab(spec: &Spec, rootfs: &Path) -> Result<()> {
+    if has_spec_mount(spec, MTAB) {
+        return Ok(());
+    }
+
+    let mtab = rootfs.join(MTAB.trim_start_matches('/'));
+    if fs::symlink_metadata(&mtab).is_ok() {
+        return Ok(());
+    }
+    if let Some(parent) = mtab.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = std::os::unix::fs::symlink(MTAB_TARGET, &mtab) {
+        // 只读镜像中无法创建，不影响容器启动
+        warn!("创建 {} 失败: {}", MTAB, e);
+        return Ok(());
+    }
+    selinux::setfilecon(&mtab.to_string_lossy(), selinux::mount_label(spec))?;
+    debug!("创建符号链接 {} -> {}", MTAB, MTAB_TARGET);
+    Ok(())
+}
+
+/// 生成 /etc 文件并注入对应的挂载
+#[tracing::instrument(name = "etc_files", skip_all)]
+pub fn setup(spec: &mut Spec, container_dir: &Path, dns: &DnsConfig) -> Result<()> {
+    generate(spec, container_dir, dns)?;
+    inject_mounts(spec, container_dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_resolv_conf_from_host() {
+        let host = "nameserver 127.0.0.53\nnameserver 8.8.8.8\nsearch example.com\n";
+        let content = render_resolv_conf(&DnsConfig::default(), host, true);
+        assert_eq!(content, "search example.com\nnameserver 8.8.8.8\n");
+    }
+
+    #[test]
+    fn test_render_resolv_conf_override() {
+        let dns = DnsConfig::new(vec!["1.1.1.1".to_string()], vec![], vec!["ndots:2".to_string()]);
+        let content = render_resolv_conf(&dns, "nameserver 8.8.8.8\nsearch lan\n", false);
+        assert_eq!(content, "search lan\nnameserver 1.1.1.1\noptions ndots:2\n");
+    }
+
+    #[test]
+    fn test_dns_from_annotations() {
+        let mut annotations = HashMap::new();
+        annotations.insert(ANNOTATION_DNS_SERVERS.to_string(), "1.1.1.1, 9.9.9.9".to_string());
+        let dns = DnsConfig::from_annotations(&annotations);
+        assert_eq!(dns.servers, vec!["1.1.1.1", "9.9.9.9"]);
+        assert!(dns.searches.is_empty());
+    }
+
+    #[test]
+    fn test_render_hosts() {
+        assert!(render_hosts("box").ends_with("127.0.1.1\tbox\n"));
+        assert!(!render_hosts("").contains("127.0.1.1"));
+    }
+
+    #[test]
+    fn test_etc_files_mounted_in_rootfs() {
+        // 挂载需要 root 权限
+        if !nix::unistd::geteuid().is_root() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("fire-etcfiles-{}", std::process::id()));
+        let (container_dir, rootfs) = (dir.join("state"), dir.join("rootfs"));
+        fs::create_dir_all(&container_dir).unwrap();
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        let mut spec = Spec {
+            hostname: "box".to_string(),
+            ..Default::default()
+        };
+        setup(&mut spec, &container_dir, &DnsConfig::default()).unwrap();
+
+        // 在只属于该线程的 mount namespace 中挂载，线程退出后挂载随之消失
+        let mounted = std::thread::spawn(move || -> Result<(String, String)> {
+            nix::sched::unshare(nix::sched::CloneFlags::CLONE_NEWNS)?;
+            crate::mounts::setup_rootfs_propagation("")?;
+            crate::mounts::mount_to(&spec, &rootfs.to_string_lossy(), false)?;
+            Ok((
+                fs::read_to_string(rootfs.join("etc/hosts"))?,
+                fs::read_to_string(rootfs.join("etc/hostname"))?,
+            ))
+        })
+        .join()
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (hosts, hostname) = mounted.unwrap();
+        assert_eq!(hosts, render_hosts("box"));
+        assert_eq!(hostname, render_hostname("box"));
+    }
+}
@@ -2,6 +2,7 @@
 use crate::errors::Result;
 use libc;
 use nix::errno::Errno;
+use nix::sys::signal::Signal;
 use std::ffi::CString;
 use std::os::unix::io::RawFd;
 
@@ -31,6 +32,12 @@ pub fn fchdir(fd: RawFd) -> Result<()> {
     Errno::result(res).map(drop).map_err(|e| e.into())
 }
 
+#[inline]
+pub fn sethostname(name: &str) -> Result<()> {
+    let res = unsafe { libc::sethostname(name.as_ptr() as *const libc::c_char, name.len()) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
 #[inline]
 pub fn setgroups(gids: &[libc::gid_t]) -> Result<()> {
     let res = unsafe { libc::setgroups(gids.len(), gids.as_ptr()) };
@@ -51,6 +58,20 @@ pub fn setrlimit(
     Errno::result(res).map(drop).map_err(|e| e.into())
 }
 
+#[inline]
+pub fn pidfd_open(pid: libc::pid_t) -> Result<RawFd> {
+    let res = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    Errno::result(res).map(|fd| fd as RawFd).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn pidfd_send_signal(pidfd: RawFd, signal: libc::c_int) -> Result<()> {
+    let res = unsafe {
+        libc::syscall(libc::SYS_pidfd_send_signal, pidfd, signal, std::ptr::null::<libc::siginfo_t>(), 0)
+    };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
 #[inline]
 pub fn clearenv() -> Result<()> {
     let res = unsafe { libc::clearenv() };
@@ -79,6 +100,195 @@ pub fn putenv(string: &CString) -> Result<()> {
     Errno::result(res).map(drop).map_err(|e| e.into())
 }
 
+/// 通过 unix socket 发送一个描述符（SCM_RIGHTS），payload 随之发送
+pub fn send_fd(socket: RawFd, fd: RawFd, payload: &[u8]) -> Result<()> {
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+    }
+    let res = unsafe { libc::sendmsg(socket, &msg, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+/// 从 unix socket 接收 send_fd 发送的描述符，接收到的描述符设置了 close-on-exec
+pub fn recv_fd(socket: RawFd) -> Result<Option<RawFd>> {
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut buf = [0u8; 4096];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+    let res = unsafe { libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    Errno::result(res)?;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null() || (*cmsg).cmsg_level != libc::SOL_SOCKET || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Ok(None);
+        }
+        Ok(Some(std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd)))
+    }
+}
+
+// mount flags missing from libc
+pub const MS_NOSYMFOLLOW: libc::c_ulong = 256;
+
+// new mount API flags that are not exported by libc on every target
+pub const OPEN_TREE_CLONE: libc::c_uint = 0x01;
+pub const OPEN_TREE_CLOEXEC: libc::c_uint = libc::O_CLOEXEC as libc::c_uint;
+pub const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x04;
+pub const MOVE_MOUNT_T_EMPTY_PATH: libc::c_uint = 0x40;
+pub const FSOPEN_CLOEXEC: libc::c_uint = 0x01;
+pub const FSMOUNT_CLOEXEC: libc::c_uint = 0x01;
+pub const FSCONFIG_SET_FLAG: libc::c_uint = 0;
+pub const FSCONFIG_SET_STRING: libc::c_uint = 1;
+pub const FSCONFIG_CMD_CREATE: libc::c_uint = 6;
+
+#[inline]
+pub fn fsopen(fstype: &CString, flags: libc::c_uint) -> Result<RawFd> {
+    let res = unsafe { libc::syscall(libc::SYS_fsopen, fstype.as_ptr(), flags) };
+    Errno::result(res).map(|fd| fd as RawFd).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn fsconfig(
+    fd: RawFd,
+    cmd: libc::c_uint,
+    key: Option<&CString>,
+    value: Option<&CString>,
+) -> Result<()> {
+    let key = key.map_or(std::ptr::null(), |k| k.as_ptr());
+    let value = value.map_or(std::ptr::null(), |v| v.as_ptr());
+    let res = unsafe { libc::syscall(libc::SYS_fsconfig, fd, cmd, key, value, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn fsmount(fd: RawFd, flags: libc::c_uint, attr_flags: libc::c_uint) -> Result<RawFd> {
+    let res = unsafe { libc::syscall(libc::SYS_fsmount, fd, flags, attr_flags) };
+    Errno::result(res).map(|fd| fd as RawFd).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn openat2(dirfd: RawFd, path: &CString, flags: libc::c_int, resolve: u64) -> Result<RawFd> {
+    // open_how 标记为 non_exhaustive，只能先清零再赋值
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = flags as u64;
+    how.resolve = resolve;
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dirfd,
+            path.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    Errno::result(res).map(|fd| fd as RawFd).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn open_tree(dirfd: RawFd, path: &CString, flags: libc::c_uint) -> Result<RawFd> {
+    let res = unsafe { libc::syscall(libc::SYS_open_tree, dirfd, path.as_ptr(), flags) };
+    Errno::result(res).map(|fd| fd as RawFd).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn move_mount(
+    from_dirfd: RawFd,
+    from_path: &CString,
+    to_dirfd: RawFd,
+    to_path: &CString,
+    flags: libc::c_uint,
+) -> Result<()> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            from_dirfd,
+            from_path.as_ptr(),
+            to_dirfd,
+            to_path.as_ptr(),
+            flags,
+        )
+    };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn mount_setattr(
+    dirfd: RawFd,
+    path: &CString,
+    flags: libc::c_uint,
+    attr: &libc::mount_attr,
+) -> Result<()> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            dirfd,
+            path.as_ptr(),
+            flags,
+            attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        )
+    };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+/// 父进程（准确地说是 fork 出当前进程的线程）退出时内核发送的信号，None 表示清除
+///
+/// 只调用 prctl，可以在 fork 与 exec 之间使用。切换用户、exec setuid 程序时内核会清除该设置。
+#[inline]
+pub fn set_pdeathsig(signal: Option<Signal>) -> Result<()> {
+    let signal = signal.map_or(0, |s| s as libc::c_ulong);
+    let res = unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, signal, 0, 0, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn set_no_new_privs() -> Result<()> {
+    let res = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+#[inline]
+pub fn set_keepcaps() -> Result<()> {
+    let res = unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+/// 成为子孙进程的收养者，孤儿进程重新挂到当前进程下而不是 pid 1
+#[inline]
+pub fn set_child_subreaper() -> Result<()> {
+    let res = unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
+/// 设置当前线程的名称（/proc/self/comm），超过 15 个字节的部分被内核截断
+#[inline]
+pub fn set_name(name: &CString) -> Result<()> {
+    let res = unsafe { libc::prctl(libc::PR_SET_NAME, name.as_ptr() as libc::c_ulong, 0, 0, 0) };
+    Errno::result(res).map(drop).map_err(|e| e.into())
+}
+
 // 便利函数，用于简化字符串处理
 pub fn lsetxattr_str(path: &str, name: &str, value: &[u8]) -> Result<()> {
     let path_cstr = std::ffi::CString::new(path)
@@ -0,0 +1,202 @@
+use crate::errors::Result;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 通过注解指定只读镜像层目录（冒号分隔，最上层在前）
+pub const ANNOTATION_ROOTFS_LAYERS: &str = "org.fire.rootfs.layers";
+/// 通过注解指定可写层目录，默认位于容器状态目录
+pub const ANNOTATION_ROOTFS_UPPERDIR: &str = "org.fire.rootfs.upperdir";
+/// 通过注解指定 overlayfs 工作目录，必须与可写层位于同一文件系统
+pub const ANNOTATION_ROOTFS_WORKDIR: &str = "org.fire.rootfs.workdir";
+
+/// 由镜像层组装 overlayfs 根文件系统的配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OverlayConfig {
+    pub lower_dirs: Vec<PathBuf>,
+    pub upper_dir: Option<PathBuf>,
+    pub work_dir: Option<PathBuf>,
+}
+
+impl OverlayConfig {
+    pub fn new(lower_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            lower_dirs,
+            upper_dir: None,
+            work_dir: None,
+        }
+    }
+
+    /// 从容器注解中读取配置，未指定镜像层时返回 None
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Option<Self> {
+        let lower_dirs: Vec<PathBuf> = annotations
+            .get(ANNOTATION_ROOTFS_LAYERS)?
+            .split(':')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        if lower_dirs.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            lower_dirs,
+            upper_dir: annotations.get(ANNOTATION_ROOTFS_UPPERDIR).map(PathBuf::from),
+            work_dir: annotations.get(ANNOTATION_ROOTFS_WORKDIR).map(PathBuf::from),
+        })
+    }
+
+    /// 生成 overlayfs 挂载参数
+    pub fn mount_data(&self, upper_dir: &Path, work_dir: &Path) -> String {
+        let lower = self
+            .lower_dirs
+            .iter()
+            .map(|p| escape_option(p))
+            .collect::<Vec<_>>()
+            .join(":");
+        format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lower,
+            escape_option(upper_dir),
+            escape_option(work_dir)
+        )
+    }
+}
+
+/// overlayfs 参数中的 `:` 与 `,` 需要转义
+fn escape_option(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        if matches!(c, ':' | ',' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 在 rootfs 上挂载由镜像层组装的 overlayfs
+///
+/// 未指定可写层和工作目录时，使用容器状态目录下的 upper/ 和 work/，
+/// 随容器删除一并清理。
+#[tracing::instrument(name = "mount_overlay", skip_all, fields(rootfs = %rootfs.display()))]
+pub fn mount_overlay(config: &OverlayConfig, rootfs: &Path, container_dir: &Path) -> Result<()> {
+    for dir in &config.lower_dirs {
+        if !dir.is_dir() {
+            return Err(crate::errors::FireError::InvalidSpec(format!(
+                "镜像层目录不存在: {}",
+                dir.display()
+            )));
+        }
+    }
+
+    let upper_dir = config
+        .upper_dir
+        .clone()
+        .unwrap_or_else(|| container_dir.join("upper"));
+    let work_dir = config
+        .work_dir
+        .clone()
+        .unwrap_or_else(|| container_dir.join("work"));
+    fs::create_dir_all(&upper_dir)?;
+    fs::create_dir_all(&work_dir)?;
+    fs::create_dir_all(rootfs)?;
+
+    let data = config.mount_data(&upper_dir, &work_dir);
+    debug!("overlayfs 挂载参数: {}", data);
+
+    let source = std::ffi::CString::new("overlay")?;
+    let target = std::ffi::CString::new(rootfs.to_string_lossy().as_bytes())?;
+    let data_cstr = std::ffi::CString::new(data)?;
+    unsafe {
+        if libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            source.as_ptr(),
+            0,
+            data_cstr.as_ptr() as *const libc::c_void,
+        ) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "挂载 overlayfs 到 {} 失败: {}",
+                rootfs.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+
+    info!(
+        "成功组装 overlayfs 根文件系统: {} (镜像层: {})",
+        rootfs.display(),
+        config.lower_dirs.len()
+    );
+    Ok(())
+}
+
+/// 判断 rootfs 上是否挂载了 overlayfs
+pub fn is_overlay_mounted(rootfs: &Path) -> bool {
+    let rootfs = match fs::canonicalize(rootfs) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    mountinfo.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let fstype = fields
+            .iter()
+            .position(|f| *f == "-")
+            .and_then(|i| fields.get(i + 1));
+        fields.get(4).map(Path::new) == Some(rootfs.as_path()) && fstype == Some(&"overlay")
+    })
+}
+
+/// 卸载 rootfs 上的 overlayfs
+pub fn unmount_overlay(rootfs: &Path) -> Result<()> {
+    let target = std::ffi::CString::new(rootfs.to_string_lossy().as_bytes())?;
+    unsafe {
+        if libc::umount2(target.as_ptr(), libc::MNT_DETACH) == -1 {
+            return Err(crate::errors::FireError::Generic(format!(
+                "卸载 overlayfs {} 失败: {}",
+                rootfs.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    info!("卸载 overlayfs 根文件系统: {}", rootfs.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_from_annotations() {
+        let mut annotations = HashMap::new();
+        assert!(OverlayConfig::from_annotations(&annotations).is_none());
+
+        annotations.insert(
+            ANNOTATION_ROOTFS_LAYERS.to_string(),
+            "/layers/app:/layers/base".to_string(),
+        );
+        annotations.insert(ANNOTATION_ROOTFS_UPPERDIR.to_string(), "/data/upper".to_string());
+        let config = OverlayConfig::from_annotations(&annotations).unwrap();
+        assert_eq!(
+            config.lower_dirs,
+            vec![PathBuf::from("/layers/app"), PathBuf::from("/layers/base")]
+        );
+        assert_eq!(config.upper_dir, Some(PathBuf::from("/data/upper")));
+        assert!(config.work_dir.is_none());
+    }
+
+    #[test]
+    fn test_overlay_mount_data() {
+        let config = OverlayConfig::new(vec![PathBuf::from("/l/a"), PathBuf::from("/l/b,c")]);
+        assert_eq!(
+            config.mount_data(Path::new("/u"), Path::new("/w")),
+            "lowerdir=/l/a:/l/b\\,c,upperdir=/u,workdir=/w"
+        );
+    }
+}
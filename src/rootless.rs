@@ -0,0 +1,122 @@
+//! 以非特权用户运行容器
+//!
+//! 非 root 用户只能在自己创建的 user namespace 中创建其他 namespace，不能配置设备 cgroup，
+//! 也不能 mknod。创建容器之前按以下方式调整，而不是在第一个需要特权的系统调用处失败：
+//!
+//! - 容器状态保存在 `$XDG_RUNTIME_DIR/fire` 下；
+//! - spec 没有 user namespace 时加入一个，把容器进程的用户映射为当前用户；
+//! - 去掉设备 cgroup 规则，设备节点退化为从主机绑定挂载，cgroup 见 [`crate::cgroups::rootless`]；
+//! - 状态文件中记录 [`ANNOTATION_ROOTLESS`]。
+
+use crate::errors::{FireError, Result};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use oci::{LinuxIDMapping, LinuxNamespace, LinuxNamespaceType, Spec};
+use std::path::PathBuf;
+
+/// 以非特权用户创建的容器在状态文件中带有该注解，值为 `true`
+pub const ANNOTATION_ROOTLESS: &str = "org.fire.rootless";
+
+lazy_static! {
+    // 创建 user namespace 之后 geteuid 返回的是命名空间中的 id，需要在此之前确定
+    static ref IDS: (u32, u32) = unsafe { (libc::geteuid(), libc::getegid()) };
+}
+
+/// 是否以非特权用户运行
+pub fn is_rootless() -> bool {
+    IDS.0 != 0
+}
+
+/// 运行 fire 的用户的有效 uid 和 gid，不受之后创建的 user namespace 影响
+pub fn ids() -> (u32, u32) {
+    *IDS
+}
+
+/// 非特权用户的状态目录 `$XDG_RUNTIME_DIR/fire`，未设置 XDG_RUNTIME_DIR 时返回 None
+pub fn runtime_dir() -> Option<PathBuf> {
+    let dir = PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?);
+    if !dir.is_absolute() {
+        return None;
+    }
+    Some(dir.join("fire"))
+}
+
+/// 调整 spec 使非特权用户可以创建容器，root 用户不做修改
+///
+/// 已有的 id 映射只能把当前用户映射到容器中的一个 id，多个 id 需要 newuidmap 等
+/// 特权程序，此时返回错误。
+pub fn adapt_spec(spec: &mut Spec) -> Result<()> {
+    if !is_rootless() {
+        return Ok(());
+    }
+    let (uid, gid) = ids();
+    let user = (spec.process.user.uid, spec.process.user.gid);
+    let Some(linux) = spec.linux.as_mut() else {
+        return Ok(());
+    };
+
+    // 加入已有的 user namespace 时不需要映射
+    let joining = match linux.namespaces.iter().find(|ns| ns.typ == LinuxNamespaceType::user) {
+        Some(ns) => !ns.path.is_empty(),
+        None => {
+            linux.namespaces.push(LinuxNamespace {
+                typ: LinuxNamespaceType::user,
+                path: String::new(),
+            });
+            false
+        }
+    };
+    if !joining && linux.uid_mappings.is_empty() && linux.gid_mappings.is_empty() {
+        info!("非特权运行，容器中的 {}:{} 映射为当前用户 {}:{}", user.0, user.1, uid, gid);
+        linux.uid_mappings = vec![self_mapping(user.0, uid)];
+        linux.gid_mappings = vec![self_mapping(user.1, gid)];
+    }
+    check_mappings(&linux.uid_mappings, uid, "uidMappings")?;
+    check_mappings(&linux.gid_mappings, gid, "gidMappings")?;
+
+    if let Some(ref mut resources) = linux.resources {
+        if !resources.devices.is_empty() {
+            warn!("非特权运行，忽略 {} 条设备 cgroup 规则", resources.devices.len());
+            resources.devices.clear();
+        }
+    }
+    Ok(())
+}
+
+fn self_mapping(container_id: u32, host_id: u32) -> LinuxIDMapping {
+    LinuxIDMapping {
+        host_id,
+        container_id,
+        size: 1,
+    }
+}
+
+/// 非特权用户写 uid_map/gid_map 时只能映射自己
+fn check_mappings(mappings: &[LinuxIDMapping], id: u32, field: &str) -> Result<()> {
+    match mappings {
+        [] => Ok(()),
+        [mapping] if mapping.host_id == id && mapping.size == 1 => Ok(()),
+        _ => Err(FireError::InvalidSpec(format!(
+            "非特权用户的 linux.{} 只能把当前用户 {} 映射为容器中的一个 id",
+            field, id
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_mappings() {
+        assert!(check_mappings(&[], 1000, "uidMappings").is_ok());
+        assert!(check_mappings(&[self_mapping(0, 1000)], 1000, "uidMappings").is_ok());
+        assert!(check_mappings(&[self_mapping(0, 1001)], 1000, "uidMappings").is_err());
+        let range = LinuxIDMapping {
+            host_id: 1000,
+            container_id: 0,
+            size: 65536,
+        };
+        assert!(check_mappings(&[range], 1000, "uidMappings").is_err());
+    }
+}
@@ -1,12 +1,25 @@
 use crate::errors::Result;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+/// 指定运行时配置文件路径的环境变量
+pub const CONFIG_ENV: &str = "FIRE_CONFIG";
+/// 运行时配置文件的默认路径
+pub const DEFAULT_CONFIG_FILE: &str = "/etc/fire/config.json";
+
+/// 运行时配置，配置文件中未出现的字段取默认值
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RuntimeConfig {
     pub state_dir: PathBuf,
     pub log_level: String,
     pub log_file: Option<PathBuf>,
+    /// 日志文件轮转的大小阈值，单位为字节，0 表示不轮转
+    #[serde(default = "default_log_max_size")]
+    pub log_max_size: u64,
+    /// 轮转后保留的旧日志文件数
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
     pub max_containers: usize,
     pub enable_systemd: bool,
     pub cgroup_manager: String,
@@ -14,13 +27,22 @@ pub struct RuntimeConfig {
     pub hooks_dir: Option<PathBuf>,
 }
 
+fn default_log_max_size() -> u64 {
+    crate::logger::DEFAULT_MAX_SIZE
+}
+
+fn default_log_max_files() -> usize {
+    crate::logger::DEFAULT_MAX_FILES
+}
+
 impl Default for RuntimeConfig {
     fn default() -> Self {
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
         Self {
-            state_dir: PathBuf::from(format!("{}/.fire", home_dir)),
+            state_dir: crate::container::state::root_dir(),
             log_level: "info".to_string(),
             log_file: None,
+            log_max_size: default_log_max_size(),
+            log_max_files: default_log_max_files(),
             max_containers: 1000,
             enable_systemd: false,
             cgroup_manager: "cgroupfs".to_string(),
@@ -35,6 +57,15 @@ impl RuntimeConfig {
         Self::default()
     }
 
+    /// 读取 FIRE_CONFIG 指定的配置文件（默认为 /etc/fire/config.json），文件不存在时使用默认配置
+    pub fn load() -> Result<Self> {
+        let path = std::env::var(CONFIG_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_FILE.to_string());
+        if !Path::new(&path).exists() {
+            return Ok(Self::default());
+        }
+        Self::load_from_file(&path)
+    }
+
     pub fn load_from_file(path: &str) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
         let config: RuntimeConfig = serde_json::from_str(&content)?;
@@ -53,10 +84,13 @@ impl RuntimeConfig {
             std::fs::create_dir_all(&self.state_dir)?;
         }
 
-        // 验证日志级别
-        match self.log_level.as_str() {
-            "trace" | "debug" | "info" | "warn" | "error" => {}
-            _ => {
+        // 验证日志级别，支持 `info,fire::cgroups=debug` 形式的按模块过滤
+        for directive in self.log_level.split(',').filter(|d| !d.is_empty()) {
+            // 不带 = 的项可以是级别或模块名
+            let Some((_, level)) = directive.split_once('=') else {
+                continue;
+            };
+            if level.parse::<log::LevelFilter>().is_err() {
                 return Err(crate::errors::FireError::InvalidSpec(format!(
                     "无效的日志级别: {}",
                     self.log_level
@@ -87,3 +121,21 @@ impl RuntimeConfig {
             .join("state.json")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_partial_file() {
+        let path = std::env::temp_dir().join(format!("fire-config-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"log_level": "warn,fire::cgroups=debug"}"#).unwrap();
+        let config = RuntimeConfig::load_from_file(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.log_level, "warn,fire::cgroups=debug");
+        assert_eq!(config.cgroup_manager, "cgroupfs");
+        assert_eq!(config.log_max_files, default_log_max_files());
+    }
+}
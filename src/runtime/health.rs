@@ -0,0 +1,279 @@
+//! 容器健康检查
+//!
+//! 前台 `fire run` 在容器启动后由后台线程按 org.fire.health.* 注解定期执行探针：exec
+//! 探针在容器的 namespace 中以容器进程的用户执行命令，tcp 探针在容器的网络 namespace
+//! 中建立连接。检查结果记录在状态文件中，连续失败达到次数后状态为 unhealthy，并按配置
+//! 终止容器，由 `fire run` 决定是否重启。
+
+use crate::container::namespace;
+use crate::container::state;
+use crate::errors::{FireError, Result};
+use crate::nix_ext;
+use crate::runtime::options::{HealthAction, HealthCheck, HealthProbe};
+use log::{debug, info, warn};
+use nix::sched::CloneFlags;
+use oci::Spec;
+use std::fs::File;
+use std::net::{SocketAddr, TcpStream};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// 健康状态：starting、healthy 或 unhealthy
+pub const ANNOTATION_HEALTH_STATUS: &str = "org.fire.health.status";
+/// 连续失败的次数
+pub const ANNOTATION_HEALTH_FAILURES: &str = "org.fire.health.failures";
+/// 因 unhealthy 重启容器的次数，记录在重新创建的容器的状态文件中
+pub const ANNOTATION_HEALTH_RESTARTS: &str = "org.fire.health.restarts";
+
+/// 等待停止信号时的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 探针命令的执行环境，取自 spec 中的 process
+#[derive(Clone)]
+struct ExecContext {
+    env: Vec<(String, String)>,
+    cwd: String,
+    uid: u32,
+    gid: u32,
+}
+
+/// 后台健康检查线程
+pub struct Monitor {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<bool>,
+}
+
+impl Monitor {
+    /// 为已经启动的容器启动健康检查
+    pub fn start(
+        id: &str,
+        container_dir: PathBuf,
+        pid: i32,
+        spec: &Spec,
+        check: HealthCheck,
+    ) -> Result<Self> {
+        if !crate::sysinfo::get().syscalls.pidfd {
+            return Err(FireError::KernelUnsupported("健康检查需要内核支持 pidfd".to_string()));
+        }
+        let pidfd = unsafe { OwnedFd::from_raw_fd(nix_ext::pidfd_open(pid)?) };
+        let context = ExecContext {
+            env: spec
+                .process
+                .env
+                .iter()
+                .filter_map(|e| e.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            cwd: if spec.process.cwd.is_empty() {
+                "/".to_string()
+            } else {
+                spec.process.cwd.clone()
+            },
+            uid: spec.process.user.uid,
+            gid: spec.process.user.gid,
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let watcher = Watcher {
+            id: id.to_string(),
+            container_dir,
+            pid,
+            pidfd,
+            check,
+            context,
+            stop: stop.clone(),
+        };
+        let handle = std::thread::Builder::new()
+            .name(format!("health-{}", id))
+            .spawn(move || watcher.run())?;
+        Ok(Self { stop, handle })
+    }
+
+    /// 容器退出后停止检查，返回容器是否因 unhealthy 被终止
+    ///
+    /// 正在执行的探针最多在超时后结束，返回时不会再写状态文件。
+    pub fn stop(self) -> bool {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+struct Watcher {
+    id: String,
+    container_dir: PathBuf,
+    pid: i32,
+    /// 用 pidfd 发送信号，主进程退出并被回收后不会误杀复用该 pid 的进程
+    pidfd: OwnedFd,
+    check: HealthCheck,
+    context: ExecContext,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    fn run(self) -> bool {
+        info!(
+            "容器 {} 开始健康检查: {:?}，间隔 {:?}",
+            self.id, self.check.probe, self.check.interval
+        );
+        let mut status = "starting";
+        let mut failures = 0u32;
+        self.record(status, failures);
+
+        loop {
+            if !self.sleep(self.check.interval) {
+                return false;
+            }
+            let result = match self.check.probe {
+                HealthProbe::Exec(ref args) => exec_probe(self.pid, args, &self.context, self.check.timeout),
+                HealthProbe::Tcp(addr) => tcp_probe(self.pid, addr, self.check.timeout),
+            };
+            // 探针执行期间容器已经退出，结果没有意义
+            if self.stop.load(Ordering::SeqCst) {
+                return false;
+            }
+
+            let last = (status, failures);
+            match result {
+                Ok(()) => {
+                    failures = 0;
+                    status = "healthy";
+                }
+                Err(e) => {
+                    failures += 1;
+                    warn!("容器 {} 健康检查失败（连续 {} 次）: {}", self.id, failures, e);
+                    if failures >= self.check.retries {
+                        status = "unhealthy";
+                    }
+                }
+            }
+            if (status, failures) != last {
+                self.record(status, failures);
+            }
+
+            if status == "unhealthy" && self.check.action != HealthAction::None {
+                warn!("容器 {} 持续 unhealthy，终止容器", self.id);
+                if let Err(e) = nix_ext::pidfd_send_signal(self.pidfd.as_raw_fd(), libc::SIGKILL) {
+                    warn!("终止容器 {} 失败: {}", self.id, e);
+                    continue;
+                }
+                return true;
+            }
+        }
+    }
+
+    fn record(&self, status: &str, failures: u32) {
+        debug!("容器 {} 的健康状态: {}，连续失败 {} 次", self.id, status, failures);
+        let failures = failures.to_string();
+        let result = state::annotate(&self.container_dir, ANNOTATION_HEALTH_STATUS, status)
+            .and_then(|_| state::annotate(&self.container_dir, ANNOTATION_HEALTH_FAILURES, &failures));
+        if let Err(e) = result {
+            warn!("记录容器 {} 的健康状态失败: {}", self.id, e);
+        }
+    }
+
+    /// 等待 duration，期间收到停止信号时返回 false
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            if self.stop.load(Ordering::SeqCst) {
+                return false;
+            }
+            std::thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+        !self.stop.load(Ordering::SeqCst)
+    }
+}
+
+/// 在容器的 namespace 中执行命令，超时后终止
+fn exec_probe(pid: i32, args: &[String], context: &ExecContext, timeout: Duration) -> Result<()> {
+    let namespaces = namespace::open_process_namespaces(pid, &namespace::JOIN_ORDER)?;
+    let context = context.clone();
+
+    let mut command = Command::new(&args[0]);
+    command
+        .args(&args[1..])
+        .env_clear()
+        .envs(context.env.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // 在 fork 出的单线程子进程中进入 namespace，之后才切换用户，否则没有权限调用 setns
+    unsafe {
+        command.pre_exec(move || {
+            for (file, flag) in &namespaces {
+                nix::sched::setns(file, *flag)?;
+            }
+            nix::unistd::chdir(context.cwd.as_str())?;
+            nix::unistd::setgid(nix::unistd::Gid::from_raw(context.gid))?;
+            nix::unistd::setuid(nix::unistd::Uid::from_raw(context.uid))?;
+            Ok(())
+        });
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| FireError::Generic(format!("执行 {} 失败: {}", args[0], e)))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return if status.success() {
+                Ok(())
+            } else {
+                Err(FireError::Generic(format!("{} 退出: {}", args[0], status)))
+            };
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(FireError::Generic(format!("{} 超时", args[0])));
+        }
+        std::thread::sleep(POLL_INTERVAL.min(timeout));
+    }
+}
+
+/// 在容器的网络 namespace 中连接 addr
+///
+/// 网络 namespace 按线程生效，在临时线程中进入，不影响其他线程。
+fn tcp_probe(pid: i32, addr: SocketAddr, timeout: Duration) -> Result<()> {
+    let netns = File::open(format!("/proc/{}/ns/net", pid))?;
+    std::thread::spawn(move || -> Result<()> {
+        nix::sched::setns(&netns, CloneFlags::CLONE_NEWNET)?;
+        TcpStream::connect_timeout(&addr, timeout)
+            .map_err(|e| FireError::Generic(format!("连接 {} 失败: {}", addr, e)))?;
+        Ok(())
+    })
+    .join()
+    .unwrap_or_else(|_| Err(FireError::Generic("tcp 探针线程异常退出".to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_probe() {
+        // 以当前进程为"容器"，所有 namespace 都相同，不需要 setns
+        let context = ExecContext {
+            env: vec![("PATH".to_string(), "/usr/bin:/bin".to_string())],
+            cwd: "/".to_string(),
+            uid: nix::unistd::getuid().as_raw(),
+            gid: nix::unistd::getgid().as_raw(),
+        };
+        let pid = std::process::id() as i32;
+        let probe = |args: &[&str], timeout: u64| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            exec_probe(pid, &args, &context, Duration::from_millis(timeout))
+        };
+
+        assert!(probe(&["true"], 5000).is_ok());
+        assert!(probe(&["/bin/sh", "-c", "test \"$(pwd)\" = /"], 5000).is_ok());
+        assert!(probe(&["false"], 5000).is_err());
+        assert!(probe(&["sleep", "5"], 200).is_err());
+    }
+}
@@ -1,4 +1,9 @@
 use crate::errors::Result;
+use log::{info, warn};
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub struct Hook {
@@ -6,6 +11,7 @@ pub struct Hook {
     pub path: String,
     pub args: Vec<String>,
     pub env: Vec<String>,
+    pub timeout: Option<Duration>,
 }
 
 impl Hook {
@@ -15,11 +21,122 @@ impl Hook {
             path,
             args,
             env,
+            timeout: None,
         }
     }
 
-    pub fn execute(&self) -> Result<()> {
-        // TODO: 实现钩子执行逻辑
-        crate::bail!("钩子执行功能尚未实现");
+    /// 执行钩子，容器状态 JSON 写入钩子的标准输入
+    ///
+    /// args 的第一项为 argv[0]，env 会替换而不是继承 fire 的环境变量。
+    pub fn execute(&self, state: &oci::State) -> Result<()> {
+        let mut command = Command::new(&self.path);
+        if let Some((argv0, args)) = self.args.split_first() {
+            command.arg0(argv0).args(args);
+        }
+        command.env_clear().envs(self.env.iter().filter_map(|e| e.split_once('=')));
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| crate::errors::FireError::Generic(format!("执行钩子 {} 失败: {}", self.name, e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let state_json = serde_json::to_vec(state)?;
+            // 钩子可能不读取标准输入就退出
+            let _ = stdin.write_all(&state_json);
+        }
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let _ = child.kill();
+                let _ = child.wait();
+                crate::bail!("钩子 {} 执行超时", self.name);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        if !status.success() {
+            crate::bail!("钩子 {} 执行失败: {}", self.name, status);
+        }
+        Ok(())
+    }
+}
+
+impl From<&oci::Hook> for Hook {
+    fn from(hook: &oci::Hook) -> Self {
+        Self {
+            name: hook.path.clone(),
+            path: hook.path.clone(),
+            args: hook.args.clone(),
+            env: hook.env.clone(),
+            timeout: hook
+                .timeout
+                .filter(|&t| t > 0)
+                .map(|t| Duration::from_secs(t as u64)),
+        }
+    }
+}
+
+/// 容器进程开始执行后依次执行 poststart 钩子，失败只记录警告
+pub fn run_poststart(spec: &oci::Spec, state: &oci::State) {
+    if let Some(ref hooks) = spec.hooks {
+        run_all("poststart", &hooks.poststart, state);
+    }
+}
+
+/// 依次执行 poststop 钩子，失败只记录警告，不影响容器删除
+pub fn run_poststop(spec: &oci::Spec, state: &oci::State) {
+    if let Some(ref hooks) = spec.hooks {
+        run_all("poststop", &hooks.poststop, state);
+    }
+}
+
+fn run_all(kind: &str, hooks: &[oci::Hook], state: &oci::State) {
+    for hook in hooks {
+        info!("执行 {} 钩子: {}", kind, hook.path);
+        if let Err(e) = Hook::from(hook).execute(state) {
+            warn!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> oci::State {
+        oci::State {
+            version: "1.0.2".to_string(),
+            id: "hook-test".to_string(),
+            status: "stopped".to_string(),
+            pid: 0,
+            bundle: "/tmp".to_string(),
+            annotations: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_hook_receives_state() {
+        let hook = Hook::new(
+            "grep".to_string(),
+            "/bin/sh".to_string(),
+            vec!["sh".to_string(), "-c".to_string(), "grep -q '\"id\":\"hook-test\"'".to_string()],
+            vec!["PATH=/usr/bin:/bin".to_string()],
+        );
+        assert!(hook.execute(&state()).is_ok());
+    }
+
+    #[test]
+    fn test_hook_timeout() {
+        let mut hook = Hook::new(
+            "sleep".to_string(),
+            "/bin/sh".to_string(),
+            vec!["sh".to_string(), "-c".to_string(), "sleep 5".to_string()],
+            Vec::new(),
+        );
+        hook.timeout = Some(Duration::from_millis(50));
+        assert!(hook.execute(&state()).is_err());
     }
 }
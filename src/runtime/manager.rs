@@ -1,86 +1,63 @@
-use crate::container::Container;
+use crate::container::{Container, ContainerSummary};
 use crate::errors::Result;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use log::{info, error};
 use lazy_static::lazy_static;
 
 lazy_static! {
-    pub static ref RUNTIME_MANAGER: Mutex<RuntimeManager> = {
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let state_dir = format!("{}/.fire", home_dir);
-        Mutex::new(RuntimeManager::new(state_dir))
-    };
+    pub static ref RUNTIME_MANAGER: Mutex<RuntimeManager> = Mutex::new(RuntimeManager::new());
 }
 
+/// 管理器中容器的共享句柄，所有调用方操作的是同一个实例
+pub type ContainerRef = Arc<Mutex<Container>>;
+
+/// 当前进程中的容器句柄，状态文件的位置由 state::root_dir 决定
+#[derive(Default)]
 pub struct RuntimeManager {
-    containers: HashMap<String, Container>,
-    state_dir: String,
+    containers: HashMap<String, ContainerRef>,
 }
 
 impl RuntimeManager {
-    pub fn new(state_dir: String) -> Self {
-        Self {
-            containers: HashMap::new(),
-            state_dir,
-        }
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    pub fn create_container(&mut self, id: String, container: Container) -> Result<()> {
+    pub fn create_container(&mut self, id: String, container: Container) -> Result<ContainerRef> {
         if self.containers.contains_key(&id) {
-            crate::bail!("容器 {} 已存在", id);
+            return Err(crate::errors::FireError::AlreadyExists(format!("容器 {} 已存在", id)));
         }
         info!("创建容器 {}", id);
-        self.containers.insert(id, container);
-        Ok(())
+        let container = Arc::new(Mutex::new(container));
+        self.containers.insert(id, container.clone());
+        Ok(container)
     }
 
     pub fn start_container(&mut self, id: &str) -> Result<()> {
-        let container = self.containers.get_mut(id)
-            .ok_or_else(|| crate::errors::FireError::Generic(
-                format!("容器 {} 不存在", id)
-            ))?;
-        
-        container.start()
+        self.require(id)?.lock().unwrap().start()
     }
 
     pub fn stop_container(&mut self, id: &str) -> Result<()> {
-        let container = self.containers.get_mut(id)
-            .ok_or_else(|| crate::errors::FireError::Generic(
-                format!("容器 {} 不存在", id)
-            ))?;
-        
-        container.stop()
+        self.require(id)?.lock().unwrap().stop()
     }
 
     pub fn pause_container(&mut self, id: &str) -> Result<()> {
-        let container = self.containers.get_mut(id)
-            .ok_or_else(|| crate::errors::FireError::Generic(
-                format!("容器 {} 不存在", id)
-            ))?;
-        
-        container.pause()
+        self.require(id)?.lock().unwrap().pause()
     }
 
     pub fn resume_container(&mut self, id: &str) -> Result<()> {
-        let container = self.containers.get_mut(id)
-            .ok_or_else(|| crate::errors::FireError::Generic(
-                format!("容器 {} 不存在", id)
-            ))?;
-        
-        container.resume()
+        self.require(id)?.lock().unwrap().resume()
     }
 
     pub fn kill_container(&mut self, id: &str, signal: i32) -> Result<()> {
-        let container = self.containers.get(id)
-            .ok_or_else(|| crate::errors::FireError::Generic(
-                format!("容器 {} 不存在", id)
-            ))?;
+        let container = self.require(id)?;
+        let container = container.lock().unwrap();
         
+        // 只向 init 进程发送信号，需要终止所有进程时由调用方使用 cgroups::kill_all
         if let Some(ref main_process) = container.main_process {
             main_process.kill(signal)?;
         } else {
-            return Err(crate::errors::FireError::Generic(
+            return Err(crate::errors::FireError::InvalidState(
                 format!("容器 {} 没有主进程", id)
             ));
         }
@@ -88,26 +65,42 @@ impl RuntimeManager {
         Ok(())
     }
 
-    pub fn get_container(&self, id: &str) -> Option<&Container> {
-        self.containers.get(id)
+    /// 返回容器的共享句柄，只复制 Arc
+    pub fn get_container(&self, id: &str) -> Option<ContainerRef> {
+        self.containers.get(id).cloned()
     }
 
-    pub fn get_container_mut(&mut self, id: &str) -> Option<&mut Container> {
-        self.containers.get_mut(id)
+    pub fn remove_container(&mut self, id: &str) -> Option<ContainerRef> {
+        self.containers.remove(id)
     }
 
-    pub fn remove_container(&mut self, id: &str) -> Option<Container> {
-        self.containers.remove(id)
+    /// 依次借出每个容器，不复制容器
+    pub fn for_each_container<F: FnMut(&Container)>(&self, mut f: F) {
+        for container in self.containers.values() {
+            f(&container.lock().unwrap());
+        }
     }
 
-    pub fn list_containers(&self) -> Vec<&Container> {
-        self.containers.values().collect()
+    /// 所有容器的摘要，按 id 排序
+    pub fn list_summaries(&self) -> Vec<ContainerSummary> {
+        let mut summaries = Vec::with_capacity(self.containers.len());
+        self.for_each_container(|c| summaries.push(c.summary()));
+        summaries.sort_by(|a, b| a.id.cmp(&b.id));
+        summaries
     }
 
     pub fn cleanup_all(&mut self) -> Result<()> {
         info!("清理所有容器资源");
         
-        for (id, container) in self.containers.iter_mut() {
+        for (id, container) in self.containers.iter() {
+            let mut container = container.lock().unwrap();
+            // create 留下的 init 进程由之后的 start 或 delete 处理
+            if let Some(pid) = container.get_main_process_pid() {
+                if nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None).is_ok() {
+                    info!("容器 {} 的 init 进程 {} 仍在运行，保留其资源", id, pid);
+                    continue;
+                }
+            }
             info!("清理容器 {} 的资源", id);
             if let Err(e) = container.cleanup() {
                 error!("清理容器 {} 失败: {}", id, e);
@@ -118,4 +111,10 @@ impl RuntimeManager {
         info!("所有容器资源清理完成");
         Ok(())
     }
+
+    fn require(&self, id: &str) -> Result<ContainerRef> {
+        self.get_container(id).ok_or_else(|| {
+            crate::errors::FireError::NotFound(format!("容器 {} 不存在", id))
+        })
+    }
 }
@@ -1,20 +1,13 @@
-use crate::container::Container;
+use crate::container::{Container, ContainerSummary};
 use crate::errors::Result;
-use manager::RuntimeManager;
-use std::sync::{Arc, Mutex};
+use manager::{ContainerRef, RUNTIME_MANAGER};
 use log::info;
 
 pub mod config;
+pub mod health;
 pub mod hooks;
 pub mod manager;
-
-lazy_static::lazy_static! {
-    static ref RUNTIME_MANAGER: Arc<Mutex<RuntimeManager>> = {
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
-        let state_dir = format!("{}/.fire", home_dir);
-        Arc::new(Mutex::new(RuntimeManager::new(state_dir)))
-    };
-}
+pub mod options;
 
 #[derive(Debug)]
 pub struct Runtime {
@@ -26,7 +19,7 @@ impl Runtime {
         Self {}
     }
 
-    pub fn create_container(&mut self, container: Container) -> Result<()> {
+    pub fn create_container(&mut self, container: Container) -> Result<ContainerRef> {
         let id = container.id.clone();
         let mut manager = RUNTIME_MANAGER.lock().unwrap();
         manager.create_container(id, container)
@@ -57,19 +50,19 @@ impl Runtime {
         manager.kill_container(id, signal)
     }
 
-    pub fn get_container(&self, id: &str) -> Option<Container> {
+    pub fn get_container(&self, id: &str) -> Option<ContainerRef> {
         let manager = RUNTIME_MANAGER.lock().unwrap();
-        manager.get_container(id).cloned()
+        manager.get_container(id)
     }
 
-    pub fn remove_container(&mut self, id: &str) -> Option<Container> {
+    pub fn remove_container(&mut self, id: &str) -> Option<ContainerRef> {
         let mut manager = RUNTIME_MANAGER.lock().unwrap();
         manager.remove_container(id)
     }
 
-    pub fn list_containers(&self) -> Vec<Container> {
+    pub fn list_containers(&self) -> Vec<ContainerSummary> {
         let manager = RUNTIME_MANAGER.lock().unwrap();
-        manager.list_containers().into_iter().cloned().collect()
+        manager.list_summaries()
     }
 
     pub fn cleanup_all(&mut self) -> Result<()> {
@@ -87,6 +80,11 @@ impl Default for Runtime {
 // 运行时初始化
 pub fn init() -> Result<()> {
     info!("初始化 Fire 运行时");
+
+    // 在创建 user namespace 之前确定是否以非特权用户运行
+    if crate::rootless::is_rootless() {
+        info!("以非特权用户运行，状态目录: {}", crate::container::state::root_dir().display());
+    }
     
     // 初始化 cgroups
     crate::cgroups::init();
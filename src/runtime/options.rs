@@ -0,0 +1,458 @@
+use crate::errors::{FireError, Result};
+use log::info;
+use oci::{LinuxNamespace, LinuxNamespaceType, LinuxSeccompAction, Spec};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// 网络模式：host 共享宿主机网络，none 使用独立的空网络 namespace
+pub const ANNOTATION_NETWORK_MODE: &str = "org.fire.network.mode";
+/// 容器输出的去向：inherit（默认）、file、json-file 或 none
+pub const ANNOTATION_LOG_DRIVER: &str = "org.fire.log.driver";
+/// file 和 json-file 日志驱动的文件路径，默认分别为容器状态目录下的 container.log 和
+/// <id>-json.log，后者与 Docker 的布局相同
+pub const ANNOTATION_LOG_PATH: &str = "org.fire.log.path";
+/// 覆盖 spec 中的 linux.rootfsPropagation
+pub const ANNOTATION_ROOTFS_PROPAGATION: &str = "org.fire.rootfs.propagation";
+/// 覆盖 seccomp 的默认动作，unconfined 表示不加载过滤器
+pub const ANNOTATION_SECCOMP_DEFAULT: &str = "org.fire.seccomp.default";
+/// 容器被 OOM kill 后的处理：none（默认）、mark 或 restart，只对前台 `fire run` 生效
+pub const ANNOTATION_OOM_POLICY: &str = "org.fire.oom.policy";
+/// restart 策略的最大重启次数，默认 3
+pub const ANNOTATION_OOM_MAX_RESTARTS: &str = "org.fire.oom.max-restarts";
+/// restart 策略第一次重启前等待的秒数，默认 1，之后每次加倍
+pub const ANNOTATION_OOM_RESTART_DELAY: &str = "org.fire.oom.restart-delay";
+/// exec 健康检查的命令，JSON 字符串数组，或者由 /bin/sh -c 执行的命令行
+pub const ANNOTATION_HEALTH_EXEC: &str = "org.fire.health.exec";
+/// tcp 健康检查在容器网络 namespace 中连接的端口，或者 `<ip>:<port>`
+pub const ANNOTATION_HEALTH_TCP: &str = "org.fire.health.tcp";
+/// 两次检查之间的秒数，默认 30
+pub const ANNOTATION_HEALTH_INTERVAL: &str = "org.fire.health.interval";
+/// 单次检查的超时秒数，默认 5
+pub const ANNOTATION_HEALTH_TIMEOUT: &str = "org.fire.health.timeout";
+/// 连续失败多少次后视为 unhealthy，默认 3
+pub const ANNOTATION_HEALTH_RETRIES: &str = "org.fire.health.retries";
+/// unhealthy 后的处理：none（默认，只记录状态）、kill 或 restart
+pub const ANNOTATION_HEALTH_ACTION: &str = "org.fire.health.action";
+/// restart 处理的最大重启次数，默认 3
+pub const ANNOTATION_HEALTH_MAX_RESTARTS: &str = "org.fire.health.max-restarts";
+/// restart 处理第一次重启前等待的秒数，默认 1，之后每次加倍
+pub const ANNOTATION_HEALTH_RESTART_DELAY: &str = "org.fire.health.restart-delay";
+
+const DEFAULT_MAX_RESTARTS: u32 = 3;
+const DEFAULT_RESTART_DELAY: u64 = 1;
+/// 重启等待时间的上限
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+const DEFAULT_HEALTH_INTERVAL: u64 = 30;
+const DEFAULT_HEALTH_TIMEOUT: u64 = 5;
+const DEFAULT_HEALTH_RETRIES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NetworkMode {
+    Host,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogDriver {
+    Inherit,
+    File,
+    /// Docker json-file 格式，每行一条 {"log","stream","time"} 记录
+    JsonFile,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeccompDefault {
+    Unconfined,
+    Action(LinuxSeccompAction),
+}
+
+/// 删除后按原配置重新创建并启动容器，等待时间每次加倍
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+    pub delay: Duration,
+}
+
+impl RestartPolicy {
+    fn from_annotations(
+        annotations: &HashMap<String, String>,
+        max_key: &str,
+        delay_key: &str,
+    ) -> Result<Self> {
+        Ok(Self {
+            max_restarts: parse_number(annotations, max_key, DEFAULT_MAX_RESTARTS)?,
+            delay: Duration::from_secs(parse_number(annotations, delay_key, DEFAULT_RESTART_DELAY)?),
+        })
+    }
+
+    /// 已经重启 restarts 次后，下一次重启前的等待时间，不再重启时返回 None
+    pub fn delay(&self, restarts: u32) -> Option<Duration> {
+        if restarts >= self.max_restarts {
+            return None;
+        }
+        let factor = 1u32.checked_shl(restarts).unwrap_or(u32::MAX);
+        Some(self.delay.saturating_mul(factor).min(MAX_RESTART_DELAY))
+    }
+}
+
+/// 容器被 OOM kill 后的处理
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OomPolicy {
+    None,
+    /// 在状态中记录 OOM kill 并保留容器，由编排系统处理
+    Mark,
+    /// 重启容器，超过次数后按 Mark 处理
+    Restart(RestartPolicy),
+}
+
+impl OomPolicy {
+    /// 已经重启 restarts 次后，下一次重启前的等待时间，不再重启时返回 None
+    pub fn restart_delay(&self, restarts: u32) -> Option<Duration> {
+        match self {
+            OomPolicy::Restart(policy) => policy.delay(restarts),
+            _ => None,
+        }
+    }
+}
+
+/// 健康检查探针
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthProbe {
+    /// 在容器中执行命令，退出码为 0 视为健康
+    Exec(Vec<String>),
+    /// 在容器的网络 namespace 中建立 TCP 连接
+    Tcp(SocketAddr),
+}
+
+/// 容器持续 unhealthy 时的处理
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthAction {
+    None,
+    Kill,
+    /// 终止后重启容器，超过次数后按 Kill 处理
+    Restart(RestartPolicy),
+}
+
+/// 由 org.fire.health.* 注解给出的健康检查，只对前台 `fire run` 生效
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthCheck {
+    pub probe: HealthProbe,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub action: HealthAction,
+}
+
+impl HealthCheck {
+    fn from_annotations(annotations: &HashMap<String, String>) -> Result<Option<Self>> {
+        let exec = annotation(annotations, ANNOTATION_HEALTH_EXEC);
+        let tcp = annotation(annotations, ANNOTATION_HEALTH_TCP);
+        let probe = match (exec, tcp) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => {
+                return Err(FireError::InvalidSpec(format!(
+                    "{} 和 {} 不能同时设置",
+                    ANNOTATION_HEALTH_EXEC, ANNOTATION_HEALTH_TCP
+                )));
+            }
+            (Some(command), None) if command.starts_with('[') => {
+                let args: Vec<String> =
+                    serde_json::from_str(command).map_err(|_| invalid(ANNOTATION_HEALTH_EXEC, command))?;
+                if args.is_empty() {
+                    return Err(invalid(ANNOTATION_HEALTH_EXEC, command));
+                }
+                HealthProbe::Exec(args)
+            }
+            (Some(command), None) => HealthProbe::Exec(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                command.to_string(),
+            ]),
+            (None, Some(target)) => {
+                let addr = match target.parse::<u16>() {
+                    Ok(port) => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+                    Err(_) => target.parse().map_err(|_| invalid(ANNOTATION_HEALTH_TCP, target))?,
+                };
+                HealthProbe::Tcp(addr)
+            }
+        };
+
+        let positive = |key: &str, value: u64| {
+            if value == 0 {
+                Err(invalid(key, "0"))
+            } else {
+                Ok(Duration::from_secs(value))
+            }
+        };
+        let interval = positive(
+            ANNOTATION_HEALTH_INTERVAL,
+            parse_number(annotations, ANNOTATION_HEALTH_INTERVAL, DEFAULT_HEALTH_INTERVAL)?,
+        )?;
+        let timeout = positive(
+            ANNOTATION_HEALTH_TIMEOUT,
+            parse_number(annotations, ANNOTATION_HEALTH_TIMEOUT, DEFAULT_HEALTH_TIMEOUT)?,
+        )?;
+        let retries = parse_number(annotations, ANNOTATION_HEALTH_RETRIES, DEFAULT_HEALTH_RETRIES)?.max(1);
+
+        let action = match annotation(annotations, ANNOTATION_HEALTH_ACTION) {
+            None | Some("none") => HealthAction::None,
+            Some("kill") => HealthAction::Kill,
+            Some("restart") => HealthAction::Restart(RestartPolicy::from_annotations(
+                annotations,
+                ANNOTATION_HEALTH_MAX_RESTARTS,
+                ANNOTATION_HEALTH_RESTART_DELAY,
+            )?),
+            Some(v) => return Err(invalid(ANNOTATION_HEALTH_ACTION, v)),
+        };
+
+        Ok(Some(Self {
+            probe,
+            interval,
+            timeout,
+            retries,
+            action,
+        }))
+    }
+}
+
+/// 由 org.fire.* 注解给出的单个容器的运行时选项
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeOptions {
+    pub network_mode: Option<NetworkMode>,
+    pub log_driver: Option<LogDriver>,
+    pub log_path: Option<PathBuf>,
+    pub rootfs_propagation: Option<String>,
+    pub seccomp_default: Option<SeccompDefault>,
+    pub oom_policy: Option<OomPolicy>,
+    pub health_check: Option<HealthCheck>,
+}
+
+impl RuntimeOptions {
+    /// 从容器注解中解析选项，取值无效时报错
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Result<Self> {
+        let get = |key: &str| annotation(annotations, key);
+
+        let network_mode = match get(ANNOTATION_NETWORK_MODE) {
+            None => None,
+            Some("host") => Some(NetworkMode::Host),
+            Some("none") => Some(NetworkMode::None),
+            Some(v) => return Err(invalid(ANNOTATION_NETWORK_MODE, v)),
+        };
+
+        let log_driver = match get(ANNOTATION_LOG_DRIVER) {
+            None => None,
+            Some("inherit") => Some(LogDriver::Inherit),
+            Some("file") => Some(LogDriver::File),
+            Some("json-file") => Some(LogDriver::JsonFile),
+            Some("none") => Some(LogDriver::None),
+            Some(v) => return Err(invalid(ANNOTATION_LOG_DRIVER, v)),
+        };
+        let log_path = get(ANNOTATION_LOG_PATH).map(PathBuf::from);
+        if let Some(ref path) = log_path {
+            if !path.is_absolute() {
+                return Err(invalid(ANNOTATION_LOG_PATH, &path.to_string_lossy()));
+            }
+        }
+
+        let rootfs_propagation = match get(ANNOTATION_ROOTFS_PROPAGATION) {
+            None => None,
+            Some(v @ ("shared" | "private" | "slave")) => Some(v.to_string()),
+            Some(v) => return Err(invalid(ANNOTATION_ROOTFS_PROPAGATION, v)),
+        };
+
+        let seccomp_default = match get(ANNOTATION_SECCOMP_DEFAULT) {
+            None => None,
+            Some("unconfined") => Some(SeccompDefault::Unconfined),
+            Some(v) => {
+                let action = serde_json::from_value(serde_json::Value::String(v.to_string()))
+                    .map_err(|_| invalid(ANNOTATION_SECCOMP_DEFAULT, v))?;
+                Some(SeccompDefault::Action(action))
+            }
+        };
+
+        let oom_policy = match get(ANNOTATION_OOM_POLICY) {
+            None => None,
+            Some("none") => Some(OomPolicy::None),
+            Some("mark") => Some(OomPolicy::Mark),
+            Some("restart") => Some(OomPolicy::Restart(RestartPolicy::from_annotations(
+                annotations,
+                ANNOTATION_OOM_MAX_RESTARTS,
+                ANNOTATION_OOM_RESTART_DELAY,
+            )?)),
+            Some(v) => return Err(invalid(ANNOTATION_OOM_POLICY, v)),
+        };
+        let health_check = HealthCheck::from_annotations(annotations)?;
+
+        Ok(Self {
+            network_mode,
+            log_driver,
+            log_path,
+            rootfs_propagation,
+            seccomp_default,
+            oom_policy,
+            health_check,
+        })
+    }
+
+    /// 将影响 spec 的选项写入 spec
+    pub fn apply(&self, spec: &mut Spec) {
+        if self.network_mode.is_none() && self.rootfs_propagation.is_none() && self.seccomp_default.is_none() {
+            return;
+        }
+        let linux = spec.linux.get_or_insert_with(Default::default);
+
+        match self.network_mode {
+            Some(NetworkMode::Host) => {
+                linux.namespaces.retain(|ns| ns.typ != LinuxNamespaceType::network);
+            }
+            Some(NetworkMode::None) => {
+                linux.namespaces.retain(|ns| ns.typ != LinuxNamespaceType::network);
+                linux.namespaces.push(LinuxNamespace {
+                    typ: LinuxNamespaceType::network,
+                    path: String::new(),
+                });
+            }
+            None => {}
+        }
+
+        if let Some(ref propagation) = self.rootfs_propagation {
+            linux.rootfs_propagation = propagation.clone();
+        }
+
+        match self.seccomp_default {
+            Some(SeccompDefault::Unconfined) => {
+                info!("注解指定不加载 seccomp 过滤器");
+                linux.seccomp = None;
+            }
+            Some(SeccompDefault::Action(action)) => {
+                if let Some(ref mut seccomp) = linux.seccomp {
+                    seccomp.default_action = action;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// 容器标准输出和标准错误重定向到的文件，沿用运行时的输出时返回 None
+    pub fn log_file(&self, container_dir: &Path) -> Option<PathBuf> {
+        match self.log_driver {
+            Some(LogDriver::File) => Some(
+                self.log_path
+                    .clone()
+                    .unwrap_or_else(|| container_dir.join("container.log")),
+            ),
+            Some(LogDriver::JsonFile) => Some(self.log_path.clone().unwrap_or_else(|| {
+                let id = container_dir.file_name().unwrap_or_default().to_string_lossy();
+                container_dir.join(format!("{}-json.log", id))
+            })),
+            Some(LogDriver::None) => Some(PathBuf::from("/dev/null")),
+            Some(LogDriver::Inherit) | None => None,
+        }
+    }
+}
+
+/// 去掉首尾空白后的注解值，未设置或为空时返回 None
+fn annotation<'a>(annotations: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    annotations.get(key).map(|v| v.trim()).filter(|v| !v.is_empty())
+}
+
+fn parse_number<T: FromStr>(annotations: &HashMap<String, String>, key: &str, default: T) -> Result<T> {
+    match annotation(annotations, key) {
+        None => Ok(default),
+        Some(v) => v.parse().map_err(|_| invalid(key, v)),
+    }
+}
+
+fn invalid(key: &str, value: &str) -> FireError {
+    FireError::InvalidSpec(format!("无效的注解 {}: {}", key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_annotations() {
+        let annotations: HashMap<String, String> = [
+            (ANNOTATION_NETWORK_MODE, "host"),
+            (ANNOTATION_LOG_DRIVER, "file"),
+            (ANNOTATION_SECCOMP_DEFAULT, "SCMP_ACT_LOG"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let options = RuntimeOptions::from_annotations(&annotations).unwrap();
+        assert_eq!(options.network_mode, Some(NetworkMode::Host));
+        assert_eq!(
+            options.seccomp_default,
+            Some(SeccompDefault::Action(LinuxSeccompAction::SCMP_ACT_LOG))
+        );
+        assert_eq!(
+            options.log_file(Path::new("/run/fire/c1")),
+            Some(PathBuf::from("/run/fire/c1/container.log"))
+        );
+
+        let json_file = RuntimeOptions {
+            log_driver: Some(LogDriver::JsonFile),
+            ..Default::default()
+        };
+        assert_eq!(
+            json_file.log_file(Path::new("/root/.fire/c1")),
+            Some(PathBuf::from("/root/.fire/c1/c1-json.log"))
+        );
+
+        let restart: HashMap<String, String> = [
+            (ANNOTATION_OOM_POLICY, "restart"),
+            (ANNOTATION_OOM_MAX_RESTARTS, "8"),
+            (ANNOTATION_OOM_RESTART_DELAY, "2"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let policy = RuntimeOptions::from_annotations(&restart).unwrap().oom_policy.unwrap();
+        let delays: Vec<_> = (0..9).map(|n| policy.restart_delay(n).map(|d| d.as_secs())).collect();
+        assert_eq!(
+            delays,
+            [2, 4, 8, 16, 32, 60, 60, 60].map(Some).into_iter().chain([None]).collect::<Vec<_>>()
+        );
+        assert_eq!(OomPolicy::Mark.restart_delay(0), None);
+
+        let invalid: HashMap<String, String> =
+            [(ANNOTATION_ROOTFS_PROPAGATION.to_string(), "rshared".to_string())].into_iter().collect();
+        assert!(RuntimeOptions::from_annotations(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_health_check() {
+        let parse = |pairs: &[(&str, &str)]| {
+            let annotations: HashMap<String, String> =
+                pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            RuntimeOptions::from_annotations(&annotations).map(|options| options.health_check)
+        };
+
+        let check = parse(&[(ANNOTATION_HEALTH_TCP, "8080"), (ANNOTATION_HEALTH_ACTION, "restart")])
+            .unwrap()
+            .unwrap();
+        assert_eq!(check.probe, HealthProbe::Tcp("127.0.0.1:8080".parse().unwrap()));
+        assert_eq!((check.interval.as_secs(), check.timeout.as_secs(), check.retries), (30, 5, 3));
+        assert!(matches!(check.action, HealthAction::Restart(p) if p.max_restarts == 3));
+
+        let check = parse(&[(ANNOTATION_HEALTH_EXEC, r#"["pg_isready", "-q"]"#)]).unwrap().unwrap();
+        assert_eq!(check.probe, HealthProbe::Exec(vec!["pg_isready".to_string(), "-q".to_string()]));
+        let check = parse(&[(ANNOTATION_HEALTH_EXEC, "test -f /ready")]).unwrap().unwrap();
+        assert_eq!(
+            check.probe,
+            HealthProbe::Exec(vec!["/bin/sh".into(), "-c".into(), "test -f /ready".into()])
+        );
+
+        assert_eq!(parse(&[(ANNOTATION_HEALTH_INTERVAL, "10")]).unwrap(), None);
+        assert!(parse(&[(ANNOTATION_HEALTH_TCP, "80"), (ANNOTATION_HEALTH_EXEC, "true")]).is_err());
+        assert!(parse(&[(ANNOTATION_HEALTH_TCP, "80"), (ANNOTATION_HEALTH_INTERVAL, "0")]).is_err());
+        assert!(parse(&[(ANNOTATION_HEALTH_EXEC, "[]")]).is_err());
+    }
+}
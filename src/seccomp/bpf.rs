@@ -0,0 +1,362 @@
+use super::{build_conditions, filter_flags, set_filter, syscalls, to_action, uses_notify, SCMP_ACT_KILL_THREAD};
+use crate::errors::*;
+use libc::{BPF_ABS, BPF_ALU, BPF_AND, BPF_JEQ, BPF_JGE, BPF_JGT, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+use log::warn;
+use oci::{LinuxSeccomp, LinuxSeccompArg, LinuxSeccompOperator};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::{FromRawFd, OwnedFd};
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc00000b7;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: u32 = 0;
+
+/// x32 ABI 的系统调用号带有该位
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x40000000;
+
+/// 内核允许的最大指令数
+const MAX_INSNS: usize = 4096;
+
+// struct seccomp_data 中各字段的偏移
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARGS_OFFSET: u32 = 16;
+
+/// 跳转目标，规则生成完毕后换算为相对偏移
+#[derive(Clone, Copy)]
+enum Target {
+    /// 下一条指令
+    Next,
+    /// 当前参数条件满足，继续检查下一个条件
+    Pass,
+    /// 条件不满足，跳到下一条规则
+    Fail,
+    /// 规则内的绝对位置
+    At(usize),
+}
+
+struct Insn {
+    code: u32,
+    jt: Target,
+    jf: Target,
+    k: u32,
+}
+
+fn stmt(code: u32, k: u32) -> Insn {
+    jump(code, k, Target::Next, Target::Next)
+}
+
+fn jump(code: u32, k: u32, jt: Target, jf: Target) -> Insn {
+    Insn { code, jt, jf, k }
+}
+
+fn load(offset: u32) -> Insn {
+    stmt(BPF_LD | BPF_W | BPF_ABS, offset)
+}
+
+/// 通过 seccomp(2) 加载纯 Rust 生成的过滤器
+///
+/// 使用 SCMP_ACT_NOTIFY 时带上 SECCOMP_FILTER_FLAG_NEW_LISTENER，
+/// 内核直接返回通知描述符。
+pub fn initialize(seccomp: &LinuxSeccomp) -> Result<Option<OwnedFd>> {
+    let program = compile(seccomp)?;
+    let mut flags = filter_flags(&seccomp.flags)?;
+    if uses_notify(seccomp) {
+        flags |= libc::SECCOMP_FILTER_FLAG_NEW_LISTENER;
+    }
+
+    let ret = set_filter(&program, flags)?;
+    if !uses_notify(seccomp) {
+        return Ok(None);
+    }
+    Ok(Some(unsafe { OwnedFd::from_raw_fd(ret as i32) }))
+}
+
+/// 将生成的 BPF 程序写入文件
+pub fn export(seccomp: &LinuxSeccomp, mut file: &File) -> Result<()> {
+    file.write_all(&compile(seccomp)?)?;
+    Ok(())
+}
+
+/// 生成 BPF 程序
+///
+/// 先校验架构，再按规则顺序比较调用号和参数，都不匹配时返回默认动作。
+/// 只支持本机架构，其他架构（包括 x32）的系统调用与 libseccomp 的
+/// BADARCH 默认处理一致，直接终止线程。
+fn compile(seccomp: &LinuxSeccomp) -> Result<Vec<u8>> {
+    if AUDIT_ARCH == 0 {
+        return Err(FireError::Generic(
+            "纯 Rust seccomp 后端不支持当前架构".to_string(),
+        ));
+    }
+    if seccomp.architectures.iter().any(|arch| *arch as u32 != AUDIT_ARCH) {
+        warn!("纯 Rust seccomp 后端只支持本机架构，其他架构的系统调用将被拒绝");
+    }
+
+    let mut program = vec![
+        load(ARCH_OFFSET),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, Target::At(3), Target::Next),
+        stmt(BPF_RET | BPF_K, SCMP_ACT_KILL_THREAD),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    program.extend([
+        load(NR_OFFSET),
+        jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, Target::Next, Target::At(6)),
+        stmt(BPF_RET | BPF_K, SCMP_ACT_KILL_THREAD),
+    ]);
+    let mut filter = resolve(program, usize::MAX)?;
+
+    for syscall in &seccomp.syscalls {
+        let action = to_action(syscall.action, syscall.errno_ret);
+        let conditions = build_conditions(&syscall.args)?;
+
+        // 旧版本的 spec 使用 name 字段
+        let names = syscall
+            .names
+            .iter()
+            .chain(std::iter::once(&syscall.name).filter(|n| !n.is_empty()));
+        for name in names {
+            let nr = match syscalls::resolve(name) {
+                Some(nr) => nr,
+                None => {
+                    warn!("unknown syscall: {}", name);
+                    continue;
+                }
+            };
+            for args in &conditions {
+                filter.extend(rule(nr as u32, args, action)?);
+            }
+        }
+    }
+
+    let default_action = to_action(seccomp.default_action, seccomp.default_errno_ret);
+    filter.extend(resolve(vec![stmt(BPF_RET | BPF_K, default_action)], usize::MAX)?);
+    if filter.len() > MAX_INSNS {
+        return Err(FireError::InvalidSpec(format!(
+            "seccomp 过滤器超过 {} 条指令: {}",
+            MAX_INSNS,
+            filter.len()
+        )));
+    }
+
+    let mut bytes = Vec::with_capacity(filter.len() * std::mem::size_of::<libc::sock_filter>());
+    for insn in &filter {
+        bytes.extend_from_slice(&insn.code.to_ne_bytes());
+        bytes.push(insn.jt);
+        bytes.push(insn.jf);
+        bytes.extend_from_slice(&insn.k.to_ne_bytes());
+    }
+    Ok(bytes)
+}
+
+/// 生成一条规则：调用号和全部参数条件都满足时返回 action
+fn rule(nr: u32, args: &[&LinuxSeccompArg], action: u32) -> Result<Vec<libc::sock_filter>> {
+    let mut insns = vec![
+        load(NR_OFFSET),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, nr, Target::Next, Target::Fail),
+    ];
+    for arg in args {
+        let cond = condition(arg);
+        let end = insns.len() + cond.len();
+        insns.extend(cond.into_iter().map(|mut insn| {
+            for target in [&mut insn.jt, &mut insn.jf] {
+                if let Target::Pass = target {
+                    *target = Target::At(end);
+                }
+            }
+            insn
+        }));
+    }
+    insns.push(stmt(BPF_RET | BPF_K, action));
+
+    let fail = insns.len();
+    resolve(insns, fail)
+}
+
+/// 将跳转目标换算为相对偏移
+fn resolve(insns: Vec<Insn>, fail: usize) -> Result<Vec<libc::sock_filter>> {
+    let offset = |index: usize, target: Target| -> Result<u8> {
+        let to = match target {
+            Target::Next => return Ok(0),
+            Target::At(to) => to,
+            Target::Fail => fail,
+            Target::Pass => unreachable!(),
+        };
+        u8::try_from(to - index - 1)
+            .map_err(|_| FireError::InvalidSpec("seccomp 规则过长".to_string()))
+    };
+    insns
+        .iter()
+        .enumerate()
+        .map(|(i, insn)| {
+            Ok(libc::sock_filter {
+                code: insn.code as u16,
+                jt: offset(i, insn.jt)?,
+                jf: offset(i, insn.jf)?,
+                k: insn.k,
+            })
+        })
+        .collect()
+}
+
+/// 参数低 32 位和高 32 位在 seccomp_data 中的偏移
+fn arg_offsets(index: usize) -> (u32, u32) {
+    let offset = ARGS_OFFSET + 8 * index as u32;
+    if cfg!(target_endian = "little") {
+        (offset, offset + 4)
+    } else {
+        (offset + 4, offset)
+    }
+}
+
+/// 生成单个参数条件，按无符号 64 位比较，先比较高 32 位
+fn condition(arg: &LinuxSeccompArg) -> Vec<Insn> {
+    use Target::{Fail, Next, Pass};
+    let (lo, hi) = arg_offsets(arg.index);
+    let (value_lo, value_hi) = (arg.value as u32, (arg.value >> 32) as u32);
+    let jeq = BPF_JMP | BPF_JEQ | BPF_K;
+    let jgt = BPF_JMP | BPF_JGT | BPF_K;
+    let jge = BPF_JMP | BPF_JGE | BPF_K;
+
+    match arg.op {
+        LinuxSeccompOperator::SCMP_CMP_EQ => vec![
+            load(hi),
+            jump(jeq, value_hi, Next, Fail),
+            load(lo),
+            jump(jeq, value_lo, Next, Fail),
+        ],
+        LinuxSeccompOperator::SCMP_CMP_NE => vec![
+            load(hi),
+            jump(jeq, value_hi, Next, Pass),
+            load(lo),
+            jump(jeq, value_lo, Fail, Next),
+        ],
+        LinuxSeccompOperator::SCMP_CMP_GT => vec![
+            load(hi),
+            jump(jgt, value_hi, Pass, Next),
+            jump(jeq, value_hi, Next, Fail),
+            load(lo),
+            jump(jgt, value_lo, Next, Fail),
+        ],
+        LinuxSeccompOperator::SCMP_CMP_GE => vec![
+            load(hi),
+            jump(jgt, value_hi, Pass, Next),
+            jump(jeq, value_hi, Next, Fail),
+            load(lo),
+            jump(jge, value_lo, Next, Fail),
+        ],
+        LinuxSeccompOperator::SCMP_CMP_LT => vec![
+            load(hi),
+            jump(jgt, value_hi, Fail, Next),
+            jump(jeq, value_hi, Next, Pass),
+            load(lo),
+            jump(jge, value_lo, Fail, Next),
+        ],
+        LinuxSeccompOperator::SCMP_CMP_LE => vec![
+            load(hi),
+            jump(jgt, value_hi, Fail, Next),
+            jump(jeq, value_hi, Next, Pass),
+            load(lo),
+            jump(jgt, value_lo, Fail, Next),
+        ],
+        // value 为掩码，valueTwo 为比较值
+        LinuxSeccompOperator::SCMP_CMP_MASKED_EQ => {
+            let (datum_lo, datum_hi) = (arg.value_two as u32, (arg.value_two >> 32) as u32);
+            vec![
+                load(hi),
+                stmt(BPF_ALU | BPF_AND | BPF_K, value_hi),
+                jump(jeq, datum_hi, Next, Fail),
+                load(lo),
+                stmt(BPF_ALU | BPF_AND | BPF_K, value_lo),
+                jump(jeq, datum_lo, Next, Fail),
+            ]
+        }
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+
+    /// 按 seccomp_data 解释执行生成的程序，返回动作
+    fn run(program: &[u8], arch: u32, nr: i64, args: [u64; 6]) -> u32 {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(nr as i32).to_ne_bytes());
+        data.extend_from_slice(&arch.to_ne_bytes());
+        data.extend_from_slice(&0u64.to_ne_bytes());
+        for arg in args {
+            data.extend_from_slice(&arg.to_ne_bytes());
+        }
+
+        let (mut pc, mut a) = (0usize, 0u32);
+        loop {
+            let insn = &program[pc * 8..pc * 8 + 8];
+            let code = u16::from_ne_bytes([insn[0], insn[1]]) as u32;
+            let (jt, jf) = (insn[2] as usize, insn[3] as usize);
+            let k = u32::from_ne_bytes([insn[4], insn[5], insn[6], insn[7]]);
+            pc += 1;
+            match code {
+                c if c == BPF_LD | BPF_W | BPF_ABS => {
+                    let k = k as usize;
+                    a = u32::from_ne_bytes(data[k..k + 4].try_into().unwrap());
+                }
+                c if c == BPF_ALU | BPF_AND | BPF_K => a &= k,
+                c if c == BPF_RET | BPF_K => return k,
+                c => {
+                    let taken = match c & 0xf0 {
+                        op if op == BPF_JEQ => a == k,
+                        op if op == BPF_JGT => a > k,
+                        op if op == BPF_JGE => a >= k,
+                        _ => panic!("unexpected instruction {:#x}", c),
+                    };
+                    pc += if taken { jt } else { jf };
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        let seccomp: LinuxSeccomp = serde_json::from_str(
+            r#"{"defaultAction":"SCMP_ACT_ERRNO","syscalls":[
+                {"names":["read","write"],"action":"SCMP_ACT_ALLOW"},
+                {"names":["personality"],"action":"SCMP_ACT_ALLOW",
+                 "args":[{"index":0,"value":4294967295,"op":"SCMP_CMP_LE"}]},
+                {"names":["clone"],"action":"SCMP_ACT_ALLOW",
+                 "args":[{"index":0,"value":2114060288,"valueTwo":0,"op":"SCMP_CMP_MASKED_EQ"}]}
+            ]}"#,
+        )
+        .unwrap();
+        let program = compile(&seccomp).unwrap();
+        let errno = 0x00050000 | libc::EPERM as u32;
+        let allow = 0x7fff0000;
+
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_read, [0; 6]), allow);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_write, [0; 6]), allow);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_open, [0; 6]), errno);
+        assert_eq!(run(&program, 0x40000003, libc::SYS_read, [0; 6]), SCMP_ACT_KILL_THREAD);
+        assert_eq!(run(&program, AUDIT_ARCH, 0x40000000, [0; 6]), SCMP_ACT_KILL_THREAD);
+
+        let personality = |arg| run(&program, AUDIT_ARCH, libc::SYS_personality, [arg, 0, 0, 0, 0, 0]);
+        assert_eq!(personality(0xffffffff), allow);
+        assert_eq!(personality(8), allow);
+        assert_eq!(personality(0x1_0000_0000), errno);
+
+        let clone = |arg| run(&program, AUDIT_ARCH, libc::SYS_clone, [arg, 0, 0, 0, 0, 0]);
+        assert_eq!(clone(libc::SIGCHLD as u64), allow);
+        assert_eq!(clone(libc::CLONE_NEWUSER as u64), errno);
+    }
+
+    #[test]
+    fn test_compile_without_rules() {
+        let seccomp: LinuxSeccomp = serde_json::from_str(r#"{"defaultAction":"SCMP_ACT_ERRNO"}"#).unwrap();
+        let program = compile(&seccomp).unwrap();
+        let errno = 0x00050000 | libc::EPERM as u32;
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_read, [0; 6]), errno);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_getpid, [0; 6]), errno);
+    }
+}
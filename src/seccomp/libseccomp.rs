@@ -0,0 +1,233 @@
+use super::{build_conditions, to_action, uses_notify};
+use crate::errors::*;
+use caps::{CapSet, Capability};
+use log::warn;
+use oci::{Arch, LinuxSeccomp, LinuxSeccompArg, LinuxSeccompOperator, LinuxSyscall};
+use seccomp_sys::*;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+extern "C" {
+    // libseccomp 2.5 提供，seccomp-sys 未声明
+    fn seccomp_notify_fd(ctx: *const scmp_filter_ctx) -> libc::c_int;
+    // seccomp-sys 的 scmp_filter_attr 缺少较新的属性，按整数传入
+    #[link_name = "seccomp_attr_set"]
+    fn seccomp_attr_set_raw(ctx: *mut scmp_filter_ctx, attr: libc::c_int, value: u32) -> libc::c_int;
+}
+
+// libseccomp 过滤器属性取值
+const SCMP_FLTATR_CTL_NNP: libc::c_int = 3;
+const SCMP_FLTATR_CTL_TSYNC: libc::c_int = 4;
+const SCMP_FLTATR_CTL_LOG: libc::c_int = 6;
+const SCMP_FLTATR_CTL_SSB: libc::c_int = 7;
+
+fn init(act: u32) -> Result<*mut scmp_filter_ctx> {
+    let ctx = unsafe { seccomp_init(act) };
+    if ctx.is_null() {
+        return Err(crate::errors::FireError::Generic(
+            "failed to initialize seccomp".to_string(),
+        ));
+    }
+    Ok(ctx)
+}
+
+/// 通过 libseccomp 编译并加载过滤器
+pub fn initialize(seccomp: &LinuxSeccomp) -> Result<Option<OwnedFd>> {
+    compile(seccomp, |ctx| {
+        load(ctx)?;
+
+        if !uses_notify(seccomp) {
+            return Ok(None);
+        }
+        let fd = unsafe { seccomp_notify_fd(ctx) };
+        if fd < 0 {
+            return Err(FireError::Generic(format!(
+                "failed to get seccomp notify fd: {}",
+                std::io::Error::from_raw_os_error(-fd)
+            )));
+        }
+        // 释放过滤器上下文时不会关闭通知描述符
+        Ok(Some(unsafe { OwnedFd::from_raw_fd(fd) }))
+    })
+}
+
+/// 将编译后的 BPF 程序写入文件
+pub fn export(seccomp: &LinuxSeccomp, file: &File) -> Result<()> {
+    compile(seccomp, |ctx| {
+        let ret = unsafe { seccomp_export_bpf(ctx, file.as_raw_fd()) };
+        if ret != 0 {
+            return Err(FireError::Generic(format!(
+                "failed to export seccomp filter: {}",
+                std::io::Error::from_raw_os_error(-ret)
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// 按 spec 构建过滤器，在 f 返回后释放
+fn compile<T, F>(seccomp: &LinuxSeccomp, f: F) -> Result<T>
+where
+    F: FnOnce(*mut scmp_filter_ctx) -> Result<T>,
+{
+    let default_action = to_action(seccomp.default_action, seccomp.default_errno_ret);
+    let ctx = init(default_action)?;
+    let ctx = scopeguard::guard(ctx, |ctx| unsafe { seccomp_release(ctx) });
+
+    add_architectures(*ctx, &seccomp.architectures)?;
+    set_flags(*ctx, &seccomp.flags)?;
+    set_no_new_privs(*ctx)?;
+
+    for syscall in &seccomp.syscalls {
+        add_syscall_rule(*ctx, syscall)?;
+    }
+
+    f(*ctx)
+}
+
+/// 将 spec 中的架构加入过滤器
+///
+/// 过滤器默认只包含本机架构，其他 ABI（如 x86_64 上的 x86 和 x32）的
+/// 系统调用会被直接按 BADARCH 处理；加入后规则会按各架构的调用号生效。
+fn add_architectures(ctx: *mut scmp_filter_ctx, architectures: &[Arch]) -> Result<()> {
+    for arch in architectures {
+        let ret = unsafe { seccomp_arch_add(ctx, *arch as u32) };
+        // 本机架构已在过滤器中
+        if ret != 0 && ret != -libc::EEXIST {
+            return Err(crate::errors::FireError::Generic(format!(
+                "failed to add seccomp architecture {:?}: {}",
+                arch,
+                std::io::Error::from_raw_os_error(-ret)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// libseccomp 默认在加载过滤器时设置 no_new_privs
+///
+/// 仍持有 CAP_SYS_ADMIN 时不需要 no_new_privs 也能加载，关闭该行为，
+/// 是否设置由 spec 的 noNewPrivileges 决定。
+fn set_no_new_privs(ctx: *mut scmp_filter_ctx) -> Result<()> {
+    if !caps::has_cap(None, CapSet::Effective, Capability::CAP_SYS_ADMIN).unwrap_or(false) {
+        return Ok(());
+    }
+    let ret = unsafe { seccomp_attr_set_raw(ctx, SCMP_FLTATR_CTL_NNP, 0) };
+    if ret != 0 {
+        return Err(FireError::Generic(format!(
+            "failed to set seccomp no_new_privs attribute: {}",
+            std::io::Error::from_raw_os_error(-ret)
+        )));
+    }
+    Ok(())
+}
+
+/// 将 spec 中的过滤器标志转换为 libseccomp 属性
+fn flag_attribute(flag: &str) -> Result<libc::c_int> {
+    match flag {
+        // 同步到进程内的所有线程
+        "SECCOMP_FILTER_FLAG_TSYNC" => Ok(SCMP_FLTATR_CTL_TSYNC),
+        // 记录除 ALLOW 以外的动作
+        "SECCOMP_FILTER_FLAG_LOG" => Ok(SCMP_FLTATR_CTL_LOG),
+        // 不启用推测执行缓解（SSB）
+        "SECCOMP_FILTER_FLAG_SPEC_ALLOW" => Ok(SCMP_FLTATR_CTL_SSB),
+        _ => Err(FireError::InvalidSpec(format!(
+            "不支持的 seccomp 标志: {}",
+            flag
+        ))),
+    }
+}
+
+fn set_flags(ctx: *mut scmp_filter_ctx, flags: &[String]) -> Result<()> {
+    for flag in flags {
+        let ret = unsafe { seccomp_attr_set_raw(ctx, flag_attribute(flag)?, 1) };
+        if ret != 0 {
+            return Err(FireError::Generic(format!(
+                "failed to set seccomp flag {}: {}",
+                flag,
+                std::io::Error::from_raw_os_error(-ret)
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn add_syscall_rule(ctx: *mut scmp_filter_ctx, syscall: &LinuxSyscall) -> Result<()> {
+    let action = to_action(syscall.action, syscall.errno_ret);
+
+    let conditions: Vec<Vec<scmp_arg_cmp>> = build_conditions(&syscall.args)?
+        .into_iter()
+        .map(|args| args.into_iter().map(to_cmp).collect())
+        .collect();
+
+    // 旧版本的 spec 使用 name 字段
+    let names = syscall
+        .names
+        .iter()
+        .chain(std::iter::once(&syscall.name).filter(|n| !n.is_empty()));
+    for name in names {
+        let name_cstr = std::ffi::CString::new(name.as_str()).map_err(|e| {
+            crate::errors::FireError::Generic(format!("Invalid syscall name: {}", e))
+        })?;
+        let syscall_nr = unsafe { seccomp_syscall_resolve_name(name_cstr.as_ptr()) };
+        if syscall_nr == __NR_SCMP_ERROR {
+            warn!("unknown syscall: {}", name);
+            continue;
+        }
+
+        for cmps in &conditions {
+            let ret = unsafe {
+                seccomp_rule_add_array(ctx, action, syscall_nr, cmps.len() as u32, cmps.as_ptr())
+            };
+            if ret != 0 {
+                return Err(crate::errors::FireError::Generic(format!(
+                    "failed to add syscall rule for {}",
+                    name
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 转换为 libseccomp 的参数比较
+fn to_cmp(arg: &LinuxSeccompArg) -> scmp_arg_cmp {
+    let op = match arg.op {
+        LinuxSeccompOperator::SCMP_CMP_NE => scmp_compare::SCMP_CMP_NE,
+        LinuxSeccompOperator::SCMP_CMP_LT => scmp_compare::SCMP_CMP_LT,
+        LinuxSeccompOperator::SCMP_CMP_LE => scmp_compare::SCMP_CMP_LE,
+        LinuxSeccompOperator::SCMP_CMP_EQ => scmp_compare::SCMP_CMP_EQ,
+        LinuxSeccompOperator::SCMP_CMP_GE => scmp_compare::SCMP_CMP_GE,
+        LinuxSeccompOperator::SCMP_CMP_GT => scmp_compare::SCMP_CMP_GT,
+        LinuxSeccompOperator::SCMP_CMP_MASKED_EQ => scmp_compare::SCMP_CMP_MASKED_EQ,
+    };
+    scmp_arg_cmp {
+        arg: arg.index as u32,
+        op,
+        datum_a: arg.value,
+        datum_b: arg.value_two,
+    }
+}
+
+fn load(ctx: *mut scmp_filter_ctx) -> Result<()> {
+    let ret = unsafe { seccomp_load(ctx) };
+    if ret != 0 {
+        return Err(crate::errors::FireError::Generic(
+            "failed to load seccomp filter".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_attribute() {
+        assert_eq!(flag_attribute("SECCOMP_FILTER_FLAG_TSYNC").unwrap(), SCMP_FLTATR_CTL_TSYNC);
+        assert_eq!(flag_attribute("SECCOMP_FILTER_FLAG_SPEC_ALLOW").unwrap(), SCMP_FLTATR_CTL_SSB);
+        assert!(flag_attribute("SECCOMP_FILTER_FLAG_NEW_LISTENER").is_err());
+    }
+}
@@ -0,0 +1,446 @@
+use crate::errors::*;
+use crate::nix_ext;
+use caps::{CapSet, Capability};
+use log::{debug, info, warn};
+use oci::{LinuxSeccomp, LinuxSeccompAction, LinuxSeccompArg};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+// 默认通过 libseccomp 编译过滤器；关闭 libseccomp 特性时使用纯 Rust 生成
+// BPF 程序，静态链接的 musl 构建不再依赖 libseccomp
+#[cfg(any(not(feature = "libseccomp"), test))]
+#[cfg_attr(feature = "libseccomp", allow(dead_code))]
+mod bpf;
+#[cfg(feature = "libseccomp")]
+mod libseccomp;
+#[cfg(any(not(feature = "libseccomp"), test))]
+mod syscalls;
+
+#[cfg(not(feature = "libseccomp"))]
+use bpf as backend;
+#[cfg(feature = "libseccomp")]
+use libseccomp as backend;
+
+/// 设置为 "true" 时将编译后的过滤器导出到容器目录，相同配置复用缓存
+pub const ANNOTATION_SECCOMP_EXPORT: &str = "org.fire.seccomp.export";
+
+/// 容器目录中导出的 BPF 程序
+const EXPORT_FILE: &str = "seccomp.bpf";
+/// 缓存目录，位于 `$XDG_CACHE_HOME`（默认 `$HOME/.cache`）下，不与容器目录混在一起
+const CACHE_DIR: &str = "fire/seccomp";
+
+// 过滤器动作，取值与内核的 SECCOMP_RET_* 一致
+const SCMP_ACT_KILL_PROCESS: u32 = 0x80000000;
+const SCMP_ACT_KILL_THREAD: u32 = 0x00000000;
+const SCMP_ACT_TRAP: u32 = 0x00030000;
+const SCMP_ACT_ERRNO: u32 = 0x00050000;
+const SCMP_ACT_TRACE: u32 = 0x7ff00000;
+const SCMP_ACT_LOG: u32 = 0x7ffc0000;
+const SCMP_ACT_ALLOW: u32 = 0x7fff0000;
+const SCMP_ACT_NOTIFY: u32 = 0x7fc00000;
+
+/// 发送给 seccomp 监听程序的容器进程状态，与 OCI 运行时规范一致
+#[derive(Serialize)]
+struct ContainerProcessState<'a> {
+    #[serde(rename = "ociVersion")]
+    oci_version: &'a str,
+    fds: Vec<&'a str>,
+    pid: i32,
+    metadata: &'a str,
+    state: &'a oci::State,
+}
+
+/// 规则中是否使用了 SCMP_ACT_NOTIFY
+pub fn uses_notify(seccomp: &LinuxSeccomp) -> bool {
+    seccomp
+        .syscalls
+        .iter()
+        .any(|s| s.action == LinuxSeccompAction::SCMP_ACT_NOTIFY)
+}
+
+fn validate_notify(seccomp: &LinuxSeccomp) -> Result<()> {
+    if seccomp.default_action == LinuxSeccompAction::SCMP_ACT_NOTIFY {
+        return Err(FireError::InvalidSpec(
+            "SCMP_ACT_NOTIFY 不能作为默认动作".to_string(),
+        ));
+    }
+    if uses_notify(seccomp) && seccomp.listener_path.is_empty() {
+        return Err(FireError::InvalidSpec(
+            "使用 SCMP_ACT_NOTIFY 时必须设置 listenerPath".to_string(),
+        ));
+    }
+    if !uses_notify(seccomp) && !seccomp.listener_path.is_empty() {
+        warn!("设置了 seccomp listenerPath 但没有规则使用 SCMP_ACT_NOTIFY");
+    }
+    Ok(())
+}
+
+/// 检查内核是否支持配置中用到的动作，如 4.14 之前的内核没有 SCMP_ACT_LOG
+pub fn check_kernel_support(seccomp: &LinuxSeccomp) -> Result<()> {
+    let info = crate::sysinfo::get();
+    let actions = std::iter::once(seccomp.default_action).chain(seccomp.syscalls.iter().map(|s| s.action));
+    for action in actions {
+        if !info.has_seccomp_action(kernel_action_name(action)) {
+            return Err(FireError::KernelUnsupported(format!("内核不支持 seccomp 动作 {:?}", action)));
+        }
+    }
+    Ok(())
+}
+
+/// 动作在 /proc/sys/kernel/seccomp/actions_avail 中的名称
+fn kernel_action_name(action: LinuxSeccompAction) -> &'static str {
+    match action {
+        LinuxSeccompAction::SCMP_ACT_KILL | LinuxSeccompAction::SCMP_ACT_KILL_THREAD => "kill_thread",
+        LinuxSeccompAction::SCMP_ACT_KILL_PROCESS => "kill_process",
+        LinuxSeccompAction::SCMP_ACT_TRAP => "trap",
+        LinuxSeccompAction::SCMP_ACT_ERRNO => "errno",
+        LinuxSeccompAction::SCMP_ACT_TRACE => "trace",
+        LinuxSeccompAction::SCMP_ACT_ALLOW => "allow",
+        LinuxSeccompAction::SCMP_ACT_LOG => "log",
+        LinuxSeccompAction::SCMP_ACT_NOTIFY => "user_notif",
+    }
+}
+
+/// 加载 seccomp 过滤器
+///
+/// 规则中使用 SCMP_ACT_NOTIFY 时返回用户态通知的文件描述符，
+/// 需要通过 [`send_to_listener`] 交给 listenerPath 上的监听程序。
+pub fn initialize_seccomp(seccomp: &LinuxSeccomp) -> Result<Option<OwnedFd>> {
+    validate_notify(seccomp)?;
+    if !needs_filter(seccomp) {
+        return Ok(None);
+    }
+
+    backend::initialize(seccomp)
+}
+
+/// 没有规则且默认放行时过滤器不起作用，不需要加载
+///
+/// 只有 defaultAction 的配置（如默认 SCMP_ACT_ERRNO）同样需要加载过滤器。
+fn needs_filter(seccomp: &LinuxSeccomp) -> bool {
+    !seccomp.syscalls.is_empty()
+        || to_action(seccomp.default_action, seccomp.default_errno_ret) != SCMP_ACT_ALLOW
+}
+
+/// 是否通过注解开启了过滤器导出
+pub fn export_enabled(annotations: &HashMap<String, String>) -> bool {
+    annotations
+        .get(ANNOTATION_SECCOMP_EXPORT)
+        .is_some_and(|v| v == "true")
+}
+
+/// 将编译后的 BPF 程序导出到容器目录并返回程序内容
+///
+/// 相同的配置直接复用缓存，省去大型配置的编译开销。使用 SCMP_ACT_NOTIFY
+/// 时需要在加载时取得通知描述符，返回 None。
+pub fn export_program(seccomp: &LinuxSeccomp, container_dir: &Path) -> Result<Option<Vec<u8>>> {
+    validate_notify(seccomp)?;
+    if !needs_filter(seccomp) || uses_notify(seccomp) {
+        return Ok(None);
+    }
+
+    let export_path = container_dir.join(EXPORT_FILE);
+    let cache_dir = cache_dir();
+    let cache_path = cache_dir.join(format!("{}.bpf", profile_hash(seccomp)?));
+
+    if let Ok(program) = fs::read(&cache_path) {
+        debug!("使用缓存的 seccomp 过滤器: {}", cache_path.display());
+        fs::write(&export_path, &program)?;
+        return Ok(Some(program));
+    }
+
+    backend::export(seccomp, &fs::File::create(&export_path)?)?;
+    let program = fs::read(&export_path)?;
+    info!("已导出 seccomp 过滤器: {}", export_path.display());
+
+    // 缓存写入失败不影响容器启动
+    if let Err(e) = write_cache(&cache_dir, &cache_path, &program) {
+        warn!("写入 seccomp 过滤器缓存失败: {}", e);
+    }
+    Ok(Some(program))
+}
+
+/// 编译后的过滤器的缓存目录
+fn cache_dir() -> PathBuf {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string())).join(".cache"),
+    };
+    base.join(CACHE_DIR)
+}
+
+/// 配置内容的 SHA-256，作为缓存的键
+///
+/// 对键排序后的 JSON 计算，结果不随 Rust 版本变化，也不用担心 64 位哈希的碰撞。
+fn profile_hash(seccomp: &LinuxSeccomp) -> Result<String> {
+    let canonical = serde_json::to_value(seccomp)?.to_string();
+    Ok(format!("{:x}", Sha256::digest(canonical.as_bytes())))
+}
+
+fn write_cache(cache_dir: &Path, cache_path: &Path, program: &[u8]) -> Result<()> {
+    fs::create_dir_all(cache_dir)?;
+    // 先写临时文件再重命名，避免并发启动读到不完整的程序
+    let tmp = cache_path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp, program)?;
+    fs::rename(&tmp, cache_path)?;
+    Ok(())
+}
+
+/// 直接加载导出的 BPF 程序
+pub fn load_program(program: &[u8], flags: &[String]) -> Result<()> {
+    set_filter(program, filter_flags(flags)?)?;
+    Ok(())
+}
+
+/// 将 spec 中的过滤器标志转换为 seccomp(2) 的标志
+fn filter_flags(flags: &[String]) -> Result<libc::c_ulong> {
+    let mut filter_flags = 0;
+    for flag in flags {
+        filter_flags |= match flag.as_str() {
+            "SECCOMP_FILTER_FLAG_TSYNC" => libc::SECCOMP_FILTER_FLAG_TSYNC,
+            "SECCOMP_FILTER_FLAG_LOG" => libc::SECCOMP_FILTER_FLAG_LOG,
+            "SECCOMP_FILTER_FLAG_SPEC_ALLOW" => libc::SECCOMP_FILTER_FLAG_SPEC_ALLOW,
+            _ => {
+                return Err(FireError::InvalidSpec(format!(
+                    "不支持的 seccomp 标志: {}",
+                    flag
+                )))
+            }
+        };
+    }
+    Ok(filter_flags)
+}
+
+/// 通过 seccomp(2) 加载 BPF 程序，返回系统调用的返回值
+///
+/// 与 libseccomp 的默认行为一致，没有 CAP_SYS_ADMIN 时先设置 no_new_privs。
+fn set_filter(program: &[u8], flags: libc::c_ulong) -> Result<libc::c_long> {
+    let insn = std::mem::size_of::<libc::sock_filter>();
+    if program.is_empty() || !program.len().is_multiple_of(insn) || program.len() / insn > u16::MAX as usize {
+        return Err(FireError::Generic(format!(
+            "无效的 seccomp 程序长度: {}",
+            program.len()
+        )));
+    }
+
+    if !caps::has_cap(None, CapSet::Effective, Capability::CAP_SYS_ADMIN).unwrap_or(false) {
+        nix_ext::set_no_new_privs()?;
+    }
+
+    let prog = libc::sock_fprog {
+        len: (program.len() / insn) as u16,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            flags,
+            &prog as *const libc::sock_fprog,
+        )
+    };
+    if ret < 0 {
+        return Err(FireError::Generic(format!(
+            "failed to load seccomp program: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    Ok(ret)
+}
+
+/// 通过 SCM_RIGHTS 将通知描述符连同容器进程状态发送给监听程序
+pub fn send_to_listener(
+    seccomp: &LinuxSeccomp,
+    notify_fd: &OwnedFd,
+    pid: i32,
+    state: &oci::State,
+) -> Result<()> {
+    let stream = UnixStream::connect(&seccomp.listener_path).map_err(|e| {
+        FireError::Generic(format!(
+            "连接 seccomp 监听程序 {} 失败: {}",
+            seccomp.listener_path, e
+        ))
+    })?;
+
+    let payload = serde_json::to_vec(&ContainerProcessState {
+        oci_version: &state.version,
+        fds: vec!["seccompFd"],
+        pid,
+        metadata: &seccomp.listener_metadata,
+        state,
+    })?;
+    send_fd(stream.as_raw_fd(), notify_fd.as_raw_fd(), &payload)?;
+
+    info!("已将 seccomp 通知描述符发送给 {}", seccomp.listener_path);
+    Ok(())
+}
+
+fn send_fd(socket: RawFd, fd: RawFd, payload: &[u8]) -> Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+        if libc::sendmsg(socket, &msg, 0) < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
+
+/// 转换为过滤器动作，ERRNO 和 TRACE 未指定 errnoRet 时返回 EPERM
+fn to_action(action: LinuxSeccompAction, errno_ret: Option<u32>) -> u32 {
+    let errno = errno_ret.unwrap_or(libc::EPERM as u32);
+    match action {
+        LinuxSeccompAction::SCMP_ACT_KILL => SCMP_ACT_KILL_THREAD,
+        LinuxSeccompAction::SCMP_ACT_KILL_PROCESS => SCMP_ACT_KILL_PROCESS,
+        LinuxSeccompAction::SCMP_ACT_KILL_THREAD => SCMP_ACT_KILL_THREAD,
+        LinuxSeccompAction::SCMP_ACT_TRAP => SCMP_ACT_TRAP,
+        LinuxSeccompAction::SCMP_ACT_ERRNO => SCMP_ACT_ERRNO | (errno & 0xffff),
+        LinuxSeccompAction::SCMP_ACT_TRACE => SCMP_ACT_TRACE | (errno & 0xffff),
+        LinuxSeccompAction::SCMP_ACT_ALLOW => SCMP_ACT_ALLOW,
+        LinuxSeccompAction::SCMP_ACT_LOG => SCMP_ACT_LOG,
+        LinuxSeccompAction::SCMP_ACT_NOTIFY => SCMP_ACT_NOTIFY,
+    }
+}
+
+/// 将参数条件分组为规则
+///
+/// 同一条规则内不同参数的条件同时满足才匹配；libseccomp 不允许同一参数
+/// 出现多个条件，这种情况下每个条件单独生成一条规则（任一满足即匹配），
+/// 与其他运行时的行为一致。
+fn build_conditions(args: &[LinuxSeccompArg]) -> Result<Vec<Vec<&LinuxSeccompArg>>> {
+    let mut cmps = Vec::new();
+    for arg in args {
+        if arg.index >= 6 {
+            return Err(crate::errors::FireError::InvalidSpec(format!(
+                "seccomp 参数索引超出范围: {}",
+                arg.index
+            )));
+        }
+        cmps.push(arg);
+    }
+
+    let repeated = cmps
+        .iter()
+        .enumerate()
+        .any(|(i, a)| cmps[..i].iter().any(|b| b.index == a.index));
+    if repeated {
+        Ok(cmps.into_iter().map(|cmp| vec![cmp]).collect())
+    } else {
+        Ok(vec![cmps])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci::LinuxSeccompOperator;
+
+    fn arg(index: usize, value: u64, op: LinuxSeccompOperator) -> LinuxSeccompArg {
+        LinuxSeccompArg {
+            index,
+            value,
+            value_two: 0,
+            op,
+        }
+    }
+
+    #[test]
+    fn test_build_conditions() {
+        assert_eq!(build_conditions(&[]).unwrap().len(), 1);
+
+        let args = [
+            arg(0, 0, LinuxSeccompOperator::SCMP_CMP_EQ),
+            arg(1, 8, LinuxSeccompOperator::SCMP_CMP_LT),
+        ];
+        let and = build_conditions(&args).unwrap();
+        assert_eq!(and.len(), 1);
+        assert_eq!(and[0].len(), 2);
+        assert_eq!((and[0][1].index, and[0][1].value), (1, 8));
+
+        let args = [
+            arg(0, 0, LinuxSeccompOperator::SCMP_CMP_EQ),
+            arg(0, 8, LinuxSeccompOperator::SCMP_CMP_EQ),
+        ];
+        let or = build_conditions(&args).unwrap();
+        assert_eq!(or.len(), 2);
+
+        assert!(build_conditions(&[arg(6, 0, LinuxSeccompOperator::SCMP_CMP_EQ)]).is_err());
+    }
+
+    #[test]
+    fn test_to_action_errno() {
+        assert_eq!(to_action(LinuxSeccompAction::SCMP_ACT_ERRNO, None), 0x00050001);
+        assert_eq!(
+            to_action(LinuxSeccompAction::SCMP_ACT_ERRNO, Some(libc::ENOSYS as u32)),
+            0x00050000 | libc::ENOSYS as u32
+        );
+        assert_eq!(to_action(LinuxSeccompAction::SCMP_ACT_ALLOW, Some(38)), SCMP_ACT_ALLOW);
+    }
+
+    #[test]
+    fn test_parse_modern_actions() {
+        let seccomp: LinuxSeccomp = serde_json::from_str(
+            r#"{"defaultAction":"SCMP_ACT_KILL_PROCESS","syscalls":[{"names":["ptrace"],"action":"SCMP_ACT_LOG"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(to_action(seccomp.default_action, None), 0x80000000);
+        assert_eq!(to_action(seccomp.syscalls[0].action, None), 0x7ffc0000);
+    }
+
+    #[test]
+    fn test_profile_hash() {
+        let parse = |json: &str| serde_json::from_str::<LinuxSeccomp>(json).unwrap();
+        let a = parse(r#"{"defaultAction":"SCMP_ACT_ERRNO","syscalls":[{"names":["read"],"action":"SCMP_ACT_ALLOW"}]}"#);
+        let b = parse(r#"{"syscalls":[{"action":"SCMP_ACT_ALLOW","names":["read"]}],"defaultAction":"SCMP_ACT_ERRNO"}"#);
+        let c = parse(r#"{"defaultAction":"SCMP_ACT_ERRNO","syscalls":[{"names":["write"],"action":"SCMP_ACT_ALLOW"}]}"#);
+        assert_eq!(profile_hash(&a).unwrap(), profile_hash(&b).unwrap());
+        assert_ne!(profile_hash(&a).unwrap(), profile_hash(&c).unwrap());
+    }
+
+    #[test]
+    fn test_needs_filter() {
+        let parse = |json: &str| serde_json::from_str::<LinuxSeccomp>(json).unwrap();
+        assert!(!needs_filter(&parse(r#"{"defaultAction":"SCMP_ACT_ALLOW"}"#)));
+        assert!(needs_filter(&parse(r#"{"defaultAction":"SCMP_ACT_ERRNO"}"#)));
+        assert!(needs_filter(&parse(r#"{"defaultAction":"SCMP_ACT_KILL","syscalls":[]}"#)));
+    }
+
+    #[test]
+    fn test_validate_notify() {
+        let parse = |json: &str| serde_json::from_str::<LinuxSeccomp>(json).unwrap();
+        let notify = r#"{"names":["mknod"],"action":"SCMP_ACT_NOTIFY"}"#;
+
+        let seccomp = parse(&format!(r#"{{"defaultAction":"SCMP_ACT_ALLOW","syscalls":[{}]}}"#, notify));
+        assert!(uses_notify(&seccomp));
+        assert!(validate_notify(&seccomp).is_err());
+
+        let seccomp = parse(&format!(
+            r#"{{"defaultAction":"SCMP_ACT_ALLOW","listenerPath":"/run/agent.sock","syscalls":[{}]}}"#,
+            notify
+        ));
+        assert!(validate_notify(&seccomp).is_ok());
+        assert!(validate_notify(&parse(r#"{"defaultAction":"SCMP_ACT_NOTIFY"}"#)).is_err());
+    }
+}
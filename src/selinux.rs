@@ -1,8 +1,80 @@
 use crate::errors::*;
 use crate::nix_ext::lsetxattr_str;
+use oci::Spec;
+use std::path::Path;
 
 const SELINUX_XATTR: &str = "security.selinux";
 
+/// 不允许重新标记的主机目录，标记后主机上的服务可能无法访问
+const PROTECTED_PATHS: [&str; 8] = ["/", "/bin", "/boot", "/dev", "/etc", "/home", "/root", "/usr"];
+
+/// 主机是否启用了 SELinux
+pub fn enabled() -> bool {
+    Path::new("/sys/fs/selinux/enforce").exists()
+}
+
+/// spec 中的 linux.mountLabel，主机未启用 SELinux 时返回空字符串
+pub fn mount_label(spec: &Spec) -> &str {
+    match spec.linux {
+        Some(ref linux) if enabled() => linux.mount_label.as_str(),
+        _ => "",
+    }
+}
+
+/// 为挂载数据追加 context= 选项，数据中已指定 context 时保持不变
+pub fn with_mount_label(data: &str, label: &str) -> String {
+    if label.is_empty() || data.split(',').any(|o| o.starts_with("context=")) {
+        return data.to_string();
+    }
+    let context = format!("context=\"{}\"", label);
+    if data.is_empty() {
+        context
+    } else {
+        format!("{},{}", data, context)
+    }
+}
+
+/// 递归重新标记绑定挂载的源路径
+///
+/// shared 对应挂载选项 `z`，多个容器共享，去掉 MCS 类别；否则对应 `Z`，
+/// 使用容器私有的标签。
+pub fn relabel(path: &Path, label: &str, shared: bool) -> Result<()> {
+    if label.is_empty() {
+        return Ok(());
+    }
+    if PROTECTED_PATHS.iter().any(|p| path == Path::new(p)) {
+        return Err(FireError::InvalidSpec(format!(
+            "不允许重新标记主机目录: {}",
+            path.display()
+        )));
+    }
+    let label = if shared {
+        shared_label(label)
+    } else {
+        label.to_string()
+    };
+    relabel_tree(path, &label)
+}
+
+/// 将标签的级别替换为 s0
+fn shared_label(label: &str) -> String {
+    let mut parts: Vec<&str> = label.splitn(4, ':').collect();
+    if parts.len() == 4 {
+        parts[3] = "s0";
+    }
+    parts.join(":")
+}
+
+fn relabel_tree(path: &Path, label: &str) -> Result<()> {
+    setfilecon(&path.to_string_lossy(), label)?;
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            relabel_tree(&entry?.path(), label)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn setexeccon(label: &str) -> Result<()> {
     if label.is_empty() {
         return Ok(());
@@ -21,3 +93,21 @@ pub fn setfilecon(file: &str, label: &str) -> Result<()> {
     lsetxattr_str(file, SELINUX_XATTR, label.as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_label() {
+        let label = "system_u:object_r:container_file_t:s0:c1,c2";
+        assert_eq!(with_mount_label("", label), format!("context=\"{}\"", label));
+        assert_eq!(
+            with_mount_label("mode=755", label),
+            format!("mode=755,context=\"{}\"", label)
+        );
+        assert_eq!(with_mount_label("context=\"x\"", label), "context=\"x\"");
+        assert_eq!(with_mount_label("mode=755", ""), "mode=755");
+        assert_eq!(shared_label(label), "system_u:object_r:container_file_t:s0");
+    }
+}
@@ -1,6 +1,16 @@
 use crate::errors::*;
-use log::warn;
+use log::{debug, info, warn};
+use nix::errno::Errno;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use crate::container::process::exit_code;
+use crate::container::state;
+use crate::nix_ext;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
 use std::collections::HashMap;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
 
 pub fn pass_signals(_child_pid: i32) -> Result<()> {
     // 简化的信号传递实现
@@ -66,6 +76,28 @@ pub fn kill_all_children(pids: &[i32], signal: i32) -> Result<()> {
     Ok(())
 }
 
+/// 通过 pidfd 向 pid 发送信号，start_time 为记录的进程启动时间
+///
+/// 打开 pidfd 之后 pid 不会再被复用，此时核对启动时间，保证信号不会发给复用了该 pid 的
+/// 无关进程；进程已经退出或启动时间不符时返回 ESRCH。内核不支持 pidfd 时退回 kill(2)。
+pub fn send_signal(pid: i32, start_time: Option<u64>, signal: Signal) -> Result<()> {
+    let pidfd = if crate::sysinfo::get().syscalls.pidfd {
+        Some(unsafe { OwnedFd::from_raw_fd(nix_ext::pidfd_open(pid)?) })
+    } else {
+        None
+    };
+    if start_time.is_some_and(|start_time| state::process_start_time(pid) != Some(start_time)) {
+        return Err(Errno::ESRCH.into());
+    }
+    match pidfd {
+        Some(pidfd) => nix_ext::pidfd_send_signal(pidfd.as_raw_fd(), signal as libc::c_int),
+        None => {
+            debug!("内核不支持 pidfd，使用 kill 向进程 {} 发送信号", pid);
+            Ok(signal::kill(Pid::from_raw(pid), signal)?)
+        }
+    }
+}
+
 pub fn raise_for_parent(signal: i32) -> Result<()> {
     unsafe {
         if libc::raise(signal) != 0 {
@@ -83,3 +115,204 @@ pub fn wait_for_signal() -> Result<i32> {
     // 在实际实现中，这里会使用 signalfd 或 sigwait
     crate::bail!("信号等待功能尚未完全实现")
 }
+
+/// 信号处理函数写入的管道写端，未安装 Reaper 时为 -1
+static SIGCHLD_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// 前台等待容器时转发给 init 进程的信号
+const FORWARDED_SIGNALS: [Signal; 2] = [Signal::SIGTERM, Signal::SIGINT];
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    let fd = SIGCHLD_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // 写入信号值；管道满时说明已有未处理的唤醒，丢弃即可
+        unsafe { libc::write(fd, [signal as u8].as_ptr() as *const libc::c_void, 1) };
+    }
+}
+
+/// 由 SIGCHLD 驱动的子进程回收
+///
+/// 处理函数只向管道写入信号值，等待方被唤醒后用 WNOHANG 回收指定的子进程，子进程
+/// 一退出就能拿到退出状态。同一时间只能有一个 Reaper，析构时恢复 SIGCHLD 的默认处理。
+pub struct Reaper {
+    read: OwnedFd,
+    _write: OwnedFd,
+}
+
+impl Reaper {
+    pub fn install() -> Result<Self> {
+        let (read, write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC | nix::fcntl::OFlag::O_NONBLOCK)?;
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write)) };
+        if SIGCHLD_PIPE
+            .compare_exchange(-1, write.as_raw_fd(), Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            crate::bail!("已经安装了 SIGCHLD 处理");
+        }
+
+        let action = SigAction::new(
+            SigHandler::Handler(handle_signal),
+            SaFlags::SA_RESTART | SaFlags::SA_NOCLDSTOP,
+            SigSet::empty(),
+        );
+        if let Err(e) = unsafe { signal::sigaction(Signal::SIGCHLD, &action) } {
+            SIGCHLD_PIPE.store(-1, Ordering::SeqCst);
+            return Err(e.into());
+        }
+        Ok(Self { read, _write: write })
+    }
+
+    /// 等待子进程 pid 退出，返回退出码，被信号终止时为 128 + 信号值
+    ///
+    /// 等待期间把当前进程收到的 SIGTERM、SIGINT 转发给 pid，第一次转发后 grace 内仍未退出时发送 SIGKILL，init 进程没有处理这些信号时同样能
+    /// 停止容器。返回退出码和是否转发过信号。
+    pub fn wait_forwarding(&self, pid: i32, grace: Duration) -> Result<(i32, bool)> {
+        let action = SigAction::new(SigHandler::Handler(handle_signal), SaFlags::SA_RESTART, SigSet::empty());
+        for signal in FORWARDED_SIGNALS {
+            unsafe { signal::sigaction(signal, &action) }?;
+        }
+        let _restore = scopeguard::guard((), |_| {
+            let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+            for signal in FORWARDED_SIGNALS {
+                let _ = unsafe { signal::sigaction(signal, &action) };
+            }
+        });
+
+        let pid = Pid::from_raw(pid);
+        let mut deadline: Option<Instant> = None;
+        let mut killed = false;
+        loop {
+            if let Some(code) = self.try_reap(pid)? {
+                return Ok((code, deadline.is_some()));
+            }
+            if !killed && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                warn!("进程 {} 在 {:?} 内没有退出，发送 SIGKILL", pid, grace);
+                let _ = signal::kill(pid, Signal::SIGKILL);
+                killed = true;
+            }
+
+            let timeout = deadline.filter(|_| !killed).map(|d| d.saturating_duration_since(Instant::now()));
+            for received in self.wait_signals(timeout)? {
+                let Some(signal) = FORWARDED_SIGNALS.into_iter().find(|s| *s as i32 == received) else {
+                    continue;
+                };
+                info!("转发 {} 给进程 {}", signal, pid);
+                if let Err(e) = signal::kill(pid, signal) {
+                    debug!("转发 {} 失败: {}", signal, e);
+                }
+                deadline.get_or_insert_with(|| Instant::now() + grace);
+            }
+        }
+    }
+
+    /// 回收已经退出的子进程 pid，仍在运行时返回 None
+    ///
+    /// 安装处理函数之前已经退出的子进程仍是僵尸进程，这里同样能回收。
+    fn try_reap(&self, pid: Pid) -> Result<Option<i32>> {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(status) => Ok(exit_code(status)),
+            Err(Errno::EINTR) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 阻塞到收到信号或超时，清空管道并返回期间收到的信号
+    fn wait_signals(&self, timeout: Option<Duration>) -> Result<Vec<i32>> {
+        // 向上取整到毫秒，避免剩余不足 1 毫秒时反复以 0 超时轮询
+        let timeout = timeout.map_or(-1, |t| {
+            (t + Duration::from_nanos(999_999)).as_millis().min(i32::MAX as u128) as i32
+        });
+        let mut pollfd = libc::pollfd {
+            fd: self.read.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, timeout) } < 0 && Errno::last() != Errno::EINTR {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut signals = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            match nix::unistd::read(self.read.as_raw_fd(), &mut buf) {
+                Ok(n) if n > 0 => signals.extend(buf[..n].iter().map(|&s| s as i32)),
+                _ => break,
+            }
+        }
+        debug!("收到信号: {:?}", signals);
+        Ok(signals)
+    }
+}
+
+/// 回收所有已经退出的子进程，如作为 subreaper 收养的孤儿进程，返回回收的数量
+///
+/// 会回收当前进程的任意子进程，只能在没有其他代码等待子进程时调用。
+pub fn reap_orphans() -> usize {
+    let mut reaped = 0;
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => break,
+            Ok(_) => reaped += 1,
+            Err(Errno::EINTR) => continue,
+            Err(_) => break,
+        }
+    }
+    reaped
+}
+
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+        let _ = unsafe { signal::sigaction(Signal::SIGCHLD, &action) };
+        SIGCHLD_PIPE.store(-1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reaper_exit_code() {
+        let reaper = Reaper::install().unwrap();
+        let grace = Duration::from_millis(200);
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 0.1; exit 7"])
+            .spawn()
+            .unwrap();
+        assert_eq!(reaper.wait_forwarding(child.id() as i32, grace).unwrap(), (7, false));
+        // 已经被 reaper 回收
+        assert!(child.wait().is_err());
+
+        // 忽略 SIGTERM 的进程在转发后超时被 SIGKILL 终止；提前到达的信号被忽略而不是终止测试进程
+        unsafe { signal::signal(Signal::SIGTERM, SigHandler::SigIgn) }.unwrap();
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; sleep 5 & wait"])
+            .spawn()
+            .unwrap();
+        std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            signal::kill(Pid::this(), Signal::SIGTERM).unwrap();
+        });
+        let start = Instant::now();
+        let result = reaper.wait_forwarding(child.id() as i32, grace).unwrap();
+        assert_eq!(result, (128 + libc::SIGKILL, true));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(child.wait().is_err());
+    }
+
+    #[test]
+    fn test_send_signal() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        let pid = child.id() as i32;
+        let start_time = state::process_start_time(pid);
+        assert!(start_time.is_some());
+
+        // 启动时间不符时视为 pid 已被复用，不发送信号
+        let reused = send_signal(pid, start_time.map(|t| t + 1), Signal::SIGKILL);
+        assert!(matches!(reused, Err(FireError::Nix(Errno::ESRCH))));
+        send_signal(pid, start_time, Signal::SIGKILL).unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+    }
+}
@@ -5,14 +5,27 @@ use std::os::unix::io::RawFd;
 pub struct Sync {
     pub child_pipe: RawFd,
     pub parent_pipe: RawFd,
+    /// 父进程通知子进程继续执行的管道
+    pub resume_pipe: RawFd,
+    pub wait_pipe: RawFd,
 }
 
 impl Sync {
     pub fn new() -> Result<Self> {
         let (read_fd, write_fd) = nix::unistd::pipe()?;
+        let (wait_fd, resume_fd) = match nix::unistd::pipe() {
+            Ok(fds) => fds,
+            Err(e) => {
+                let _ = close(read_fd);
+                let _ = close(write_fd);
+                return Err(e.into());
+            }
+        };
         Ok(Sync {
             child_pipe: write_fd,
             parent_pipe: read_fd,
+            resume_pipe: resume_fd,
+            wait_pipe: wait_fd,
         })
     }
 
@@ -27,6 +40,22 @@ impl Sync {
         Ok(())
     }
 
+    /// 子进程等待父进程完成准备工作
+    ///
+    /// 父进程未通知就关闭了管道（如准备失败）时返回错误。
+    pub fn wait_for_parent(&self) -> Result<()> {
+        let mut buf = [0u8; 1];
+        if read(self.wait_pipe, &mut buf)? == 0 {
+            return Err(FireError::Generic("父进程已退出".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn notify_child(&self) -> Result<()> {
+        nix::unistd::write(self.resume_pipe, b"1")?;
+        Ok(())
+    }
+
     pub fn close_child_pipe(&self) -> Result<()> {
         close(self.child_pipe)?;
         Ok(())
@@ -36,11 +65,18 @@ impl Sync {
         close(self.parent_pipe)?;
         Ok(())
     }
+
+    pub fn close_resume_pipe(&self) -> Result<()> {
+        close(self.resume_pipe)?;
+        Ok(())
+    }
 }
 
 impl Drop for Sync {
     fn drop(&mut self) {
         let _ = close(self.child_pipe);
         let _ = close(self.parent_pipe);
+        let _ = close(self.resume_pipe);
+        let _ = close(self.wait_pipe);
     }
 }
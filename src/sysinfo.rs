@@ -0,0 +1,191 @@
+//! 内核特性探测
+//!
+//! 在第一次使用时探测一次并缓存：可用的 namespace、cgroup 版本和控制器、seccomp 动作，
+//! 以及 openat2、mount_setattr、pidfd 等较新的系统调用。创建容器时据此提前报告不支持的
+//! 配置，各处也不再各自尝试系统调用，而是直接选择可用的实现。
+
+use crate::cgroups::controllers;
+use crate::nix_ext;
+use lazy_static::lazy_static;
+use nix::errno::Errno;
+use oci::LinuxNamespaceType;
+use serde::Serialize;
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+
+/// 探测到的内核特性，`fire features` 以 JSON 输出
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SysInfo {
+    /// /proc/self/ns 下的 namespace，如 `net`、`time`
+    pub namespaces: Vec<String>,
+    pub cgroup: CgroupInfo,
+    pub seccomp: SeccompInfo,
+    pub syscalls: Syscalls,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CgroupInfo {
+    /// 1 或 2，/sys/fs/cgroup 下没有挂载 cgroup 时为 None
+    pub version: Option<u8>,
+    /// v1 主机同时挂载了 cgroup2，没有绑定到 v1 的控制器由 cgroup2 管理
+    pub hybrid: bool,
+    /// 可用的控制器，混合模式下包括 cgroup2 中的控制器
+    pub controllers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SeccompInfo {
+    /// /proc/sys/kernel/seccomp/actions_avail 中的动作，如 `kill_process`、`user_notif`，
+    /// 内核不提供该文件时为空
+    pub actions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Syscalls {
+    pub openat2: bool,
+    pub mount_setattr: bool,
+    pub pidfd: bool,
+}
+
+lazy_static! {
+    static ref SYSINFO: SysInfo = SysInfo::probe();
+}
+
+/// 当前主机的内核特性
+pub fn get() -> &'static SysInfo {
+    &SYSINFO
+}
+
+/// namespace 在 /proc/<pid>/ns 下的名称
+pub fn namespace_name(typ: LinuxNamespaceType) -> &'static str {
+    match typ {
+        LinuxNamespaceType::pid => "pid",
+        LinuxNamespaceType::network => "net",
+        LinuxNamespaceType::mount => "mnt",
+        LinuxNamespaceType::ipc => "ipc",
+        LinuxNamespaceType::uts => "uts",
+        LinuxNamespaceType::user => "user",
+        LinuxNamespaceType::cgroup => "cgroup",
+        LinuxNamespaceType::time => "time",
+    }
+}
+
+impl SysInfo {
+    fn probe() -> Self {
+        let version = cgroup_version();
+        let hybrid = version == Some(1) && controllers::unified_mounted();
+        let mut controllers = match version {
+            Some(1) => controllers::v1_available(),
+            Some(_) => controllers::v2_available(),
+            None => Vec::new(),
+        };
+        if hybrid {
+            controllers.extend(controllers::v2_available());
+            controllers.sort();
+            controllers.dedup();
+        }
+        Self {
+            namespaces: namespaces(),
+            cgroup: CgroupInfo {
+                version,
+                hybrid,
+                controllers,
+            },
+            seccomp: SeccompInfo {
+                actions: fs::read_to_string("/proc/sys/kernel/seccomp/actions_avail")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(|s| s.to_string())
+                    .collect(),
+            },
+            syscalls: Syscalls {
+                openat2: probe_openat2(),
+                mount_setattr: probe_mount_setattr(),
+                pidfd: probe_pidfd(),
+            },
+        }
+    }
+
+    /// 内核是否支持该 namespace，名称同 [`namespace_name`]
+    pub fn has_namespace(&self, name: &str) -> bool {
+        self.namespaces.iter().any(|ns| ns == name)
+    }
+
+    /// 内核是否支持该 seccomp 动作，无法确定时视为支持
+    pub fn has_seccomp_action(&self, action: &str) -> bool {
+        self.seccomp.actions.is_empty() || self.seccomp.actions.iter().any(|a| a == action)
+    }
+}
+
+fn namespaces() -> Vec<String> {
+    let mut namespaces: Vec<String> = fs::read_dir("/proc/self/ns")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| !name.ends_with("_for_children"))
+        .collect();
+    namespaces.sort();
+    namespaces
+}
+
+fn cgroup_version() -> Option<u8> {
+    if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        Some(2)
+    } else if Path::new("/sys/fs/cgroup/cpu").exists() {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// 在容器中运行时 seccomp 可能以 EPERM 拒绝 openat2，同样视为不支持，改用回退的实现
+fn probe_openat2() -> bool {
+    let root = CString::new("/").unwrap();
+    match nix_ext::openat2(libc::AT_FDCWD, &root, libc::O_PATH | libc::O_CLOEXEC, 0) {
+        Ok(fd) => {
+            let _ = nix::unistd::close(fd);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// 不设置任何属性时不会修改挂载；没有权限时返回 EPERM，之后实际挂载时再报告
+fn probe_mount_setattr() -> bool {
+    let empty = CString::new("").unwrap();
+    let attr: libc::mount_attr = unsafe { std::mem::zeroed() };
+    let flags = libc::AT_EMPTY_PATH as libc::c_uint;
+    !matches!(
+        nix_ext::mount_setattr(-1, &empty, flags, &attr),
+        Err(crate::errors::FireError::Nix(Errno::ENOSYS))
+    )
+}
+
+fn probe_pidfd() -> bool {
+    match nix_ext::pidfd_open(std::process::id() as libc::pid_t) {
+        Ok(fd) => {
+            let _ = nix::unistd::close(fd);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe() {
+        let info = get();
+        assert!(info.has_namespace("mnt"));
+        assert!(!info.namespaces.iter().any(|ns| ns.ends_with("_for_children")));
+        assert!(info.has_seccomp_action("allow"));
+
+        let json = serde_json::to_value(info).unwrap();
+        assert!(json["syscalls"]["mountSetattr"].is_boolean());
+        assert!(json["cgroup"]["controllers"].is_array());
+    }
+}
@@ -0,0 +1,214 @@
+//! 终端：为容器进程分配伪终端，把当前终端切换到原始模式，并在 pty 主端与标准输入
+//! 输出之间转发数据，当前终端的窗口大小变化时同步到 pty。
+
+use crate::errors::Result;
+use crate::nix_ext;
+use log::debug;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::termios::{self, SetArg, Termios};
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// 伪终端，从端交给容器进程作为控制终端和标准输入输出
+pub struct Pty {
+    pub master: OwnedFd,
+    pub slave: OwnedFd,
+}
+
+impl Pty {
+    /// 分配伪终端，标准输入是终端时沿用其窗口大小
+    ///
+    /// 两端都设置了 close-on-exec，容器进程只通过 dup 到 0、1、2 的从端使用终端。
+    pub fn open() -> Result<Self> {
+        let pty = nix::pty::openpty(None, None)?;
+        for fd in [&pty.master, &pty.slave] {
+            fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        }
+        copy_winsize(libc::STDIN_FILENO, pty.master.as_raw_fd());
+        Ok(Self {
+            master: pty.master,
+            slave: pty.slave,
+        })
+    }
+}
+
+/// 把标准输入所在的终端切换到原始模式，析构时恢复原来的设置
+pub struct RawMode {
+    original: Termios,
+}
+
+impl RawMode {
+    /// 标准输入不是终端时返回 None
+    pub fn enter() -> Result<Option<Self>> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return Ok(None);
+        }
+        let stdin = unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) };
+        let original = termios::tcgetattr(stdin)?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(stdin, SetArg::TCSANOW, &raw)?;
+        Ok(Some(Self { original }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let stdin = unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) };
+        let _ = termios::tcsetattr(stdin, SetArg::TCSANOW, &self.original);
+    }
+}
+
+/// 让当前进程以 fd 为控制终端，并替换标准输入输出，在容器进程 exec 之前调用
+pub fn set_controlling_terminal(fd: RawFd) -> Result<()> {
+    nix::unistd::setsid()?;
+    if unsafe { libc::ioctl(fd, libc::TIOCSCTTY, 0) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    for target in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        nix::unistd::dup2(fd, target)?;
+    }
+    if fd > libc::STDERR_FILENO {
+        let _ = nix::unistd::close(fd);
+    }
+    Ok(())
+}
+
+/// 把 pty 主端发送到 console socket，与 runc 的 --console-socket 协议相同
+pub fn send_master(socket: &Path, master: RawFd) -> Result<()> {
+    let stream = UnixStream::connect(socket)?;
+    nix_ext::send_fd(stream.as_raw_fd(), master, b"/dev/ptmx")
+}
+
+/// 从 console socket 的连接中接收 pty 主端
+pub fn receive_master(stream: &UnixStream) -> Result<Option<OwnedFd>> {
+    let fd = nix_ext::recv_fd(stream.as_raw_fd())?;
+    Ok(fd.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+/// SIGWINCH 处理函数写入的管道写端，未在转发时为 -1
+static SIGWINCH_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_sigwinch(_: libc::c_int) {
+    let fd = SIGWINCH_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        unsafe { libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1) };
+    }
+}
+
+/// 在 pty 主端与标准输入输出之间转发数据，直到所有从端都被关闭
+///
+/// forward_stdin 为 false 时不读取标准输入。标准输入不是终端且读到 EOF 时向 pty 写入
+/// EOT，使容器进程同样读到 EOF。
+pub fn proxy(master: RawFd, forward_stdin: bool) -> Result<()> {
+    let (read, write) = nix::unistd::pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK)?;
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write)) };
+    SIGWINCH_PIPE.store(write.as_raw_fd(), Ordering::SeqCst);
+    let action = SigAction::new(SigHandler::Handler(handle_sigwinch), SaFlags::SA_RESTART, SigSet::empty());
+    unsafe { signal::sigaction(Signal::SIGWINCH, &action) }?;
+    let _restore = scopeguard::guard((), |_| {
+        let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+        let _ = unsafe { signal::sigaction(Signal::SIGWINCH, &action) };
+        SIGWINCH_PIPE.store(-1, Ordering::SeqCst);
+    });
+    // 转发开始前窗口大小可能已经变化
+    copy_winsize(libc::STDIN_FILENO, master);
+
+    let stdin_tty = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    let mut stdin_open = forward_stdin;
+    let mut stdout = std::io::stdout().lock();
+    let mut buf = [0u8; 8192];
+    loop {
+        let mut fds = [
+            libc::pollfd { fd: master, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: read.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            libc::pollfd {
+                fd: if stdin_open { libc::STDIN_FILENO } else { -1 },
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            if Errno::last() == Errno::EINTR {
+                continue;
+            }
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        if fds[0].revents != 0 {
+            match nix::unistd::read(master, &mut buf) {
+                // 从端全部关闭后读主端返回 EIO
+                Ok(0) | Err(Errno::EIO) => break,
+                Ok(n) => {
+                    stdout.write_all(&buf[..n])?;
+                    stdout.flush()?;
+                }
+                Err(Errno::EINTR | Errno::EAGAIN) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if fds[1].revents != 0 {
+            while nix::unistd::read(read.as_raw_fd(), &mut buf).is_ok_and(|n| n > 0) {}
+            debug!("终端窗口大小变化");
+            copy_winsize(libc::STDIN_FILENO, master);
+        }
+        if fds[2].revents != 0 {
+            match nix::unistd::read(libc::STDIN_FILENO, &mut buf) {
+                Ok(0) | Err(Errno::EIO) => {
+                    stdin_open = false;
+                    if !stdin_tty {
+                        write_all(master, &[0x04])?;
+                    }
+                }
+                Ok(n) => write_all(master, &buf[..n])?,
+                Err(Errno::EINTR | Errno::EAGAIN) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_all(fd: RawFd, mut data: &[u8]) -> Result<()> {
+    while !data.is_empty() {
+        match nix::unistd::write(fd, data) {
+            Ok(n) => data = &data[n..],
+            Err(Errno::EINTR) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// 把窗口大小从终端 from 复制到 to，from 不是终端时忽略
+fn copy_winsize(from: RawFd, to: RawFd) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(from, libc::TIOCGWINSZ, &mut size) } == 0 {
+        unsafe { libc::ioctl(to, libc::TIOCSWINSZ, &size) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_master() {
+        let pty = Pty::open().unwrap();
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        nix_ext::send_fd(sender.as_raw_fd(), pty.master.as_raw_fd(), b"/dev/ptmx").unwrap();
+        let master = receive_master(&receiver).unwrap().unwrap();
+        drop(pty.master);
+
+        // 收到的描述符指向同一个 pty，从端可以读到写入的数据
+        write_all(master.as_raw_fd(), b"hi\n").unwrap();
+        let mut buf = [0u8; 16];
+        let n = nix::unistd::read(pty.slave.as_raw_fd(), &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"hi\n");
+    }
+}